@@ -4,7 +4,7 @@ use toml::{from_str, Value};
 
 fn get_revision() -> Option<String> {
     let output = std::process::Command::new("git")
-        .args(&["rev-parse", "HEAD"])
+        .args(["rev-parse", "HEAD"])
         .output()
         .ok()?;
     if !output.status.success() {
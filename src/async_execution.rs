@@ -0,0 +1,153 @@
+// Bookkeeping for gdb commands that are executed without blocking the ui thread. Results (or
+// errors) are dispatched to the registered callbacks from the main event loop.
+use crate::gdbmi::{output::ResultRecord, ExecuteError, Token};
+use crate::tui::Tui;
+use crate::{Context, Event};
+use log::info;
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender};
+use std::time::{Duration, Instant};
+
+pub type ResultCallback =
+    Box<dyn for<'t> FnOnce(&mut Tui<'t>, &mut Context, Result<ResultRecord, ExecuteError>)>;
+pub type DeferredCallback = Box<dyn for<'t> FnOnce(&mut Tui<'t>, &mut Context)>;
+
+#[derive(Default)]
+pub struct PendingCommands {
+    callbacks: HashMap<Token, ResultCallback>,
+    deferred: Vec<DeferredCallback>,
+}
+
+impl PendingCommands {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert(&mut self, token: Token, callback: ResultCallback) {
+        let prev = self.callbacks.insert(token, callback);
+        debug_assert!(prev.is_none(), "Token used for multiple commands");
+    }
+
+    pub fn remove(&mut self, token: Token) -> Option<ResultCallback> {
+        self.callbacks.remove(&token)
+    }
+
+    pub fn is_pending(&self, token: Token) -> bool {
+        self.callbacks.contains_key(&token)
+    }
+
+    pub fn take_all(&mut self) -> Vec<ResultCallback> {
+        self.callbacks
+            .drain()
+            .map(|(_, callback)| callback)
+            .collect()
+    }
+
+    pub fn defer(&mut self, callback: DeferredCallback) {
+        self.deferred.push(callback);
+    }
+
+    fn take_deferred(&mut self) -> Vec<DeferredCallback> {
+        std::mem::take(&mut self.deferred)
+    }
+}
+
+// Sends Event::AsyncTimeout for commands whose timeout expired. A single thread waits for all
+// deadlines, so that commands do not need a thread each.
+pub struct Timeouts {
+    deadlines: Sender<(Instant, Token)>,
+}
+
+impl Timeouts {
+    pub fn new(event_sink: Sender<Event>) -> Self {
+        let (deadlines, receiver) = channel();
+        let _ = std::thread::spawn(move || wait_for_deadlines(receiver, event_sink));
+        Timeouts { deadlines }
+    }
+
+    pub fn start(&self, token: Token, timeout: Duration) {
+        let _ = self.deadlines.send((Instant::now() + timeout, token));
+    }
+}
+
+// Runs until the Timeouts or the receiver of the events are gone.
+fn wait_for_deadlines(deadlines: Receiver<(Instant, Token)>, event_sink: Sender<Event>) {
+    let mut queue = BinaryHeap::new();
+    loop {
+        let now = Instant::now();
+        while let Some(&Reverse((deadline, token))) = queue.peek() {
+            if deadline > now {
+                break;
+            }
+            queue.pop();
+            if event_sink.send(Event::AsyncTimeout(token)).is_err() {
+                return;
+            }
+        }
+        let next = match queue.peek() {
+            Some(Reverse((deadline, _))) => deadlines.recv_timeout(*deadline - now),
+            None => deadlines.recv().map_err(|_| RecvTimeoutError::Disconnected),
+        };
+        match next {
+            Ok(deadline) => queue.push(Reverse(deadline)),
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => return,
+        }
+    }
+}
+
+// Call the callback registered for the command with the given token (if it has not been cancelled
+// or timed out already).
+pub fn dispatch(
+    tui: &mut Tui,
+    p: &mut Context,
+    token: Token,
+    result: Result<ResultRecord, ExecuteError>,
+) {
+    if let Some(callback) = p.pending_commands.remove(token) {
+        callback(tui, p, result);
+    } else {
+        info!(
+            "Dropping result of cancelled or timed out command {}: {:?}",
+            token, result
+        );
+    }
+}
+
+// Run all callbacks that were deferred to the event loop. Callbacks may defer further callbacks,
+// which will be run as well.
+pub fn run_deferred(tui: &mut Tui, p: &mut Context) {
+    loop {
+        let deferred = p.pending_commands.take_deferred();
+        if deferred.is_empty() {
+            break;
+        }
+        for callback in deferred {
+            callback(tui, p);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_timeouts() {
+        let (event_sink, events) = channel();
+        let timeouts = Timeouts::new(event_sink);
+        timeouts.start(1, Duration::from_millis(200));
+        timeouts.start(2, Duration::from_millis(50));
+        timeouts.start(3, Duration::from_millis(100));
+        let tokens = events
+            .iter()
+            .take(3)
+            .map(|event| match event {
+                Event::AsyncTimeout(token) => token,
+                event => panic!("Unexpected event {:?}", event),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(tokens, [2, 3, 1]);
+    }
+}
@@ -1,7 +1,10 @@
 use crate::gdbmi::commands::MiCommand;
-use crate::gdbmi::output::{JsonValue, ResultClass};
+use crate::gdbmi::output::{ResultClass, ResultRecord, VarObjectInfo, Variable};
+use crate::gdbmi::ExecuteError;
+use crate::tui::Tui;
 use crate::Context;
 use log::{error, info};
+use std::collections::VecDeque;
 use std::ffi::OsString;
 use std::ops::Range;
 use std::path::Path;
@@ -34,6 +37,11 @@ impl CompletionState {
         )
     }
 
+    // Check if the completion was generated for the given line and cursor position.
+    pub fn matches(&self, line: &str, cursor_pos: usize) -> bool {
+        self.original == line && self.cursor_pos == cursor_pos
+    }
+
     pub fn current_option(&self) -> &str {
         self.completion_options
             .get(self.current_option)
@@ -107,143 +115,123 @@ impl Completer for CommandCompleter<'_> {
     }
 }
 
-pub struct IdentifierCompleter<'a>(pub &'a mut Context);
-
-struct VarObject {
-    name: String,
-    expr: Option<String>,
-    typ: Option<String>,
-}
+// Completion results are delivered via the event loop, see Context::execute_async.
+type Continuation<T> = Box<dyn for<'t> FnOnce(&mut Tui<'t>, &mut Context, T)>;
 
-impl VarObject {
-    fn from_val(o: &JsonValue) -> Result<Self, String> {
-        let name = if let Some(name) = o["name"].as_str() {
-            name.to_string()
-        } else {
-            return Err("Missing field 'name'".into());
-        };
-        let expr = o["exp"].as_str().map(|s| s.to_owned());
-        let typ = o["type"].as_str().map(|s| s.to_owned());
-        Ok(VarObject { name, expr, typ })
-    }
-    fn create(p: &mut Context, expr: &str) -> Result<Self, String> {
-        let res = p
-            .gdb
-            .mi
-            .execute(MiCommand::var_create(None, expr, None))
-            .map_err(|e| format!("{:?}", e))?;
-
-        match res.class {
-            ResultClass::Done => {}
-            ResultClass::Error => return Err(format!("{}", res.results["msg"])),
-            o => return Err(format!("Unexpected result class: {:?}", o)),
-        }
-
-        VarObject::from_val(&JsonValue::Object(res.results))
+fn check_result(result: Result<ResultRecord, ExecuteError>) -> Result<ResultRecord, String> {
+    let res = result.map_err(|e| format!("{:?}", e))?;
+    match res.class {
+        ResultClass::Done => Ok(res),
+        ResultClass::Error => Err(format!("{}", res.results["msg"])),
+        o => Err(format!("Unexpected result class: {:?}", o)),
     }
+}
 
-    fn children(&self, p: &mut Context) -> Result<Vec<Self>, String> {
-        let res = p
-            .gdb
-            .mi
-            .execute(MiCommand::var_list_children(&self.name, true, None))
-            .map_err(|e| format!("{:?}", e))?;
-
-        match res.class {
-            ResultClass::Done => {}
-            ResultClass::Error => return Err(format!("{}", res.results["msg"])),
-            o => return Err(format!("Unexpected result class: {:?}", o)),
-        }
+// Pseudo children (like public, private, anonymous unions, ...) are flattened.
+fn is_pseudo_child(var: &VarObjectInfo) -> bool {
+    let flatten_exprs = ["<anonymous union>", "<anonymous struct>"];
 
-        res.results["children"]
-            .members()
-            .map(VarObject::from_val)
-            .collect::<Result<Vec<_>, String>>()
+    match (&var.typ, &var.exp) {
+        (Some(_), Some(exp)) => flatten_exprs.iter().any(|n| n == exp),
+        _ => true,
     }
+}
 
-    fn collect_children_exprs(
-        &self,
-        p: &mut Context,
-        output: &mut Vec<String>,
-    ) -> Result<(), String> {
-        // try to flatten public/private fields etc.
-        let flatten_exprs = ["<anonymous union>", "<anonymous struct>"];
-
-        for child in self.children(p)? {
-            if child.typ.is_none()
-                || child.expr.is_none()
-                || flatten_exprs
-                    .iter()
-                    .any(|n| *n == child.expr.as_ref().unwrap())
-            {
-                // This is the case for pseudo children (like public, ...)
-                child.collect_children_exprs(p, output)?;
-            } else if let Some(expr) = child.expr {
-                output.push(expr);
+// Collect the expressions of all (non-pseudo) children of the var objects in `queue`.
+fn collect_children_exprs(
+    p: &mut Context,
+    mut queue: VecDeque<String>,
+    mut output: Vec<String>,
+    done: Continuation<Result<Vec<String>, String>>,
+) {
+    let name = if let Some(name) = queue.pop_front() {
+        name
+    } else {
+        p.defer(move |tui, p| done(tui, p, Ok(output)));
+        return;
+    };
+    p.execute_async(
+        MiCommand::var_list_children(name, true, None),
+        move |tui, p, result| {
+            let children = check_result(result).and_then(|res| {
+                VarObjectInfo::decode_children(&res.results).map_err(|e| e.to_string())
+            });
+            match children {
+                Ok(children) => {
+                    for child in children {
+                        if is_pseudo_child(&child) {
+                            // This is the case for pseudo children (like public, ...)
+                            queue.push_back(child.name);
+                        } else if let Some(expr) = child.exp {
+                            output.push(expr);
+                        }
+                    }
+                    collect_children_exprs(p, queue, output, done);
+                }
+                Err(e) => done(tui, p, Err(e)),
             }
-        }
-        Ok(())
-    }
-
-    fn delete(self, p: &mut Context) -> Result<(), String> {
-        let res = p
-            .gdb
-            .mi
-            .execute(MiCommand::var_delete(self.name, true))
-            .map_err(|e| format!("{:?}", e))?;
-
-        match res.class {
-            ResultClass::Done => {}
-            ResultClass::Error => return Err(format!("{}", res.results["msg"])),
-            o => return Err(format!("Unexpected result class: {:?}", o)),
-        }
-        Ok(())
-    }
+        },
+    );
 }
 
-fn get_children(p: &mut Context, expr: &str) -> Result<Vec<String>, String> {
-    let root = VarObject::create(p, expr)?;
-
-    let mut children = Vec::new();
-
-    root.collect_children_exprs(p, &mut children)?;
-
-    root.delete(p)?;
-
-    Ok(children)
+fn get_children(p: &mut Context, expr: &str, done: Continuation<Result<Vec<String>, String>>) {
+    p.execute_async(
+        MiCommand::var_create(None, expr, None),
+        move |tui, p, result| {
+            let root = check_result(result)
+                .and_then(|res| VarObjectInfo::decode(&res.results).map_err(|e| e.to_string()));
+            let root = match root {
+                Ok(root) => root,
+                Err(e) => return done(tui, p, Err(e)),
+            };
+            let root_name = root.name.clone();
+            collect_children_exprs(
+                p,
+                VecDeque::from(vec![root.name]),
+                Vec::new(),
+                Box::new(move |tui, p, children| {
+                    p.execute_async(MiCommand::var_delete(root_name, true), |_, _, result| {
+                        if let Err(e) = check_result(result) {
+                            info!("Could not delete var object: {}", e);
+                        }
+                    });
+                    done(tui, p, children);
+                }),
+            );
+        },
+    );
 }
 
-fn get_variables(p: &mut Context) -> Result<Vec<String>, String> {
-    let res = p
-        .gdb
-        .mi
-        .execute(MiCommand::stack_list_variables(None, None))
-        .map_err(|e| format!("{:?}", e))?;
-
-    match res.class {
-        ResultClass::Done => {}
-        ResultClass::Error => return Err(format!("{}", res.results["msg"])),
-        o => return Err(format!("Unexpected result class: {:?}", o)),
-    }
-
-    Ok(res.results["variables"]
-        .members()
-        .map(|o| o["name"].as_str().unwrap().to_string())
-        .collect::<Vec<_>>())
+fn get_variables(p: &mut Context, done: Continuation<Result<Vec<String>, String>>) {
+    p.execute_async(
+        MiCommand::stack_list_variables(None, None),
+        move |tui, p, result| {
+            let variables = check_result(result).and_then(|res| {
+                Variable::decode_list(&res.results)
+                    .map(|vars| vars.into_iter().map(|v| v.name).collect::<Vec<_>>())
+                    .map_err(|e| e.to_string())
+            });
+            done(tui, p, variables);
+        },
+    );
 }
 
-impl Completer for IdentifierCompleter<'_> {
-    fn complete(&mut self, original: &str, cursor_pos: usize) -> CompletionState {
-        let expr = if let Ok(e) = CompletableExpression::from_str(&original[..cursor_pos]) {
-            e
-        } else {
-            return CompletionState::empty(original.to_owned(), cursor_pos);
-        };
-        let res = if expr.parent.is_empty() {
-            get_variables(self.0)
-        } else {
-            get_children(self.0, &expr.parent)
-        };
+// Complete the identifier in front of the cursor. Since this requires querying gdb (possibly
+// multiple times), the result is passed to `on_completion` once it is available.
+pub fn complete_identifier<F>(p: &mut Context, original: &str, cursor_pos: usize, on_completion: F)
+where
+    F: for<'t> FnOnce(&mut Tui<'t>, &mut Context, CompletionState) + 'static,
+{
+    let expr = if let Ok(e) = CompletableExpression::from_str(&original[..cursor_pos]) {
+        e
+    } else {
+        let state = CompletionState::empty(original.to_owned(), cursor_pos);
+        p.defer(move |tui, p| on_completion(tui, p, state));
+        return;
+    };
+    let CompletableExpression { parent, prefix } = expr;
+    let original = original.to_owned();
+    let done: Continuation<Result<Vec<String>, String>> = Box::new(move |tui, p, res| {
         let children = match res {
             Ok(c) => c,
             Err(e) => {
@@ -251,28 +239,38 @@ impl Completer for IdentifierCompleter<'_> {
                 vec![]
             }
         };
-        let candidates = find_candidates(&expr.prefix, children.as_slice());
-        CompletionState::new(original.to_owned(), cursor_pos, candidates)
+        let candidates = find_candidates(&prefix, children.as_slice());
+        on_completion(
+            tui,
+            p,
+            CompletionState::new(original, cursor_pos, candidates),
+        );
+    });
+    if parent.is_empty() {
+        get_variables(p, done)
+    } else {
+        get_children(p, &parent, done)
     }
 }
 
-pub struct CmdlineCompleter<'a>(pub &'a mut Context);
-impl Completer for CmdlineCompleter<'_> {
-    fn complete(&mut self, original: &str, cursor_pos: usize) -> CompletionState {
-        if original[..cursor_pos].find(' ').is_some() {
-            // gdb command already typed, try to complete identifier in expression
-            IdentifierCompleter(self.0).complete(original, cursor_pos)
-        } else {
-            // First "word" in command line, complete gdb command
-            CommandCompleter {
-                binary_path: self.0.gdb.mi.binary_path(),
-                init_options: self.0.gdb.mi.init_options(),
-            }
-            .complete(original, cursor_pos)
+pub fn complete_cmdline<F>(p: &mut Context, original: &str, cursor_pos: usize, on_completion: F)
+where
+    F: for<'t> FnOnce(&mut Tui<'t>, &mut Context, CompletionState) + 'static,
+{
+    if original[..cursor_pos].find(' ').is_some() {
+        // gdb command already typed, try to complete identifier in expression
+        complete_identifier(p, original, cursor_pos, on_completion)
+    } else {
+        // First "word" in command line, complete gdb command
+        let state = CommandCompleter {
+            binary_path: p.gdb.mi.binary_path(),
+            init_options: p.gdb.mi.init_options(),
         }
-        //TODO: path completer? not sure how to distinguish between IdentifierCompleter and path
-        //completer. Maybe based on gdb command...
+        .complete(original, cursor_pos);
+        p.defer(move |tui, p| on_completion(tui, p, state));
     }
+    //TODO: path completer? not sure how to distinguish between identifier completion and path
+    //completer. Maybe based on gdb command...
 }
 
 #[derive(Copy, Clone, PartialEq, Debug)]
@@ -0,0 +1,965 @@
+// A server for the Debug Adapter Protocol (DAP), so that editors can act as an additional frontend
+// for the session that is shown in the tui (see --dap).
+//
+// Like ipc requests, DAP requests are handled on the main thread. Breakpoints are shared with the
+// tui: Changes of the BreakPointSet (no matter who made them) are announced to all clients.
+use crate::gdb::{BreakPoint, BreakPointSet, BreakpointOperationError};
+use crate::gdbmi::{
+    commands::{BreakPointLocation, MiCommand},
+    output::{
+        AsyncClass, Frame, Object, ResultClass, ResultRecord, StopEvent, StopReason, StreamKind,
+        ThreadEvent, ThreadGroupEvent, ThreadInfo, ThreadListEntry, Variable,
+    },
+    ExecuteError,
+};
+use crate::{ipc, Context, Event};
+use json::object;
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{SocketAddr, TcpListener};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::Sender;
+use std::thread;
+use std::time::{Duration, Instant};
+use unix_socket::UnixListener;
+
+const CLIENT_WRITE_TIMEOUT: Duration = Duration::from_millis(100);
+// Frames are identified by their thread and level: id = thread * FRAME_ID_FACTOR + level. Since
+// gdb starts counting threads at 1, frame ids can also be used as variablesReference (which has
+// to be > 0).
+const FRAME_ID_FACTOR: u64 = 1 << 16;
+
+pub type ClientId = usize;
+
+pub enum Message {
+    Connected(ClientId, Box<dyn Write + Send>),
+    Request(ClientId, json::JsonValue),
+    Disconnected(ClientId),
+}
+
+impl std::fmt::Debug for Message {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Message::Connected(id, _) => write!(f, "Connected({})", id),
+            Message::Request(id, request) => write!(f, "Request({}, {})", id, request.dump()),
+            Message::Disconnected(id) => write!(f, "Disconnected({})", id),
+        }
+    }
+}
+
+pub struct Server {
+    // Only present for tcp addresses
+    local_addr: Option<SocketAddr>,
+    // Only present for unix domain sockets, which have to be removed again.
+    socket_path: Option<PathBuf>,
+}
+
+impl Server {
+    // Listen on a tcp address (host:port) or, otherwise, on a unix domain socket at the given path.
+    // Anybody who can connect controls the debugged program: Unix domain sockets are only
+    // accessible by the current user. Tcp ports are reachable by all users of the machine, so they
+    // require clients to pass a token and have to be local unless `allow_remote` is set.
+    pub fn setup(
+        address: &str,
+        token_required: bool,
+        allow_remote: bool,
+        event_sink: Sender<Event>,
+    ) -> std::io::Result<Self> {
+        if let Ok(address) = address.parse::<SocketAddr>() {
+            if !token_required {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::PermissionDenied,
+                    "Refusing to serve on a tcp address without --dap-token",
+                ));
+            }
+            if !address.ip().is_loopback() && !allow_remote {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::PermissionDenied,
+                    "Refusing to serve on a non-loopback address without --dap-allow-remote",
+                ));
+            }
+            let listener = TcpListener::bind(address)?;
+            let local_addr = listener.local_addr()?;
+            let _ = thread::Builder::new()
+                .name("DAP Connection Listener".to_owned())
+                .spawn(move || {
+                    for (id, connection) in listener.incoming().flatten().enumerate() {
+                        if let Ok(writer) = connection.try_clone() {
+                            let _ = writer.set_write_timeout(Some(CLIENT_WRITE_TIMEOUT));
+                            start_connection(id, connection, writer, event_sink.clone());
+                        }
+                    }
+                });
+            Ok(Server {
+                local_addr: Some(local_addr),
+                socket_path: None,
+            })
+        } else {
+            use std::os::unix::fs::PermissionsExt;
+            let socket_path = socket_path(address);
+            if let Some(dir) = socket_path.parent().filter(|dir| !dir.exists()) {
+                ipc::create_private_dir(dir)?;
+            }
+            let listener = UnixListener::bind(&socket_path)?;
+            std::fs::set_permissions(&socket_path, std::fs::Permissions::from_mode(0o600))?;
+            let _ = thread::Builder::new()
+                .name("DAP Connection Listener".to_owned())
+                .spawn(move || {
+                    for (id, connection) in listener.incoming().flatten().enumerate() {
+                        // See ipc::IPC::setup
+                        if !ipc::is_same_user(&connection) {
+                            log::warn!("Rejected dap connection from another user");
+                            continue;
+                        }
+                        if let Ok(writer) = connection.try_clone() {
+                            let _ = writer.set_write_timeout(Some(CLIENT_WRITE_TIMEOUT));
+                            start_connection(id, connection, writer, event_sink.clone());
+                        }
+                    }
+                });
+            Ok(Server {
+                local_addr: None,
+                socket_path: Some(socket_path),
+            })
+        }
+    }
+
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        self.local_addr
+    }
+
+    pub fn socket_path(&self) -> Option<&Path> {
+        self.socket_path.as_deref()
+    }
+}
+
+// Plain names are placed in the private directory that also contains the ipc sockets.
+fn socket_path(address: &str) -> PathBuf {
+    let path = Path::new(address);
+    if path.components().count() == 1 && path.is_relative() {
+        ipc::ugdb_dir().join(path)
+    } else {
+        path.to_owned()
+    }
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        if let Some(socket_path) = &self.socket_path {
+            let _ = std::fs::remove_file(socket_path);
+        }
+    }
+}
+
+fn start_connection(
+    id: ClientId,
+    reader: impl Read + Send + 'static,
+    writer: impl Write + Send + 'static,
+    event_sink: Sender<Event>,
+) {
+    let _ = thread::Builder::new()
+        .name("DAP Connection".to_owned())
+        .spawn(move || {
+            let _ = event_sink.send(Event::Dap(Message::Connected(id, Box::new(writer))));
+            let mut reader = BufReader::new(reader);
+            // Malformed messages terminate the connection.
+            while let Ok(Some(request)) = read_message(&mut reader) {
+                let _ = event_sink.send(Event::Dap(Message::Request(id, request)));
+            }
+            let _ = event_sink.send(Event::Dap(Message::Disconnected(id)));
+        });
+}
+
+// Messages consist of http-like headers (of which only Content-Length is required) and a json body.
+fn read_message<R: BufRead>(reader: &mut R) -> std::io::Result<Option<json::JsonValue>> {
+    let invalid = |msg: &str| std::io::Error::new(std::io::ErrorKind::InvalidData, msg);
+    let mut content_length = None;
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some(length) = line.strip_prefix("Content-Length:") {
+            content_length = Some(
+                length
+                    .trim()
+                    .parse::<usize>()
+                    .map_err(|_| invalid("Malformed Content-Length"))?,
+            );
+        }
+    }
+    let mut body = vec![0u8; content_length.ok_or_else(|| invalid("Missing Content-Length"))?];
+    reader.read_exact(&mut body)?;
+    let body = String::from_utf8(body).map_err(|_| invalid("Malformed utf8"))?;
+    json::parse(&body)
+        .map(Some)
+        .map_err(|_| invalid("Malformed json"))
+}
+
+struct Client {
+    id: ClientId,
+    writer: Box<dyn Write + Send>,
+    seq: u64,
+    // Whether the client passed the token (if required) in its launch or attach request
+    authorized: bool,
+}
+
+impl Client {
+    fn send(&mut self, mut message: json::JsonValue) -> std::io::Result<()> {
+        self.seq += 1;
+        message["seq"] = self.seq.into();
+        let body = message.dump();
+        write!(
+            self.writer,
+            "Content-Length: {}\r\n\r\n{}",
+            body.len(),
+            body
+        )?;
+        self.writer.flush()
+    }
+}
+
+#[derive(Default)]
+pub struct Clients {
+    clients: Vec<Client>,
+    // The token that clients have to pass (see --dap-token)
+    token: Option<String>,
+    // The breakpoints (by major number) as last announced to the clients
+    breakpoints: HashMap<usize, json::JsonValue>,
+    breakpoints_synced: Option<Instant>,
+}
+
+impl Clients {
+    pub fn new(token: Option<String>) -> Self {
+        Clients {
+            token,
+            ..Self::default()
+        }
+    }
+
+    // Send the message to all authorized clients but `except` and drop clients that cannot be
+    // reached.
+    fn broadcast(&mut self, message: &json::JsonValue, except: Option<ClientId>) {
+        self.clients.retain_mut(|c| {
+            Some(c.id) == except || !c.authorized || c.send(message.clone()).is_ok()
+        });
+    }
+
+    // Clients have to pass the token in the arguments of their launch or attach request before
+    // they can do anything but initialize the connection.
+    fn authorize(&mut self, id: ClientId, command: &str, arguments: &json::JsonValue) -> bool {
+        let client = match self.clients.iter_mut().find(|c| c.id == id) {
+            Some(client) => client,
+            None => return false,
+        };
+        if !client.authorized && (command == "launch" || command == "attach") {
+            client.authorized = arguments["token"].as_str() == self.token.as_deref();
+        }
+        client.authorized || command == "initialize"
+    }
+
+    fn send_event(&mut self, event: &str, body: json::JsonValue) {
+        self.broadcast(
+            &object! {
+                "type" => "event",
+                "event" => event,
+                "body" => body,
+            },
+            None,
+        );
+    }
+
+    pub fn output(&mut self, kind: StreamKind, text: &str) {
+        let category = match kind {
+            StreamKind::Console => "console",
+            StreamKind::Target => "stdout",
+            // The log mostly echoes commands.
+            StreamKind::Log => return,
+        };
+        if self.clients.is_empty() {
+            return;
+        }
+        self.send_event(
+            "output",
+            object! {
+                "category" => category,
+                "output" => text,
+            },
+        );
+    }
+
+    // Translate a notification from gdb to events for the clients.
+    pub fn notify(&mut self, class: &AsyncClass, results: &Object) {
+        if self.clients.is_empty() {
+            return;
+        }
+        match class {
+            AsyncClass::Stopped => match StopEvent::decode(results) {
+                // Exits are announced via thread group events.
+                Ok(StopEvent {
+                    reason:
+                        Some(StopReason::Exited { .. })
+                        | Some(StopReason::ExitedNormally)
+                        | Some(StopReason::ExitedSignalled { .. }),
+                    ..
+                }) => {}
+                Ok(event) => self.send_event(
+                    "stopped",
+                    object! {
+                        "reason" => event.reason.as_ref().map(stop_reason).unwrap_or("pause"),
+                        "description" => event.reason.map(|r| r.to_string()),
+                        "threadId" => event.thread_id,
+                        "allThreadsStopped" => true,
+                    },
+                ),
+                Err(e) => log::warn!("Malformed stop event: {}", e),
+            },
+            AsyncClass::Running => {
+                // "all" if all threads are running
+                let thread_id = results
+                    .get("thread-id")
+                    .and_then(|id| id.as_str())
+                    .and_then(|id| id.parse::<u64>().ok());
+                self.send_event(
+                    "continued",
+                    object! {
+                        "threadId" => thread_id.unwrap_or(1),
+                        "allThreadsContinued" => thread_id.is_none(),
+                    },
+                )
+            }
+            AsyncClass::Thread(event @ ThreadEvent::Created)
+            | AsyncClass::Thread(event @ ThreadEvent::Exited) => {
+                match ThreadInfo::decode(results) {
+                    Ok(thread) => {
+                        let reason = if *event == ThreadEvent::Created {
+                            "started"
+                        } else {
+                            "exited"
+                        };
+                        self.send_event(
+                            "thread",
+                            object! {
+                                "reason" => reason,
+                                "threadId" => thread.id,
+                            },
+                        )
+                    }
+                    Err(e) => log::warn!("Malformed thread event: {}", e),
+                }
+            }
+            AsyncClass::Thread(ThreadEvent::GroupExited) => match ThreadGroupEvent::decode(results)
+            {
+                Ok(group) => self.send_event(
+                    "exited",
+                    object! {
+                        "exitCode" => group.exit_code.unwrap_or(0),
+                    },
+                ),
+                Err(e) => log::warn!("Malformed thread group event: {}", e),
+            },
+            _ => {}
+        }
+    }
+
+    // Announce changes of the breakpoints to all clients but `except`.
+    pub fn sync_breakpoints(&mut self, breakpoints: &BreakPointSet, except: Option<ClientId>) {
+        if self.breakpoints_synced == Some(breakpoints.last_change) {
+            return;
+        }
+        self.breakpoints_synced = Some(breakpoints.last_change);
+        let current = breakpoints_to_json(breakpoints);
+        let mut events = Vec::new();
+        for (number, bp) in &current {
+            match self.breakpoints.get(number) {
+                None => events.push(("new", bp.clone())),
+                Some(old) if old != bp => events.push(("changed", bp.clone())),
+                Some(_) => {}
+            }
+        }
+        for number in self.breakpoints.keys() {
+            if !current.contains_key(number) {
+                events.push(("removed", object! { "id" => *number }));
+            }
+        }
+        self.breakpoints = current;
+        for (reason, breakpoint) in events {
+            self.broadcast(
+                &object! {
+                    "type" => "event",
+                    "event" => "breakpoint",
+                    "body" => object! {
+                        "reason" => reason,
+                        "breakpoint" => breakpoint,
+                    },
+                },
+                except,
+            );
+        }
+    }
+}
+
+fn stop_reason(reason: &StopReason) -> &'static str {
+    match reason {
+        StopReason::BreakpointHit { .. } => "breakpoint",
+        StopReason::WatchpointTrigger { .. } => "data breakpoint",
+        StopReason::FunctionFinished { .. }
+        | StopReason::LocationReached
+        | StopReason::EndSteppingRange => "step",
+        StopReason::SignalReceived { name, .. } if name.as_deref() != Some("SIGINT") => "exception",
+        _ => "pause",
+    }
+}
+
+fn source_to_json(path: &Path) -> json::JsonValue {
+    object! {
+        "name" => path.file_name().map(|n| n.to_string_lossy().into_owned()),
+        "path" => path.to_string_lossy().into_owned(),
+    }
+}
+
+// Breakpoints with multiple locations are represented by their first location with a source
+// position.
+fn breakpoint_to_json(number: usize, locations: &[&BreakPoint]) -> json::JsonValue {
+    let location = locations
+        .iter()
+        .find(|bp| bp.src_pos.is_some())
+        .or_else(|| locations.first());
+    let mut bp = object! {
+        "id" => number,
+        "verified" => location.is_some_and(|l| l.address.is_some() || l.src_pos.is_some()),
+    };
+    if let Some(src_pos) = location.and_then(|l| l.src_pos.as_ref()) {
+        bp["source"] = source_to_json(&src_pos.file);
+        bp["line"] = src_pos.line.raw_value().into();
+    }
+    bp
+}
+
+fn breakpoints_to_json(breakpoints: &BreakPointSet) -> HashMap<usize, json::JsonValue> {
+    let mut by_number = HashMap::<usize, Vec<&BreakPoint>>::new();
+    for bp in breakpoints.values() {
+        by_number.entry(bp.number.major).or_default().push(bp);
+    }
+    by_number
+        .into_iter()
+        .map(|(number, mut locations)| {
+            locations.sort_by_key(|bp| bp.number.minor);
+            (number, breakpoint_to_json(number, &locations))
+        })
+        .collect()
+}
+
+pub fn handle(p: &mut Context, message: Message) {
+    match message {
+        Message::Connected(id, writer) => {
+            let authorized = p.dap_clients.token.is_none();
+            p.dap_clients.clients.push(Client {
+                id,
+                writer,
+                seq: 0,
+                authorized,
+            })
+        }
+        Message::Disconnected(id) => p.dap_clients.clients.retain(|c| c.id != id),
+        Message::Request(id, request) => {
+            let command = request["command"].as_str().unwrap_or("").to_owned();
+            let mut response = object! {
+                "type" => "response",
+                "request_seq" => request["seq"].clone(),
+                "command" => command.clone(),
+            };
+            let result = if p.dap_clients.authorize(id, &command, &request["arguments"]) {
+                handle_request(p, &command, &request["arguments"])
+            } else {
+                Err(
+                    "Invalid token: This instance requires the token passed via --dap-token"
+                        .to_owned(),
+                )
+            };
+            match result {
+                Ok(body) => {
+                    response["success"] = true.into();
+                    response["body"] = body;
+                }
+                Err(message) => {
+                    response["success"] = false.into();
+                    response["message"] = message.into();
+                }
+            }
+            // The client learns about its own changes from the response.
+            p.dap_clients.sync_breakpoints(&p.gdb.breakpoints, Some(id));
+            let client = match p.dap_clients.clients.iter_mut().find(|c| c.id == id) {
+                Some(client) => client,
+                None => return,
+            };
+            let mut result = client.send(response);
+            if command == "initialize" {
+                result = result.and_then(|_| {
+                    client.send(object! {
+                        "type" => "event",
+                        "event" => "initialized",
+                    })
+                });
+            }
+            if result.is_err() || command == "disconnect" {
+                p.dap_clients.clients.retain(|c| c.id != id);
+            }
+        }
+    }
+}
+
+fn handle_request(
+    p: &mut Context,
+    command: &str,
+    arguments: &json::JsonValue,
+) -> Result<json::JsonValue, String> {
+    match command {
+        "initialize" => Ok(object! {
+            "supportsConfigurationDoneRequest" => true,
+            "supportsEvaluateForHovers" => true,
+        }),
+        // The session is owned by ugdb, so clients always attach to it and detach from it.
+        "launch" | "attach" | "configurationDone" | "setExceptionBreakpoints" | "disconnect" => {
+            Ok(json::JsonValue::new_object())
+        }
+        "setBreakpoints" => set_breakpoints(p, arguments),
+        "threads" => threads(p),
+        "stackTrace" => stack_trace(p, arguments),
+        "scopes" => {
+            let frame_id = arguments["frameId"].as_u64().ok_or("Missing frameId")?;
+            Ok(object! {
+                "scopes" => vec![object! {
+                    "name" => "Locals",
+                    "variablesReference" => frame_id,
+                    "expensive" => false,
+                }],
+            })
+        }
+        "variables" => variables(p, arguments),
+        "evaluate" => {
+            let expression = arguments["expression"]
+                .as_str()
+                .ok_or("Missing expression")?;
+            let mut command = MiCommand::data_evaluate_expression(expression.to_owned());
+            if let Some(frame_id) = arguments["frameId"].as_u64() {
+                command = command.in_frame(frame_id / FRAME_ID_FACTOR, frame_id % FRAME_ID_FACTOR);
+            }
+            let result = execute(p, command)?;
+            Ok(object! {
+                "result" => result.results["value"].clone(),
+                "variablesReference" => 0,
+            })
+        }
+        "continue" => {
+            execute(p, MiCommand::exec_continue())?;
+            Ok(object! { "allThreadsContinued" => true })
+        }
+        "next" => execute(p, MiCommand::exec_next()).map(|_| json::JsonValue::new_object()),
+        "stepIn" => execute(p, MiCommand::exec_step()).map(|_| json::JsonValue::new_object()),
+        "stepOut" => execute(p, MiCommand::exec_finish()).map(|_| json::JsonValue::new_object()),
+        "pause" => p
+            .gdb
+            .mi
+            .interrupt_execution()
+            .map(|_| json::JsonValue::new_object())
+            .map_err(|e| e.to_string()),
+        other => Err(format!("Unsupported request: {}", other)),
+    }
+}
+
+fn execute(p: &mut Context, command: MiCommand) -> Result<ResultRecord, String> {
+    let result = p.gdb.mi.execute(command).map_err(|e| match e {
+        ExecuteError::Busy => "GDB is busy".to_owned(),
+        ExecuteError::Quit => "GDB quit".to_owned(),
+        ExecuteError::Timeout => "GDB timed out".to_owned(),
+    })?;
+    if result.class == ResultClass::Error {
+        Err(result.results["msg"]
+            .as_str()
+            .map(|s| s.to_owned())
+            .unwrap_or_else(|| result.results.dump()))
+    } else {
+        Ok(result)
+    }
+}
+
+// The line that was requested for the breakpoint (gdb may move it to the next line with code).
+fn requested_line(bp: &BreakPoint, path: &Path) -> Option<usize> {
+    let requested = bp.original_location.as_ref().and_then(|l| {
+        l.strip_prefix(path.to_str()?)?
+            .strip_prefix(':')?
+            .parse()
+            .ok()
+    });
+    requested.or_else(|| {
+        bp.src_pos
+            .as_ref()
+            .filter(|pos| pos.file == path)
+            .map(|pos| pos.line.raw_value())
+    })
+}
+
+// Replace all breakpoints in the given source file.
+fn set_breakpoints(
+    p: &mut Context,
+    arguments: &json::JsonValue,
+) -> Result<json::JsonValue, String> {
+    let path = Path::new(
+        arguments["source"]["path"]
+            .as_str()
+            .ok_or("Missing source path")?,
+    );
+    let lines = arguments["breakpoints"]
+        .members()
+        .filter_map(|bp| bp["line"].as_usize())
+        .collect::<Vec<_>>();
+
+    let existing = p
+        .gdb
+        .breakpoints
+        .values()
+        .filter(|bp| bp.number.minor.is_none())
+        .filter_map(|bp| Some((requested_line(bp, path)?, bp.number)))
+        .collect::<Vec<_>>();
+    let obsolete = existing
+        .iter()
+        .filter(|(line, _)| !lines.contains(line))
+        .map(|(_, number)| *number)
+        .collect::<Vec<_>>();
+    if !obsolete.is_empty() {
+        p.gdb
+            .delete_breakpoints(obsolete.into_iter())
+            .map_err(breakpoint_error)?;
+    }
+
+    let mut breakpoints = Vec::new();
+    for line in lines {
+        let number = match existing.iter().find(|(l, _)| *l == line) {
+            Some((_, number)) => Ok(*number),
+            None => p
+                .gdb
+                .insert_breakpoint(BreakPointLocation::Line(path, line))
+                .map_err(breakpoint_error),
+        };
+        breakpoints.push(match number {
+            Ok(number) => {
+                let locations = p
+                    .gdb
+                    .breakpoints
+                    .values()
+                    .filter(|bp| bp.number.major == number.major)
+                    .collect::<Vec<_>>();
+                breakpoint_to_json(number.major, &locations)
+            }
+            Err(message) => object! {
+                "verified" => false,
+                "line" => line,
+                "message" => message,
+            },
+        });
+    }
+    Ok(object! {
+        "breakpoints" => breakpoints,
+    })
+}
+
+fn breakpoint_error(e: BreakpointOperationError) -> String {
+    match e {
+        BreakpointOperationError::Busy => "GDB is busy".to_owned(),
+        BreakpointOperationError::ExecutionError(msg) => msg,
+    }
+}
+
+fn threads(p: &mut Context) -> Result<json::JsonValue, String> {
+    let result = execute(p, MiCommand::thread_info(None))?;
+    let threads = ThreadListEntry::decode_list(&result.results)
+        .map_err(|e| e.to_string())?
+        .into_iter()
+        .map(|thread| {
+            let id = thread.id;
+            let name = thread
+                .name
+                .or(thread.target_id)
+                .unwrap_or_else(|| format!("Thread {}", id));
+            object! {
+                "id" => id,
+                "name" => name,
+            }
+        })
+        .collect::<Vec<_>>();
+    Ok(object! {
+        "threads" => threads,
+    })
+}
+
+fn stack_trace(p: &mut Context, arguments: &json::JsonValue) -> Result<json::JsonValue, String> {
+    let thread_id = arguments["threadId"].as_u64().ok_or("Missing threadId")?;
+    let result = execute(p, MiCommand::stack_list_frames().in_thread(thread_id))?;
+    let frames = Frame::decode_list(&result.results).map_err(|e| e.to_string())?;
+    let total_frames = frames.len();
+    let start = arguments["startFrame"].as_usize().unwrap_or(0);
+    let levels = match arguments["levels"].as_usize() {
+        Some(0) | None => total_frames,
+        Some(levels) => levels,
+    };
+    let frames = frames
+        .into_iter()
+        .skip(start)
+        .take(levels)
+        .map(|frame| {
+            let name = frame
+                .function
+                .clone()
+                .or_else(|| frame.address.map(|a| a.to_string()))
+                .unwrap_or_default();
+            let mut json = object! {
+                "id" => thread_id * FRAME_ID_FACTOR + frame.level.unwrap_or(0),
+                "name" => name,
+                "line" => frame.line.unwrap_or(0),
+                "column" => 0,
+            };
+            if let Some(path) = &frame.fullname {
+                json["source"] = source_to_json(path);
+            }
+            if let Some(address) = frame.address {
+                json["instructionPointerReference"] = address.to_string().into();
+            }
+            json
+        })
+        .collect::<Vec<_>>();
+    Ok(object! {
+        "stackFrames" => frames,
+        "totalFrames" => total_frames,
+    })
+}
+
+fn variables(p: &mut Context, arguments: &json::JsonValue) -> Result<json::JsonValue, String> {
+    let reference = arguments["variablesReference"]
+        .as_u64()
+        .ok_or("Missing variablesReference")?;
+    let result = execute(
+        p,
+        MiCommand::stack_list_variables(
+            Some(reference / FRAME_ID_FACTOR),
+            Some(reference % FRAME_ID_FACTOR),
+        ),
+    )?;
+    let variables = Variable::decode_list(&result.results)
+        .map_err(|e| e.to_string())?
+        .into_iter()
+        .map(|var| {
+            object! {
+                "name" => var.name,
+                // Values of aggregates are not listed with --simple-values.
+                "value" => var.value.unwrap_or_else(|| "{...}".to_owned()),
+                "type" => var.typ,
+                "variablesReference" => 0,
+            }
+        })
+        .collect::<Vec<_>>();
+    Ok(object! {
+        "variables" => variables,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl SharedBuffer {
+        fn take_messages(&self) -> Vec<json::JsonValue> {
+            let buf = std::mem::take(&mut *self.0.lock().unwrap());
+            let mut reader = &buf[..];
+            let mut messages = Vec::new();
+            while let Some(message) = read_message(&mut reader).unwrap() {
+                messages.push(message);
+            }
+            messages
+        }
+    }
+
+    fn request(p: &mut Context, seq: u64, command: &str, arguments: json::JsonValue) {
+        handle(
+            p,
+            Message::Request(
+                0,
+                object! {
+                    "seq" => seq,
+                    "type" => "request",
+                    "command" => command,
+                    "arguments" => arguments,
+                },
+            ),
+        );
+    }
+
+    #[test]
+    fn test_read_message() {
+        let input = b"Content-Length: 13\r\nContent-Type: application/json\r\n\r\n{\"seq\": 1234}Content-Length: 2\r\n\r\n{}";
+        let mut reader = &input[..];
+        assert_eq!(read_message(&mut reader).unwrap().unwrap()["seq"], 1234);
+        assert!(read_message(&mut reader).unwrap().unwrap().is_empty());
+        assert!(read_message(&mut reader).unwrap().is_none());
+
+        let mut reader = &b"Content-Type: application/json\r\n\r\n{}"[..];
+        assert!(read_message(&mut reader).is_err());
+    }
+
+    const TRANSCRIPT: &str = r#"> 1-break-insert /tmp/foo.c:12
+< 1^done,bkpt={number="1",type="breakpoint",disp="keep",enabled="y",addr="0x0000000000401126",func="main",file="foo.c",fullname="/tmp/foo.c",line="13",original-location="/tmp/foo.c:12"}
+< (gdb)
+> 2-break-delete 1
+< 2^done
+< (gdb)
+> 3-break-insert /tmp/foo.c:20
+< 3^error,msg="No line 20 in file \"/tmp/foo.c\"."
+< (gdb)
+"#;
+
+    #[test]
+    fn test_breakpoints() {
+        let (mut p, _events) = Context::replay(TRANSCRIPT);
+        let client = SharedBuffer::default();
+        handle(&mut p, Message::Connected(0, Box::new(client.clone())));
+        // Other clients are notified about the changes.
+        let other = SharedBuffer::default();
+        handle(&mut p, Message::Connected(1, Box::new(other.clone())));
+
+        request(&mut p, 1, "initialize", object! { "adapterID" => "ugdb" });
+        let messages = client.take_messages();
+        assert_eq!(messages[0]["type"], "response");
+        assert_eq!(messages[0]["request_seq"], 1);
+        assert_eq!(messages[0]["success"], true);
+        assert_eq!(messages[1]["event"], "initialized");
+
+        let set_breakpoints = |lines: &[usize]| {
+            object! {
+                "source" => object! { "path" => "/tmp/foo.c" },
+                "breakpoints" => lines.iter().map(|l| object! { "line" => *l }).collect::<Vec<_>>(),
+            }
+        };
+        request(&mut p, 2, "setBreakpoints", set_breakpoints(&[12]));
+        let messages = client.take_messages();
+        assert_eq!(messages.len(), 1);
+        let bp = &messages[0]["body"]["breakpoints"][0];
+        assert_eq!(bp["id"], 1);
+        assert_eq!(bp["verified"], true);
+        assert_eq!(bp["line"], 13);
+        let events = other.take_messages();
+        assert_eq!(events[0]["body"]["reason"], "new");
+        assert_eq!(events[0]["body"]["breakpoint"], *bp);
+
+        // The existing breakpoint is kept although gdb moved it to the next line.
+        request(&mut p, 3, "setBreakpoints", set_breakpoints(&[12]));
+        assert_eq!(client.take_messages()[0]["body"]["breakpoints"][0]["id"], 1);
+
+        request(&mut p, 4, "setBreakpoints", set_breakpoints(&[20]));
+        let messages = client.take_messages();
+        let bp = &messages[0]["body"]["breakpoints"][0];
+        assert_eq!(bp["verified"], false);
+        assert_eq!(bp["message"], "No line 20 in file \"/tmp/foo.c\".");
+        assert!(p.gdb.breakpoints.is_empty());
+        assert_eq!(other.take_messages()[0]["body"]["reason"], "removed");
+
+        request(&mut p, 5, "restart", json::JsonValue::Null);
+        assert_eq!(client.take_messages()[0]["success"], false);
+    }
+
+    #[test]
+    fn test_token() {
+        let (mut p, _events) = Context::replay("");
+        p.dap_clients = Clients::new(Some("secret".to_owned()));
+        let client = SharedBuffer::default();
+        handle(&mut p, Message::Connected(0, Box::new(client.clone())));
+
+        request(&mut p, 1, "initialize", object! { "adapterID" => "ugdb" });
+        assert_eq!(client.take_messages()[0]["success"], true);
+        request(&mut p, 2, "attach", object! { "token" => "guess" });
+        request(&mut p, 3, "threads", json::JsonValue::new_object());
+        p.dap_clients.output(StreamKind::Target, "secret output");
+        let messages = client.take_messages();
+        assert_eq!(messages.len(), 2);
+        assert!(messages.iter().all(|m| m["success"] == false));
+        assert!(messages[1]["message"]
+            .as_str()
+            .unwrap()
+            .starts_with("Invalid token"));
+
+        request(&mut p, 4, "attach", object! { "token" => "secret" });
+        p.dap_clients.output(StreamKind::Target, "output");
+        let messages = client.take_messages();
+        assert_eq!(messages[0]["success"], true);
+        assert_eq!(messages[1]["body"]["output"], "output");
+    }
+
+    #[test]
+    fn test_tcp_token() {
+        use std::net::TcpStream;
+        let (mut p, _events) = Context::replay("");
+        p.dap_clients = Clients::new(Some("secret".to_owned()));
+        let (event_sink, events) = std::sync::mpsc::channel();
+        let server = Server::setup("127.0.0.1:0", true, false, event_sink).unwrap();
+
+        let mut stream = TcpStream::connect(server.local_addr().unwrap()).unwrap();
+        let body = object! {
+            "seq" => 1,
+            "type" => "request",
+            "command" => "evaluate",
+            "arguments" => object! { "expression" => "abort()" },
+        }
+        .dump();
+        write!(stream, "Content-Length: {}\r\n\r\n{}", body.len(), body).unwrap();
+        for _ in 0..2 {
+            match events.recv_timeout(Duration::from_secs(5)).unwrap() {
+                Event::Dap(message) => handle(&mut p, message),
+                _ => panic!("Unexpected event"),
+            }
+        }
+        let response = read_message(&mut BufReader::new(stream)).unwrap().unwrap();
+        assert_eq!(response["request_seq"], 1);
+        assert_eq!(response["success"], false);
+        assert!(response["message"]
+            .as_str()
+            .unwrap()
+            .starts_with("Invalid token"));
+    }
+
+    #[test]
+    fn test_setup() {
+        use std::os::unix::fs::PermissionsExt;
+        let (event_sink, _events) = std::sync::mpsc::channel();
+        assert!(Server::setup("127.0.0.1:0", false, false, event_sink.clone()).is_err());
+        assert!(Server::setup("0.0.0.0:0", true, false, event_sink.clone()).is_err());
+        assert!(Server::setup("127.0.0.1:0", true, false, event_sink.clone()).is_ok());
+
+        let dir = std::env::temp_dir().join(format!("ugdb-dap-test-{}", std::process::id()));
+        let socket_path = dir.join("dap.sock");
+        let server =
+            Server::setup(socket_path.to_str().unwrap(), false, false, event_sink).unwrap();
+        assert_eq!(server.socket_path(), Some(socket_path.as_path()));
+        let mode = |path: &Path| std::fs::metadata(path).unwrap().permissions().mode() & 0o777;
+        assert_eq!(mode(&dir), 0o700);
+        assert_eq!(mode(&socket_path), 0o600);
+        drop(server);
+        assert!(!socket_path.exists());
+        std::fs::remove_dir(&dir).unwrap();
+    }
+}
@@ -2,16 +2,20 @@
 // may want to move it to a separate crate or merge it with gdbmi-rs
 use crate::gdbmi::{
     self,
-    commands::{BreakPointLocation, BreakPointNumber, MiCommand},
-    output::{BreakPointEvent, JsonValue, Object, ResultClass},
-    ExecuteError,
+    commands::{BreakPointLocation, BreakPointNumber, MiCommand, WatchMode},
+    output::{
+        field, parse_field, BreakPointEvent, BreakPointInfo, DecodeError, Object, ResultClass,
+        ThreadEvent, ThreadGroup, ThreadGroupEvent, ThreadInfo,
+    },
+    ExecuteError, Interruption,
 };
 use std::{
     collections::{HashMap, HashSet},
-    fmt,
-    ops::{Add, Sub},
     path::PathBuf,
+    time::Duration,
 };
+
+pub use crate::gdbmi::output::Address;
 use unsegen::base::LineNumber;
 
 #[derive(Debug, Clone)]
@@ -26,68 +30,33 @@ impl SrcPosition {
     }
 }
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
-pub struct Address(pub usize);
-impl Address {
-    pub fn parse(string: &str) -> Result<Self, (::std::num::ParseIntError, String)> {
-        usize::from_str_radix(&string[2..], 16)
-            .map(Address)
-            .map_err(|e| (e, string.to_owned()))
-    }
-}
-impl fmt::Display for Address {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "0x{:x}", self.0)
-    }
-}
-impl Add<usize> for Address {
-    type Output = Self;
-    fn add(self, rhs: usize) -> Self {
-        Address(self.0 + rhs)
-    }
-}
-
-impl Sub<usize> for Address {
-    type Output = Self;
-    fn sub(self, rhs: usize) -> Self {
-        Address(self.0 - rhs)
-    }
-}
-
 pub struct BreakPoint {
     pub number: BreakPointNumber,
     pub address: Option<Address>,
     pub enabled: bool,
     pub src_pos: Option<SrcPosition>, // May not be present if debug information is missing!
+    pub original_location: Option<String>,
+    pub condition: Option<String>,
+    pub ignore_count: Option<usize>,
+    pub watchpoint: Option<(WatchMode, String)>,
 }
 
-impl BreakPoint {
-    pub fn from_json(bkpt: &Object) -> Self {
-        let number = bkpt["number"]
-            .as_str()
-            .expect("find bp number")
-            .parse::<BreakPointNumber>()
-            .expect("Parse usize");
-        let enabled = bkpt["enabled"].as_str().expect("find enabled") == "y";
-        let address = bkpt["addr"]
-            .as_str()
-            .and_then(|addr| Address::parse(addr).ok()); //addr may not be present or contain
-        let src_pos = {
-            let maybe_file = bkpt["fullname"].as_str();
-            let maybe_line = bkpt["line"]
-                .as_str()
-                .map(|l_nr| LineNumber::new(l_nr.parse::<usize>().expect("Parse usize")));
-            if let (Some(file), Some(line)) = (maybe_file, maybe_line) {
-                Some(SrcPosition::new(PathBuf::from(file), line))
-            } else {
-                None
-            }
+impl From<BreakPointInfo> for BreakPoint {
+    fn from(info: BreakPointInfo) -> Self {
+        let src_pos = if let (Some(file), Some(line)) = (info.fullname, info.line) {
+            Some(SrcPosition::new(file, LineNumber::new(line)))
+        } else {
+            None
         };
         BreakPoint {
-            number,
-            address,
-            enabled,
+            number: info.number,
+            address: info.address,
+            enabled: info.enabled,
             src_pos,
+            original_location: info.original_location,
+            condition: info.condition,
+            ignore_count: info.ignore_count,
+            watchpoint: info.watchpoint,
         }
     }
 }
@@ -138,10 +107,109 @@ impl std::ops::Deref for BreakPointSet {
     }
 }
 
+pub struct Inferior {
+    pub id: String,
+    pub pid: Option<u32>,
+    pub exit_code: Option<i64>,
+}
+
+impl Inferior {
+    // The number used by gdb commands (e.g., "2" for "i2").
+    pub fn number(&self) -> &str {
+        self.id.strip_prefix('i').unwrap_or(&self.id)
+    }
+}
+
+// Inferiors (thread groups in mi terms) as announced by gdb's notifications.
+#[derive(Default)]
+pub struct InferiorSet {
+    inferiors: Vec<Inferior>,
+    thread_groups: HashMap<u64, String>,
+    current: Option<String>,
+}
+
+impl InferiorSet {
+    pub fn handle_event(
+        &mut self,
+        event: ThreadEvent,
+        results: &Object,
+    ) -> Result<(), DecodeError> {
+        match event {
+            ThreadEvent::Created => {
+                let info = ThreadInfo::decode(results)?;
+                self.thread_groups.insert(info.id, info.group_id);
+            }
+            ThreadEvent::Exited => {
+                let info = ThreadInfo::decode(results)?;
+                self.thread_groups.remove(&info.id);
+            }
+            ThreadEvent::GroupAdded => {
+                let event = ThreadGroupEvent::decode(results)?;
+                if self.current.is_none() {
+                    self.current = Some(event.id.clone());
+                }
+                self.inferiors.push(Inferior {
+                    id: event.id,
+                    pid: None,
+                    exit_code: None,
+                });
+            }
+            ThreadEvent::GroupRemoved => {
+                let event = ThreadGroupEvent::decode(results)?;
+                self.inferiors.retain(|i| i.id != event.id);
+                if self.current.as_ref() == Some(&event.id) {
+                    self.current = self.inferiors.first().map(|i| i.id.clone());
+                }
+            }
+            ThreadEvent::GroupStarted | ThreadEvent::GroupExited => {
+                let event = ThreadGroupEvent::decode(results)?;
+                if let Some(inferior) = self.inferiors.iter_mut().find(|i| i.id == event.id) {
+                    inferior.pid = event.pid;
+                    inferior.exit_code = event.exit_code;
+                }
+            }
+            ThreadEvent::Selected => {}
+        }
+        Ok(())
+    }
+
+    pub fn inferior_of_thread(&self, thread_id: u64) -> Option<&str> {
+        self.thread_groups.get(&thread_id).map(|s| s.as_str())
+    }
+
+    pub fn select_thread(&mut self, thread_id: u64) {
+        if let Some(id) = self.thread_groups.get(&thread_id) {
+            self.current = Some(id.clone());
+        }
+    }
+
+    pub fn select(&mut self, id: &str) {
+        if self.inferiors.iter().any(|i| i.id == id) {
+            self.current = Some(id.to_owned());
+        }
+    }
+
+    pub fn current(&self) -> Option<&Inferior> {
+        let current = self.current.as_ref()?;
+        self.inferiors.iter().find(|i| &i.id == current)
+    }
+}
+
+impl std::ops::Deref for InferiorSet {
+    type Target = [Inferior];
+
+    fn deref(&self) -> &Self::Target {
+        &self.inferiors
+    }
+}
+
 #[allow(clippy::upper_case_acronyms)]
 pub struct GDB {
     pub mi: gdbmi::GDB,
     pub breakpoints: BreakPointSet,
+    // Last known working directory of gdb (see Context::update_working_dir)
+    pub working_dir: Option<PathBuf>,
+    pub inferiors: InferiorSet,
 }
 
 pub enum BreakpointOperationError {
@@ -149,34 +217,76 @@ pub enum BreakpointOperationError {
     ExecutionError(String),
 }
 
+// Whether while_stopped had to interrupt the program
+#[derive(Clone, Debug, PartialEq)]
+pub enum Resumption {
+    // The program was not running or stopped on its own.
+    NotInterrupted,
+    Resumed,
+    NotResumed(String),
+}
+
+impl Resumption {
+    // A note for the user about what happened to the program (if anything)
+    pub fn describe(&self) -> Option<String> {
+        match self {
+            Resumption::NotInterrupted => None,
+            Resumption::Resumed => Some("interrupted and resumed the program".to_owned()),
+            Resumption::NotResumed(e) => Some(format!(
+                "interrupted the program, but failed to resume it: {}",
+                e
+            )),
+        }
+    }
+}
+
+impl From<ExecuteError> for BreakpointOperationError {
+    fn from(e: ExecuteError) -> Self {
+        match e {
+            ExecuteError::Busy => BreakpointOperationError::Busy,
+            // gdb may crash (and be restarted) at any time.
+            ExecuteError::Quit => BreakpointOperationError::ExecutionError("GDB quit".to_owned()),
+            ExecuteError::Timeout => {
+                BreakpointOperationError::ExecutionError("timed out".to_owned())
+            }
+        }
+    }
+}
+
 impl GDB {
     pub fn new(mi: gdbmi::GDB) -> Self {
         GDB {
             mi,
             breakpoints: BreakPointSet::new(),
+            working_dir: None,
+            inferiors: InferiorSet::default(),
         }
     }
 
     pub fn kill(&mut self) {
-        self.mi.interrupt_execution().expect("interrupt worked");
-        self.mi.execute_later(&gdbmi::commands::MiCommand::exit());
+        // gdb may have died already.
+        let _ = self.mi.interrupt_execution();
+        self.mi.execute_later(gdbmi::commands::MiCommand::exit());
     }
 
+    // Returns the number of the new breakpoint.
     pub fn insert_breakpoint(
         &mut self,
         location: BreakPointLocation,
-    ) -> Result<(), BreakpointOperationError> {
-        let bp_result = self
-            .mi
-            .execute(&MiCommand::insert_breakpoint(location))
-            .map_err(|e| match e {
-                ExecuteError::Busy => BreakpointOperationError::Busy,
-                ExecuteError::Quit => panic!("Could not insert breakpoint: GDB quit"),
-            })?;
+    ) -> Result<BreakPointNumber, BreakpointOperationError> {
+        let bp_result = self.mi.execute(MiCommand::insert_breakpoint(location))?;
         match bp_result.class {
             ResultClass::Done => {
-                self.handle_breakpoint_event(BreakPointEvent::Created, &bp_result.results);
-                Ok(())
+                let breakpoints = BreakPointInfo::decode_all(&bp_result.results).map_err(|e| {
+                    BreakpointOperationError::ExecutionError(format!("Malformed response: {}", e))
+                })?;
+                let number = breakpoints.first().map(|bp| bp.number).ok_or_else(|| {
+                    BreakpointOperationError::ExecutionError("No breakpoint created".to_owned())
+                })?;
+                for bp in breakpoints {
+                    self.breakpoints.update_breakpoint(bp.into());
+                }
+                Ok(number)
             }
             ResultClass::Error => Err(BreakpointOperationError::ExecutionError(
                 bp_result
@@ -192,17 +302,108 @@ impl GDB {
         }
     }
 
+    // Returns the number of the new watchpoint.
+    pub fn insert_watchpoint(
+        &mut self,
+        expression: &str,
+        mode: WatchMode,
+    ) -> Result<BreakPointNumber, BreakpointOperationError> {
+        let result = self
+            .mi
+            .execute(MiCommand::insert_watchpoing(expression, mode))?;
+        match result.class {
+            ResultClass::Done => {
+                let number = BreakPointInfo::decode_watchpoint(&result.results).map_err(|e| {
+                    BreakpointOperationError::ExecutionError(format!("Malformed response: {}", e))
+                })?;
+                // gdb does not announce watchpoints that were inserted via mi.
+                self.breakpoints.update_breakpoint(BreakPoint {
+                    number,
+                    address: None,
+                    enabled: true,
+                    src_pos: None,
+                    original_location: None,
+                    condition: None,
+                    ignore_count: None,
+                    watchpoint: Some((mode, expression.to_owned())),
+                });
+                Ok(number)
+            }
+            ResultClass::Error => Err(BreakpointOperationError::ExecutionError(
+                result
+                    .results
+                    .get("msg")
+                    .and_then(|msg_obj| msg_obj.as_str())
+                    .map(|s| s.to_owned())
+                    .unwrap_or_else(|| result.results.dump()),
+            )),
+            _ => {
+                panic!("Unexpected resultclass: {:?}", result.class);
+            }
+        }
+    }
+
+    // Run the given breakpoint operation while the program is stopped: If it is running, it is
+    // interrupted first and resumed afterwards, which is reported via the returned Resumption. The
+    // stop caused by the interrupt is not reported as a stop of the program.
+    //
+    // If the program stops for another reason (e.g., a breakpoint) before the interrupt arrives, it
+    // is not resumed.
+    pub fn while_stopped<R>(
+        &mut self,
+        timeout: Duration,
+        operation: impl FnOnce(&mut Self) -> Result<R, BreakpointOperationError>,
+    ) -> Result<(R, Resumption), BreakpointOperationError> {
+        if !self.mi.is_running() {
+            return operation(self).map(|r| (r, Resumption::NotInterrupted));
+        }
+        let interruption = self.mi.interrupt_and_wait(timeout).map_err(|e| {
+            BreakpointOperationError::ExecutionError(format!("Cannot interrupt program: {}", e))
+        })?;
+        match interruption {
+            Interruption::Interrupted => {}
+            Interruption::StoppedOtherwise => {
+                return operation(self).map(|r| (r, Resumption::NotInterrupted))
+            }
+            // The stop (if it happens at all) is reported as usual.
+            Interruption::TimedOut => {
+                return Err(BreakpointOperationError::ExecutionError(
+                    "The program did not stop in time (it may still stop later)".to_owned(),
+                ))
+            }
+        }
+        let result = operation(self);
+        let resumption = match self.mi.execute(MiCommand::exec_continue()) {
+            Ok(r) if r.class == ResultClass::Running => Resumption::Resumed,
+            Ok(r) => Resumption::NotResumed(
+                r.results["msg"]
+                    .as_str()
+                    .map(|s| s.to_owned())
+                    .unwrap_or_else(|| r.results.dump()),
+            ),
+            Err(e) => Resumption::NotResumed(format!("{:?}", e)),
+        };
+        match (result, resumption) {
+            (Ok(r), resumption) => Ok((r, resumption)),
+            // The caller only learns about the error, so it has to tell about the program, too.
+            (Err(BreakpointOperationError::ExecutionError(msg)), resumption) => {
+                Err(BreakpointOperationError::ExecutionError(format!(
+                    "{} ({})",
+                    msg,
+                    resumption.describe().unwrap_or_default()
+                )))
+            }
+            (Err(e), _) => Err(e),
+        }
+    }
+
     pub fn delete_breakpoints<I: Clone + Iterator<Item = BreakPointNumber>>(
         &mut self,
         bp_numbers: I,
     ) -> Result<(), BreakpointOperationError> {
         let bp_result = self
             .mi
-            .execute(MiCommand::delete_breakpoints(bp_numbers.clone()))
-            .map_err(|e| match e {
-                ExecuteError::Busy => BreakpointOperationError::Busy,
-                ExecuteError::Quit => panic!("Could not insert breakpoint: GDB quit"),
-            })?;
+            .execute(MiCommand::delete_breakpoints(bp_numbers.clone()))?;
         match bp_result.class {
             ResultClass::Done => {
                 let major_to_delete = bp_numbers.map(|n| n.major).collect::<HashSet<usize>>();
@@ -237,55 +438,176 @@ impl GDB {
         }
     }
 
-    pub fn handle_breakpoint_event(&mut self, bp_type: BreakPointEvent, info: &Object) {
+    pub fn set_breakpoints_enabled<I: Clone + Iterator<Item = BreakPointNumber>>(
+        &mut self,
+        bp_numbers: I,
+        enabled: bool,
+    ) -> Result<(), BreakpointOperationError> {
+        let command = if enabled {
+            MiCommand::enable_breakpoints(bp_numbers.clone())
+        } else {
+            MiCommand::disable_breakpoints(bp_numbers.clone())
+        };
+        let bp_result = self.mi.execute(command)?;
+        match bp_result.class {
+            ResultClass::Done => {
+                // gdb does not notify us about changes caused by our own commands.
+                for number in bp_numbers {
+                    if let Some(bp) = self.breakpoints.map.get_mut(&number) {
+                        bp.enabled = enabled;
+                    }
+                }
+                self.breakpoints.notify_change();
+                Ok(())
+            }
+            ResultClass::Error => Err(BreakpointOperationError::ExecutionError(
+                bp_result
+                    .results
+                    .get("msg")
+                    .and_then(|msg_obj| msg_obj.as_str())
+                    .map(|s| s.to_owned())
+                    .unwrap_or_else(|| bp_result.results.dump()),
+            )),
+            _ => {
+                panic!("Unexpected resultclass: {:?}", bp_result.class);
+            }
+        }
+    }
+
+    pub fn handle_breakpoint_event(
+        &mut self,
+        bp_type: BreakPointEvent,
+        info: &Object,
+    ) -> Result<(), DecodeError> {
         match bp_type {
             BreakPointEvent::Created | BreakPointEvent::Modified => {
-                match &info["bkpt"] {
-                    JsonValue::Object(bkpt) => {
-                        let bp = BreakPoint::from_json(bkpt);
-                        self.breakpoints.update_breakpoint(bp);
-
-                        // If there are multiple locations (recent versions of) gdb return the
-                        // sub-breakpoints in the array "locations".
-                        if let Some(JsonValue::Array(bkpts)) = bkpt.get("locations") {
-                            for bkpt in bkpts {
-                                if let JsonValue::Object(bkpt) = bkpt {
-                                    let bp = BreakPoint::from_json(bkpt);
-                                    self.breakpoints.update_breakpoint(bp);
-                                } else {
-                                    panic!("Malformed breakpoint list");
-                                }
-                            }
-                        }
-                    }
-                    JsonValue::Array(bkpts) => {
-                        // In previous versions, gdb returned multiple sub-breakpoints as a series
-                        // of objects under the "bkpt" key (thus breaking the spec). This appears
-                        // to be fixed now, but we keep the current case (for now) for users of old
-                        // gdb versions.
-                        for bkpt in bkpts {
-                            if let JsonValue::Object(bkpt) = bkpt {
-                                let bp = BreakPoint::from_json(bkpt);
-                                self.breakpoints.update_breakpoint(bp);
-                            } else {
-                                panic!("Malformed breakpoint list");
-                            }
-                        }
-                    }
-                    _ => {
-                        panic!("Invalid bkpt structure");
-                    }
+                for bp in BreakPointInfo::decode_all(info)? {
+                    self.breakpoints.update_breakpoint(bp.into());
                 }
             }
             BreakPointEvent::Deleted => {
-                let id = info["id"]
-                    .as_str()
-                    .expect("find id")
-                    .parse::<BreakPointNumber>()
-                    .expect("Parse usize");
+                let id = BreakPointInfo::decode_deleted(info)?;
                 self.breakpoints.remove_breakpoint(id);
             }
         }
+        Ok(())
+    }
+
+    // Replace the (crashed) gdb instance with a new one and restore the working directory,
+    // breakpoints (including their conditions and ignore counts) and watchpoints of the previous
+    // session. Returns messages for everything that failed and the new numbers of the restored
+    // breakpoints.
+    pub fn restore_session(
+        &mut self,
+        mi: gdbmi::GDB,
+    ) -> (Vec<String>, HashMap<BreakPointNumber, BreakPointNumber>) {
+        let mut failures = Vec::new();
+        let old_mi = std::mem::replace(&mut self.mi, mi);
+        // Results for commands of the old instance must not be mistaken for new ones.
+        self.mi.continue_tokens_of(&old_mi);
+        // The new instance announces its inferiors again.
+        self.inferiors = InferiorSet::default();
+
+        if let Some(dir) = self.working_dir.clone() {
+            match self.mi.execute(MiCommand::environment_cd(&dir)) {
+                Ok(res) if res.class == ResultClass::Done => {}
+                Ok(res) => failures.push(format!(
+                    "Cannot change directory to {}: {}",
+                    dir.display(),
+                    res.results["msg"].as_str().unwrap_or("unknown error")
+                )),
+                Err(e) => failures.push(format!(
+                    "Cannot change directory to {}: {:?}",
+                    dir.display(),
+                    e
+                )),
+            }
+        }
+
+        let old_breakpoints = std::mem::take(&mut self.breakpoints.map);
+        self.breakpoints.notify_change();
+        let mut old_breakpoints = old_breakpoints
+            .into_values()
+            .filter(|bp| bp.number.minor.is_none())
+            .collect::<Vec<_>>();
+        old_breakpoints.sort_by_key(|bp| bp.number.major);
+        let mut renumbered = HashMap::new();
+        for bp in old_breakpoints {
+            match self.restore_breakpoint(&bp) {
+                Ok(number) => {
+                    renumbered.insert(bp.number, number);
+                }
+                Err(BreakpointOperationError::Busy) => failures.push(format!(
+                    "Cannot restore breakpoint {}: Gdb is busy",
+                    bp.number
+                )),
+                Err(BreakpointOperationError::ExecutionError(msg)) => {
+                    failures.push(format!("Cannot restore breakpoint {}: {}", bp.number, msg))
+                }
+            }
+        }
+        (failures, renumbered)
+    }
+
+    fn restore_breakpoint(
+        &mut self,
+        bp: &BreakPoint,
+    ) -> Result<BreakPointNumber, BreakpointOperationError> {
+        let number = if let Some((mode, expression)) = &bp.watchpoint {
+            self.insert_watchpoint(expression, *mode)?
+        } else {
+            let location = if let Some(spec) = &bp.original_location {
+                BreakPointLocation::Spec(spec)
+            } else if let Some(pos) = &bp.src_pos {
+                BreakPointLocation::Line(&pos.file, pos.line.into())
+            } else if let Some(address) = bp.address {
+                BreakPointLocation::Address(address.0)
+            } else {
+                return Err(BreakpointOperationError::ExecutionError(
+                    "No location".to_owned(),
+                ));
+            };
+            self.insert_breakpoint(location)?
+        };
+        if let Some(condition) = &bp.condition {
+            let command = MiCommand::cli_exec(&format!("condition {} {}", number, condition));
+            self.modify_restored_breakpoint(number, command, |new_bp| {
+                new_bp.condition = Some(condition.clone())
+            })?;
+        }
+        if let Some(count) = bp.ignore_count.filter(|count| *count > 0) {
+            let command = MiCommand::cli_exec(&format!("ignore {} {}", number, count));
+            self.modify_restored_breakpoint(number, command, |new_bp| {
+                new_bp.ignore_count = Some(count)
+            })?;
+        }
+        if !bp.enabled {
+            let command = MiCommand::disable_breakpoints(std::iter::once(number));
+            self.modify_restored_breakpoint(number, command, |new_bp| new_bp.enabled = false)?;
+        }
+        Ok(number)
+    }
+
+    // gdb announces the changes as well, but we want the BreakPointSet to be complete right away.
+    fn modify_restored_breakpoint(
+        &mut self,
+        number: BreakPointNumber,
+        command: MiCommand,
+        modify: impl FnOnce(&mut BreakPoint),
+    ) -> Result<(), BreakpointOperationError> {
+        let result = self.mi.execute(command)?;
+        if result.class == ResultClass::Error {
+            return Err(BreakpointOperationError::ExecutionError(
+                result.results["msg"]
+                    .as_str()
+                    .unwrap_or("unknown error")
+                    .to_owned(),
+            ));
+        }
+        if let Some(bp) = self.breakpoints.map.get_mut(&number) {
+            modify(bp);
+        }
+        Ok(())
     }
 
     // Warning: This is a hack, as gdbmi does not currently offer a command to query the current target
@@ -293,42 +615,40 @@ impl GDB {
     pub fn get_target(&mut self) -> Result<Option<PathBuf>, ExecuteError> {
         let result = self.mi.execute(MiCommand::list_thread_groups(false, &[]))?;
         if result.class == ResultClass::Done {
-            Ok(result.results["groups"]
-                .members()
-                .filter_map(|thread| thread["executable"].as_str())
-                .next()
-                .map(|exec| exec.into()))
+            Ok(ThreadGroup::decode_list(&result.results)
+                .ok()
+                .into_iter()
+                .flatten()
+                .find_map(|group| group.executable))
         } else {
             Ok(None)
         }
     }
 
     pub fn get_stack_level(&mut self) -> Result<u64, response::GDBResponseError> {
-        let frame = self.mi.execute(MiCommand::stack_info_frame(None))?;
-        response::get_u64(&frame.results["frame"], "level")
+        response::stack_level(self.mi.execute(MiCommand::stack_info_frame(None)))
     }
 
     pub fn get_stack_depth(&mut self) -> Result<u64, response::GDBResponseError> {
-        let frame = self.mi.execute(MiCommand::stack_info_depth())?;
-        response::get_u64_obj(&frame.results, "depth")
+        response::stack_depth(self.mi.execute(MiCommand::stack_info_depth()))
     }
 }
 
-// Various helper for getting stuff out of gdb response values
+// Errors when getting stuff out of gdb responses
 pub mod response {
     use super::*;
+    use crate::gdbmi::output::ResultRecord;
 
     #[derive(Clone, Debug, PartialEq)]
     pub enum GDBResponseError {
-        MissingField(&'static str, JsonValue),
-        MalformedAddress(String),
+        Decode(DecodeError),
         Other(String),
         Execution(ExecuteError),
     }
 
-    impl From<(::std::num::ParseIntError, String)> for GDBResponseError {
-        fn from((_, s): (::std::num::ParseIntError, String)) -> Self {
-            GDBResponseError::MalformedAddress(s)
+    impl From<DecodeError> for GDBResponseError {
+        fn from(e: DecodeError) -> Self {
+            GDBResponseError::Decode(e)
         }
     }
     impl From<ExecuteError> for GDBResponseError {
@@ -337,40 +657,191 @@ pub mod response {
         }
     }
 
-    pub fn get_str<'a>(obj: &'a JsonValue, key: &'static str) -> Result<&'a str, GDBResponseError> {
-        obj[key]
-            .as_str()
-            .ok_or_else(|| GDBResponseError::MissingField(key, obj.clone()))
+    // Decode the result of MiCommand::stack_info_frame
+    pub fn stack_level(
+        result: Result<ResultRecord, ExecuteError>,
+    ) -> Result<u64, GDBResponseError> {
+        Ok(parse_field(field(&result?.results, "frame")?, "level")?)
     }
 
-    pub fn get_str_obj<'a>(
-        obj: &'a Object,
-        key: &'static str,
-    ) -> Result<&'a str, GDBResponseError> {
-        obj[key]
-            .as_str()
-            .ok_or_else(|| GDBResponseError::MissingField(key, JsonValue::Object(obj.clone())))
+    // Decode the result of MiCommand::stack_info_depth
+    pub fn stack_depth(
+        result: Result<ResultRecord, ExecuteError>,
+    ) -> Result<u64, GDBResponseError> {
+        Ok(parse_field(&result?.results, "depth")?)
     }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::gdbmi::transcript::{DiscardingSink, Transcript};
+    use std::path::Path;
+
+    const TRANSCRIPT: &str = r#"> 1-break-insert /tmp/foo.c:12
+< 1^done,bkpt={number="1",type="breakpoint",disp="keep",enabled="y",addr="0x0000000000401126",func="main",file="foo.c",fullname="/tmp/foo.c",line="12",thread-groups=["i1"],times="0",original-location="/tmp/foo.c:12"}
+< (gdb)
+> 2-break-insert /tmp/foo.c:1000
+< 2^error,msg="No line 1000 in file \"/tmp/foo.c\"."
+< (gdb)
+> 3-break-delete 1
+< 3^done
+< (gdb)
+"#;
+
+    #[test]
+    fn test_breakpoints() {
+        let transcript = Transcript::parse(TRANSCRIPT.as_bytes()).unwrap();
+        let mi = gdbmi::GDB::replay(transcript, DiscardingSink, "gdb".into()).unwrap();
+        let mut gdb = GDB::new(mi);
+        let file = Path::new("/tmp/foo.c");
+
+        assert!(gdb
+            .insert_breakpoint(BreakPointLocation::Line(file, 12))
+            .is_ok());
+        let number = BreakPointNumber {
+            major: 1,
+            minor: None,
+        };
+        let bp = &gdb.breakpoints[&number];
+        assert_eq!(bp.address, Some(Address(0x401126)));
+        assert!(bp.enabled);
+        let src_pos = bp.src_pos.as_ref().unwrap();
+        assert_eq!(src_pos.file, file);
+        assert_eq!(src_pos.line, LineNumber::new(12));
+
+        match gdb.insert_breakpoint(BreakPointLocation::Line(file, 1000)) {
+            Err(BreakpointOperationError::ExecutionError(msg)) => {
+                assert_eq!(msg, "No line 1000 in file \"/tmp/foo.c\".")
+            }
+            _ => panic!("Expected an execution error"),
+        }
+        assert_eq!(gdb.breakpoints.len(), 1);
 
-    pub fn get_addr(obj: &JsonValue, key: &'static str) -> Result<Address, GDBResponseError> {
-        let s = get_str(obj, key)?;
-        Ok(Address::parse(s)?)
+        assert!(gdb.delete_breakpoints(std::iter::once(number)).is_ok());
+        assert!(gdb.breakpoints.is_empty());
     }
 
-    pub fn get_addr_obj(obj: &Object, key: &'static str) -> Result<Address, GDBResponseError> {
-        let s = get_str_obj(obj, key)?;
-        Ok(Address::parse(s)?)
+    const RESTARTED_TRANSCRIPT: &str = r#"> 4-environment-cd /tmp/work
+< 4^done
+< (gdb)
+> 5-break-insert /tmp/foo.c:12
+< 5^done,bkpt={number="1",type="breakpoint",disp="keep",enabled="y",addr="<PENDING>",original-location="/tmp/foo.c:12"}
+< (gdb)
+> 6-interpreter-exec console "condition 1 x > 3"
+< 6^done
+< (gdb)
+> 7-interpreter-exec console "ignore 1 2"
+< ~"Will ignore next 2 crossings of breakpoint 1.\n"
+< 7^done
+< (gdb)
+> 8-break-insert *0x401130
+< 8^done,bkpt={number="2",type="breakpoint",disp="keep",enabled="y",addr="0x0000000000401130",func="main",original-location="*0x401130"}
+< (gdb)
+> 9-break-disable 2
+< 9^done
+< (gdb)
+> 10-break-watch -r -- x
+< 10^done,hw-rwpt={number="3",exp="x"}
+< (gdb)
+"#;
+
+    #[test]
+    fn test_restore_session() {
+        let transcript = Transcript::parse(RESTARTED_TRANSCRIPT.as_bytes()).unwrap();
+        let old_mi = gdbmi::GDB::replay(
+            Transcript::parse(b"").unwrap(),
+            DiscardingSink,
+            "gdb".into(),
+        )
+        .unwrap();
+        let new_mi = gdbmi::GDB::replay(transcript, DiscardingSink, "gdb".into()).unwrap();
+        let mut gdb = GDB::new(old_mi);
+        gdb.working_dir = Some("/tmp/work".into());
+        let number = |major| BreakPointNumber { major, minor: None };
+        let old_bp = |major, original_location: &str| BreakPoint {
+            number: number(major),
+            address: None,
+            enabled: true,
+            src_pos: None,
+            original_location: Some(original_location.to_owned()),
+            condition: None,
+            ignore_count: None,
+            watchpoint: None,
+        };
+        gdb.breakpoints.update_breakpoint(BreakPoint {
+            condition: Some("x > 3".to_owned()),
+            ignore_count: Some(2),
+            ..old_bp(3, "/tmp/foo.c:12")
+        });
+        gdb.breakpoints.update_breakpoint(BreakPoint {
+            enabled: false,
+            ..old_bp(5, "*0x401130")
+        });
+        gdb.breakpoints.update_breakpoint(BreakPoint {
+            original_location: None,
+            watchpoint: Some((WatchMode::Read, "x".to_owned())),
+            ..old_bp(7, "")
+        });
+
+        let (failures, renumbered) = gdb.restore_session(new_mi);
+        assert!(failures.is_empty(), "{:?}", failures);
+        assert_eq!(renumbered[&number(3)], number(1));
+        assert_eq!(renumbered[&number(5)], number(2));
+        assert_eq!(renumbered[&number(7)], number(3));
+        assert_eq!(gdb.breakpoints.len(), 3);
+        let bp = |major| &gdb.breakpoints[&number(major)];
+        assert!(bp(1).enabled);
+        assert_eq!(bp(1).address, None);
+        assert_eq!(bp(1).condition.as_deref(), Some("x > 3"));
+        assert_eq!(bp(1).ignore_count, Some(2));
+        assert!(!bp(2).enabled);
+        assert_eq!(bp(2).address, Some(Address(0x401130)));
+        assert_eq!(bp(3).watchpoint, Some((WatchMode::Read, "x".to_owned())));
     }
 
-    pub fn get_u64(obj: &JsonValue, key: &'static str) -> Result<u64, GDBResponseError> {
-        let s = get_str(obj, key)?;
-        s.parse::<u64>()
-            .map_err(|e| GDBResponseError::Other(format!("Malformed frame description: {:?}", e)))
+    fn results(fields: &[(&str, &str)]) -> Object {
+        let mut results = Object::new();
+        for (key, value) in fields {
+            results.insert(key, (*value).into());
+        }
+        results
     }
 
-    pub fn get_u64_obj(obj: &Object, key: &'static str) -> Result<u64, GDBResponseError> {
-        let s = get_str_obj(obj, key)?;
-        s.parse::<u64>()
-            .map_err(|e| GDBResponseError::Other(format!("Malformed frame description: {:?}", e)))
+    #[test]
+    fn test_inferiors() {
+        use ThreadEvent::*;
+        let mut inferiors = InferiorSet::default();
+        let events = [
+            (GroupAdded, results(&[("id", "i1")])),
+            (GroupStarted, results(&[("id", "i1"), ("pid", "100")])),
+            (Created, results(&[("id", "1"), ("group-id", "i1")])),
+            (GroupAdded, results(&[("id", "i2")])),
+            (GroupStarted, results(&[("id", "i2"), ("pid", "101")])),
+            (Created, results(&[("id", "2"), ("group-id", "i2")])),
+        ];
+        for (event, results) in &events {
+            inferiors.handle_event(*event, results).unwrap();
+        }
+        assert_eq!(inferiors.len(), 2);
+        assert_eq!(inferiors.current().unwrap().id, "i1");
+        assert_eq!(inferiors.inferior_of_thread(2), Some("i2"));
+
+        inferiors.select_thread(2);
+        assert_eq!(inferiors.current().unwrap().number(), "2");
+        assert_eq!(inferiors.current().unwrap().pid, Some(101));
+
+        let exited = results(&[("id", "i2"), ("exit-code", "01")]);
+        inferiors.handle_event(GroupExited, &exited).unwrap();
+        assert_eq!(inferiors.current().unwrap().pid, None);
+        assert_eq!(inferiors.current().unwrap().exit_code, Some(1));
+
+        inferiors
+            .handle_event(GroupRemoved, &results(&[("id", "i2")]))
+            .unwrap();
+        assert_eq!(inferiors.len(), 1);
+        assert_eq!(inferiors.current().unwrap().id, "i1");
+        inferiors.select("i2");
+        assert_eq!(inferiors.current().unwrap().id, "i1");
     }
 }
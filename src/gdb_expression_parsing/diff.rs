@@ -0,0 +1,219 @@
+// A structural comparison of parsed values, e.g., to find out what changed between two stops.
+use super::{element_runs, Node};
+
+#[derive(Debug, PartialEq)]
+pub enum Difference {
+    Changed {
+        path: String,
+        old: String,
+        new: String,
+    },
+    Added {
+        path: String,
+        value: String,
+    },
+    Removed {
+        path: String,
+        value: String,
+    },
+}
+
+// Compare the values member by member. Paths start with root_path, members are appended as
+// `.key` and array elements as `[i]`.
+pub fn diff(root_path: &str, old: &Node, new: &Node) -> Vec<Difference> {
+    let mut differences = Vec::new();
+    diff_nodes(root_path.to_owned(), old, new, &mut differences);
+    differences
+}
+
+fn diff_nodes(path: String, old: &Node, new: &Node, out: &mut Vec<Difference>) {
+    match (old, new) {
+        (Node::Array(old_description, old_items), Node::Array(new_description, new_items)) => {
+            diff_descriptions(&path, old, new, old_description, new_description, out);
+            diff_elements(
+                &path,
+                &element_runs(old_items),
+                &element_runs(new_items),
+                out,
+            );
+        }
+        (Node::Tuple(old_description, old_items), Node::Tuple(new_description, new_items))
+            if old_items.len() == new_items.len() =>
+        {
+            diff_descriptions(&path, old, new, old_description, new_description, out);
+            for (i, (old, new)) in old_items.iter().zip(new_items).enumerate() {
+                diff_nodes(format!("{}.{}", path, i), old, new, out);
+            }
+        }
+        // The referenced values are compared unless the reference now refers to something else.
+        (
+            Node::Reference(_, old_address, old_value),
+            Node::Reference(_, new_address, new_value),
+        ) if old_address == new_address => diff_nodes(path, old_value, new_value, out),
+        (Node::Map(old_description, old_items), Node::Map(new_description, new_items)) => {
+            diff_descriptions(&path, old, new, old_description, new_description, out);
+            for (key, new) in new_items {
+                let member_path = format!("{}.{}", path, key);
+                match old_items.iter().find(|(k, _)| k == key) {
+                    Some((_, old)) => diff_nodes(member_path, old, new, out),
+                    None => out.push(Difference::Added {
+                        path: member_path,
+                        value: new.to_string(),
+                    }),
+                }
+            }
+            for (key, old) in old_items {
+                if !new_items.iter().any(|(k, _)| k == key) {
+                    out.push(Difference::Removed {
+                        path: format!("{}.{}", path, key),
+                        value: old.to_string(),
+                    });
+                }
+            }
+        }
+        (old, new) if old != new => out.push(Difference::Changed {
+            path,
+            old: old.to_string(),
+            new: new.to_string(),
+        }),
+        _ => {}
+    }
+}
+
+// Runs of equal elements are compared as a whole (as far as they overlap), so that the differences
+// refer to ranges of elements like `a[2..10]`.
+fn diff_elements(
+    path: &str,
+    old_runs: &[(usize, usize, &Node)],
+    new_runs: &[(usize, usize, &Node)],
+    out: &mut Vec<Difference>,
+) {
+    let elements = |start: usize, end: usize| {
+        if end - start == 1 {
+            format!("{}[{}]", path, start)
+        } else {
+            format!("{}[{}..{}]", path, start, end)
+        }
+    };
+    let (mut old_runs, mut new_runs) = (old_runs.iter().peekable(), new_runs.iter().peekable());
+    let mut start = 0;
+    while let (Some(&&(o, o_count, old)), Some(&&(n, n_count, new))) =
+        (old_runs.peek(), new_runs.peek())
+    {
+        let end = (o + o_count).min(n + n_count);
+        diff_nodes(elements(start, end), old, new, out);
+        start = end;
+        if o + o_count == end {
+            old_runs.next();
+        }
+        if n + n_count == end {
+            new_runs.next();
+        }
+    }
+    for &(i, count, old) in old_runs {
+        out.push(Difference::Removed {
+            path: elements(i.max(start), i + count),
+            value: old.to_string(),
+        });
+    }
+    for &(i, count, new) in new_runs {
+        out.push(Difference::Added {
+            path: elements(i.max(start), i + count),
+            value: new.to_string(),
+        });
+    }
+}
+
+// Descriptions of pretty printers usually contain the length of a container.
+fn diff_descriptions(
+    path: &str,
+    old: &Node,
+    new: &Node,
+    old_description: &Option<&str>,
+    new_description: &Option<&str>,
+    out: &mut Vec<Difference>,
+) {
+    if old_description != new_description {
+        out.push(Difference::Changed {
+            path: path.to_owned(),
+            old: old.summary(),
+            new: new.summary(),
+        });
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::parse_gdb_value;
+    use super::*;
+
+    #[test]
+    fn test_diff() {
+        let old = parse_gdb_value("{a = 1, b = {1, 2, 3}, c = {x = 1}, d = 5}").unwrap();
+        let new = parse_gdb_value("{a = 2, b = {1, 2}, c = 0x0, e = {1, 2}}").unwrap();
+        let changed = |path: &str, old: &str, new: &str| Difference::Changed {
+            path: path.to_owned(),
+            old: old.to_owned(),
+            new: new.to_owned(),
+        };
+        assert_eq!(
+            diff("s", &old, &new),
+            vec![
+                changed("s.a", "1", "2"),
+                Difference::Removed {
+                    path: "s.b[2]".to_owned(),
+                    value: "3".to_owned()
+                },
+                changed("s.c", "{x = 1}", "0x0"),
+                Difference::Added {
+                    path: "s.e".to_owned(),
+                    value: "{1, 2}".to_owned()
+                },
+                Difference::Removed {
+                    path: "s.d".to_owned(),
+                    value: "5".to_owned()
+                },
+            ]
+        );
+        assert_eq!(diff("s", &old, &old), vec![]);
+
+        let old = parse_gdb_value("(S &) @0x10: {a = 1, t = (1, 2)}").unwrap();
+        let new = parse_gdb_value("(S &) @0x10: {a = 1, t = (1, 3)}").unwrap();
+        assert_eq!(diff("r", &old, &new), vec![changed("r.t.1", "2", "3")]);
+
+        // Repeated elements are compared as ranges.
+        let old = parse_gdb_value("{0 <repeats 3 times>, 1}").unwrap();
+        let new = parse_gdb_value("{0, 2, 0, 1}").unwrap();
+        assert_eq!(diff("a", &old, &new), vec![changed("a[1]", "0", "2")]);
+        let old = parse_gdb_value("{1, 0 <repeats 4000000000 times>}").unwrap();
+        let new =
+            parse_gdb_value("{1, 0 <repeats 20 times>, 1 <repeats 3999999980 times>, 3}").unwrap();
+        assert_eq!(
+            diff("a", &old, &new),
+            vec![
+                changed("a[21..4000000001]", "0", "1"),
+                Difference::Added {
+                    path: "a[4000000001]".to_owned(),
+                    value: "3".to_owned()
+                },
+            ]
+        );
+
+        let old = parse_gdb_value("std::vector of length 1, capacity 1 = {1}").unwrap();
+        let new = parse_gdb_value("std::vector of length 2, capacity 2 = {1, 2}").unwrap();
+        assert_eq!(
+            diff("v", &old, &new),
+            vec![
+                changed(
+                    "v",
+                    "std::vector of length 1, capacity 1",
+                    "std::vector of length 2, capacity 2"
+                ),
+                Difference::Added {
+                    path: "v[1]".to_owned(),
+                    value: "2".to_owned()
+                },
+            ]
+        );
+    }
+}
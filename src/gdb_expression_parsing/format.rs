@@ -0,0 +1,288 @@
+// Alternative representations of the leaves of printed values. Since gdb only prints the value,
+// the size of its type has to be supplied (e.g., to show the bit pattern of negative integers).
+use std::convert::TryFrom;
+use std::fmt::Write;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    Natural,
+    Decimal,
+    Hex,
+    Octal,
+    Binary,
+    Char,
+    Float,
+    HexFloat,
+    String,
+    RawBytes,
+}
+
+impl Format {
+    pub fn name(self) -> &'static str {
+        match self {
+            Format::Natural => "natural",
+            Format::Decimal => "decimal",
+            Format::Hex => "hex",
+            Format::Octal => "octal",
+            Format::Binary => "binary",
+            Format::Char => "char",
+            Format::Float => "float",
+            Format::HexFloat => "hex float",
+            Format::String => "string",
+            Format::RawBytes => "raw bytes",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Number {
+    Int(i128),
+    Float(f64),
+}
+
+// gdb annotates some integers, e.g., `97 'a'` for chars or `0x4005d6 <main>` for pointers.
+fn parse_number(leaf: &str) -> Option<Number> {
+    let first = leaf.split_whitespace().next()?;
+    if let Ok(i) = parse_int::parse::<i128>(first) {
+        Some(Number::Int(i))
+    } else if first.len() == leaf.len() {
+        leaf.parse::<f64>().ok().map(Number::Float)
+    } else {
+        None
+    }
+}
+
+// Only leaves that are numbers can be formatted (and thus need the size of their type).
+pub fn is_number(leaf: &str) -> bool {
+    parse_number(leaf).is_some()
+}
+
+// The bit pattern of the integer in a type of the given size (in bytes).
+fn bit_pattern(i: i128, size: Option<usize>) -> Option<u128> {
+    match size {
+        _ if i >= 0 => Some(i as u128),
+        Some(size) if size >= 16 => Some(i as u128),
+        Some(size) => Some(i as u128 & ((1u128 << (8 * size)) - 1)),
+        None => None,
+    }
+}
+
+// The bit pattern of the number as an integer, i.e., floats are reinterpreted.
+fn integer_bits(number: Number, size: Option<usize>) -> Option<u128> {
+    match (number, size) {
+        (Number::Int(i), size) => bit_pattern(i, size),
+        (Number::Float(f), Some(4)) => Some((f as f32).to_bits() as u128),
+        (Number::Float(f), Some(8)) => Some(f.to_bits() as u128),
+        (Number::Float(_), _) => None,
+    }
+}
+
+fn float_value(number: Number, size: Option<usize>) -> Option<f64> {
+    match (number, size) {
+        (Number::Float(f), _) => Some(f),
+        (Number::Int(i), Some(4)) => Some(f32::from_bits(bit_pattern(i, size)? as u32) as f64),
+        (Number::Int(i), Some(8)) => Some(f64::from_bits(bit_pattern(i, size)? as u64)),
+        (Number::Int(_), _) => None,
+    }
+}
+
+fn with_radix(
+    number: Number,
+    size: Option<usize>,
+    radix: impl Fn(u128) -> String,
+) -> Option<String> {
+    match (integer_bits(number, size), number) {
+        (Some(bits), _) => Some(radix(bits)),
+        // Without the size of the type, we can only show the sign.
+        (None, Number::Int(i)) => Some(format!("-{}", radix(i.unsigned_abs()))),
+        (None, Number::Float(_)) => None,
+    }
+}
+
+fn hex_float(f: f64) -> String {
+    if f.is_nan() {
+        return "nan".to_owned();
+    }
+    let sign = if f.is_sign_negative() { "-" } else { "" };
+    if f.is_infinite() {
+        return format!("{}inf", sign);
+    }
+    let bits = f.to_bits();
+    let exponent = ((bits >> 52) & 0x7ff) as i64;
+    let mantissa = bits & ((1 << 52) - 1);
+    let (lead, exponent) = match (exponent, mantissa) {
+        (0, 0) => (0, 0),
+        (0, _) => (0, -1022),
+        (e, _) => (1, e - 1023),
+    };
+    let digits = format!("{:013x}", mantissa);
+    let digits = digits.trim_end_matches('0');
+    let point = if digits.is_empty() { "" } else { "." };
+    format!("{}0x{}{}{}p{:+}", sign, lead, point, digits, exponent)
+}
+
+// Write the character code escaped for a literal delimited by quote. Non-ascii characters are only
+// shown for wide chars, single bytes are probably part of a multibyte sequence.
+fn push_escaped(out: &mut String, code: u128, quote: char, wide: bool) {
+    let c = u32::try_from(code).ok().and_then(char::from_u32);
+    match c {
+        Some('\n') => out.push_str("\\n"),
+        Some('\t') => out.push_str("\\t"),
+        Some('\r') => out.push_str("\\r"),
+        Some('\0') => out.push_str("\\0"),
+        Some(c) if c == '\\' || c == quote => {
+            out.push('\\');
+            out.push(c);
+        }
+        Some(c) if c.is_ascii() && !c.is_ascii_control() => out.push(c),
+        Some(c) if wide && !c.is_ascii() && !c.is_control() => out.push(c),
+        _ => {
+            let _ = write!(out, "\\x{:x}", code);
+        }
+    }
+}
+
+fn string_literal(codes: impl Iterator<Item = u128>, wide: bool) -> String {
+    let mut codes = codes.collect::<Vec<_>>();
+    // Trailing nul bytes are most likely just the unused rest of a buffer.
+    while codes.last() == Some(&0) {
+        codes.pop();
+    }
+    let mut out = "\"".to_owned();
+    for code in codes {
+        push_escaped(&mut out, code, '"', wide);
+    }
+    out.push('"');
+    out
+}
+
+fn le_bytes(bits: u128, size: usize) -> Vec<u8> {
+    bits.to_le_bytes()[..size].to_vec()
+}
+
+// Format a leaf of a printed value whose type has the given size (in bytes, if known). Returns
+// None if the leaf cannot be shown in the format, in which case it should be shown as printed.
+pub fn format_leaf(leaf: &str, format: Format, size: Option<usize>) -> Option<String> {
+    let number = parse_number(leaf)?;
+    let size = size.filter(|s| (1..=16).contains(s));
+    match format {
+        Format::Natural => None,
+        Format::Decimal => match number {
+            Number::Int(i) => Some(i.to_string()),
+            Number::Float(_) => integer_bits(number, size).map(|b| b.to_string()),
+        },
+        Format::Hex => with_radix(number, size, |b| format!("{:#x}", b)),
+        Format::Octal => with_radix(number, size, |b| format!("{:#o}", b)),
+        Format::Binary => with_radix(number, size, |b| format!("{:#b}", b)),
+        Format::Char => {
+            let code = integer_bits(number, size)?;
+            let mut out = format!("{} '", code);
+            push_escaped(&mut out, code, '\'', size.is_none_or(|s| s > 1));
+            out.push('\'');
+            Some(out)
+        }
+        Format::Float => match number {
+            Number::Float(_) => None,
+            Number::Int(_) => match (float_value(number, size)?, size) {
+                (f, Some(4)) => Some(format!("{:?}", f as f32)),
+                (f, _) => Some(format!("{:?}", f)),
+            },
+        },
+        Format::HexFloat => float_value(number, size).map(hex_float),
+        Format::String => {
+            let size = size?;
+            let bits = integer_bits(number, Some(size))?;
+            Some(string_literal(
+                le_bytes(bits, size).into_iter().map(u128::from),
+                false,
+            ))
+        }
+        Format::RawBytes => {
+            let size = size?;
+            let bits = integer_bits(number, Some(size))?;
+            let bytes = le_bytes(bits, size)
+                .into_iter()
+                .map(|b| format!("{:02x}", b));
+            Some(bytes.collect::<Vec<_>>().join(" "))
+        }
+    }
+}
+
+// Format the elements of an array (whose type has the given size) as a string. Returns None if
+// not all elements are integers.
+pub fn format_string<'a>(
+    elements: impl IntoIterator<Item = &'a str>,
+    size: Option<usize>,
+) -> Option<String> {
+    let codes = elements
+        .into_iter()
+        .map(|e| match parse_number(e)? {
+            Number::Int(i) => bit_pattern(i, size.or(Some(1))),
+            Number::Float(_) => None,
+        })
+        .collect::<Option<Vec<_>>>()?;
+    Some(string_literal(
+        codes.into_iter(),
+        size.is_some_and(|s| s > 1),
+    ))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_format_leaf() {
+        let f = |leaf, format, size| format_leaf(leaf, format, size);
+        assert_eq!(f("-1", Format::Hex, Some(4)).unwrap(), "0xffffffff");
+        assert_eq!(f("-1", Format::Hex, Some(1)).unwrap(), "0xff");
+        assert_eq!(f("-1", Format::Hex, None).unwrap(), "-0x1");
+        assert_eq!(f("-2", Format::Binary, Some(1)).unwrap(), "0b11111110");
+        assert_eq!(f("8", Format::Octal, Some(4)).unwrap(), "0o10");
+        assert_eq!(f("0x10", Format::Decimal, Some(8)).unwrap(), "16");
+        assert_eq!(f("97 'a'", Format::Hex, Some(1)).unwrap(), "0x61");
+        assert_eq!(f("10", Format::Char, Some(1)).unwrap(), "10 '\\n'");
+        assert_eq!(f("-23", Format::Char, Some(1)).unwrap(), "233 '\\xe9'");
+        assert_eq!(f("233", Format::Char, Some(4)).unwrap(), "233 'é'");
+        assert_eq!(f("1.5", Format::Hex, Some(4)).unwrap(), "0x3fc00000");
+        assert_eq!(f("1069547520", Format::Float, Some(4)).unwrap(), "1.5");
+        assert_eq!(f("1036831949", Format::Float, Some(4)).unwrap(), "0.1");
+        assert_eq!(f("1069547520", Format::Float, None), None);
+        assert_eq!(f("3.0", Format::HexFloat, Some(4)).unwrap(), "0x1.8p+1");
+        assert_eq!(
+            f("1077936128", Format::HexFloat, Some(4)).unwrap(),
+            "0x1.8p+1"
+        );
+        assert_eq!(f("-0.25", Format::HexFloat, None).unwrap(), "-0x1p-2");
+        assert_eq!(f("0.0", Format::HexFloat, None).unwrap(), "0x0p+0");
+        assert_eq!(
+            f("1684234849", Format::String, Some(4)).unwrap(),
+            "\"abcd\""
+        );
+        assert_eq!(f("258", Format::RawBytes, Some(4)).unwrap(), "02 01 00 00");
+        assert_eq!(f("-1", Format::RawBytes, Some(2)).unwrap(), "ff ff");
+        assert_eq!(f("1", Format::RawBytes, None), None);
+        assert_eq!(f("std::vector of length 0", Format::Hex, Some(4)), None);
+        assert_eq!(
+            f("0x400bf0 <main>", Format::Decimal, Some(8)).unwrap(),
+            "4197360"
+        );
+    }
+
+    #[test]
+    fn test_format_string() {
+        assert_eq!(
+            format_string(
+                vec!["104 'h'", "105 'i'", "0 '\\000'", "0 '\\000'"],
+                Some(1)
+            )
+            .unwrap(),
+            "\"hi\""
+        );
+        assert_eq!(
+            format_string(vec!["34", "-1"], Some(1)).unwrap(),
+            "\"\\\"\\xff\""
+        );
+        assert_eq!(format_string(vec!["1", "x"], Some(1)), None);
+    }
+}
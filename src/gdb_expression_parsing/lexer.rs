@@ -15,6 +15,9 @@ pub enum Token {
     RPointyBracket,
     Comma,
     Equals,
+    // Only colons that are followed by whitespace (`x: 1` in Rust structs, `@0x1234: 5`)
+    Colon,
+    // String and char literals
     String,
     Text,
     Newline,
@@ -24,8 +27,9 @@ pub enum Token {
 enum LexerState {
     Free,
     PendingOutput(TokenWithLocation),
-    InString(Location),
-    InStringEscapedChar(Location),
+    // Literals end with the quote that they begin with.
+    InString(Location, char),
+    InStringEscapedChar(Location, char),
     InText(Location, Location),
 }
 
@@ -51,10 +55,17 @@ impl<'input> Lexer<'input> {
 impl<'input> Iterator for Lexer<'input> {
     type Item = Result<TokenWithLocation, LexicalError>;
     fn next(&mut self) -> Option<Self::Item> {
-        for (i, c) in self.chars.by_ref() {
+        while let Some((i, c)) = self.chars.next() {
+            let separator = c == ':'
+                && self
+                    .chars
+                    .clone()
+                    .next()
+                    .is_some_and(|(_, next)| next.is_whitespace());
             let (output, new_state) = match self.state {
                 LexerState::Free => match c {
-                    '"' => (None, LexerState::InString(i)),
+                    '"' | '\'' => (None, LexerState::InString(i, c)),
+                    ':' if separator => (Some((i, Token::Colon, i + 1)), LexerState::Free),
                     '{' => (Some((i, Token::LBrace, i + 1)), LexerState::Free),
                     '}' => (Some((i, Token::RBrace, i + 1)), LexerState::Free),
                     '[' => (Some((i, Token::LSquareBracket, i + 1)), LexerState::Free),
@@ -70,7 +81,15 @@ impl<'input> Iterator for Lexer<'input> {
                     _ => (None, LexerState::InText(i, i + 1)),
                 },
                 LexerState::PendingOutput(output) => match c {
-                    '"' => (Some(output), LexerState::InString(i)),
+                    '"' => (Some(output), LexerState::InString(i, c)),
+                    // Lifetimes of Rust types (`<'a>`) are not char literals.
+                    '\'' if output.1 != Token::LPointyBracket => {
+                        (Some(output), LexerState::InString(i, c))
+                    }
+                    ':' if separator => (
+                        Some(output),
+                        LexerState::PendingOutput((i, Token::Colon, i + 1)),
+                    ),
                     '{' => (
                         Some(output),
                         LexerState::PendingOutput((i, Token::LBrace, i + 1)),
@@ -118,14 +137,25 @@ impl<'input> Iterator for Lexer<'input> {
                     ' ' | '\t' => (Some(output), LexerState::Free),
                     _ => (Some(output), LexerState::InText(i, i + 1)),
                 },
-                LexerState::InString(begin) => match c {
-                    '"' => (Some((begin, Token::String, i + 1)), LexerState::Free),
-                    '\\' => (None, LexerState::InStringEscapedChar(begin)),
-                    _ => (None, LexerState::InString(begin)),
+                LexerState::InString(begin, quote) => match c {
+                    '\\' => (None, LexerState::InStringEscapedChar(begin, quote)),
+                    _ if c == quote => (Some((begin, Token::String, i + 1)), LexerState::Free),
+                    _ => (None, LexerState::InString(begin, quote)),
                 },
-                LexerState::InStringEscapedChar(begin) => (None, LexerState::InString(begin)),
+                LexerState::InStringEscapedChar(begin, quote) => {
+                    (None, LexerState::InString(begin, quote))
+                }
                 LexerState::InText(begin, end) => match c {
-                    '"' => (Some((begin, Token::Text, end)), LexerState::InString(i)),
+                    '"' => (Some((begin, Token::Text, end)), LexerState::InString(i, c)),
+                    // Char literals follow whitespace (`97 'a'`), other quotes are part of the
+                    // text.
+                    '\'' if i > end => {
+                        (Some((begin, Token::Text, end)), LexerState::InString(i, c))
+                    }
+                    ':' if separator => (
+                        Some((begin, Token::Text, end)),
+                        LexerState::PendingOutput((i, Token::Colon, i + 1)),
+                    ),
                     '{' => (
                         Some((begin, Token::Text, end)),
                         LexerState::PendingOutput((i, Token::LBrace, i + 1)),
@@ -185,7 +215,7 @@ impl<'input> Iterator for Lexer<'input> {
                 self.state = LexerState::Free;
                 Some(Ok(output))
             }
-            LexerState::InString(begin) | LexerState::InStringEscapedChar(begin) => {
+            LexerState::InString(begin, _) | LexerState::InStringEscapedChar(begin, _) => {
                 self.state = LexerState::Free;
                 Some(Err(LexicalError::UnfinishedString { begin_index: begin }))
             }
@@ -241,6 +271,37 @@ mod test {
         }
     }
 
+    #[test]
+    fn test_lexer_literals_and_colons() {
+        assert_eq_lexer_tokens(
+            "{c = 44 ',', d = '}'}",
+            &[
+                Token::LBrace,
+                Token::Text,
+                Token::Equals,
+                Token::Text,
+                Token::String,
+                Token::Comma,
+                Token::Text,
+                Token::Equals,
+                Token::String,
+                Token::RBrace,
+            ],
+        );
+        assert_eq_lexer_tokens("can't", &[Token::Text]);
+        assert_eq_lexer_tokens(
+            "a::B {x: 1}",
+            &[
+                Token::Text,
+                Token::LBrace,
+                Token::Text,
+                Token::Colon,
+                Token::Text,
+                Token::RBrace,
+            ],
+        );
+    }
+
     #[test]
     fn test_lexer_error() {
         assert_eq_lexer_error("\"", LexicalError::UnfinishedString { begin_index: 0 });
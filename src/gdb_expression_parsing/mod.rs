@@ -1,75 +1,160 @@
 mod ast;
+mod diff;
+mod format;
 mod lexer;
 mod parser;
 
-const ANON_KEY: &str = "*anon*";
+pub use diff::{diff, Difference};
+pub use format::{format_leaf, format_string, is_number, Format};
+use std::fmt;
+
+// Key of the (anonymous) members of unnamed structs and unions
+pub const ANON_KEY: &str = "*anon*";
 
 pub type ParseError = parser::Error;
 
-pub fn parse_gdb_value(result_string: &str) -> Result<Node, ParseError> {
+pub fn parse_gdb_value(result_string: &str) -> Result<Node<'_>, ParseError> {
     let lexer = lexer::Lexer::new(result_string);
     parser::parse(lexer, result_string)
 }
 
 #[derive(Debug, PartialEq)]
 pub enum Node<'a> {
+    // Numbers, pointers, enumerators and everything else without structure
     Leaf(&'a str),
+    // A character: Its code and literal (`97 'a'`)
+    Char(&'a str, &'a str),
+    // String literals including the quotes, possibly in several segments (`'a' <repeats 20
+    // times>, "bc"`) or cut off at the `print elements` limit (`"abc"...`)
+    String(&'a str),
+    // A value that gdb cannot show (`<optimized out>`, `<error: ...>`) without the brackets
+    Unavailable(&'a str),
+    // A function (pointer): `{int (int)} 0x401126 <square(int)>`
+    Function {
+        signature: &'a str,
+        address: &'a str,
+        symbol: Option<&'a str>,
+    },
+    // A C++ reference: The type (if printed), the address and the value (`(int &) @0x7ffe: 5`)
+    Reference(Option<&'a str>, &'a str, Box<Node<'a>>),
+    // Rust tuples, tuple structs and tuple variants (`core::option::Option<i32>::Some(5)`)
+    Tuple(Option<&'a str>, Vec<Node<'a>>),
     Array(Option<&'a str>, Vec<Node<'a>>),
+    // Structs, including Rust structs (`Point {x: 1, y: 2}`)
     Map(Option<&'a str>, Vec<(&'a str, Node<'a>)>),
+    // Array elements: A run of equal elements (`0 <repeats 16 times>`) and the marker for elements
+    // that were not printed because of the `print elements` limit (`1, 2...`)
+    Repeated(Box<Node<'a>>, usize),
+    Truncated,
 }
 
-#[derive(Clone, Copy)]
-pub enum Format {
-    Decimal,
-    Hex,
-    Octal,
-    Binary,
+// The elements of an array as runs of equal elements (index, count, element). Repeated elements
+// (of which there may be arbitrarily many) are not expanded and the marker for unprinted elements
+// is dropped.
+pub fn element_runs<'n, 'a>(items: &'n [Node<'a>]) -> Vec<(usize, usize, &'n Node<'a>)> {
+    let mut runs = Vec::new();
+    let mut len = 0;
+    for item in items {
+        let (element, count) = match item {
+            Node::Repeated(element, count) => (&**element, *count),
+            Node::Truncated => continue,
+            item => (item, 1),
+        };
+        runs.push((len, count, element));
+        len += count;
+    }
+    runs
 }
 
-#[derive(Clone)]
-pub struct Value<'s> {
-    pub node: &'s Node<'s>,
-    pub format: Option<Format>,
+impl Node<'_> {
+    // A short description of the node that fits in a single line.
+    pub fn summary(&self) -> String {
+        match self {
+            Node::Leaf(s) | Node::String(s) => s.to_string(),
+            Node::Char(..) | Node::Unavailable(_) => self.to_string(),
+            Node::Function {
+                address, symbol, ..
+            } => symbol.unwrap_or(address).to_owned(),
+            Node::Reference(_, _, value) => value.summary(),
+            Node::Tuple(Some(description), _) => description.to_string(),
+            Node::Tuple(None, items) if items.is_empty() => "()".to_owned(),
+            Node::Tuple(None, _) => "(...)".to_owned(),
+            Node::Array(Some(description), _) | Node::Map(Some(description), _) => {
+                description.trim_end_matches('=').trim_end().to_owned()
+            }
+            Node::Repeated(element, _) => element.summary(),
+            Node::Truncated => "...".to_owned(),
+            Node::Array(None, items) if items.is_empty() => "{}".to_owned(),
+            Node::Map(None, items) if items.is_empty() => "{}".to_owned(),
+            _ => "{...}".to_owned(),
+        }
+    }
 }
 
-impl<'n> unsegen_jsonviewer::Value for Value<'n> {
-    fn visit<'s>(self) -> unsegen_jsonviewer::ValueVariant<'s, Self> {
-        match self.node {
-            Node::Leaf(s) => {
-                let res = if let Some(format) = self.format {
-                    match parse_int::parse::<i128>(s) {
-                        Err(_) => s.to_string(),
-                        Ok(i) => match format {
-                            Format::Decimal => i.to_string(),
-                            Format::Hex => format!("{:#x}", i),
-                            Format::Octal => format!("{:#o}", i),
-                            Format::Binary => format!("{:#b}", i),
-                        },
+// Prints the value in a single line (similar to gdb without pretty printing).
+impl fmt::Display for Node<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Node::Leaf(s) | Node::String(s) => write!(f, "{}", s.replace('\n', " ")),
+            Node::Char(code, literal) => write!(f, "{} {}", code, literal),
+            Node::Unavailable(s) => write!(f, "<{}>", s),
+            Node::Function {
+                signature,
+                address,
+                symbol,
+            } => {
+                write!(f, "{{{}}} {}", signature, address)?;
+                if let Some(symbol) = symbol {
+                    write!(f, " <{}>", symbol)?;
+                }
+                Ok(())
+            }
+            Node::Reference(typ, address, value) => {
+                if let Some(typ) = typ {
+                    write!(f, "({}) ", typ)?;
+                }
+                write!(f, "@{}: {}", address, value)
+            }
+            Node::Tuple(description, items) => {
+                write!(f, "{}(", description.unwrap_or(""))?;
+                for (i, item) in items.iter().enumerate() {
+                    let separator = if i == 0 { "" } else { ", " };
+                    write!(f, "{}{}", separator, item)?;
+                }
+                write!(f, ")")
+            }
+            Node::Array(description, items) => {
+                if let Some(description) = description {
+                    write!(f, "{} ", description)?;
+                }
+                write!(f, "{{")?;
+                for (i, item) in items.iter().enumerate() {
+                    let separator = if i == 0 || *item == Node::Truncated {
+                        ""
+                    } else {
+                        ", "
+                    };
+                    write!(f, "{}{}", separator, item)?;
+                }
+                write!(f, "}}")
+            }
+            Node::Repeated(element, count) => write!(f, "{} <repeats {} times>", element, count),
+            Node::Truncated => write!(f, "..."),
+            Node::Map(description, items) => {
+                if let Some(description) = description {
+                    write!(f, "{} ", description)?;
+                }
+                write!(f, "{{")?;
+                for (i, (key, item)) in items.iter().enumerate() {
+                    let separator = if i == 0 { "" } else { ", " };
+                    if *key == ANON_KEY {
+                        write!(f, "{}{}", separator, item)?;
+                    } else {
+                        write!(f, "{}{} = {}", separator, key, item)?;
                     }
-                } else {
-                    s.to_string()
-                };
-                unsegen_jsonviewer::ValueVariant::Scalar(res)
+                }
+                write!(f, "}}")
             }
-            Node::Map(description, items) => unsegen_jsonviewer::ValueVariant::Map(
-                description.map(|s| s.to_owned()),
-                Box::new(items.iter().map(move |(s, v)| {
-                    (
-                        s.to_string(),
-                        Value {
-                            node: v,
-                            format: self.format,
-                        },
-                    )
-                })),
-            ),
-            Node::Array(description, items) => unsegen_jsonviewer::ValueVariant::Array(
-                description.map(|s| s.to_owned()),
-                Box::new(items.iter().map(move |v| Value {
-                    node: v,
-                    format: self.format,
-                })),
-            ),
         }
     }
 }
@@ -84,7 +169,7 @@ mod test {
         assert_eq!(parse_gdb_value("false").unwrap(), Node::Leaf("false"));
         assert_eq!(parse_gdb_value("27").unwrap(), Node::Leaf("27")); //This is probably sufficient for us
         assert_eq!(parse_gdb_value("27.1").unwrap(), Node::Leaf("27.1"));
-        assert_eq!(parse_gdb_value("\"dfd\"").unwrap(), Node::Leaf("\"dfd\""));
+        assert_eq!(parse_gdb_value("\"dfd\"").unwrap(), Node::String("\"dfd\""));
         assert_eq!(parse_gdb_value(" l r ").unwrap(), Node::Leaf("l r"));
         assert_eq!(parse_gdb_value(" 0x123").unwrap(), Node::Leaf("0x123"));
         assert_eq!(parse_gdb_value(" -123").unwrap(), Node::Leaf("-123"));
@@ -146,7 +231,7 @@ mod test {
             vec![
                 ("boolean", Node::Leaf("128")),
                 ("x", Node::Leaf("0")),
-                ("y", Node::Leaf("\"kdf\\\\j}{\\\"\"")),
+                ("y", Node::String("\"kdf\\\\j}{\\\"\"")),
                 (
                     "named_inner",
                     Node::Map(
@@ -225,21 +310,21 @@ mod test {
     #[test]
     fn test_parse_string() {
         //assert_eq!(parse_gdb_value("\"foo{]}]]}]<>,\\\\\""), Node::Leaf("\"foo{]}]]}]<>,\\\"".to_string()));
-        assert_eq!(parse_gdb_value("\"foo\"").unwrap(), Node::Leaf("\"foo\""));
+        assert_eq!(parse_gdb_value("\"foo\"").unwrap(), Node::String("\"foo\""));
         assert_eq!(
             parse_gdb_value("\"foo\\\"\"").unwrap(),
-            Node::Leaf("\"foo\\\"\"")
+            Node::String("\"foo\\\"\"")
         );
         assert_eq!(
             parse_gdb_value("\"\\\\}{\\\"\"").unwrap(),
-            Node::Leaf("\"\\\\}{\\\"\"")
+            Node::String("\"\\\\}{\\\"\"")
         );
-        assert_eq!(parse_gdb_value("\"\\t\"").unwrap(), Node::Leaf("\"\\t\""));
-        assert_eq!(parse_gdb_value("\"\\n\"").unwrap(), Node::Leaf("\"\\n\""));
-        assert_eq!(parse_gdb_value("\"\\r\"").unwrap(), Node::Leaf("\"\\r\""));
+        assert_eq!(parse_gdb_value("\"\\t\"").unwrap(), Node::String("\"\\t\""));
+        assert_eq!(parse_gdb_value("\"\\n\"").unwrap(), Node::String("\"\\n\""));
+        assert_eq!(parse_gdb_value("\"\\r\"").unwrap(), Node::String("\"\\r\""));
         assert_eq!(
             parse_gdb_value("\"kdf\\\\j}{\\\"\"").unwrap(),
-            Node::Leaf("\"kdf\\\\j}{\\\"\"")
+            Node::String("\"kdf\\\\j}{\\\"\"")
         );
     }
 
@@ -297,17 +382,19 @@ mod test {
         );
 
         // GDB really does not make it easy for us...
+        let function = Node::Function {
+            signature: "int (int, int)",
+            address: "0x400a76",
+            symbol: Some("foo(int, int)"),
+        };
         assert_eq!(
             parse_gdb_value("{int (int, int)} 0x400a76 <foo(int, int)>").unwrap(),
-            Node::Leaf("{int (int, int)} 0x400a76 <foo(int, int)>")
+            function
         );
 
         assert_eq!(
             parse_gdb_value("{ {int (int, int)} 0x400a76 <foo(int, int)> }").unwrap(),
-            Node::Array(
-                None,
-                vec![Node::Leaf("{int (int, int)} 0x400a76 <foo(int, int)>")]
-            )
+            Node::Array(None, vec![function])
         );
     }
 
@@ -376,4 +463,225 @@ mod test {
             Node::Array(None, vec![Node::Leaf("27"), Node::Leaf("37")])
         );
     }
+
+    // Outputs of gdb for a C++ and a Rust program
+    #[test]
+    fn test_parse_gdb_outputs() {
+        assert_eq!(
+            parse_gdb_value("{c = 97 'a', comma = 44 ',', brace = 125 '}', nul = 0 '\\000'}")
+                .unwrap(),
+            Node::Map(
+                None,
+                vec![
+                    ("c", Node::Char("97", "'a'")),
+                    ("comma", Node::Char("44", "','")),
+                    ("brace", Node::Char("125", "'}'")),
+                    ("nul", Node::Char("0", "'\\000'")),
+                ]
+            )
+        );
+        assert_eq!(
+            parse_gdb_value(
+                "{name = 'x' <repeats 20 times>, \"yz\", len = 22, msg = \"a, \\\"b\\\"\"}"
+            )
+            .unwrap(),
+            Node::Map(
+                None,
+                vec![
+                    ("name", Node::String("'x' <repeats 20 times>, \"yz\"")),
+                    ("len", Node::Leaf("22")),
+                    ("msg", Node::String("\"a, \\\"b\\\"\"")),
+                ]
+            )
+        );
+        assert_eq!(
+            parse_gdb_value("'\\000' <repeats 15 times>").unwrap(),
+            Node::String("'\\000' <repeats 15 times>")
+        );
+        assert_eq!(
+            parse_gdb_value(
+                "{value = <optimized out>, next = <error: Cannot access memory at address 0x8>}"
+            )
+            .unwrap(),
+            Node::Map(
+                None,
+                vec![
+                    ("value", Node::Unavailable("optimized out")),
+                    (
+                        "next",
+                        Node::Unavailable("error: Cannot access memory at address 0x8")
+                    ),
+                ]
+            )
+        );
+
+        let reference = parse_gdb_value("(int &) @0x7fffffffe0ac: 5").unwrap();
+        assert_eq!(
+            reference,
+            Node::Reference(Some("int &"), "0x7fffffffe0ac", Box::new(Node::Leaf("5")))
+        );
+        assert_eq!(reference.to_string(), "(int &) @0x7fffffffe0ac: 5");
+        assert_eq!(
+            parse_gdb_value("{r = @0x7fffffffe0a0: {a = 1, b = 2}, s = 3}").unwrap(),
+            Node::Map(
+                None,
+                vec![
+                    (
+                        "r",
+                        Node::Reference(
+                            None,
+                            "0x7fffffffe0a0",
+                            Box::new(Node::Map(
+                                None,
+                                vec![("a", Node::Leaf("1")), ("b", Node::Leaf("2"))]
+                            ))
+                        )
+                    ),
+                    ("s", Node::Leaf("3")),
+                ]
+            )
+        );
+        assert_eq!(
+            parse_gdb_value("{callback = {int (int)} 0x401126 <square(int)>, fp = 0x401126 <square(int)>, null = {void (void)} 0x0}")
+                .unwrap(),
+            Node::Map(
+                None,
+                vec![
+                    (
+                        "callback",
+                        Node::Function {
+                            signature: "int (int)",
+                            address: "0x401126",
+                            symbol: Some("square(int)"),
+                        }
+                    ),
+                    ("fp", Node::Leaf("0x401126 <square(int)>")),
+                    (
+                        "null",
+                        Node::Function {
+                            signature: "void (void)",
+                            address: "0x0",
+                            symbol: None,
+                        }
+                    ),
+                ]
+            )
+        );
+
+        // Rust
+        assert_eq!(
+            parse_gdb_value("core::option::Option<i32>::Some(5)").unwrap(),
+            Node::Tuple(
+                Some("core::option::Option<i32>::Some"),
+                vec![Node::Leaf("5")]
+            )
+        );
+        assert_eq!(
+            parse_gdb_value("core::option::Option<i32>::None").unwrap(),
+            Node::Leaf("core::option::Option<i32>::None")
+        );
+        let tuple = parse_gdb_value("(1, \"a,b\", rust::Point {x: 1, y: -2})").unwrap();
+        assert_eq!(
+            tuple,
+            Node::Tuple(
+                None,
+                vec![
+                    Node::Leaf("1"),
+                    Node::String("\"a,b\""),
+                    Node::Map(
+                        Some("rust::Point"),
+                        vec![("x", Node::Leaf("1")), ("y", Node::Leaf("-2"))]
+                    ),
+                ]
+            )
+        );
+        assert_eq!(
+            tuple.to_string(),
+            "(1, \"a,b\", rust::Point {x = 1, y = -2})"
+        );
+        assert_eq!(
+            parse_gdb_value("rust::Shape::Circle{radius: 1.5, center: (0, 0)}").unwrap(),
+            Node::Map(
+                Some("rust::Shape::Circle"),
+                vec![
+                    ("radius", Node::Leaf("1.5")),
+                    (
+                        "center",
+                        Node::Tuple(None, vec![Node::Leaf("0"), Node::Leaf("0")])
+                    ),
+                ]
+            )
+        );
+        assert_eq!(
+            parse_gdb_value("&[i32] {data_ptr: 0x5555555a2b10, length: 3}").unwrap(),
+            Node::Map(
+                Some("&[i32]"),
+                vec![
+                    ("data_ptr", Node::Leaf("0x5555555a2b10")),
+                    ("length", Node::Leaf("3")),
+                ]
+            )
+        );
+        assert_eq!(
+            parse_gdb_value("rust::Wrapper<'static> {s: \"it's\"}").unwrap(),
+            Node::Map(
+                Some("rust::Wrapper<'static>"),
+                vec![("s", Node::String("\"it's\""))]
+            )
+        );
+        // Casts are not tuples.
+        assert_eq!(
+            parse_gdb_value("(int *) 0x0").unwrap(),
+            Node::Leaf("(int *) 0x0")
+        );
+    }
+
+    #[test]
+    fn test_parse_repeats_and_truncation() {
+        let value = "{1, 0 <repeats 15 times>, 2...}";
+        let node = parse_gdb_value(value).unwrap();
+        assert_eq!(
+            node,
+            Node::Array(
+                None,
+                vec![
+                    Node::Leaf("1"),
+                    Node::Repeated(Box::new(Node::Leaf("0")), 15),
+                    Node::Leaf("2"),
+                    Node::Truncated,
+                ]
+            )
+        );
+        assert_eq!(node.to_string(), value);
+        if let Node::Array(_, items) = &node {
+            let runs = element_runs(items);
+            assert_eq!(runs.len(), 3);
+            assert_eq!((runs[1].0, runs[1].1), (1, 15));
+            assert_eq!(runs[2].0, 16);
+        }
+
+        assert_eq!(
+            parse_gdb_value("{{a = 1} <repeats 3 times>, {a = 2}...}").unwrap(),
+            Node::Array(
+                None,
+                vec![
+                    Node::Repeated(Box::new(Node::Map(None, vec![("a", Node::Leaf("1"))])), 3),
+                    Node::Map(None, vec![("a", Node::Leaf("2"))]),
+                    Node::Truncated,
+                ]
+            )
+        );
+        // Truncated strings and elided structs are leaves.
+        assert_eq!(
+            parse_gdb_value("{\"ab\"..., \"c\"...}").unwrap(),
+            Node::Array(
+                None,
+                vec![Node::String("\"ab\"..."), Node::String("\"c\"...")]
+            )
+        );
+        assert_eq!(
+            parse_gdb_value("{...}").unwrap(),
+            Node::Array(None, vec![Node::Leaf("...")])
+        );
+    }
 }
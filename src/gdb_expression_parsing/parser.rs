@@ -9,6 +9,7 @@ pub enum TokenS2 {
     LBrace,
     RBrace,
     Equals,
+    Colon,
     Newline,
     Comma,
 }
@@ -55,6 +56,10 @@ fn collapse_tokens(
                     ret.push((begin, TokenS2::Equals, end));
                     State::Free
                 }
+                Token::Colon => {
+                    ret.push((begin, TokenS2::Colon, end));
+                    State::Free
+                }
                 Token::Newline => {
                     ret.push((begin, TokenS2::Newline, end));
                     State::Free
@@ -88,6 +93,11 @@ fn collapse_tokens(
                     ret.push((begin, TokenS2::Equals, end));
                     State::Free
                 }
+                Token::Colon => {
+                    ret.push((b, TokenS2::Text, e));
+                    ret.push((begin, TokenS2::Colon, end));
+                    State::Free
+                }
                 Token::Newline => {
                     ret.push((b, TokenS2::Text, e));
                     ret.push((begin, TokenS2::Newline, end));
@@ -146,6 +156,16 @@ fn parse_structure<'s>(tokens: &mut Tokens, string: &'s str) -> Result<Struct<'s
                 tokens.consume(2);
                 res
             }
+            // Members of Rust structs
+            [(b, TokenS2::Text, e), (_, TokenS2::Colon, _), ..]
+                if string[b..e]
+                    .chars()
+                    .all(|c| c.is_alphanumeric() || c == '_') =>
+            {
+                let res = Some((b, e));
+                tokens.consume(2);
+                res
+            }
             _ => None,
         };
         let val = parse_value(
@@ -156,6 +176,7 @@ fn parse_structure<'s>(tokens: &mut Tokens, string: &'s str) -> Result<Struct<'s
             } else {
                 ValueContext::SingleLineStruct
             },
+            key.is_some(),
         );
         if let Some((b, e)) = key {
             kv.push((&string[b..e], val));
@@ -174,7 +195,7 @@ fn parse_structure<'s>(tokens: &mut Tokens, string: &'s str) -> Result<Struct<'s
                 tokens.consume(if multiline { 2 } else { 1 });
                 return Ok(match (kv.is_empty(), single.len()) {
                     (true, 0) => Struct::Array(Vec::new()),
-                    (true, _) => Struct::Array(single),
+                    (true, _) => Struct::Array(array_elements(single)),
                     (false, 0) => Struct::Map(kv),
                     (false, 1) => {
                         kv.push((super::ANON_KEY, single.drain(..).next().unwrap()));
@@ -193,6 +214,238 @@ fn parse_structure<'s>(tokens: &mut Tokens, string: &'s str) -> Result<Struct<'s
     }
 }
 
+// gdb abbreviates runs of equal elements (`0 <repeats 15 times>`) and stops printing elements at
+// the `print elements` limit (`1, 2...`). Both are only recognized as a text suffix, which makes
+// the element (even a struct) a leaf that is parsed again without the suffix.
+fn array_elements(items: Vec<Node>) -> Vec<Node> {
+    let num_items = items.len();
+    let mut elements = Vec::with_capacity(num_items);
+    for (i, item) in items.into_iter().enumerate() {
+        // Runs of chars (`'a' <repeats 20 times>`) are part of a string.
+        let text = match item {
+            Node::Leaf(text) => text,
+            Node::String(text) if text.starts_with('"') => text,
+            item => {
+                elements.push(item);
+                continue;
+            }
+        };
+        // Strings that are cut off also end in `...`.
+        let (text, truncated) = match text.strip_suffix("...") {
+            Some(rest) if i + 1 == num_items && !rest.is_empty() && !rest.ends_with('"') => {
+                (rest.trim_end(), true)
+            }
+            _ => (text, false),
+        };
+        elements.push(match repeats(text) {
+            Some((element, count)) => Node::Repeated(Box::new(reparse(element)), count),
+            None if truncated => reparse(text),
+            None => item,
+        });
+        if truncated {
+            elements.push(Node::Truncated);
+        }
+    }
+    elements
+}
+
+// Split `<element> <repeats N times>`.
+fn repeats(text: &str) -> Option<(&str, usize)> {
+    let rest = text.strip_suffix(" times>")?;
+    let begin = rest.rfind(" <repeats ")?;
+    let count = rest[begin + " <repeats ".len()..].parse().ok()?;
+    Some((rest[..begin].trim_end(), count))
+}
+
+fn reparse(text: &str) -> Node<'_> {
+    parse(Lexer::new(text), text).unwrap_or(Node::Leaf(text))
+}
+
+// The characters of the text that are not part of string or char literals with their bracket depth
+// (that of the surrounding text for brackets themselves).
+fn unquoted(text: &str) -> Vec<(usize, char, usize)> {
+    let mut out = Vec::new();
+    let mut depth = 0usize;
+    let mut quote = None;
+    let mut escaped = false;
+    let mut prev = ' ';
+    for (i, c) in text.char_indices() {
+        match quote {
+            Some(_) if escaped => escaped = false,
+            Some(_) if c == '\\' => escaped = true,
+            Some(q) if c == q => quote = None,
+            Some(_) => {}
+            None => match c {
+                '"' => quote = Some(c),
+                '\'' if prev.is_whitespace() || "({[<,=".contains(prev) => quote = Some(c),
+                '(' | '[' | '{' | '<' => {
+                    out.push((i, c, depth));
+                    depth += 1;
+                }
+                ')' | ']' | '}' | '>' => {
+                    depth = depth.saturating_sub(1);
+                    out.push((i, c, depth));
+                }
+                c => out.push((i, c, depth)),
+            },
+        }
+        prev = c;
+    }
+    out
+}
+
+// The position of the bracket that closes the one at the beginning of the text.
+fn closing_bracket(text: &str) -> Option<usize> {
+    let close = match text.chars().next()? {
+        '(' => ')',
+        '[' => ']',
+        '{' => '}',
+        '<' => '>',
+        _ => return None,
+    };
+    unquoted(text)
+        .into_iter()
+        .skip(1)
+        .find(|&(_, c, depth)| c == close && depth == 0)
+        .map(|(i, _, _)| i)
+}
+
+// Split at the commas outside of literals and brackets.
+fn split_top_level(text: &str) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut begin = 0;
+    for (i, c, depth) in unquoted(text) {
+        if c == ',' && depth == 0 {
+            parts.push(text[begin..i].trim());
+            begin = i + 1;
+        }
+    }
+    let last = text[begin..].trim();
+    if !last.is_empty() || !parts.is_empty() {
+        parts.push(last);
+    }
+    parts
+}
+
+fn is_literal(text: &str) -> bool {
+    // Prefixes of wide (`L"a"`) and Rust byte strings (`b"a"`)
+    let text = text.trim_start_matches(['L', 'u', 'U', '8', 'b']);
+    text.starts_with('"') || text.starts_with('\'')
+}
+
+// `(int &) @0x7ffe: 5` or `@0x7ffe: {a = 1}`: The type, the address and the printed value (which
+// is empty for structs).
+fn reference(text: &str) -> Option<(Option<&str>, &str, &str)> {
+    let (typ, rest) = if text.starts_with('(') {
+        let end = closing_bracket(text)?;
+        (Some(&text[1..end]), text[end + 1..].trim_start())
+    } else {
+        (None, text)
+    };
+    let rest = rest.strip_prefix('@')?;
+    let (address, value) = rest.split_once(':')?;
+    if !address.starts_with("0x") {
+        return None;
+    }
+    Some((typ, address, value.trim_start()))
+}
+
+// `{int (int)} 0x401126 <square(int)>`
+fn function(text: &str) -> Option<Node<'_>> {
+    if !text.starts_with('{') {
+        return None;
+    }
+    let end = closing_bracket(text)?;
+    let rest = text[end + 1..].trim_start();
+    let (address, symbol) = match rest.split_once(' ') {
+        Some((address, symbol)) => (address, Some(symbol.trim())),
+        None => (rest, None),
+    };
+    if !address.starts_with("0x") {
+        return None;
+    }
+    let symbol = match symbol {
+        Some(s) => Some(s.strip_prefix('<')?.strip_suffix('>')?),
+        None => None,
+    };
+    Some(Node::Function {
+        signature: &text[1..end],
+        address,
+        symbol,
+    })
+}
+
+// `core::option::Option<i32>::Some(5)` or `(1, 2.5)`: The path (if any) and the elements.
+fn tuple(text: &str) -> Option<(Option<&str>, Vec<&str>)> {
+    if !text.ends_with(')') {
+        return None;
+    }
+    let (open, _, _) = unquoted(text)
+        .into_iter()
+        .find(|&(_, c, depth)| c == '(' && depth == 0)?;
+    if closing_bracket(&text[open..])? != text.len() - open - 1 {
+        return None;
+    }
+    let path = &text[..open];
+    let is_path = path.starts_with(|c: char| c.is_alphabetic() || c == '_')
+        && !path.ends_with(char::is_whitespace)
+        && !path.contains(['"', '\'', '@']);
+    if !path.is_empty() && !is_path {
+        return None;
+    }
+    let path = if path.is_empty() { None } else { Some(path) };
+    Some((path, split_top_level(&text[open + 1..text.len() - 1])))
+}
+
+// Values without braces are classified by their text.
+fn leaf(text: &str) -> Node<'_> {
+    if let Some((typ, address, value)) = reference(text).filter(|r| !r.2.is_empty()) {
+        return Node::Reference(typ, address, Box::new(reparse(value)));
+    }
+    if let Some(function) = function(text) {
+        return function;
+    }
+    if is_literal(text) {
+        return Node::String(text);
+    }
+    if text.starts_with('<') && closing_bracket(text) == Some(text.len() - 1) {
+        return Node::Unavailable(&text[1..text.len() - 1]);
+    }
+    if let Some((code, literal)) = text.split_once(' ') {
+        if super::is_number(code) && is_literal(literal) && literal.ends_with('\'') {
+            return Node::Char(code, literal);
+        }
+    }
+    if let Some((path, items)) = tuple(text) {
+        return Node::Tuple(path, items.into_iter().map(reparse).collect());
+    }
+    Node::Leaf(text)
+}
+
+// Structs with a reference as their description (`@0x7ffe: {a = 1}`) are referenced.
+fn structure<'s>(description: Option<&'s str>, strct: Struct<'s>) -> Node<'s> {
+    let (reference, description) = match description.and_then(reference) {
+        Some((typ, address, _)) => (Some((typ, address)), None),
+        None => (None, description),
+    };
+    let node = match strct {
+        Struct::Map(v) => Node::Map(description, v),
+        Struct::Array(v) => Node::Array(description, v),
+    };
+    match reference {
+        Some((typ, address)) => Node::Reference(typ, address, Box::new(node)),
+        None => node,
+    }
+}
+
+// Char arrays that are printed in segments (`'a' <repeats 20 times>, "bc"`) contain commas. In
+// arrays, this is indistinguishable from arrays of strings, so only values of members are joined.
+fn continues_string(current: &str, next: &str) -> bool {
+    is_literal(current)
+        && is_literal(next)
+        && (current.contains("<repeats ") || next.contains("<repeats "))
+}
+
 struct Tokens<'a> {
     tokens: &'a [(usize, TokenS2, usize)],
 }
@@ -213,7 +466,12 @@ enum ValueContext {
 pub type Error = LexicalError;
 
 // TODO: Use or-patterns starting from 1.53?
-fn parse_value<'s>(tokens: &mut Tokens, string: &'s str, context: ValueContext) -> Node<'s> {
+fn parse_value<'s>(
+    tokens: &mut Tokens,
+    string: &'s str,
+    context: ValueContext,
+    member: bool,
+) -> Node<'s> {
     let mut text_begin = None;
     let mut text_end = 0;
     let update_range =
@@ -223,6 +481,20 @@ fn parse_value<'s>(tokens: &mut Tokens, string: &'s str, context: ValueContext)
         };
     let mut current_struct = None;
     loop {
+        if let (
+            ValueContext::SingleLineStruct,
+            &[(_, TokenS2::Comma, e), (next_begin, TokenS2::Text, next_end), ..],
+        ) = (context, tokens.tokens)
+        {
+            if let (true, None, Some(text_begin)) = (member, &current_struct, text_begin) {
+                let current = &string[text_begin..text_end];
+                if continues_string(current, &string[next_begin..next_end]) {
+                    text_end = text_end.max(e);
+                    tokens.consume(1);
+                    continue;
+                }
+            }
+        }
         match (context, tokens.tokens) {
             (ValueContext::SingleLineStruct, &[(_, TokenS2::Comma, _), ..])
             | (
@@ -240,13 +512,13 @@ fn parse_value<'s>(tokens: &mut Tokens, string: &'s str, context: ValueContext)
                 let text = &string[text_begin..text_end];
                 let description = if text.is_empty() { None } else { Some(text) };
                 return match current_struct {
-                    None => Node::Leaf(text),
-                    Some(Struct::Map(v)) => Node::Map(description, v),
-                    Some(Struct::Array(v)) => Node::Array(description, v),
+                    None => leaf(text),
+                    Some(strct) => structure(description, strct),
                 };
             }
             (_, &[(b, TokenS2::Text, e), ..])
             | (_, &[(b, TokenS2::Equals, e), ..])
+            | (_, &[(b, TokenS2::Colon, e), ..])
             | (ValueContext::MultiLineStruct, &[(b, TokenS2::Comma, e), ..])
             | (ValueContext::Free, &[(b, TokenS2::Comma, e), ..])
             | (ValueContext::Free, &[(b, TokenS2::RBrace, e), ..]) => {
@@ -277,7 +549,7 @@ pub fn parse<'s>(lexer: Lexer, string: &'s str) -> Result<Node<'s>, Error> {
     let mut tokens = Tokens {
         tokens: &tokens[..],
     };
-    Ok(parse_value(&mut tokens, string, ValueContext::Free))
+    Ok(parse_value(&mut tokens, string, ValueContext::Free, false))
 }
 
 #[cfg(test)]
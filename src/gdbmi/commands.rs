@@ -1,4 +1,4 @@
-use std::ffi::OsString;
+use std::ffi::{OsStr, OsString};
 use std::fmt;
 use std::io::{Error, Write};
 use std::path::Path;
@@ -17,19 +17,38 @@ pub enum DisassembleMode {
     MixedSourceAndDisassemblyWithRawOpcodes = 3, // deprecated and 5 would be preferred, same as above
 }
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum WatchMode {
     Read,
     Write,
     Access,
 }
 
+impl WatchMode {
+    pub fn name(self) -> &'static str {
+        match self {
+            WatchMode::Write => "write",
+            WatchMode::Read => "read",
+            WatchMode::Access => "access",
+        }
+    }
+}
+
 pub enum BreakPointLocation<'a> {
     Address(usize),
     Function(&'a Path, &'a str),
     Line(&'a Path, usize),
+    // Any location specification gdb understands, e.g. "main" or "foo.c:12"
+    Spec(&'a str),
 }
 
-#[derive(Copy, Clone, Hash, PartialEq, Eq)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum TargetType {
+    Remote,
+    ExtendedRemote,
+}
+
+#[derive(Copy, Clone, Debug, Hash, PartialEq, Eq)]
 pub struct BreakPointNumber {
     pub major: usize,
     pub minor: Option<usize>,
@@ -107,6 +126,27 @@ impl MiCommand {
         writeln!(sink)?;
         Ok(())
     }
+    // Execute the command in the context of the given thread (instead of the selected one).
+    pub fn in_thread(mut self, thread: u64) -> MiCommand {
+        self.options
+            .splice(0..0, ["--thread".into(), thread.to_string().into()]);
+        self
+    }
+
+    // Execute the command in the context of the given frame (instead of the selected one).
+    pub fn in_frame(mut self, thread: u64, frame: u64) -> MiCommand {
+        self.options.splice(
+            0..0,
+            [
+                "--thread".into(),
+                thread.to_string().into(),
+                "--frame".into(),
+                frame.to_string().into(),
+            ],
+        );
+        self
+    }
+
     pub fn interpreter_exec<S1: Into<OsString>, S2: Into<OsString>>(
         interpreter: S1,
         command: S2,
@@ -200,6 +240,7 @@ impl MiCommand {
                     //OsString::from(format!("{}", line_number)),
                     //],
                 }
+                BreakPointLocation::Spec(spec) => vec![OsString::from(spec)],
             },
             parameters: Vec::new(),
         }
@@ -222,6 +263,30 @@ impl MiCommand {
         }
     }
 
+    pub fn enable_breakpoints<I: Iterator<Item = BreakPointNumber>>(
+        breakpoint_numbers: I,
+    ) -> MiCommand {
+        MiCommand {
+            operation: "break-enable",
+            options: breakpoint_numbers
+                .map(|n| n.to_string().into())
+                .collect::<Vec<OsString>>(),
+            parameters: Vec::new(),
+        }
+    }
+
+    pub fn disable_breakpoints<I: Iterator<Item = BreakPointNumber>>(
+        breakpoint_numbers: I,
+    ) -> MiCommand {
+        MiCommand {
+            operation: "break-disable",
+            options: breakpoint_numbers
+                .map(|n| n.to_string().into())
+                .collect::<Vec<OsString>>(),
+            parameters: Vec::new(),
+        }
+    }
+
     pub fn insert_watchpoing(expression: &str, mode: WatchMode) -> MiCommand {
         let options = match mode {
             WatchMode::Write => Vec::new(),
@@ -235,6 +300,37 @@ impl MiCommand {
         }
     }
 
+    pub fn target_select(target_type: TargetType, parameters: &str) -> MiCommand {
+        MiCommand {
+            operation: "target-select",
+            options: vec![
+                match target_type {
+                    TargetType::Remote => "remote",
+                    TargetType::ExtendedRemote => "extended-remote",
+                }
+                .into(),
+                parameters.into(),
+            ],
+            parameters: Vec::new(),
+        }
+    }
+
+    pub fn inferior_tty_set(thread_group: &str, tty: &OsStr) -> MiCommand {
+        MiCommand {
+            operation: "inferior-tty-set",
+            options: vec!["--thread-group".into(), thread_group.into(), tty.into()],
+            parameters: Vec::new(),
+        }
+    }
+
+    pub fn environment_cd(dir: &Path) -> MiCommand {
+        MiCommand {
+            operation: "environment-cd",
+            options: vec![dir.into()],
+            parameters: Vec::new(),
+        }
+    }
+
     pub fn environment_pwd() -> MiCommand {
         MiCommand {
             operation: "environment-pwd",
@@ -243,6 +339,46 @@ impl MiCommand {
         }
     }
 
+    pub fn exec_run() -> MiCommand {
+        MiCommand {
+            operation: "exec-run",
+            options: Vec::new(),
+            parameters: Vec::new(),
+        }
+    }
+
+    pub fn exec_continue() -> MiCommand {
+        MiCommand {
+            operation: "exec-continue",
+            options: Vec::new(),
+            parameters: Vec::new(),
+        }
+    }
+
+    pub fn exec_next() -> MiCommand {
+        MiCommand {
+            operation: "exec-next",
+            options: Vec::new(),
+            parameters: Vec::new(),
+        }
+    }
+
+    pub fn exec_step() -> MiCommand {
+        MiCommand {
+            operation: "exec-step",
+            options: Vec::new(),
+            parameters: Vec::new(),
+        }
+    }
+
+    pub fn exec_finish() -> MiCommand {
+        MiCommand {
+            operation: "exec-finish",
+            options: Vec::new(),
+            parameters: Vec::new(),
+        }
+    }
+
     // Be aware: This does not seem to always interrupt execution.
     // Use gdb.interrupt_execution instead.
     pub fn exec_interrupt() -> MiCommand {
@@ -300,6 +436,14 @@ impl MiCommand {
         }
     }
 
+    pub fn stack_list_frames() -> MiCommand {
+        MiCommand {
+            operation: "stack-list-frames",
+            options: Vec::new(),
+            parameters: Vec::new(),
+        }
+    }
+
     pub fn stack_list_variables(
         thread_number: Option<u64>,
         frame_number: Option<u64>,
@@ -386,6 +530,32 @@ impl MiCommand {
             ],
         }
     }
+    // Floating var objects are evaluated in the selected frame whenever they are updated.
+    pub fn var_create_floating(expression: &str) -> MiCommand {
+        MiCommand {
+            operation: "var-create",
+            options: vec![],
+            parameters: vec![
+                "\"-\"".into(),
+                "\"@\"".into(),
+                escape_command(expression).into(),
+            ],
+        }
+    }
+    pub fn var_update(name: impl Into<OsString>) -> MiCommand {
+        MiCommand {
+            operation: "var-update",
+            options: vec![],
+            parameters: vec!["--all-values".into(), name.into()],
+        }
+    }
+    pub fn enable_pretty_printing() -> MiCommand {
+        MiCommand {
+            operation: "enable-pretty-printing",
+            options: Vec::new(),
+            parameters: Vec::new(),
+        }
+    }
     pub fn var_delete(name: impl Into<OsString>, delete_children: bool) -> MiCommand {
         let mut parameters = vec![];
         if delete_children {
@@ -398,6 +568,13 @@ impl MiCommand {
             parameters,
         }
     }
+    pub fn var_info_path_expression(name: impl Into<OsString>) -> MiCommand {
+        MiCommand {
+            operation: "var-info-path-expression",
+            options: Vec::new(),
+            parameters: vec![name.into()],
+        }
+    }
     pub fn var_list_children(
         name: impl Into<OsString>,
         print_values: bool,
@@ -1,23 +1,52 @@
 pub mod commands;
 pub mod output;
+pub mod transcript;
 
 use log::info;
 use std::ffi::OsString;
+use std::io::{Read, Write};
 use std::path::Path;
 use std::path::PathBuf;
-use std::process::{Child, ChildStdin, Command, Stdio};
+use std::process::{Child, Command, ExitStatus, Stdio};
 use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::{mpsc, Arc};
+use std::sync::{mpsc, Arc, Mutex};
 use std::thread;
+use std::time::Duration;
+use transcript::{RecordingReader, RecordingWriter, SharedRecorder, Transcript};
 
-type Token = u64;
+pub type Token = u64;
+
+// The gdb process (or something pretending to be one, see transcript::replay) that we talk to.
+pub trait Backend {
+    fn interrupt(&self) -> Result<(), nix::Error>;
+    fn try_wait(&mut self) -> std::io::Result<Option<ExitStatus>>;
+}
+
+struct ProcessBackend(Child);
+
+impl Backend for ProcessBackend {
+    fn interrupt(&self) -> Result<(), nix::Error> {
+        use nix::sys::signal;
+        use nix::unistd::Pid;
+        signal::kill(Pid::from_raw(self.0.id() as i32), signal::SIGINT)
+    }
+    fn try_wait(&mut self) -> std::io::Result<Option<ExitStatus>> {
+        self.0.try_wait()
+    }
+}
 
 #[allow(clippy::upper_case_acronyms)]
 pub struct GDB {
-    pub process: Child,
-    stdin: ChildStdin,
+    backend: Box<dyn Backend>,
+    stdin: Box<dyn Write + Send>,
+    recorder: Option<SharedRecorder>,
     is_running: Arc<AtomicBool>,
     result_output: mpsc::Receiver<output::ResultRecord>,
+    async_tokens: output::AsyncTokens,
+    // Console output is collected here while it is Some (see execute_capturing_console).
+    console_capture: Arc<Mutex<Option<String>>>,
+    // Notified (instead of the OutOfBandRecordSink) about the next stop (see interrupt_and_wait).
+    stop_waiter: output::StopWaiter,
     current_command_token: Token,
     binary_path: PathBuf,
     init_options: Vec<OsString>,
@@ -26,14 +55,27 @@ pub struct GDB {
 
 pub trait OutOfBandRecordSink: std::marker::Send {
     fn send(&self, record: output::OutOfBandRecord);
+    // Result records of commands issued via GDB::execute_async end up here instead of being
+    // returned from a (blocking) call to GDB::execute.
+    fn send_async_result(&self, record: output::ResultRecord);
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Interruption {
+    Interrupted,
+    // The program stopped for another reason (e.g., a breakpoint) before the interrupt arrived.
+    StoppedOtherwise,
+    TimedOut,
 }
 
 #[derive(Clone, Debug, PartialEq)]
 pub enum ExecuteError {
     Busy,
     Quit,
+    Timeout,
 }
 
+#[derive(Clone)]
 pub struct GDBBuilder {
     gdb_path: PathBuf,
     opt_nh: bool,
@@ -50,6 +92,9 @@ pub struct GDBBuilder {
     opt_program: Option<PathBuf>,
     opt_tty: Option<PathBuf>,
     rr_args: Option<(PathBuf, Vec<OsString>)>,
+    record_mi: Option<PathBuf>,
+    record_mi_append: bool,
+    remote_target: Option<(commands::TargetType, String)>,
 }
 impl GDBBuilder {
     pub fn new(gdb: PathBuf) -> Self {
@@ -69,6 +114,9 @@ impl GDBBuilder {
             opt_program: None,
             opt_tty: None,
             rr_args: None,
+            record_mi: None,
+            record_mi_append: false,
+            remote_target: None,
         }
     }
 
@@ -128,6 +176,26 @@ impl GDBBuilder {
         self.opt_tty = Some(tty);
         self
     }
+    pub fn record_mi(mut self, transcript_file: PathBuf) -> Self {
+        self.record_mi = Some(transcript_file);
+        self
+    }
+    // Append to the transcript (if any) instead of overwriting it, e.g., when restarting gdb.
+    pub fn append_record(mut self) -> Self {
+        self.record_mi_append = true;
+        self
+    }
+    // The target is not selected by try_spawn, since connecting may take a while. Instead, the
+    // user of the builder is responsible for issuing -target-select (see get_remote_target).
+    pub fn remote_target(mut self, target_type: commands::TargetType, target: String) -> Self {
+        self.remote_target = Some((target_type, target));
+        self
+    }
+    pub fn get_remote_target(&self) -> Option<(commands::TargetType, &str)> {
+        self.remote_target
+            .as_ref()
+            .map(|(target_type, target)| (*target_type, target.as_str()))
+    }
     pub fn try_spawn<S>(self, oob_sink: S) -> Result<GDB, std::io::Error>
     where
         S: OutOfBandRecordSink + 'static,
@@ -226,34 +294,158 @@ impl GDBBuilder {
 
         let stdin = child.stdin.take().expect("take stdin");
         let stdout = child.stdout.take().expect("take stdout");
+        let backend = Box::new(ProcessBackend(child));
+        if let Some(transcript_file) = self.record_mi {
+            let recorder = if self.record_mi_append {
+                transcript::Recorder::append(&transcript_file)?
+            } else {
+                transcript::Recorder::create(&transcript_file)?
+            };
+            GDB::start(
+                backend,
+                RecordingWriter::new(stdin, recorder.clone()),
+                RecordingReader::new(stdout, recorder.clone()),
+                oob_sink,
+                Some(recorder),
+                self.gdb_path,
+                init_options,
+            )
+        } else {
+            GDB::start(
+                backend,
+                stdin,
+                stdout,
+                oob_sink,
+                None,
+                self.gdb_path,
+                init_options,
+            )
+        }
+    }
+}
+
+impl GDB {
+    // Run against a fake gdb that replays the given transcript.
+    pub fn replay<S>(
+        transcript: Transcript,
+        oob_sink: S,
+        binary_path: PathBuf,
+    ) -> Result<GDB, std::io::Error>
+    where
+        S: OutOfBandRecordSink + 'static,
+    {
+        let (backend, stdin, stdout) = transcript::replay(transcript);
+        GDB::start(
+            Box::new(backend),
+            stdin,
+            stdout,
+            oob_sink,
+            None,
+            binary_path,
+            Vec::new(),
+        )
+    }
+
+    fn start<S, R, W>(
+        backend: Box<dyn Backend>,
+        stdin: W,
+        stdout: R,
+        oob_sink: S,
+        recorder: Option<SharedRecorder>,
+        binary_path: PathBuf,
+        init_options: Vec<OsString>,
+    ) -> Result<GDB, std::io::Error>
+    where
+        S: OutOfBandRecordSink + 'static,
+        R: Read + Send + 'static,
+        W: Write + Send + 'static,
+    {
         let is_running = Arc::new(AtomicBool::new(false));
         let is_running_for_thread = is_running.clone();
         let (result_input, result_output) = mpsc::channel();
+        let async_tokens = output::AsyncTokens::default();
+        let async_tokens_for_thread = async_tokens.clone();
+        let console_capture = Arc::new(Mutex::new(None));
+        let console_capture_for_thread = console_capture.clone();
+        let stop_waiter = output::StopWaiter::default();
+        let stop_waiter_for_thread = stop_waiter.clone();
         /*let outputThread = */
         thread::Builder::new()
             .name("gdbmi parser".to_owned())
             .spawn(move || {
-                output::process_output(stdout, result_input, oob_sink, is_running_for_thread);
+                output::process_output(
+                    stdout,
+                    result_input,
+                    oob_sink,
+                    is_running_for_thread,
+                    async_tokens_for_thread,
+                    console_capture_for_thread,
+                    stop_waiter_for_thread,
+                );
             })?;
         let gdb = GDB {
-            process: child,
-            stdin,
+            backend,
+            stdin: Box::new(stdin),
+            recorder,
             is_running,
             result_output,
+            async_tokens,
+            console_capture,
+            stop_waiter,
             current_command_token: 0,
-            binary_path: self.gdb_path,
+            binary_path,
             init_options,
             //outputThread: outputThread,
         };
         Ok(gdb)
     }
-}
 
-impl GDB {
     pub fn interrupt_execution(&self) -> Result<(), nix::Error> {
-        use nix::sys::signal;
-        use nix::unistd::Pid;
-        signal::kill(Pid::from_raw(self.process.id() as i32), signal::SIGINT)
+        if let Some(recorder) = &self.recorder {
+            recorder.lock().unwrap().record_interrupt();
+        }
+        self.backend.interrupt()
+    }
+
+    // Interrupt the program and wait (at most `timeout`) until it stops. If it stopped because of
+    // the interrupt, the stop is not passed to the OutOfBandRecordSink.
+    pub fn interrupt_and_wait(&mut self, timeout: Duration) -> Result<Interruption, nix::Error> {
+        let (sender, receiver) = mpsc::channel();
+        *self.stop_waiter.lock().unwrap() = Some(sender);
+        // The program may have stopped on its own in the meantime.
+        if !self.is_running() {
+            self.stop_waiter.lock().unwrap().take();
+            return Ok(Interruption::StoppedOtherwise);
+        }
+        if let Err(e) = self.interrupt_execution() {
+            self.stop_waiter.lock().unwrap().take();
+            return Err(e);
+        }
+        let interrupted = match receiver.recv_timeout(timeout) {
+            Ok(interrupted) => interrupted,
+            Err(_) => {
+                // Later stops are reported as usual. The stop may have arrived right before, though.
+                self.stop_waiter.lock().unwrap().take();
+                match receiver.try_recv() {
+                    Ok(interrupted) => interrupted,
+                    Err(_) => return Ok(Interruption::TimedOut),
+                }
+            }
+        };
+        Ok(if interrupted {
+            Interruption::Interrupted
+        } else {
+            Interruption::StoppedOtherwise
+        })
+    }
+
+    pub fn try_wait(&mut self) -> std::io::Result<Option<ExitStatus>> {
+        self.backend.try_wait()
+    }
+
+    // Continue numbering commands where the given instance left off.
+    pub fn continue_tokens_of(&mut self, previous: &GDB) {
+        self.current_command_token = previous.current_command_token;
     }
 
     pub fn binary_path(&self) -> &Path {
@@ -287,10 +479,9 @@ impl GDB {
             .expect("write interpreter command");
 
         info!("Writing msg {}", String::from_utf8_lossy(&bytes),);
-        command
-            .borrow()
-            .write_interpreter_string(&mut self.stdin, command_token)
-            .expect("write interpreter command");
+        if self.stdin.write_all(&bytes).is_err() {
+            return Err(ExecuteError::Quit);
+        }
         loop {
             match self.result_output.recv() {
                 Ok(record) => match record.token {
@@ -305,17 +496,81 @@ impl GDB {
         }
     }
 
-    pub fn execute_later<C: std::borrow::Borrow<commands::MiCommand>>(&mut self, command: C) {
+    // Like execute, but also return the console output that gdb printed before the result.
+    pub fn execute_capturing_console<C: std::borrow::Borrow<commands::MiCommand>>(
+        &mut self,
+        command: C,
+    ) -> Result<(output::ResultRecord, String), ExecuteError> {
+        *self.console_capture.lock().unwrap() = Some(String::new());
+        let result = self.execute(command);
+        let console = self
+            .console_capture
+            .lock()
+            .unwrap()
+            .take()
+            .unwrap_or_default();
+        result.map(|record| (record, console))
+    }
+
+    // Write the command to gdb without waiting for the result. The result record will be passed to
+    // the OutOfBandRecordSink (see send_async_result) and can be identified via the returned token.
+    pub fn execute_async<C: std::borrow::Borrow<commands::MiCommand>>(
+        &mut self,
+        command: C,
+    ) -> Result<Token, ExecuteError> {
+        self.write_async(command.borrow(), false)
+    }
+
+    // Like execute_async, but the console output that gdb prints for the command is added to the
+    // result (as field "console") instead of being passed to the OutOfBandRecordSink.
+    pub fn execute_async_capturing_console<C: std::borrow::Borrow<commands::MiCommand>>(
+        &mut self,
+        command: C,
+    ) -> Result<Token, ExecuteError> {
+        self.write_async(command.borrow(), true)
+    }
+
+    fn write_async(
+        &mut self,
+        command: &commands::MiCommand,
+        capture_console: bool,
+    ) -> Result<Token, ExecuteError> {
+        if self.is_running() {
+            return Err(ExecuteError::Busy);
+        }
         let command_token = self.get_usable_token();
+        self.async_tokens
+            .lock()
+            .unwrap()
+            .insert(command_token, capture_console);
+
+        let mut bytes = Vec::new();
         command
+            .write_interpreter_string(&mut bytes, command_token)
+            .expect("write interpreter command");
+        info!("Writing async msg {}", String::from_utf8_lossy(&bytes),);
+        if self.stdin.write_all(&bytes).is_err() {
+            self.async_tokens.lock().unwrap().remove(&command_token);
+            return Err(ExecuteError::Quit);
+        }
+        Ok(command_token)
+    }
+
+    pub fn execute_later<C: std::borrow::Borrow<commands::MiCommand>>(&mut self, command: C) {
+        let command_token = self.get_usable_token();
+        // There is no result to wait for if gdb is gone already.
+        if command
             .borrow()
             .write_interpreter_string(&mut self.stdin, command_token)
-            .expect("write interpreter command");
-        let _ = self.result_output.recv();
+            .is_ok()
+        {
+            let _ = self.result_output.recv();
+        }
     }
 
     pub fn is_session_active(&mut self) -> Result<bool, ExecuteError> {
         let res = self.execute(commands::MiCommand::thread_info(None))?;
-        Ok(!res.results["threads"].is_empty())
+        // Better ask once too often than to kill a session because of output we do not understand.
+        Ok(output::ThreadListEntry::decode_list(&res.results).map_or(true, |t| !t.is_empty()))
     }
 }
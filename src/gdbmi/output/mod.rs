@@ -0,0 +1,237 @@
+use super::Token;
+pub use json::object::Object;
+pub use json::JsonValue;
+
+use log::{error, info};
+
+mod parser;
+mod records;
+pub use self::records::{
+    field, opt_str_field, parse_field, str_field, Address, AsmInstruction, BreakPointInfo,
+    DecodeError, Frame, SrcAndAsmLine, StopEvent, StopReason, ThreadGroup, ThreadGroupEvent,
+    ThreadInfo, ThreadListEntry, ThreadSelectedEvent, VarObjectChange, VarObjectInfo,
+    VarObjectScope, Variable,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResultClass {
+    Done,
+    Running,
+    Connected,
+    Error,
+    Exit,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BreakPointEvent {
+    Created,
+    Deleted,
+    Modified,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ThreadEvent {
+    Created,
+    GroupAdded,
+    GroupRemoved,
+    GroupStarted,
+    Exited,
+    GroupExited,
+    Selected,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AsyncClass {
+    Running,
+    Stopped,
+    CmdParamChanged,
+    LibraryLoaded,
+    Thread(ThreadEvent),
+    BreakPoint(BreakPointEvent),
+    RecordStarted,
+    RecordStopped,
+    Other(String), //?
+}
+
+#[derive(Debug, PartialEq)]
+pub enum AsyncKind {
+    Exec,
+    Status,
+    Notify,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum StreamKind {
+    Console,
+    Target,
+    Log,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct ResultRecord {
+    pub(crate) token: Option<Token>,
+    pub class: ResultClass,
+    pub results: Object,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum OutOfBandRecord {
+    AsyncRecord {
+        token: Option<Token>,
+        kind: AsyncKind,
+        class: AsyncClass,
+        results: Object,
+    },
+    StreamRecord {
+        kind: StreamKind,
+        data: String,
+    },
+}
+
+#[derive(Debug, PartialEq)]
+enum Output {
+    Result(ResultRecord),
+    OutOfBand(OutOfBandRecord),
+    GDBLine,
+}
+
+use crate::OutOfBandRecordSink;
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Read};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::Sender;
+use std::sync::{Arc, Mutex};
+
+// Tokens of the pending async commands and whether their console output is captured (see
+// GDB::execute_async_capturing_console).
+pub type AsyncTokens = Arc<Mutex<HashMap<Token, bool>>>;
+
+// Receives whether the next stop was caused by an interrupt, see GDB::interrupt_and_wait.
+pub type StopWaiter = Arc<Mutex<Option<Sender<bool>>>>;
+
+fn is_interrupt(results: &Object) -> bool {
+    matches!(
+        StopEvent::decode(results),
+        Ok(StopEvent {
+            reason: Some(StopReason::SignalReceived { name: Some(name), .. }),
+            ..
+        }) if name == "SIGINT"
+    )
+}
+
+pub fn process_output<T: Read, S: OutOfBandRecordSink>(
+    output: T,
+    result_pipe: Sender<ResultRecord>,
+    out_of_band_pipe: S,
+    is_running: Arc<AtomicBool>,
+    async_tokens: AsyncTokens,
+    console_capture: Arc<Mutex<Option<String>>>,
+    stop_waiter: StopWaiter,
+) {
+    let mut reader = BufReader::new(output);
+    // Console output is held back while a command that captures it is pending, because gdb prints
+    // it before the result that tells us which command it belongs to.
+    let mut held_console = String::new();
+    let release_console = |held: &mut String| {
+        if !held.is_empty() {
+            out_of_band_pipe.send(OutOfBandRecord::StreamRecord {
+                kind: StreamKind::Console,
+                data: std::mem::take(held),
+            });
+        }
+    };
+
+    loop {
+        let mut buffer = Vec::new();
+        match reader.read_until(b'\n', &mut buffer) {
+            Ok(0) => {
+                return;
+            }
+            Ok(_) => {
+                let line = String::from_utf8_lossy(&buffer);
+                info!("{}", line.trim_end());
+                if line.trim().is_empty() {
+                    continue;
+                }
+
+                let parse_result = match parser::parse_line(&buffer) {
+                    Ok(r) => r,
+                    Err(e) => {
+                        // This may be output that is not meant for us, e.g., from the inferior or
+                        // a gdb extension, so we show it to the user.
+                        error!("{}", e);
+                        out_of_band_pipe.send(OutOfBandRecord::StreamRecord {
+                            kind: StreamKind::Target,
+                            data: line.into_owned(),
+                        });
+                        continue;
+                    }
+                };
+                match parse_result {
+                    Output::Result(mut record) => {
+                        match record.class {
+                            ResultClass::Running => is_running.store(true, Ordering::SeqCst),
+                            //Apparently sometimes gdb first claims to be running, only to then stop again (without notifying the user)...
+                            ResultClass::Error => is_running.store(false, Ordering::SeqCst),
+                            _ => {}
+                        }
+                        let async_token = record
+                            .token
+                            .and_then(|t| async_tokens.lock().unwrap().remove(&t));
+                        let is_async = async_token.is_some();
+                        let is_capturing = async_token.unwrap_or(false);
+                        if is_capturing {
+                            record
+                                .results
+                                .insert("console", std::mem::take(&mut held_console).into());
+                        } else {
+                            release_console(&mut held_console);
+                        }
+                        if is_async {
+                            out_of_band_pipe.send_async_result(record);
+                        } else {
+                            result_pipe.send(record).expect("send result to pipe");
+                        }
+                    }
+                    Output::OutOfBand(record) => {
+                        if let OutOfBandRecord::AsyncRecord {
+                            class: AsyncClass::Stopped,
+                            results,
+                            ..
+                        } = &record
+                        {
+                            release_console(&mut held_console);
+                            is_running.store(false, Ordering::SeqCst);
+                            if let Some(waiter) = stop_waiter.lock().unwrap().take() {
+                                let interrupted = is_interrupt(results);
+                                let _ = waiter.send(interrupted);
+                                // The stop is an implementation detail of the waiter.
+                                if interrupted {
+                                    continue;
+                                }
+                            }
+                        }
+                        if let OutOfBandRecord::StreamRecord {
+                            kind: StreamKind::Console,
+                            data,
+                        } = &record
+                        {
+                            if let Some(capture) = console_capture.lock().unwrap().as_mut() {
+                                capture.push_str(data);
+                            }
+                            if async_tokens.lock().unwrap().values().any(|c| *c) {
+                                held_console.push_str(data);
+                                continue;
+                            }
+                        }
+                        out_of_band_pipe.send(record);
+                    }
+                    Output::GDBLine => {}
+                }
+            }
+            Err(e) => {
+                panic!("{}", e);
+            }
+        }
+    }
+}
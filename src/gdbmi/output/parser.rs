@@ -0,0 +1,680 @@
+// Parser for the gdb/mi output syntax, see
+// https://sourceware.org/gdb/current/onlinedocs/gdb.html/GDB_002fMI-Output-Syntax.html
+//
+// The parser works on raw bytes, since gdb does not guarantee that the output is valid utf8 (e.g.,
+// for strings in the inferior). Strings are converted lossily after unescaping.
+use super::{
+    AsyncClass, AsyncKind, BreakPointEvent, JsonValue, Object, OutOfBandRecord, Output,
+    ResultClass, ResultRecord, StreamKind, ThreadEvent, Token,
+};
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseError {
+    pub line: String,
+    pub position: usize,
+    pub reason: &'static str,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Failed to parse gdb output ({} at byte {}): {}",
+            self.reason, self.position, self.line
+        )
+    }
+}
+
+struct Parser<'a> {
+    input: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<u8> {
+        self.input.get(self.pos).cloned()
+    }
+
+    fn peek_at(&self, offset: usize) -> Option<u8> {
+        self.input.get(self.pos + offset).cloned()
+    }
+
+    fn next(&mut self) -> Option<u8> {
+        let b = self.peek()?;
+        self.pos += 1;
+        Some(b)
+    }
+
+    fn eat(&mut self, b: u8) -> bool {
+        if self.peek() == Some(b) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, b: u8, reason: &'static str) -> Result<(), ParseError> {
+        if self.eat(b) {
+            Ok(())
+        } else {
+            Err(self.error(reason))
+        }
+    }
+
+    fn error(&self, reason: &'static str) -> ParseError {
+        ParseError {
+            line: String::from_utf8_lossy(self.input).into_owned(),
+            position: self.pos,
+            reason,
+        }
+    }
+
+    fn take_while<F: Fn(u8) -> bool>(&mut self, f: F) -> &'a [u8] {
+        let begin = self.pos;
+        while self.peek().map(&f).unwrap_or(false) {
+            self.pos += 1;
+        }
+        &self.input[begin..self.pos]
+    }
+
+    fn token(&mut self) -> Result<Option<Token>, ParseError> {
+        let begin = self.pos;
+        let digits = self.take_while(|b| b.is_ascii_digit());
+        if digits.is_empty() {
+            return Ok(None);
+        }
+        let mut token: Token = 0;
+        for d in digits {
+            token = token
+                .checked_mul(10)
+                .and_then(|t| t.checked_add((d - b'0') as Token))
+                .ok_or_else(|| {
+                    let mut e = self.error("token too large");
+                    e.position = begin;
+                    e
+                })?;
+        }
+        Ok(Some(token))
+    }
+
+    fn string(&mut self) -> Result<String, ParseError> {
+        self.expect(b'"', "expected string")?;
+        let mut bytes = Vec::new();
+        loop {
+            match self.next() {
+                None => return Err(self.error("unterminated string")),
+                Some(b'"') => break,
+                Some(b'\\') => {
+                    let escaped = match self.next() {
+                        None => return Err(self.error("unterminated escape sequence")),
+                        Some(b'n') => b'\n',
+                        Some(b'r') => b'\r',
+                        Some(b't') => b'\t',
+                        Some(b'a') => 0x07,
+                        Some(b'b') => 0x08,
+                        Some(b'f') => 0x0c,
+                        Some(b'v') => 0x0b,
+                        Some(b'e') => 0x1b,
+                        Some(b'"') => b'"',
+                        Some(b'\'') => b'\'',
+                        Some(b'\\') => b'\\',
+                        Some(b'x') => {
+                            let digits = self.take_while(|b| b.is_ascii_hexdigit());
+                            if digits.is_empty() || digits.len() > 2 {
+                                return Err(self.error("malformed hex escape sequence"));
+                            }
+                            digits
+                                .iter()
+                                .fold(0, |acc, d| acc * 16 + (*d as char).to_digit(16).unwrap())
+                                as u8
+                        }
+                        Some(d @ b'0'..=b'7') => {
+                            // gdb escapes non-printable bytes as (up to) three octal digits.
+                            let mut val = (d - b'0') as u32;
+                            for _ in 0..2 {
+                                match self.peek() {
+                                    Some(d @ b'0'..=b'7') => {
+                                        self.pos += 1;
+                                        val = val * 8 + (d - b'0') as u32;
+                                    }
+                                    _ => break,
+                                }
+                            }
+                            if val > 0xff {
+                                return Err(self.error("octal escape sequence out of range"));
+                            }
+                            val as u8
+                        }
+                        Some(other) => {
+                            // Unknown escape sequences are kept as they are.
+                            bytes.push(b'\\');
+                            other
+                        }
+                    };
+                    bytes.push(escaped);
+                }
+                Some(b) => bytes.push(b),
+            }
+        }
+        Ok(String::from_utf8_lossy(&bytes).into_owned())
+    }
+
+    fn variable(&mut self) -> Result<String, ParseError> {
+        let name = self.take_while(|b| !b"=,{}[]\"".contains(&b));
+        if name.is_empty() {
+            return Err(self.error("expected variable name"));
+        }
+        Ok(String::from_utf8_lossy(name).into_owned())
+    }
+
+    fn value(&mut self) -> Result<JsonValue, ParseError> {
+        match self.peek() {
+            Some(b'"') => Ok(JsonValue::String(self.string()?)),
+            Some(b'{') => {
+                self.pos += 1;
+                let mut obj = Object::new();
+                if !self.eat(b'}') {
+                    loop {
+                        let (name, value) = self.result()?;
+                        // gdb does not emit duplicate names in tuples (as far as we know). If it
+                        // does, the last one wins.
+                        obj.insert(&name, value);
+                        if self.eat(b'}') {
+                            break;
+                        }
+                        self.expect(b',', "expected ',' or '}' in tuple")?;
+                    }
+                }
+                Ok(JsonValue::Object(obj))
+            }
+            Some(b'[') => {
+                self.pos += 1;
+                let mut values = Vec::new();
+                if !self.eat(b']') {
+                    loop {
+                        // Lists either contain values or results, but we only care about the
+                        // values in both cases.
+                        let value = match self.peek() {
+                            Some(b'"') | Some(b'{') | Some(b'[') => self.value()?,
+                            _ => self.result()?.1,
+                        };
+                        values.push(value);
+                        if self.eat(b']') {
+                            break;
+                        }
+                        self.expect(b',', "expected ',' or ']' in list")?;
+                    }
+                }
+                Ok(JsonValue::Array(values))
+            }
+            _ => Err(self.error("expected value")),
+        }
+    }
+
+    fn result(&mut self) -> Result<(String, JsonValue), ParseError> {
+        let name = self.variable()?;
+        self.expect(b'=', "expected '=' after variable name")?;
+        let first = self.value()?;
+
+        // Against its spec, gdb(mi) sometimes emits multiple values for a single result as a
+        // comma separated list, e.g., bkpt={...},{...}.
+        let mut values = Vec::new();
+        while self.peek() == Some(b',')
+            && matches!(self.peek_at(1), Some(b'"') | Some(b'{') | Some(b'['))
+        {
+            self.pos += 1;
+            values.push(self.value()?);
+        }
+        if values.is_empty() {
+            Ok((name, first))
+        } else {
+            values.insert(0, first);
+            Ok((name, JsonValue::Array(values)))
+        }
+    }
+
+    fn results(&mut self) -> Result<Object, ParseError> {
+        let mut obj = Object::new();
+        while self.eat(b',') {
+            if let Some(b'"') | Some(b'{') | Some(b'[') = self.peek() {
+                // Some records (e.g., +download) contain values without a name.
+                let value = self.value()?;
+                obj.insert("", value);
+            } else {
+                let (name, value) = self.result()?;
+                obj.insert(&name, value);
+            }
+        }
+        Ok(obj)
+    }
+
+    fn result_class(&mut self) -> Result<ResultClass, ParseError> {
+        let begin = self.pos;
+        let class = match self.take_while(|b| b.is_ascii_alphabetic()) {
+            b"done" => ResultClass::Done,
+            b"running" => ResultClass::Running,
+            b"connected" => ResultClass::Connected,
+            b"error" => ResultClass::Error,
+            b"exit" => ResultClass::Exit,
+            _ => {
+                self.pos = begin;
+                return Err(self.error("unknown result class"));
+            }
+        };
+        Ok(class)
+    }
+
+    fn async_class(&mut self) -> Result<AsyncClass, ParseError> {
+        let class = self.take_while(|b| b != b',');
+        Ok(match class {
+            b"" => return Err(self.error("expected async class")),
+            b"running" => AsyncClass::Running,
+            b"record-started" => AsyncClass::RecordStarted,
+            b"record-stopped" => AsyncClass::RecordStopped,
+            b"stopped" => AsyncClass::Stopped,
+            b"thread-created" => AsyncClass::Thread(ThreadEvent::Created),
+            b"thread-group-added" => AsyncClass::Thread(ThreadEvent::GroupAdded),
+            b"thread-group-removed" => AsyncClass::Thread(ThreadEvent::GroupRemoved),
+            b"thread-group-started" => AsyncClass::Thread(ThreadEvent::GroupStarted),
+            b"thread-exited" => AsyncClass::Thread(ThreadEvent::Exited),
+            b"thread-group-exited" => AsyncClass::Thread(ThreadEvent::GroupExited),
+            b"thread-selected" => AsyncClass::Thread(ThreadEvent::Selected),
+            b"cmd-param-changed" => AsyncClass::CmdParamChanged,
+            b"library-loaded" => AsyncClass::LibraryLoaded,
+            b"breakpoint-created" => AsyncClass::BreakPoint(BreakPointEvent::Created),
+            b"breakpoint-deleted" => AsyncClass::BreakPoint(BreakPointEvent::Deleted),
+            b"breakpoint-modified" => AsyncClass::BreakPoint(BreakPointEvent::Modified),
+            other => AsyncClass::Other(String::from_utf8_lossy(other).into_owned()),
+        })
+    }
+
+    fn output(&mut self) -> Result<Output, ParseError> {
+        if self.input.starts_with(b"(gdb)")
+            && self.input[5..].iter().all(|b| b.is_ascii_whitespace())
+        {
+            return Ok(Output::GDBLine);
+        }
+        let token = self.token()?;
+        let output = match self.next() {
+            Some(b'^') => {
+                let class = self.result_class()?;
+                let results = self.results()?;
+                Output::Result(ResultRecord {
+                    token,
+                    class,
+                    results,
+                })
+            }
+            Some(kind @ b'*') | Some(kind @ b'+') | Some(kind @ b'=') => {
+                let kind = match kind {
+                    b'*' => AsyncKind::Exec,
+                    b'+' => AsyncKind::Status,
+                    _ => AsyncKind::Notify,
+                };
+                let class = self.async_class()?;
+                let results = self.results()?;
+                Output::OutOfBand(OutOfBandRecord::AsyncRecord {
+                    token,
+                    kind,
+                    class,
+                    results,
+                })
+            }
+            Some(kind @ b'~') | Some(kind @ b'@') | Some(kind @ b'&') => {
+                if token.is_some() {
+                    self.pos -= 1;
+                    return Err(self.error("stream records cannot have a token"));
+                }
+                let kind = match kind {
+                    b'~' => StreamKind::Console,
+                    b'@' => StreamKind::Target,
+                    _ => StreamKind::Log,
+                };
+                let data = self.string()?;
+                Output::OutOfBand(OutOfBandRecord::StreamRecord { kind, data })
+            }
+            _ => {
+                self.pos = self.pos.saturating_sub(1);
+                return Err(self.error("unknown record type"));
+            }
+        };
+        if self.pos != self.input.len() {
+            return Err(self.error("unexpected trailing characters"));
+        }
+        Ok(output)
+    }
+}
+
+// Parse a single line of gdb/mi output. A trailing newline (\n or \r\n) is ignored.
+pub fn parse_line(line: &[u8]) -> Result<Output, ParseError> {
+    let line = line.strip_suffix(b"\n").unwrap_or(line);
+    let line = line.strip_suffix(b"\r").unwrap_or(line);
+    Parser {
+        input: line,
+        pos: 0,
+    }
+    .output()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rand::{Rng, SeedableRng, XorShiftRng};
+
+    fn parse(line: &str) -> Output {
+        parse_line(line.as_bytes()).unwrap()
+    }
+
+    fn results(line: &str) -> Object {
+        match parse(line) {
+            Output::Result(r) => r.results,
+            Output::OutOfBand(OutOfBandRecord::AsyncRecord { results, .. }) => results,
+            other => panic!("unexpected output: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_records() {
+        assert_eq!(parse("(gdb) \n"), Output::GDBLine);
+        assert_eq!(parse("(gdb)"), Output::GDBLine);
+        assert_eq!(
+            parse("12^done\r\n"),
+            Output::Result(ResultRecord {
+                token: Some(12),
+                class: ResultClass::Done,
+                results: Object::new(),
+            })
+        );
+        assert_eq!(
+            parse("7*running,thread-id=\"all\"\n"),
+            Output::OutOfBand(OutOfBandRecord::AsyncRecord {
+                token: Some(7),
+                kind: AsyncKind::Exec,
+                class: AsyncClass::Running,
+                results: results("^done,thread-id=\"all\""),
+            })
+        );
+        assert_eq!(
+            parse("=thread-group-added,id=\"i1\""),
+            Output::OutOfBand(OutOfBandRecord::AsyncRecord {
+                token: None,
+                kind: AsyncKind::Notify,
+                class: AsyncClass::Thread(ThreadEvent::GroupAdded),
+                results: results("^done,id=\"i1\""),
+            })
+        );
+        assert_eq!(
+            parse("&\"warning: foo\\n\""),
+            Output::OutOfBand(OutOfBandRecord::StreamRecord {
+                kind: StreamKind::Log,
+                data: "warning: foo\n".to_owned(),
+            })
+        );
+    }
+
+    #[test]
+    fn test_escapes() {
+        assert_eq!(
+            parse(r#"~"a\"b\\c\n\t\r\e\101\x42\q""#),
+            Output::OutOfBand(OutOfBandRecord::StreamRecord {
+                kind: StreamKind::Console,
+                data: "a\"b\\c\n\t\r\x1bAB\\q".to_owned(),
+            })
+        );
+        // Octal escaped utf8
+        assert_eq!(
+            results(r#"^done,value="\303\244""#)["value"],
+            JsonValue::String("ä".to_owned())
+        );
+    }
+
+    #[test]
+    fn test_non_utf8() {
+        let results = match parse_line(b"^done,value=\"a\xffb\"").unwrap() {
+            Output::Result(r) => r.results,
+            other => panic!("unexpected output: {:?}", other),
+        };
+        assert_eq!(results["value"], "a\u{fffd}b");
+    }
+
+    #[test]
+    fn test_nested() {
+        let r =
+            results("^done,a={b=[],c={},d=[\"1\",[\"2\"],{e=\"3\"}],f=[g=\"4\",g=\"5\"]},h=\"6\"");
+        assert_eq!(r["a"]["b"].len(), 0);
+        assert!(r["a"]["c"].is_object());
+        assert_eq!(r["a"]["d"][1][0], "2");
+        assert_eq!(r["a"]["d"][2]["e"], "3");
+        assert_eq!(r["a"]["f"][1], "5");
+        assert_eq!(r["h"], "6");
+    }
+
+    #[test]
+    fn test_buggy_list_in_result() {
+        let r = results("=breakpoint-modified,bkpt={number=\"1\"},{number=\"1.1\"},x=\"y\"");
+        assert_eq!(r["bkpt"][1]["number"], "1.1");
+        assert_eq!(r["x"], "y");
+    }
+
+    #[test]
+    fn test_errors() {
+        for (line, position) in &[
+            ("", 0),
+            ("foo", 0),
+            ("^dne", 1),
+            ("^done,", 6),
+            ("^done,a=", 8),
+            ("^done,a=\"b", 10),
+            ("^done,a={b=\"c\"", 14),
+            ("^done,a=[\"b\"", 12),
+            ("^done,a=\"b\"x", 11),
+            ("1~\"a\"", 1),
+            ("99999999999999999999999^done", 0),
+            ("~\"\\777\"", 6),
+        ] {
+            let e = parse_line(line.as_bytes()).unwrap_err();
+            assert_eq!(e.line, *line);
+            assert_eq!(e.position, *position, "{}", e);
+        }
+    }
+
+    // Lines of real gdb output, one per line.
+    const CORPUS: &str = include_str!("corpus.txt");
+
+    #[test]
+    fn test_corpus() {
+        for line in CORPUS.lines() {
+            if let Err(e) = parse_line(line.as_bytes()) {
+                panic!("{}", e);
+            }
+        }
+    }
+
+    fn rng() -> XorShiftRng {
+        XorShiftRng::from_seed([0x75, 0x67, 0x64, 0x62])
+    }
+
+    // Feed randomly mutated corpus lines to the parser, which must never panic.
+    #[test]
+    fn fuzz_corpus() {
+        let mut rng = rng();
+        let special = b"\"\\{}[],=^*+~@&0123456789\n\r\xff";
+        for line in CORPUS.lines() {
+            for _ in 0..200 {
+                let mut bytes = line.as_bytes().to_vec();
+                for _ in 0..rng.gen_range(1, 4) {
+                    let pos = rng.gen_range(0, bytes.len() + 1);
+                    match rng.gen_range(0, 4) {
+                        0 => bytes.truncate(pos),
+                        1 => bytes.insert(pos, special[rng.gen_range(0, special.len())]),
+                        2 => bytes.insert(pos, rng.gen()),
+                        _ => {
+                            if pos < bytes.len() {
+                                bytes.remove(pos);
+                            }
+                        }
+                    }
+                }
+                let _ = parse_line(&bytes);
+            }
+        }
+    }
+
+    fn gen_string<R: Rng>(rng: &mut R) -> String {
+        let len = rng.gen_range(0, 8);
+        (0..len)
+            .map(|_| match rng.gen_range(0, 4) {
+                0 => ['"', '\\', '\n', '\t', '\r', '\x1b', '{', ','][rng.gen_range(0, 8)],
+                1 => ['ä', '→', '\u{1F600}'][rng.gen_range(0, 3)],
+                _ => rng.gen_range(b' ', b'~' + 1) as char,
+            })
+            .collect()
+    }
+
+    fn gen_name<R: Rng>(rng: &mut R) -> String {
+        let chars = b"abcdefghijklmnopqrstuvwxyz-_";
+        let len = rng.gen_range(1, 6);
+        (0..len)
+            .map(|_| chars[rng.gen_range(0, chars.len())] as char)
+            .collect()
+    }
+
+    fn gen_value<R: Rng>(rng: &mut R, depth: usize) -> JsonValue {
+        match if depth == 0 { 0 } else { rng.gen_range(0, 3) } {
+            0 => JsonValue::String(gen_string(rng)),
+            1 => {
+                let mut obj = Object::new();
+                for _ in 0..rng.gen_range(0, 4) {
+                    obj.insert(&gen_name(rng), gen_value(rng, depth - 1));
+                }
+                JsonValue::Object(obj)
+            }
+            _ => JsonValue::Array(
+                (0..rng.gen_range(0, 4))
+                    .map(|_| gen_value(rng, depth - 1))
+                    .collect(),
+            ),
+        }
+    }
+
+    fn write_string<R: Rng>(rng: &mut R, s: &str, out: &mut Vec<u8>) {
+        out.push(b'"');
+        for b in s.bytes() {
+            match b {
+                b'"' => out.extend_from_slice(b"\\\""),
+                b'\\' => out.extend_from_slice(b"\\\\"),
+                b'\n' => out.extend_from_slice(b"\\n"),
+                b'\t' => out.extend_from_slice(b"\\t"),
+                b'\r' => out.extend_from_slice(b"\\r"),
+                b if !(0x20..0x80).contains(&b) || rng.gen_weighted_bool(10) => {
+                    out.extend_from_slice(format!("\\{:03o}", b).as_bytes())
+                }
+                b => out.push(b),
+            }
+        }
+        out.push(b'"');
+    }
+
+    fn write_value<R: Rng>(rng: &mut R, value: &JsonValue, out: &mut Vec<u8>) {
+        match value {
+            JsonValue::Object(obj) => {
+                out.push(b'{');
+                for (i, (name, value)) in obj.iter().enumerate() {
+                    if i > 0 {
+                        out.push(b',');
+                    }
+                    out.extend_from_slice(name.as_bytes());
+                    out.push(b'=');
+                    write_value(rng, value, out);
+                }
+                out.push(b'}');
+            }
+            JsonValue::Array(values) => {
+                // Randomly use the list-of-results representation.
+                let as_results = rng.gen();
+                out.push(b'[');
+                for (i, value) in values.iter().enumerate() {
+                    if i > 0 {
+                        out.push(b',');
+                    }
+                    if as_results {
+                        out.extend_from_slice(gen_name(rng).as_bytes());
+                        out.push(b'=');
+                    }
+                    write_value(rng, value, out);
+                }
+                out.push(b']');
+            }
+            other => write_string(rng, other.as_str().unwrap(), out),
+        }
+    }
+
+    // Generate random records, write them in mi syntax and check that parsing yields the original.
+    #[test]
+    fn property_roundtrip() {
+        let mut rng = rng();
+        for _ in 0..2000 {
+            let token = if rng.gen() {
+                Some(rng.gen::<u32>() as Token)
+            } else {
+                None
+            };
+            let mut results = Object::new();
+            for _ in 0..rng.gen_range(0, 4) {
+                results.insert(&gen_name(&mut rng), gen_value(&mut rng, 3));
+            }
+
+            let mut line = Vec::new();
+            let kind = rng.gen_range(0, 3);
+            if let (Some(t), true) = (token, kind != 2) {
+                line.extend_from_slice(t.to_string().as_bytes());
+            }
+            let expected = match kind {
+                0 => {
+                    line.extend_from_slice(b"^error");
+                    Output::Result(ResultRecord {
+                        token,
+                        class: ResultClass::Error,
+                        results: results.clone(),
+                    })
+                }
+                1 => {
+                    line.extend_from_slice(b"=breakpoint-created");
+                    Output::OutOfBand(OutOfBandRecord::AsyncRecord {
+                        token,
+                        kind: AsyncKind::Notify,
+                        class: AsyncClass::BreakPoint(BreakPointEvent::Created),
+                        results: results.clone(),
+                    })
+                }
+                _ => {
+                    let data = gen_string(&mut rng);
+                    line.push(b'@');
+                    write_string(&mut rng, &data, &mut line);
+                    line.extend_from_slice(b"\r\n");
+                    assert_eq!(
+                        parse_line(&line).unwrap(),
+                        Output::OutOfBand(OutOfBandRecord::StreamRecord {
+                            kind: StreamKind::Target,
+                            data,
+                        })
+                    );
+                    continue;
+                }
+            };
+            for (name, value) in results.iter() {
+                line.push(b',');
+                line.extend_from_slice(name.as_bytes());
+                line.push(b'=');
+                write_value(&mut rng, value, &mut line);
+            }
+            line.push(b'\n');
+            match parse_line(&line) {
+                Ok(output) => assert_eq!(output, expected),
+                Err(e) => panic!("{}", e),
+            }
+        }
+    }
+}
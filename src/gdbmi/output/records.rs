@@ -0,0 +1,1020 @@
+// Typed representations of the results that gdb reports in result and async records.
+//
+// Decoding never panics: Missing or malformed fields are reported as a DecodeError, since the
+// exact output of gdb differs between versions (and is not always conforming to its own spec).
+use super::{JsonValue, Object};
+use crate::gdbmi::commands::{BreakPointNumber, WatchMode};
+use std::fmt;
+use std::ops::{Add, Sub};
+use std::path::PathBuf;
+use std::str::FromStr;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Address(pub usize);
+impl Address {
+    pub fn parse(string: &str) -> Result<Self, (::std::num::ParseIntError, String)> {
+        let digits = string
+            .strip_prefix("0x")
+            .or_else(|| string.strip_prefix("0X"))
+            .unwrap_or(string);
+        usize::from_str_radix(digits, 16)
+            .map(Address)
+            .map_err(|e| (e, string.to_owned()))
+    }
+}
+impl FromStr for Address {
+    type Err = (::std::num::ParseIntError, String);
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Address::parse(s)
+    }
+}
+impl fmt::Display for Address {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "0x{:x}", self.0)
+    }
+}
+impl Add<usize> for Address {
+    type Output = Self;
+    fn add(self, rhs: usize) -> Self {
+        Address(self.0 + rhs)
+    }
+}
+
+impl Sub<usize> for Address {
+    type Output = Self;
+    fn sub(self, rhs: usize) -> Self {
+        Address(self.0 - rhs)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum DecodeError {
+    MissingField(&'static str, JsonValue),
+    MalformedField(&'static str, JsonValue),
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DecodeError::MissingField(key, val) => {
+                write!(f, "Missing field '{}' in {}", key, val.dump())
+            }
+            DecodeError::MalformedField(key, val) => {
+                write!(f, "Malformed field '{}' in {}", key, val.dump())
+            }
+        }
+    }
+}
+
+// Both the top level results of a record and nested tuples can be decoded.
+pub trait Fields {
+    fn get_field(&self, key: &str) -> Option<&JsonValue>;
+    fn to_value(&self) -> JsonValue;
+}
+
+impl Fields for Object {
+    fn get_field(&self, key: &str) -> Option<&JsonValue> {
+        self.get(key)
+    }
+    fn to_value(&self) -> JsonValue {
+        JsonValue::Object(self.clone())
+    }
+}
+
+impl Fields for JsonValue {
+    fn get_field(&self, key: &str) -> Option<&JsonValue> {
+        match self {
+            JsonValue::Object(o) => o.get(key),
+            _ => None,
+        }
+    }
+    fn to_value(&self) -> JsonValue {
+        self.clone()
+    }
+}
+
+pub fn field<'a, F: Fields + ?Sized>(
+    obj: &'a F,
+    key: &'static str,
+) -> Result<&'a JsonValue, DecodeError> {
+    obj.get_field(key)
+        .ok_or_else(|| DecodeError::MissingField(key, obj.to_value()))
+}
+
+pub fn opt_str_field<'a, F: Fields + ?Sized>(
+    obj: &'a F,
+    key: &'static str,
+) -> Result<Option<&'a str>, DecodeError> {
+    match obj.get_field(key) {
+        None => Ok(None),
+        Some(v) => v
+            .as_str()
+            .map(Some)
+            .ok_or_else(|| DecodeError::MalformedField(key, obj.to_value())),
+    }
+}
+
+pub fn str_field<'a, F: Fields + ?Sized>(
+    obj: &'a F,
+    key: &'static str,
+) -> Result<&'a str, DecodeError> {
+    opt_str_field(obj, key)?.ok_or_else(|| DecodeError::MissingField(key, obj.to_value()))
+}
+
+pub fn opt_parse_field<T: FromStr, F: Fields + ?Sized>(
+    obj: &F,
+    key: &'static str,
+) -> Result<Option<T>, DecodeError> {
+    match opt_str_field(obj, key)? {
+        None => Ok(None),
+        Some(s) => s
+            .parse::<T>()
+            .map(Some)
+            .map_err(|_| DecodeError::MalformedField(key, obj.to_value())),
+    }
+}
+
+pub fn parse_field<T: FromStr, F: Fields + ?Sized>(
+    obj: &F,
+    key: &'static str,
+) -> Result<T, DecodeError> {
+    opt_parse_field(obj, key)?.ok_or_else(|| DecodeError::MissingField(key, obj.to_value()))
+}
+
+fn opt_owned<F: Fields + ?Sized>(
+    obj: &F,
+    key: &'static str,
+) -> Result<Option<String>, DecodeError> {
+    Ok(opt_str_field(obj, key)?.map(|s| s.to_owned()))
+}
+
+// Lists are either encoded as [value, ...] or [key=value, ...]. The latter (as well as a missing
+// list) are both decoded as arrays of values by our parser.
+fn list_field<'a, F: Fields + ?Sized>(
+    obj: &'a F,
+    key: &'static str,
+) -> Result<&'a [JsonValue], DecodeError> {
+    match obj.get_field(key) {
+        None => Ok(&[]),
+        Some(JsonValue::Array(a)) => Ok(a),
+        Some(_) => Err(DecodeError::MalformedField(key, obj.to_value())),
+    }
+}
+
+// gdb reports exit codes in octal.
+fn opt_exit_code<F: Fields + ?Sized>(
+    obj: &F,
+    key: &'static str,
+) -> Result<Option<i64>, DecodeError> {
+    opt_str_field(obj, key)?
+        .map(|c| {
+            i64::from_str_radix(c, 8).map_err(|_| DecodeError::MalformedField(key, obj.to_value()))
+        })
+        .transpose()
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Frame {
+    pub level: Option<u64>,
+    pub address: Option<Address>,
+    pub function: Option<String>,
+    pub file: Option<String>,
+    pub fullname: Option<PathBuf>,
+    pub line: Option<usize>,
+}
+
+impl Frame {
+    pub fn decode<F: Fields + ?Sized>(frame: &F) -> Result<Self, DecodeError> {
+        Ok(Frame {
+            level: opt_parse_field(frame, "level")?,
+            address: opt_parse_field(frame, "addr")?,
+            function: opt_owned(frame, "func")?,
+            file: opt_owned(frame, "file")?,
+            fullname: opt_str_field(frame, "fullname")?.map(PathBuf::from),
+            line: opt_parse_field(frame, "line")?,
+        })
+    }
+
+    // Decode the "frame" tuple of the given results, if present.
+    pub fn decode_in<F: Fields + ?Sized>(results: &F) -> Result<Option<Self>, DecodeError> {
+        results.get_field("frame").map(Frame::decode).transpose()
+    }
+
+    // Decode the result of -stack-list-frames.
+    pub fn decode_list(results: &Object) -> Result<Vec<Self>, DecodeError> {
+        list_field(results, "stack")?
+            .iter()
+            .map(Self::decode)
+            .collect()
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct BreakPointInfo {
+    pub number: BreakPointNumber,
+    pub enabled: bool,
+    pub address: Option<Address>,
+    pub fullname: Option<PathBuf>,
+    pub line: Option<usize>,
+    pub original_location: Option<String>,
+    pub condition: Option<String>,
+    pub ignore_count: Option<usize>,
+    // Watchpoints watch an expression instead of stopping at a location.
+    pub watchpoint: Option<(WatchMode, String)>,
+}
+
+impl BreakPointInfo {
+    pub fn decode<F: Fields + ?Sized>(bkpt: &F) -> Result<Self, DecodeError> {
+        Ok(BreakPointInfo {
+            number: parse_field(bkpt, "number")?,
+            // Sub-breakpoints (of older gdb versions) do not always specify "enabled".
+            enabled: opt_str_field(bkpt, "enabled")?.is_none_or(|e| e == "y"),
+            // addr may not be present or contain "<PENDING>" or "<MULTIPLE>".
+            address: opt_str_field(bkpt, "addr")?.and_then(|a| Address::parse(a).ok()),
+            fullname: opt_str_field(bkpt, "fullname")?.map(PathBuf::from),
+            line: opt_parse_field(bkpt, "line")?,
+            original_location: opt_owned(bkpt, "original-location")?,
+            condition: opt_owned(bkpt, "cond")?,
+            ignore_count: opt_parse_field(bkpt, "ignore")?,
+            watchpoint: match (opt_str_field(bkpt, "type")?, opt_owned(bkpt, "what")?) {
+                (Some("watchpoint"), Some(what)) | (Some("hw watchpoint"), Some(what)) => {
+                    Some((WatchMode::Write, what))
+                }
+                (Some("read watchpoint"), Some(what)) => Some((WatchMode::Read, what)),
+                (Some("acc watchpoint"), Some(what)) => Some((WatchMode::Access, what)),
+                _ => None,
+            },
+        })
+    }
+
+    // Decode all breakpoints (including sub-breakpoints for multiple locations) from the results
+    // of a breakpoint-created or breakpoint-modified event (or a -break-insert command).
+    pub fn decode_all(results: &Object) -> Result<Vec<Self>, DecodeError> {
+        let mut breakpoints = Vec::new();
+        match field(results, "bkpt")? {
+            bkpt @ JsonValue::Object(_) => {
+                breakpoints.push(Self::decode(bkpt)?);
+
+                // If there are multiple locations (recent versions of) gdb return the
+                // sub-breakpoints in the array "locations".
+                for location in list_field(bkpt, "locations")? {
+                    breakpoints.push(Self::decode(location)?);
+                }
+            }
+            JsonValue::Array(bkpts) => {
+                // In previous versions, gdb returned multiple sub-breakpoints as a series of
+                // objects under the "bkpt" key (thus breaking the spec). This appears to be fixed
+                // now, but we keep the current case (for now) for users of old gdb versions.
+                for bkpt in bkpts {
+                    breakpoints.push(Self::decode(bkpt)?);
+                }
+            }
+            _ => return Err(DecodeError::MalformedField("bkpt", results.to_value())),
+        }
+        Ok(breakpoints)
+    }
+
+    // Decode the number of the breakpoint of a breakpoint-deleted event.
+    pub fn decode_deleted(results: &Object) -> Result<BreakPointNumber, DecodeError> {
+        parse_field(results, "id")
+    }
+
+    // Decode the number of the watchpoint created by -break-watch (whose key depends on the mode).
+    pub fn decode_watchpoint(results: &Object) -> Result<BreakPointNumber, DecodeError> {
+        let key = ["wpt", "hw-rwpt", "hw-awpt"]
+            .iter()
+            .find(|key| results.get_field(key).is_some())
+            .ok_or_else(|| DecodeError::MissingField("wpt", results.to_value()))?;
+        parse_field(field(results, key)?, "number")
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ThreadSelectedEvent {
+    pub id: u64,
+    pub frame: Option<Frame>,
+}
+
+impl ThreadSelectedEvent {
+    pub fn decode(results: &Object) -> Result<Self, DecodeError> {
+        Ok(ThreadSelectedEvent {
+            id: parse_field(results, "id")?,
+            frame: Frame::decode_in(results)?,
+        })
+    }
+}
+
+// =thread-created and =thread-exited
+#[derive(Clone, Debug, PartialEq)]
+pub struct ThreadInfo {
+    pub id: u64,
+    pub group_id: String,
+}
+
+impl ThreadInfo {
+    pub fn decode(results: &Object) -> Result<Self, DecodeError> {
+        Ok(ThreadInfo {
+            id: parse_field(results, "id")?,
+            group_id: str_field(results, "group-id")?.to_owned(),
+        })
+    }
+}
+
+// =thread-group-added, =thread-group-removed, =thread-group-started and =thread-group-exited
+#[derive(Clone, Debug, PartialEq)]
+pub struct ThreadGroupEvent {
+    pub id: String,
+    // Only present if the group was started.
+    pub pid: Option<u32>,
+    // Only present if the group exited (and the exit code is known).
+    pub exit_code: Option<i64>,
+}
+
+impl ThreadGroupEvent {
+    pub fn decode(results: &Object) -> Result<Self, DecodeError> {
+        Ok(ThreadGroupEvent {
+            id: str_field(results, "id")?.to_owned(),
+            pid: opt_parse_field(results, "pid")?,
+            exit_code: opt_exit_code(results, "exit-code")?,
+        })
+    }
+}
+
+// An entry of the result of -thread-info
+#[derive(Clone, Debug, PartialEq)]
+pub struct ThreadListEntry {
+    pub id: u64,
+    pub target_id: Option<String>,
+    // Only present if the thread was named (by the program or the user).
+    pub name: Option<String>,
+    pub frame: Option<Frame>,
+}
+
+impl ThreadListEntry {
+    pub fn decode<F: Fields + ?Sized>(thread: &F) -> Result<Self, DecodeError> {
+        Ok(ThreadListEntry {
+            id: parse_field(thread, "id")?,
+            target_id: opt_owned(thread, "target-id")?,
+            name: opt_owned(thread, "name")?,
+            frame: Frame::decode_in(thread)?,
+        })
+    }
+
+    // Decode the result of -thread-info.
+    pub fn decode_list(results: &Object) -> Result<Vec<Self>, DecodeError> {
+        list_field(results, "threads")?
+            .iter()
+            .map(Self::decode)
+            .collect()
+    }
+}
+
+// An entry of the result of -list-thread-groups
+#[derive(Clone, Debug, PartialEq)]
+pub struct ThreadGroup {
+    pub id: String,
+    // Only present if the group is running.
+    pub pid: Option<u32>,
+    pub executable: Option<PathBuf>,
+}
+
+impl ThreadGroup {
+    pub fn decode<F: Fields + ?Sized>(group: &F) -> Result<Self, DecodeError> {
+        Ok(ThreadGroup {
+            id: str_field(group, "id")?.to_owned(),
+            pid: opt_parse_field(group, "pid")?,
+            executable: opt_str_field(group, "executable")?.map(PathBuf::from),
+        })
+    }
+
+    // Decode the result of -list-thread-groups.
+    pub fn decode_list(results: &Object) -> Result<Vec<Self>, DecodeError> {
+        list_field(results, "groups")?
+            .iter()
+            .map(Self::decode)
+            .collect()
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct AsmInstruction {
+    pub address: Address,
+    pub func_name: Option<String>,
+    pub offset: Option<usize>,
+    pub inst: String,
+}
+
+impl AsmInstruction {
+    pub fn decode<F: Fields + ?Sized>(insn: &F) -> Result<Self, DecodeError> {
+        Ok(AsmInstruction {
+            address: parse_field(insn, "address")?,
+            func_name: opt_owned(insn, "func-name")?,
+            offset: opt_parse_field(insn, "offset")?,
+            inst: str_field(insn, "inst")?.to_owned(),
+        })
+    }
+
+    // Decode the result of -data-disassemble in a mode without source information.
+    pub fn decode_list(results: &Object) -> Result<Vec<Self>, DecodeError> {
+        field(results, "asm_insns")?;
+        list_field(results, "asm_insns")?
+            .iter()
+            .map(Self::decode)
+            .collect()
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct SrcAndAsmLine {
+    pub line: usize,
+    pub file: Option<String>,
+    pub fullname: Option<PathBuf>,
+    pub instructions: Vec<AsmInstruction>,
+}
+
+impl SrcAndAsmLine {
+    pub fn decode<F: Fields + ?Sized>(line: &F) -> Result<Self, DecodeError> {
+        Ok(SrcAndAsmLine {
+            line: parse_field(line, "line")?,
+            file: opt_owned(line, "file")?,
+            fullname: opt_str_field(line, "fullname")?.map(PathBuf::from),
+            instructions: list_field(line, "line_asm_insn")?
+                .iter()
+                .map(AsmInstruction::decode)
+                .collect::<Result<Vec<_>, _>>()?,
+        })
+    }
+
+    // Decode the result of -data-disassemble in a mode with source information.
+    pub fn decode_list(results: &Object) -> Result<Vec<Self>, DecodeError> {
+        field(results, "asm_insns")?;
+        list_field(results, "asm_insns")?
+            .iter()
+            .map(Self::decode)
+            .collect()
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct VarObjectInfo {
+    pub name: String,
+    pub exp: Option<String>,
+    pub numchild: Option<usize>,
+    pub value: Option<String>,
+    pub typ: Option<String>,
+    // Set for var objects of pretty printers, whose number of children is only known once they
+    // are listed.
+    pub dynamic: bool,
+    pub has_more: bool,
+}
+
+impl VarObjectInfo {
+    pub fn decode<F: Fields + ?Sized>(var: &F) -> Result<Self, DecodeError> {
+        Ok(VarObjectInfo {
+            name: str_field(var, "name")?.to_owned(),
+            exp: opt_owned(var, "exp")?,
+            numchild: opt_parse_field(var, "numchild")?,
+            value: opt_owned(var, "value")?,
+            typ: opt_owned(var, "type")?,
+            dynamic: opt_str_field(var, "dynamic")? == Some("1"),
+            has_more: opt_str_field(var, "has_more")? == Some("1"),
+        })
+    }
+
+    pub fn has_children(&self) -> bool {
+        self.numchild.unwrap_or(0) > 0 || self.has_more
+    }
+
+    // Decode the result of -var-list-children.
+    pub fn decode_children(results: &Object) -> Result<Vec<Self>, DecodeError> {
+        list_field(results, "children")?
+            .iter()
+            .map(Self::decode)
+            .collect()
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum VarObjectScope {
+    InScope,
+    OutOfScope,
+    // The var object cannot be used anymore (e.g., because the program was restarted).
+    Invalid,
+}
+
+// An entry in the changelist of -var-update.
+#[derive(Clone, Debug, PartialEq)]
+pub struct VarObjectChange {
+    pub name: String,
+    pub value: Option<String>,
+    pub scope: VarObjectScope,
+    pub new_type: Option<String>,
+    pub new_num_children: Option<usize>,
+    // Pretty printers report children that have been added.
+    pub new_children: Vec<VarObjectInfo>,
+    pub has_more: bool,
+}
+
+impl VarObjectChange {
+    pub fn decode<F: Fields + ?Sized>(change: &F) -> Result<Self, DecodeError> {
+        let scope = match str_field(change, "in_scope")? {
+            "true" => VarObjectScope::InScope,
+            "false" => VarObjectScope::OutOfScope,
+            "invalid" => VarObjectScope::Invalid,
+            _ => return Err(DecodeError::MalformedField("in_scope", change.to_value())),
+        };
+        Ok(VarObjectChange {
+            name: str_field(change, "name")?.to_owned(),
+            value: opt_owned(change, "value")?,
+            scope,
+            new_type: if opt_str_field(change, "type_changed")? == Some("true") {
+                opt_owned(change, "new_type")?
+            } else {
+                None
+            },
+            new_num_children: opt_parse_field(change, "new_num_children")?,
+            new_children: list_field(change, "new_children")?
+                .iter()
+                .map(VarObjectInfo::decode)
+                .collect::<Result<_, _>>()?,
+            has_more: opt_str_field(change, "has_more")? == Some("1"),
+        })
+    }
+
+    // Decode the result of -var-update.
+    pub fn decode_list(results: &Object) -> Result<Vec<Self>, DecodeError> {
+        list_field(results, "changelist")?
+            .iter()
+            .map(Self::decode)
+            .collect()
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Variable {
+    pub name: String,
+    pub value: Option<String>,
+    pub typ: Option<String>,
+    pub is_arg: bool,
+}
+
+impl Variable {
+    pub fn decode<F: Fields + ?Sized>(var: &F) -> Result<Self, DecodeError> {
+        Ok(Variable {
+            name: str_field(var, "name")?.to_owned(),
+            value: opt_owned(var, "value")?,
+            typ: opt_owned(var, "type")?,
+            is_arg: opt_str_field(var, "arg")? == Some("1"),
+        })
+    }
+
+    // Decode the result of -stack-list-variables.
+    pub fn decode_list(results: &Object) -> Result<Vec<Self>, DecodeError> {
+        list_field(results, "variables")?
+            .iter()
+            .map(Self::decode)
+            .collect()
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum StopReason {
+    BreakpointHit {
+        number: Option<BreakPointNumber>,
+    },
+    WatchpointTrigger {
+        mode: WatchMode,
+        number: Option<BreakPointNumber>,
+        expression: Option<String>,
+        old_value: Option<String>,
+        new_value: Option<String>,
+    },
+    WatchpointScope {
+        number: Option<BreakPointNumber>,
+    },
+    FunctionFinished {
+        return_value: Option<String>,
+    },
+    LocationReached,
+    EndSteppingRange,
+    SignalReceived {
+        name: Option<String>,
+        meaning: Option<String>,
+    },
+    Exited {
+        exit_code: Option<i64>,
+    },
+    ExitedNormally,
+    ExitedSignalled {
+        name: Option<String>,
+        meaning: Option<String>,
+    },
+    NoHistory,
+    Other(String),
+}
+
+impl StopReason {
+    fn decode(reason: &str, results: &Object) -> Result<Self, DecodeError> {
+        let watchpoint = |mode, wpt_key| -> Result<Self, DecodeError> {
+            let wpt = results.get_field(wpt_key);
+            let value = results.get_field("value");
+            Ok(StopReason::WatchpointTrigger {
+                mode,
+                number: wpt
+                    .map(|w| opt_parse_field(w, "number"))
+                    .transpose()?
+                    .flatten(),
+                expression: wpt.map(|w| opt_owned(w, "exp")).transpose()?.flatten(),
+                old_value: value.map(|v| opt_owned(v, "old")).transpose()?.flatten(),
+                new_value: value
+                    .map(|v| -> Result<_, DecodeError> {
+                        Ok(opt_owned(v, "new")?.or(opt_owned(v, "value")?))
+                    })
+                    .transpose()?
+                    .flatten(),
+            })
+        };
+        Ok(match reason {
+            "breakpoint-hit" => StopReason::BreakpointHit {
+                number: opt_parse_field(results, "bkptno")?,
+            },
+            "watchpoint-trigger" => watchpoint(WatchMode::Write, "wpt")?,
+            "read-watchpoint-trigger" => watchpoint(WatchMode::Read, "hw-rwpt")?,
+            "access-watchpoint-trigger" => watchpoint(WatchMode::Access, "hw-awpt")?,
+            "watchpoint-scope" => StopReason::WatchpointScope {
+                number: opt_parse_field(results, "wpnum")?,
+            },
+            "function-finished" => StopReason::FunctionFinished {
+                return_value: opt_owned(results, "return-value")?,
+            },
+            "location-reached" => StopReason::LocationReached,
+            "end-stepping-range" => StopReason::EndSteppingRange,
+            "signal-received" => StopReason::SignalReceived {
+                name: opt_owned(results, "signal-name")?,
+                meaning: opt_owned(results, "signal-meaning")?,
+            },
+            "exited" => StopReason::Exited {
+                exit_code: opt_exit_code(results, "exit-code")?,
+            },
+            "exited-normally" => StopReason::ExitedNormally,
+            "exited-signalled" => StopReason::ExitedSignalled {
+                name: opt_owned(results, "signal-name")?,
+                meaning: opt_owned(results, "signal-meaning")?,
+            },
+            "no-history" => StopReason::NoHistory,
+            other => StopReason::Other(other.to_owned()),
+        })
+    }
+}
+
+impl fmt::Display for StopReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fn signal(
+            f: &mut fmt::Formatter,
+            name: &Option<String>,
+            meaning: &Option<String>,
+        ) -> fmt::Result {
+            write!(f, "{}", name.as_deref().unwrap_or("unknown signal"))?;
+            if let Some(meaning) = meaning {
+                write!(f, " ({})", meaning)?;
+            }
+            Ok(())
+        }
+        match self {
+            StopReason::BreakpointHit { number } => {
+                write!(f, "Breakpoint")?;
+                if let Some(number) = number {
+                    write!(f, " {}", number)?;
+                }
+                write!(f, " hit")
+            }
+            StopReason::WatchpointTrigger {
+                mode,
+                number,
+                expression,
+                old_value,
+                new_value,
+            } => {
+                write!(
+                    f,
+                    "{}",
+                    match mode {
+                        WatchMode::Write => "Watchpoint",
+                        WatchMode::Read => "Read watchpoint",
+                        WatchMode::Access => "Access watchpoint",
+                    }
+                )?;
+                if let Some(number) = number {
+                    write!(f, " {}", number)?;
+                }
+                if let Some(expression) = expression {
+                    write!(f, " ({})", expression)?;
+                }
+                match (old_value, new_value) {
+                    (Some(old), Some(new)) => write!(f, ": {} -> {}", old, new),
+                    (None, Some(value)) => write!(f, ": value = {}", value),
+                    _ => write!(f, " triggered"),
+                }
+            }
+            StopReason::WatchpointScope { number } => {
+                write!(f, "Watchpoint")?;
+                if let Some(number) = number {
+                    write!(f, " {}", number)?;
+                }
+                write!(f, " went out of scope")
+            }
+            StopReason::FunctionFinished { return_value } => {
+                write!(f, "Function finished")?;
+                if let Some(value) = return_value {
+                    write!(f, ", returned {}", value)?;
+                }
+                Ok(())
+            }
+            StopReason::LocationReached => write!(f, "Location reached"),
+            StopReason::EndSteppingRange => write!(f, "End of stepping range"),
+            StopReason::SignalReceived { name, meaning } => {
+                write!(f, "Received signal ")?;
+                signal(f, name, meaning)
+            }
+            StopReason::Exited { exit_code } => match exit_code {
+                Some(code) => write!(f, "Program exited with code {}", code),
+                None => write!(f, "Program exited"),
+            },
+            StopReason::ExitedNormally => write!(f, "Program exited normally"),
+            StopReason::ExitedSignalled { name, meaning } => {
+                write!(f, "Program terminated by signal ")?;
+                signal(f, name, meaning)
+            }
+            StopReason::NoHistory => write!(f, "Reached end of execution history"),
+            StopReason::Other(reason) => write!(f, "Stopped: {}", reason),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct StopEvent {
+    // Not present if the inferior was stopped without a specific reason (e.g., interrupted).
+    pub reason: Option<StopReason>,
+    pub frame: Option<Frame>,
+    pub thread_id: Option<u64>,
+}
+
+impl StopEvent {
+    pub fn decode(results: &Object) -> Result<Self, DecodeError> {
+        Ok(StopEvent {
+            reason: opt_str_field(results, "reason")?
+                .map(|r| StopReason::decode(r, results))
+                .transpose()?,
+            frame: Frame::decode_in(results)?,
+            // "all" if all threads were stopped.
+            thread_id: opt_str_field(results, "thread-id")?.and_then(|id| id.parse().ok()),
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::{parser::parse_line, Output};
+    use super::*;
+
+    fn parse_results(line: &str) -> Object {
+        match parse_line(line.as_bytes()) {
+            Ok(Output::Result(r)) => r.results,
+            Ok(Output::OutOfBand(super::super::OutOfBandRecord::AsyncRecord {
+                results, ..
+            })) => results,
+            other => panic!("unexpected output: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_address() {
+        assert_eq!(Address::parse("0x1f"), Ok(Address(0x1f)));
+        assert_eq!(Address::parse("1f"), Ok(Address(0x1f)));
+        assert!(Address::parse("").is_err());
+        assert!(Address::parse("0").is_ok());
+        assert!(Address::parse("<PENDING>").is_err());
+    }
+
+    #[test]
+    fn test_stop_event() {
+        let results = parse_results("*stopped,reason=\"breakpoint-hit\",disp=\"keep\",bkptno=\"1\",frame={addr=\"0x0000555555555131\",func=\"main\",args=[],file=\"test.c\",fullname=\"/tmp/test.c\",line=\"3\",arch=\"i386:x86-64\"},thread-id=\"1\",stopped-threads=\"all\",core=\"2\"\n");
+        let event = StopEvent::decode(&results).unwrap();
+        assert_eq!(
+            event.reason,
+            Some(StopReason::BreakpointHit {
+                number: Some("1".parse().unwrap())
+            })
+        );
+        let frame = event.frame.unwrap();
+        assert_eq!(frame.address, Some(Address(0x555555555131)));
+        assert_eq!(frame.function.as_deref(), Some("main"));
+        assert_eq!(frame.fullname, Some(PathBuf::from("/tmp/test.c")));
+        assert_eq!(frame.line, Some(3));
+        assert_eq!(event.thread_id, Some(1));
+
+        let results = parse_results("*stopped,reason=\"exited\",exit-code=\"010\"\n");
+        assert_eq!(
+            StopEvent::decode(&results).unwrap().reason,
+            Some(StopReason::Exited { exit_code: Some(8) })
+        );
+
+        let results = parse_results("*stopped,reason=\"watchpoint-trigger\",wpt={number=\"2\",exp=\"x\"},value={old=\"1\",new=\"2\"},frame={addr=\"0x1\"}\n");
+        assert_eq!(
+            StopEvent::decode(&results).unwrap().reason,
+            Some(StopReason::WatchpointTrigger {
+                mode: WatchMode::Write,
+                number: Some("2".parse().unwrap()),
+                expression: Some("x".to_owned()),
+                old_value: Some("1".to_owned()),
+                new_value: Some("2".to_owned()),
+            })
+        );
+
+        let results = parse_results(
+            "*stopped,reason=\"watchpoint-scope\",wpnum=\"2\",frame={addr=\"0x1\"}\n",
+        );
+        assert_eq!(
+            StopEvent::decode(&results).unwrap().reason,
+            Some(StopReason::WatchpointScope {
+                number: Some("2".parse().unwrap())
+            })
+        );
+    }
+
+    #[test]
+    fn test_thread_group_event() {
+        let results = parse_results("=thread-group-started,id=\"i2\",pid=\"4242\"\n");
+        assert_eq!(
+            ThreadGroupEvent::decode(&results).unwrap(),
+            ThreadGroupEvent {
+                id: "i2".to_owned(),
+                pid: Some(4242),
+                exit_code: None,
+            }
+        );
+        let results = parse_results("=thread-group-exited,id=\"i1\",exit-code=\"03\"\n");
+        assert_eq!(
+            ThreadGroupEvent::decode(&results).unwrap().exit_code,
+            Some(3)
+        );
+        let results = parse_results("=thread-created,id=\"3\",group-id=\"i2\"\n");
+        assert_eq!(
+            ThreadInfo::decode(&results).unwrap(),
+            ThreadInfo {
+                id: 3,
+                group_id: "i2".to_owned(),
+            }
+        );
+    }
+
+    #[test]
+    fn test_stop_reason_display() {
+        let display = |line: &str| {
+            StopEvent::decode(&parse_results(line))
+                .unwrap()
+                .reason
+                .unwrap()
+                .to_string()
+        };
+        assert_eq!(
+            display("*stopped,reason=\"breakpoint-hit\",bkptno=\"1\"\n"),
+            "Breakpoint 1 hit"
+        );
+        assert_eq!(
+            display("*stopped,reason=\"signal-received\",signal-name=\"SIGSEGV\",signal-meaning=\"Segmentation fault\"\n"),
+            "Received signal SIGSEGV (Segmentation fault)"
+        );
+        assert_eq!(
+            display("*stopped,reason=\"exited\",exit-code=\"012\"\n"),
+            "Program exited with code 10"
+        );
+        assert_eq!(
+            display("*stopped,reason=\"watchpoint-trigger\",wpt={number=\"2\",exp=\"x\"},value={old=\"1\",new=\"2\"}\n"),
+            "Watchpoint 2 (x): 1 -> 2"
+        );
+        assert_eq!(
+            display("*stopped,reason=\"read-watchpoint-trigger\",hw-rwpt={number=\"3\",exp=\"y\"},value={value=\"7\"}\n"),
+            "Read watchpoint 3 (y): value = 7"
+        );
+    }
+
+    #[test]
+    fn test_malformed_is_error() {
+        let results = parse_results("*stopped,frame={addr=\"main\",line=\"3\"}\n");
+        assert!(matches!(
+            StopEvent::decode(&results),
+            Err(DecodeError::MalformedField("addr", _))
+        ));
+        let results = parse_results("^done,bkpt={enabled=\"y\"}\n");
+        assert!(matches!(
+            BreakPointInfo::decode_all(&results),
+            Err(DecodeError::MissingField("number", _))
+        ));
+    }
+
+    #[test]
+    fn test_breakpoints() {
+        let results = parse_results("=breakpoint-modified,bkpt={number=\"1\",type=\"breakpoint\",disp=\"keep\",enabled=\"y\",addr=\"<MULTIPLE>\",times=\"0\",original-location=\"foo\",locations=[{number=\"1.1\",enabled=\"y\",addr=\"0x1\",func=\"foo\",file=\"a.c\",fullname=\"/a.c\",line=\"1\"},{number=\"1.2\",enabled=\"n\",addr=\"0x2\"}]}\n");
+        let bps = BreakPointInfo::decode_all(&results).unwrap();
+        assert_eq!(bps.len(), 3);
+        assert_eq!(bps[0].address, None);
+        assert_eq!(bps[1].number, "1.1".parse().unwrap());
+        assert_eq!(bps[1].line, Some(1));
+        assert!(!bps[2].enabled);
+
+        let results = parse_results(
+            "=breakpoint-created,bkpt={number=\"1\",enabled=\"y\",addr=\"0x1\"},{number=\"1.1\",enabled=\"y\",addr=\"0x1\"}\n",
+        );
+        assert_eq!(BreakPointInfo::decode_all(&results).unwrap().len(), 2);
+
+        let results = parse_results("=breakpoint-modified,bkpt={number=\"2\",type=\"hw watchpoint\",disp=\"keep\",enabled=\"y\",addr=\"\",what=\"x\",cond=\"x > 3\",times=\"0\",ignore=\"2\",original-location=\"-location x\"}\n");
+        let bp = &BreakPointInfo::decode_all(&results).unwrap()[0];
+        assert_eq!(bp.condition.as_deref(), Some("x > 3"));
+        assert_eq!(bp.ignore_count, Some(2));
+        assert_eq!(bp.watchpoint, Some((WatchMode::Write, "x".to_owned())));
+
+        let results = parse_results("^done,hw-rwpt={number=\"3\",exp=\"x\"}\n");
+        assert_eq!(
+            BreakPointInfo::decode_watchpoint(&results).unwrap(),
+            "3".parse().unwrap()
+        );
+        assert!(BreakPointInfo::decode_watchpoint(&Object::new()).is_err());
+    }
+
+    #[test]
+    fn test_threads() {
+        let results = parse_results("^done,threads=[{id=\"1\",target-id=\"process 42\",name=\"main\",frame={level=\"0\",addr=\"0x1131\",func=\"main\",args=[]},state=\"stopped\",core=\"1\"},{id=\"2\",target-id=\"Thread 0x7f (LWP 43)\",state=\"running\"}],current-thread-id=\"1\"\n");
+        let threads = ThreadListEntry::decode_list(&results).unwrap();
+        assert_eq!(threads.len(), 2);
+        assert_eq!(threads[0].name.as_deref(), Some("main"));
+        assert_eq!(
+            threads[0].frame.as_ref().unwrap().address,
+            Some(Address(0x1131))
+        );
+        assert_eq!(threads[1].id, 2);
+        assert_eq!(threads[1].name, None);
+        assert_eq!(threads[1].frame, None);
+        assert!(ThreadListEntry::decode_list(&Object::new())
+            .unwrap()
+            .is_empty());
+
+        let results = parse_results("^done,groups=[{id=\"i1\",type=\"process\",pid=\"42\",executable=\"/tmp/test\",cores=[\"1\"]},{id=\"i2\",type=\"process\"}]\n");
+        let groups = ThreadGroup::decode_list(&results).unwrap();
+        assert_eq!(groups[0].pid, Some(42));
+        assert_eq!(groups[0].executable, Some(PathBuf::from("/tmp/test")));
+        assert_eq!(groups[1].id, "i2");
+        assert_eq!(groups[1].executable, None);
+    }
+
+    #[test]
+    fn test_backtrace() {
+        let results = parse_results("^done,stack=[frame={level=\"0\",addr=\"0x1131\",func=\"foo\",file=\"test.c\",fullname=\"/tmp/test.c\",line=\"3\",arch=\"i386:x86-64\"},frame={level=\"1\",addr=\"0x1150\",func=\"main\",from=\"/lib/libc.so.6\"}]\n");
+        let frames = Frame::decode_list(&results).unwrap();
+        assert_eq!(frames.len(), 2);
+        assert_eq!(frames[0].line, Some(3));
+        assert_eq!(frames[1].level, Some(1));
+        assert_eq!(frames[1].fullname, None);
+    }
+
+    #[test]
+    fn test_disassembly() {
+        let results = parse_results("^done,asm_insns=[src_and_asm_line={line=\"3\",file=\"test.c\",fullname=\"/tmp/test.c\",line_asm_insn=[{address=\"0x0000000000001129\",func-name=\"main\",offset=\"0\",inst=\"push   %rbp\"}]},src_and_asm_line={line=\"4\",file=\"test.c\",line_asm_insn=[]}]\n");
+        let lines = SrcAndAsmLine::decode_list(&results).unwrap();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0].instructions[0].address, Address(0x1129));
+        assert_eq!(lines[0].instructions[0].offset, Some(0));
+        assert_eq!(lines[1].fullname, None);
+
+        let results = parse_results("^done,asm_insns=[{address=\"0x1\",inst=\"nop\"}]\n");
+        let insns = AsmInstruction::decode_list(&results).unwrap();
+        assert_eq!(insns[0].func_name, None);
+        assert!(AsmInstruction::decode_list(&Object::new()).is_err());
+    }
+
+    #[test]
+    fn test_var_objects() {
+        let results = parse_results("^done,numchild=\"2\",children=[child={name=\"var1.a\",exp=\"a\",numchild=\"0\",type=\"int\",thread-id=\"1\"},child={name=\"var1.public\",exp=\"public\",numchild=\"1\"}],has_more=\"0\"\n");
+        let children = VarObjectInfo::decode_children(&results).unwrap();
+        assert_eq!(children.len(), 2);
+        assert_eq!(children[0].typ.as_deref(), Some("int"));
+        assert_eq!(children[1].typ, None);
+
+        let results = parse_results("^done,numchild=\"0\"\n");
+        assert!(VarObjectInfo::decode_children(&results).unwrap().is_empty());
+
+        let results = parse_results("^done,name=\"var2\",numchild=\"0\",value=\"std::vector of length 2, capacity 2\",type=\"std::vector<int>\",thread-id=\"1\",displayhint=\"array\",dynamic=\"1\",has_more=\"1\"\n");
+        let var = VarObjectInfo::decode(&results).unwrap();
+        assert!(var.dynamic);
+        assert!(var.has_children());
+
+        let results = parse_results("^done,changelist=[{name=\"var1.a\",value=\"2\",in_scope=\"true\",type_changed=\"false\",has_more=\"0\"},{name=\"var3\",in_scope=\"false\",type_changed=\"false\",has_more=\"0\"},{name=\"var2\",value=\"std::vector of length 3, capacity 4\",in_scope=\"true\",type_changed=\"false\",new_num_children=\"3\",dynamic=\"1\",has_more=\"0\",new_children=[{name=\"var2.[2]\",exp=\"[2]\",numchild=\"0\",value=\"3\",type=\"int\"}]}]\n");
+        let changes = VarObjectChange::decode_list(&results).unwrap();
+        assert_eq!(changes.len(), 3);
+        assert_eq!(changes[0].value.as_deref(), Some("2"));
+        assert_eq!(changes[0].new_type, None);
+        assert_eq!(changes[1].scope, VarObjectScope::OutOfScope);
+        assert_eq!(changes[2].new_num_children, Some(3));
+        assert_eq!(changes[2].new_children[0].exp.as_deref(), Some("[2]"));
+    }
+}
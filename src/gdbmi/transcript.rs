@@ -0,0 +1,469 @@
+// Recording and replaying of the mi traffic between ugdb and gdb.
+//
+// Transcripts are line based: Lines sent to gdb are prefixed with "> ", lines received from gdb are
+// prefixed with "< " and interrupts are recorded as "!interrupt". Empty lines and lines starting
+// with "#" are ignored, so that transcripts can be annotated (or written) by hand.
+use super::{Backend, Token};
+use log::{info, warn};
+use std::collections::{HashMap, VecDeque};
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::os::unix::process::ExitStatusExt;
+use std::path::Path;
+use std::process::ExitStatus;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+
+const INPUT_PREFIX: &[u8] = b"> ";
+const OUTPUT_PREFIX: &[u8] = b"< ";
+const INTERRUPT: &[u8] = b"!interrupt";
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Entry {
+    Input(Vec<u8>),
+    Output(Vec<u8>),
+    Interrupt,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Transcript {
+    entries: Vec<Entry>,
+}
+
+impl Transcript {
+    pub fn parse(bytes: &[u8]) -> Result<Self, String> {
+        let mut entries = Vec::new();
+        for (i, line) in bytes.split(|b| *b == b'\n').enumerate() {
+            let line = line.strip_suffix(b"\r").unwrap_or(line);
+            if line.is_empty() || line.starts_with(b"#") {
+                continue;
+            }
+            entries.push(if let Some(l) = line.strip_prefix(INPUT_PREFIX) {
+                Entry::Input(l.to_vec())
+            } else if let Some(l) = line.strip_prefix(OUTPUT_PREFIX) {
+                Entry::Output(l.to_vec())
+            } else if line == INTERRUPT {
+                Entry::Interrupt
+            } else {
+                return Err(format!(
+                    "Invalid transcript entry in line {}: {}",
+                    i + 1,
+                    String::from_utf8_lossy(line)
+                ));
+            });
+        }
+        Ok(Transcript { entries })
+    }
+
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let mut bytes = Vec::new();
+        File::open(path)?.read_to_end(&mut bytes)?;
+        Self::parse(&bytes).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+}
+
+enum Direction {
+    Input,
+    Output,
+}
+
+// Writes a transcript of all traffic passing through RecordingWriter and RecordingReader.
+pub struct Recorder {
+    sink: Box<dyn Write + Send>,
+    pending_input: Vec<u8>,
+    pending_output: Vec<u8>,
+}
+
+pub type SharedRecorder = Arc<Mutex<Recorder>>;
+
+impl Recorder {
+    pub fn new(sink: Box<dyn Write + Send>) -> SharedRecorder {
+        Arc::new(Mutex::new(Recorder {
+            sink,
+            pending_input: Vec::new(),
+            pending_output: Vec::new(),
+        }))
+    }
+
+    pub fn create(path: &Path) -> io::Result<SharedRecorder> {
+        Ok(Self::new(Box::new(File::create(path)?)))
+    }
+
+    pub fn append(path: &Path) -> io::Result<SharedRecorder> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self::new(Box::new(file)))
+    }
+
+    fn write_entry(&mut self, prefix: &[u8], line: &[u8]) {
+        // Lines are written (unbuffered) as soon as they are complete, so that the transcript is
+        // still useful if ugdb crashes.
+        let res = self
+            .sink
+            .write_all(prefix)
+            .and_then(|_| self.sink.write_all(line))
+            .and_then(|_| self.sink.write_all(b"\n"));
+        if let Err(e) = res {
+            warn!("Failed to write mi transcript: {}", e);
+        }
+    }
+
+    fn record(&mut self, direction: Direction, bytes: &[u8]) {
+        let (prefix, mut pending) = match direction {
+            Direction::Input => (INPUT_PREFIX, std::mem::take(&mut self.pending_input)),
+            Direction::Output => (OUTPUT_PREFIX, std::mem::take(&mut self.pending_output)),
+        };
+        pending.extend_from_slice(bytes);
+        let mut begin = 0;
+        while let Some(len) = pending[begin..].iter().position(|b| *b == b'\n') {
+            let line = &pending[begin..begin + len];
+            self.write_entry(prefix, line.strip_suffix(b"\r").unwrap_or(line));
+            begin += len + 1;
+        }
+        pending.drain(..begin);
+        match direction {
+            Direction::Input => self.pending_input = pending,
+            Direction::Output => self.pending_output = pending,
+        }
+    }
+
+    pub fn record_interrupt(&mut self) {
+        self.write_entry(b"", INTERRUPT);
+    }
+}
+
+pub struct RecordingWriter<W> {
+    inner: W,
+    recorder: SharedRecorder,
+}
+
+impl<W: Write> RecordingWriter<W> {
+    pub fn new(inner: W, recorder: SharedRecorder) -> Self {
+        RecordingWriter { inner, recorder }
+    }
+}
+
+impl<W: Write> Write for RecordingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let len = self.inner.write(buf)?;
+        self.recorder
+            .lock()
+            .unwrap()
+            .record(Direction::Input, &buf[..len]);
+        Ok(len)
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+pub struct RecordingReader<R> {
+    inner: R,
+    recorder: SharedRecorder,
+}
+
+impl<R: Read> RecordingReader<R> {
+    pub fn new(inner: R, recorder: SharedRecorder) -> Self {
+        RecordingReader { inner, recorder }
+    }
+}
+
+impl<R: Read> Read for RecordingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let len = self.inner.read(buf)?;
+        self.recorder
+            .lock()
+            .unwrap()
+            .record(Direction::Output, &buf[..len]);
+        Ok(len)
+    }
+}
+
+fn split_token(line: &[u8]) -> (Option<Token>, &[u8]) {
+    let len = line.iter().take_while(|b| b.is_ascii_digit()).count();
+    let token = std::str::from_utf8(&line[..len])
+        .ok()
+        .and_then(|t| t.parse().ok());
+    (token, &line[len..])
+}
+
+// State of a replayed transcript, shared by ReplayInput and ReplayBackend.
+struct ReplayState {
+    entries: VecDeque<Entry>,
+    // Tokens of the recorded session are mapped to the ones used in the replay.
+    token_map: HashMap<Token, Token>,
+    output: Option<Sender<Vec<u8>>>,
+    exited: Arc<AtomicBool>,
+}
+
+impl ReplayState {
+    fn send(&mut self, mut line: Vec<u8>) {
+        line.push(b'\n');
+        if let Some(output) = &self.output {
+            let _ = output.send(line);
+        }
+    }
+
+    // Send all recorded output up to the next input or interrupt.
+    fn send_recorded_output(&mut self) {
+        while let Some(Entry::Output(_)) = self.entries.front() {
+            let line = match self.entries.pop_front() {
+                Some(Entry::Output(line)) => line,
+                _ => unreachable!(),
+            };
+            let line = match split_token(&line) {
+                (Some(token), rest) if self.token_map.contains_key(&token) => {
+                    let mut l = self.token_map[&token].to_string().into_bytes();
+                    l.extend_from_slice(rest);
+                    l
+                }
+                _ => line,
+            };
+            self.send(line);
+        }
+    }
+
+    fn handle_input(&mut self, line: &[u8]) {
+        let (token, command) = split_token(line);
+        let expected = match self.entries.front() {
+            Some(Entry::Input(expected)) => Some(split_token(expected)),
+            _ => None,
+        };
+        match expected {
+            // Trailing whitespace is ignored, since it is easily lost when editing transcripts.
+            Some((recorded_token, expected_command))
+                if expected_command.trim_ascii_end() == command.trim_ascii_end() =>
+            {
+                if let (Some(recorded), Some(token)) = (recorded_token, token) {
+                    self.token_map.insert(recorded, token);
+                }
+                self.entries.pop_front();
+                self.send_recorded_output();
+            }
+            _ => {
+                warn!(
+                    "Replay: Unexpected command {} (expected {:?})",
+                    String::from_utf8_lossy(line),
+                    self.entries.front()
+                );
+                let mut error = token
+                    .map(|t| t.to_string())
+                    .unwrap_or_default()
+                    .into_bytes();
+                error.extend_from_slice(b"^error,msg=\"Command not in transcript\"");
+                self.send(error);
+            }
+        }
+        if command == b"-gdb-exit" {
+            self.exit();
+        }
+    }
+
+    fn exit(&mut self) {
+        // Dropping the sender signals the end of output to the reader.
+        self.output = None;
+        self.exited.store(true, Ordering::SeqCst);
+    }
+}
+
+// Stdin of the replayed "gdb" process.
+pub struct ReplayInput {
+    state: Arc<Mutex<ReplayState>>,
+    line: Vec<u8>,
+}
+
+impl Write for ReplayInput {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.line.extend_from_slice(buf);
+        while let Some(len) = self.line.iter().position(|b| *b == b'\n') {
+            let line = self.line.drain(..len + 1).collect::<Vec<_>>();
+            self.state
+                .lock()
+                .unwrap()
+                .handle_input(&line[..line.len() - 1]);
+        }
+        Ok(buf.len())
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+// Stdout of the replayed "gdb" process.
+pub struct ReplayOutput {
+    receiver: Receiver<Vec<u8>>,
+    buffer: Vec<u8>,
+}
+
+impl Read for ReplayOutput {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.buffer.is_empty() {
+            match self.receiver.recv() {
+                Ok(line) => self.buffer = line,
+                Err(_) => return Ok(0),
+            }
+        }
+        let len = buf.len().min(self.buffer.len());
+        buf[..len].copy_from_slice(&self.buffer[..len]);
+        self.buffer.drain(..len);
+        Ok(len)
+    }
+}
+
+pub struct ReplayBackend {
+    state: Arc<Mutex<ReplayState>>,
+    exited: Arc<AtomicBool>,
+}
+
+impl Backend for ReplayBackend {
+    fn interrupt(&self) -> Result<(), nix::Error> {
+        let mut state = self.state.lock().unwrap();
+        if let Some(Entry::Interrupt) = state.entries.front() {
+            state.entries.pop_front();
+            state.send_recorded_output();
+        } else {
+            info!("Replay: Ignoring interrupt that is not in transcript");
+        }
+        Ok(())
+    }
+
+    fn try_wait(&mut self) -> io::Result<Option<ExitStatus>> {
+        Ok(if self.exited.load(Ordering::SeqCst) {
+            Some(ExitStatus::from_raw(0))
+        } else {
+            None
+        })
+    }
+}
+
+// Create a fake gdb process that replays the given transcript. Output that was recorded before the
+// first command is available immediately.
+pub fn replay(transcript: Transcript) -> (ReplayBackend, ReplayInput, ReplayOutput) {
+    let (sender, receiver) = channel();
+    let exited = Arc::new(AtomicBool::new(false));
+    let mut state = ReplayState {
+        entries: transcript.entries.into(),
+        token_map: HashMap::new(),
+        output: Some(sender),
+        exited: exited.clone(),
+    };
+    state.send_recorded_output();
+    let state = Arc::new(Mutex::new(state));
+    (
+        ReplayBackend {
+            state: state.clone(),
+            exited,
+        },
+        ReplayInput {
+            state,
+            line: Vec::new(),
+        },
+        ReplayOutput {
+            receiver,
+            buffer: Vec::new(),
+        },
+    )
+}
+
+// Out of band sink for tests that do not care about async records.
+#[cfg(test)]
+pub struct DiscardingSink;
+
+#[cfg(test)]
+impl super::OutOfBandRecordSink for DiscardingSink {
+    fn send(&self, _: super::output::OutOfBandRecord) {}
+    fn send_async_result(&self, _: super::output::ResultRecord) {}
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::{commands::MiCommand, ExecuteError, GDB};
+    use super::*;
+    use crate::gdbmi::output::ResultClass;
+
+    struct VecSink(Arc<Mutex<Vec<u8>>>);
+    impl Write for VecSink {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    const TRANSCRIPT: &str = r#"# A hand written transcript
+< =thread-group-added,id="i1"
+< (gdb)
+> 5-environment-pwd
+< 5^done,cwd="/tmp"
+< (gdb)
+!interrupt
+< *stopped,reason="signal-received",signal-name="SIGINT"
+> 6-gdb-exit
+< 6^exit
+"#;
+
+    #[test]
+    fn test_parse() {
+        let transcript = Transcript::parse(TRANSCRIPT.as_bytes()).unwrap();
+        assert_eq!(transcript.entries.len(), 9);
+        assert_eq!(
+            transcript.entries[2],
+            Entry::Input(b"5-environment-pwd".to_vec())
+        );
+        assert_eq!(transcript.entries[5], Entry::Interrupt);
+        assert!(Transcript::parse(b"> 1-foo\nbar\n").is_err());
+    }
+
+    #[test]
+    fn test_recorder() {
+        let buffer = Arc::new(Mutex::new(Vec::new()));
+        let recorder = Recorder::new(Box::new(VecSink(buffer.clone())));
+        let mut writer = RecordingWriter::new(Vec::new(), recorder.clone());
+        let mut reader = RecordingReader::new(&b"1^done\r\n(gdb) \n"[..], recorder.clone());
+
+        writer.write_all(b"1-exec-").unwrap();
+        writer.write_all(b"run\n").unwrap();
+        let mut output = Vec::new();
+        reader.read_to_end(&mut output).unwrap();
+        recorder.lock().unwrap().record_interrupt();
+
+        assert_eq!(writer.inner, b"1-exec-run\n");
+        assert_eq!(output, b"1^done\r\n(gdb) \n");
+        let transcript = Transcript::parse(&buffer.lock().unwrap()).unwrap();
+        assert_eq!(
+            transcript.entries,
+            vec![
+                Entry::Input(b"1-exec-run".to_vec()),
+                Entry::Output(b"1^done".to_vec()),
+                Entry::Output(b"(gdb) ".to_vec()),
+                Entry::Interrupt,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_replay() {
+        let transcript = Transcript::parse(TRANSCRIPT.as_bytes()).unwrap();
+        let mut gdb = GDB::replay(transcript, DiscardingSink, "gdb".into()).unwrap();
+
+        // Tokens differ from the recorded ones.
+        let _ = gdb.get_usable_token();
+        let res = gdb.execute(MiCommand::environment_pwd()).unwrap();
+        assert_eq!(res.class, ResultClass::Done);
+        assert_eq!(res.results["cwd"], "/tmp");
+
+        let res = gdb.execute(MiCommand::environment_pwd()).unwrap();
+        assert_eq!(res.class, ResultClass::Error);
+
+        gdb.interrupt_execution().unwrap();
+        assert_eq!(gdb.try_wait().unwrap(), None);
+        gdb.execute_later(MiCommand::exit());
+        assert!(gdb.try_wait().unwrap().unwrap().success());
+        assert_eq!(
+            gdb.execute(MiCommand::environment_pwd()).unwrap_err(),
+            ExecuteError::Quit
+        );
+    }
+}
@@ -0,0 +1,250 @@
+// A local gdbserver that runs the debugged program on behalf of gdb (see --gdbserver).
+//
+// Since gdb only talks to the server, its --tty option has no effect. Instead, the terminal of the
+// program (i.e., the io of gdbserver) is connected to the terminal pane directly.
+// Only stderr passes through ugdb (see watch_stderr), which learns from it whether the server
+// could bind its port.
+use std::ffi::OsString;
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Write};
+use std::net::{Ipv4Addr, TcpListener};
+use std::os::unix::io::FromRawFd;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command};
+use std::sync::mpsc;
+use std::time::Duration;
+
+// gdbserver does not report a port that it picked itself, so we have to find a free one. Another
+// process may take the port before gdbserver binds it, in which case gdbserver exits right away
+// and we try again with another port.
+const SPAWN_ATTEMPTS: usize = 5;
+// gdbserver announces that it is listening right after starting the program. If it takes longer,
+// we assume that it is busy with something else and carry on.
+const STARTUP_TIMEOUT: Duration = Duration::from_secs(5);
+
+// What gdbserver reported on stderr while starting
+enum Startup {
+    Listening,
+    BindFailed(String),
+    // gdbserver exited with the given output.
+    Exited(String),
+}
+
+pub struct GdbServer {
+    gdbserver_path: PathBuf,
+    tty: PathBuf,
+    program_and_args: Vec<OsString>,
+    process: Child,
+    address: String,
+}
+
+impl GdbServer {
+    pub fn spawn(
+        gdbserver_path: &Path,
+        tty: &Path,
+        program_and_args: &[OsString],
+    ) -> std::io::Result<Self> {
+        let (process, address) = spawn_process(gdbserver_path, tty, program_and_args)?;
+        Ok(GdbServer {
+            gdbserver_path: gdbserver_path.to_owned(),
+            tty: tty.to_owned(),
+            program_and_args: program_and_args.to_vec(),
+            process,
+            address,
+        })
+    }
+
+    // Start a new server for the program, e.g., because the old one exited together with the gdb
+    // session that was connected to it. The address of the server changes.
+    pub fn respawn(&mut self) -> std::io::Result<()> {
+        kill(&mut self.process);
+        let (process, address) =
+            spawn_process(&self.gdbserver_path, &self.tty, &self.program_and_args)?;
+        self.process = process;
+        self.address = address;
+        Ok(())
+    }
+
+    pub fn address(&self) -> &str {
+        &self.address
+    }
+}
+
+fn spawn_process(
+    gdbserver_path: &Path,
+    tty: &Path,
+    program_and_args: &[OsString],
+) -> std::io::Result<(Child, String)> {
+    let open_tty =
+        || -> std::io::Result<File> { OpenOptions::new().read(true).write(true).open(tty) };
+    let mut attempt = 0;
+    loop {
+        let port = TcpListener::bind((Ipv4Addr::LOCALHOST, 0))?
+            .local_addr()?
+            .port();
+        let address = format!("{}:{}", Ipv4Addr::LOCALHOST, port);
+        let (stderr, startup) = watch_stderr(open_tty()?)?;
+        let mut process = Command::new(gdbserver_path)
+            .arg(&address)
+            .args(program_and_args)
+            .stdin(open_tty()?)
+            .stdout(open_tty()?)
+            .stderr(stderr)
+            .spawn()?;
+        match startup.recv_timeout(STARTUP_TIMEOUT) {
+            Ok(Startup::Listening) | Err(mpsc::RecvTimeoutError::Timeout) => {
+                return Ok((process, address))
+            }
+            Ok(Startup::BindFailed(message)) => {
+                kill(&mut process);
+                attempt += 1;
+                if attempt == SPAWN_ATTEMPTS {
+                    return Err(std::io::Error::other(message));
+                }
+            }
+            // Any other error is reported right away, because another port does not help.
+            Ok(Startup::Exited(output)) => {
+                let status = process.wait()?;
+                return Err(std::io::Error::other(format!(
+                    "gdbserver exited with {}: {}",
+                    status,
+                    output.trim()
+                )));
+            }
+            Err(mpsc::RecvTimeoutError::Disconnected) => {
+                kill(&mut process);
+                return Err(std::io::Error::other("Lost the output of gdbserver"));
+            }
+        }
+    }
+}
+
+// The stderr of gdbserver (which the program inherits) is a pseudo terminal, so that the program
+// still writes to a terminal. Its output is forwarded to the terminal pane and watched for the
+// messages of gdbserver about the port.
+fn watch_stderr(mut tty: File) -> std::io::Result<(File, mpsc::Receiver<Startup>)> {
+    let pty = nix::pty::openpty(None, None).map_err(|e| std::io::Error::other(e.to_string()))?;
+    // Safe, because the descriptors were just created and are owned by nobody else.
+    let (mut master, slave) =
+        unsafe { (File::from_raw_fd(pty.master), File::from_raw_fd(pty.slave)) };
+    // The terminal must not be kept open by other processes, so that we notice when gdbserver exits.
+    for fd in [pty.master, pty.slave].iter() {
+        nix::fcntl::fcntl(
+            *fd,
+            nix::fcntl::FcntlArg::F_SETFD(nix::fcntl::FdFlag::FD_CLOEXEC),
+        )
+        .map_err(|e| std::io::Error::other(e.to_string()))?;
+    }
+    let (sender, receiver) = mpsc::channel();
+    std::thread::Builder::new()
+        .name("gdbserver stderr".to_owned())
+        .spawn(move || {
+            let mut output = String::new();
+            let mut sender = Some(sender);
+            let mut buffer = [0; 1024];
+            // Reading fails once gdbserver and the program have closed the terminal.
+            while let Ok(n) = master.read(&mut buffer) {
+                if n == 0 {
+                    break;
+                }
+                let _ = tty.write_all(&buffer[..n]);
+                if sender.is_none() {
+                    continue;
+                }
+                output.push_str(&String::from_utf8_lossy(&buffer[..n]));
+                let startup = if output.contains("Listening on port") {
+                    Startup::Listening
+                } else if let Some(line) = output.lines().find(|l| l.contains("Can't bind address"))
+                {
+                    Startup::BindFailed(line.trim().to_owned())
+                } else {
+                    continue;
+                };
+                let _ = sender.take().unwrap().send(startup);
+            }
+            if let Some(sender) = sender {
+                let _ = sender.send(Startup::Exited(output));
+            }
+        })?;
+    Ok((slave, receiver))
+}
+
+fn kill(process: &mut Child) {
+    let _ = process.kill();
+    let _ = process.wait();
+}
+
+impl Drop for GdbServer {
+    fn drop(&mut self) {
+        kill(&mut self.process);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::fs;
+    use std::os::unix::fs::PermissionsExt;
+
+    // A gdbserver that runs the script and counts how often it was started
+    fn fake_gdbserver(dir: &Path, name: &str, script: &str) -> PathBuf {
+        let path = dir.join(name);
+        fs::write(
+            &path,
+            format!("#!/bin/sh\necho >> \"$0.starts\"\n{}\n", script),
+        )
+        .unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+        path
+    }
+
+    fn starts(gdbserver: &Path) -> usize {
+        fs::read_to_string(gdbserver.with_extension("starts"))
+            .unwrap()
+            .lines()
+            .count()
+    }
+
+    #[test]
+    fn test_spawn() {
+        let dir = std::env::temp_dir().join(format!("ugdb-gdbserver-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let tty = Path::new("/dev/null");
+
+        let listening = fake_gdbserver(
+            &dir,
+            "listening",
+            "echo 'Listening on port 1' >&2\nexec sleep 10",
+        );
+        let server = GdbServer::spawn(&listening, tty, &[]).unwrap();
+        assert!(server.address().starts_with("127.0.0.1:"));
+        assert_eq!(starts(&listening), 1);
+        drop(server);
+
+        // Only failures to bind the port are retried (with another port).
+        let taken = fake_gdbserver(
+            &dir,
+            "taken",
+            "echo \"Can't bind address: Address already in use.\" >&2\necho Exiting >&2\nexit 1",
+        );
+        let error = GdbServer::spawn(&taken, tty, &[]).err().unwrap();
+        assert_eq!(
+            error.to_string(),
+            "Can't bind address: Address already in use."
+        );
+        assert_eq!(starts(&taken), SPAWN_ATTEMPTS);
+
+        let failing = fake_gdbserver(
+            &dir,
+            "failing",
+            "echo 'Cannot exec prog: No such file or directory.' >&2\nexit 1",
+        );
+        let error = GdbServer::spawn(&failing, tty, &[]).err().unwrap();
+        assert!(error
+            .to_string()
+            .ends_with("Cannot exec prog: No such file or directory."));
+        assert_eq!(starts(&failing), 1);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,17 +1,25 @@
 use json::object;
 use unix_socket::{UnixListener, UnixStream};
 
-use crate::gdb::BreakpointOperationError;
+use crate::gdb::{BreakPoint, BreakpointOperationError, GDB};
 use crate::gdbmi::{
-    commands::{BreakPointLocation, MiCommand},
+    commands::{BreakPointLocation, BreakPointNumber, MiCommand},
+    output::{
+        str_field, AsyncClass, BreakPointEvent, BreakPointInfo, Frame, Object, ResultClass,
+        ResultRecord, StopEvent, ThreadEvent, ThreadGroupEvent,
+    },
     ExecuteError,
 };
+use crate::layout;
 use crate::{Context, Event};
 use std::fs;
 use std::io::Read;
 use std::io::Write;
 use std::path::{Path, PathBuf};
+use std::sync::Arc;
 use std::thread;
+use std::time::Duration;
+use unsegen::base::LineNumber;
 
 struct IPCError {
     reason: &'static str,
@@ -37,20 +45,28 @@ impl IPCError {
 pub struct IPCRequest {
     raw_request: Vec<u8>,
     response_channel: UnixStream,
+    // The token that the request has to contain (see --ipc-token)
+    token: Option<Arc<String>>,
 }
 
 impl IPCRequest {
     pub fn respond(mut self, p: &mut Context) {
-        let reply = match Self::handle(p, self.raw_request) {
+        let token = self.token.as_ref().map(|t| t.as_str());
+        let reply = match Self::handle(p, self.raw_request, &self.response_channel, token) {
             Ok(reply_success) => reply_success,
             Err(reply_fail) => reply_fail.into_json(),
         };
         // Client may just close the channel, so we ignore any errors.
         // If they mess up it's on them.
-        let _ = write_ipc_response(&mut self.response_channel, reply.dump().as_bytes());
+        let _ = write_ipc_message(&mut self.response_channel, reply.dump().as_bytes());
     }
 
-    fn handle(p: &mut Context, raw_request: Vec<u8>) -> Result<json::JsonValue, IPCError> {
+    fn handle(
+        p: &mut Context,
+        raw_request: Vec<u8>,
+        connection: &UnixStream,
+        token: Option<&str>,
+    ) -> Result<json::JsonValue, IPCError> {
         let str_request = std::str::from_utf8(raw_request.as_slice())
             .map_err(|_| IPCError::new("Malformed utf8.", ""))?;
         let json_request =
@@ -63,6 +79,13 @@ impl IPCRequest {
                     .and_then(|o| o.as_str())
                     .ok_or_else(|| IPCError::new("Missing function name", json_request.dump()))?;
 
+                if token.is_some() && obj.get("token").and_then(|t| t.as_str()) != token {
+                    return Err(IPCError::new(
+                        "Invalid token",
+                        "This instance requires the token passed via --ipc-token",
+                    ));
+                }
+
                 let parameters = &obj["parameters"];
 
                 (function_name, parameters)
@@ -74,7 +97,12 @@ impl IPCRequest {
                 ));
             }
         };
-        let result = Self::dispatch(function_name)?(p, parameters)?;
+        // Subscribing is the only function that needs access to the connection itself.
+        let result = if function_name == "subscribe" {
+            Self::subscribe(p, parameters, connection)?
+        } else {
+            Self::dispatch(function_name)?(p, parameters)?
+        };
 
         Ok(object! {
             "type" => "success",
@@ -89,73 +117,155 @@ impl IPCRequest {
     {
         match function_name {
             "set_breakpoint" => Ok(Self::set_breakpoint),
+            "delete_breakpoint" => Ok(Self::delete_breakpoint),
+            "toggle_breakpoint" => Ok(Self::toggle_breakpoint),
+            "set_breakpoint_enabled" => Ok(Self::set_breakpoint_enabled),
+            "list_breakpoints" => Ok(Self::list_breakpoints),
             "show_file" => Ok(Self::show_file),
             "get_instance_info" => Ok(Self::get_instance_info),
+            "execute_command" => Ok(Self::execute_command),
+            "evaluate" => Ok(Self::evaluate),
+            "get_location" => Ok(Self::get_location),
+            "get_backtrace" => Ok(Self::get_backtrace),
+            "add_expression" => Ok(Self::add_expression),
+            "set_layout" => Ok(Self::set_layout),
+            "control_execution" => Ok(Self::control_execution),
             _ => Err(IPCError::new("unknown function", function_name)),
         }
     }
 
+    fn subscribe(
+        p: &mut Context,
+        parameters: &json::JsonValue,
+        connection: &UnixStream,
+    ) -> Result<json::JsonValue, IPCError> {
+        let events = match &parameters["events"] {
+            json::JsonValue::Null => None,
+            json::JsonValue::Array(events) => Some(
+                events
+                    .iter()
+                    .map(|e| {
+                        e.as_str()
+                            .filter(|e| EVENTS.contains(e))
+                            .map(|e| e.to_owned())
+                            .ok_or_else(|| IPCError::new("Unknown event", e.dump()))
+                    })
+                    .collect::<Result<Vec<_>, _>>()?,
+            ),
+            other => return Err(IPCError::new("Events is not an array", other.dump())),
+        };
+        let connection = connection
+            .try_clone()
+            .map_err(|e| IPCError::new("Could not subscribe", e.to_string()))?;
+        // A client that does not read its events must not block ugdb.
+        connection
+            .set_write_timeout(Some(SUBSCRIBER_WRITE_TIMEOUT))
+            .map_err(|e| IPCError::new("Could not subscribe", e.to_string()))?;
+        p.ipc_subscribers.add(connection, events);
+        Ok(json::JsonValue::String("Subscribed to events".to_owned()))
+    }
+
     fn set_breakpoint(
         p: &mut Context,
         parameters: &json::JsonValue,
     ) -> Result<json::JsonValue, IPCError> {
-        let parameters_obj = if let json::JsonValue::Object(parameters_obj) = parameters {
-            parameters_obj
+        let (file, line) = file_and_line(parameters)?;
+        let (_, note) = modify_breakpoints(p, parameters, |gdb| {
+            gdb.insert_breakpoint(BreakPointLocation::Line(Path::new(file), line as usize))
+        })
+        .map_err(|e| breakpoint_error("Could not insert breakpoint", e))?;
+        Ok(json::JsonValue::String(format!(
+            "Inserted breakpoint at {}:{}{}",
+            file, line, note
+        )))
+    }
+
+    fn delete_breakpoint(
+        p: &mut Context,
+        parameters: &json::JsonValue,
+    ) -> Result<json::JsonValue, IPCError> {
+        let numbers = breakpoints_at(p, parameters)?;
+        if numbers.is_empty() {
+            return Err(IPCError::new("No such breakpoint", parameters.dump()));
+        }
+        let (_, note) = modify_breakpoints(p, parameters, |gdb| {
+            gdb.delete_breakpoints(numbers.iter().copied())
+        })
+        .map_err(|e| breakpoint_error("Could not delete breakpoint", e))?;
+        Ok(json::JsonValue::String(format!(
+            "Deleted breakpoint {}{}",
+            display_numbers(&numbers),
+            note
+        )))
+    }
+
+    // Insert a breakpoint at the given line or delete the breakpoints at it, just like the pager.
+    fn toggle_breakpoint(
+        p: &mut Context,
+        parameters: &json::JsonValue,
+    ) -> Result<json::JsonValue, IPCError> {
+        let (file, line) = file_and_line(parameters)?;
+        let numbers = breakpoints_at(p, parameters)?;
+        if numbers.is_empty() {
+            let (_, note) = modify_breakpoints(p, parameters, |gdb| {
+                gdb.insert_breakpoint(BreakPointLocation::Line(Path::new(file), line as usize))
+            })
+            .map_err(|e| breakpoint_error("Could not insert breakpoint", e))?;
+            Ok(json::JsonValue::String(format!(
+                "Inserted breakpoint at {}:{}{}",
+                file, line, note
+            )))
         } else {
-            return Err(IPCError::new(
-                "Parameters is not an object",
-                parameters.dump(),
-            ));
-        };
-        let file = parameters_obj
-            .get("file")
-            .and_then(|o| o.as_str())
-            .ok_or_else(|| IPCError::new("Missing file name", parameters.dump()))?;
-        let line = parameters_obj
-            .get("line")
-            .and_then(|o| o.as_u32())
-            .ok_or_else(|| IPCError::new("Missing integer line number", parameters.dump()))?;
-        match p
-            .gdb
-            .insert_breakpoint(BreakPointLocation::Line(Path::new(file), line as usize))
-        {
-            Ok(()) => Ok(json::JsonValue::String(format!(
-                "Inserted breakpoint at {}:{}",
-                file, line
-            ))),
-            Err(BreakpointOperationError::Busy) => {
-                //TODO: we may want to investigate if we can interrupt execution, insert
-                //breakpoint, and resume execution thereafter.
-                Err(IPCError::new("Could not insert breakpoint", "GDB is busy"))
-            }
-            Err(BreakpointOperationError::ExecutionError(msg)) => {
-                //TODO: we may want to investigate if we can interrupt execution, insert
-                //breakpoint, and resume execution thereafter.
-                Err(IPCError::new("Could not insert breakpoint:", msg))
-            }
+            let (_, note) = modify_breakpoints(p, parameters, |gdb| {
+                gdb.delete_breakpoints(numbers.iter().copied())
+            })
+            .map_err(|e| breakpoint_error("Could not delete breakpoint", e))?;
+            Ok(json::JsonValue::String(format!(
+                "Deleted breakpoint {}{}",
+                display_numbers(&numbers),
+                note
+            )))
         }
     }
 
+    fn set_breakpoint_enabled(
+        p: &mut Context,
+        parameters: &json::JsonValue,
+    ) -> Result<json::JsonValue, IPCError> {
+        let enabled = parameters["enabled"]
+            .as_bool()
+            .ok_or_else(|| IPCError::new("Missing boolean enabled", parameters.dump()))?;
+        let numbers = breakpoints_at(p, parameters)?;
+        if numbers.is_empty() {
+            return Err(IPCError::new("No such breakpoint", parameters.dump()));
+        }
+        let (_, note) = modify_breakpoints(p, parameters, |gdb| {
+            gdb.set_breakpoints_enabled(numbers.iter().copied(), enabled)
+        })
+        .map_err(|e| breakpoint_error("Could not modify breakpoint", e))?;
+        Ok(json::JsonValue::String(format!(
+            "{} breakpoint {}{}",
+            if enabled { "Enabled" } else { "Disabled" },
+            display_numbers(&numbers),
+            note
+        )))
+    }
+
+    fn list_breakpoints(p: &mut Context, _: &json::JsonValue) -> Result<json::JsonValue, IPCError> {
+        let mut breakpoints = p.gdb.breakpoints.values().collect::<Vec<_>>();
+        breakpoints.sort_by_key(|bp| (bp.number.major, bp.number.minor));
+        Ok(breakpoints
+            .into_iter()
+            .map(breakpoint_to_json)
+            .collect::<Vec<_>>()
+            .into())
+    }
+
     fn show_file(
         p: &mut Context,
         parameters: &json::JsonValue,
     ) -> Result<json::JsonValue, IPCError> {
-        let parameters_obj = if let json::JsonValue::Object(parameters_obj) = parameters {
-            parameters_obj
-        } else {
-            return Err(IPCError::new(
-                "Parameters is not an object",
-                parameters.dump(),
-            ));
-        };
-        let file = parameters_obj
-            .get("file")
-            .and_then(|o| o.as_str())
-            .ok_or_else(|| IPCError::new("Missing file name", parameters.dump()))?;
-        let line = parameters_obj
-            .get("line")
-            .and_then(|o| o.as_u32())
-            .ok_or_else(|| IPCError::new("Missing integer line number", parameters.dump()))?;
+        let (file, line) = file_and_line(parameters)?;
 
         if line < 1 {
             return Err(IPCError::new(
@@ -174,25 +284,447 @@ impl IPCRequest {
         p: &mut Context,
         _: &json::JsonValue,
     ) -> Result<json::JsonValue, IPCError> {
+        //TODO: we may want to investigate if we can interrupt execution, get information
+        //and resume execution thereafter.
         let result = p
             .gdb
             .mi
             .execute(MiCommand::environment_pwd())
-            .map_err(|e| match e {
-                ExecuteError::Busy => {
-                    //TODO: we may want to investigate if we can interrupt execution, get information
-                    //and resume execution thereafter.
-                    IPCError::new("Could not get working directory", "GDB is busy")
-                }
-                ExecuteError::Quit => IPCError::new("Could not get working directory", "GDB quit"),
-            })?;
-        let working_directory = result.results["cwd"].as_str().ok_or_else(|| {
-            IPCError::new("Could not get working directory", "Malformed GDB response")
+            .map_err(|e| execute_error("Could not get working directory", e))?;
+        let working_directory = str_field(&result.results, "cwd").map_err(|e| {
+            IPCError::new(
+                "Could not get working directory",
+                format!("Malformed GDB response: {}", e),
+            )
         })?;
         Ok(object! {
             "working_directory" => working_directory
         })
     }
+
+    fn execute_command(
+        p: &mut Context,
+        parameters: &json::JsonValue,
+    ) -> Result<json::JsonValue, IPCError> {
+        let command = parameters["command"]
+            .as_str()
+            .ok_or_else(|| IPCError::new("Missing command", parameters.dump()))?;
+        // See the console: gdb stops speaking mi while the shell is running.
+        if command.split_whitespace().next() == Some("shell") {
+            return Err(IPCError::new("Command is not supported", command));
+        }
+        let (result, output) = p
+            .gdb
+            .mi
+            .execute_capturing_console(MiCommand::cli_exec(command))
+            .map_err(|e| execute_error("Could not execute command", e))?;
+        check_result("Could not execute command", &result)?;
+        p.update_working_dir();
+        Ok(object! {
+            "output" => output
+        })
+    }
+
+    fn evaluate(
+        p: &mut Context,
+        parameters: &json::JsonValue,
+    ) -> Result<json::JsonValue, IPCError> {
+        let expression = parameters["expression"]
+            .as_str()
+            .ok_or_else(|| IPCError::new("Missing expression", parameters.dump()))?;
+        let result = p
+            .gdb
+            .mi
+            .execute(MiCommand::data_evaluate_expression(expression.to_owned()))
+            .map_err(|e| execute_error("Could not evaluate expression", e))?;
+        check_result("Could not evaluate expression", &result)?;
+        let value = str_field(&result.results, "value").map_err(|e| {
+            IPCError::new(
+                "Could not evaluate expression",
+                format!("Malformed GDB response: {}", e),
+            )
+        })?;
+        Ok(object! {
+            "value" => value
+        })
+    }
+
+    fn get_location(p: &mut Context, _: &json::JsonValue) -> Result<json::JsonValue, IPCError> {
+        let result = p
+            .gdb
+            .mi
+            .execute(MiCommand::stack_info_frame(None))
+            .map_err(|e| execute_error("Could not get location", e))?;
+        check_result("Could not get location", &result)?;
+        let frame = Frame::decode_in(&result.results)
+            .ok()
+            .flatten()
+            .ok_or_else(|| {
+                IPCError::new(
+                    "Could not get location",
+                    format!("Malformed GDB response: {}", result.results.dump()),
+                )
+            })?;
+        Ok(object! {
+            "inferior" => p.gdb.inferiors.current().map(|i| i.id.clone()),
+            "frame" => frame_to_json(&frame)
+        })
+    }
+
+    fn get_backtrace(p: &mut Context, _: &json::JsonValue) -> Result<json::JsonValue, IPCError> {
+        let result = p
+            .gdb
+            .mi
+            .execute(MiCommand::stack_list_frames())
+            .map_err(|e| execute_error("Could not get backtrace", e))?;
+        check_result("Could not get backtrace", &result)?;
+        let frames = Frame::decode_list(&result.results).map_err(|e| {
+            IPCError::new(
+                "Could not get backtrace",
+                format!("Malformed GDB response: {}", e),
+            )
+        })?;
+        Ok(frames.iter().map(frame_to_json).collect::<Vec<_>>().into())
+    }
+
+    fn add_expression(
+        p: &mut Context,
+        parameters: &json::JsonValue,
+    ) -> Result<json::JsonValue, IPCError> {
+        let expression = parameters["expression"]
+            .as_str()
+            .ok_or_else(|| IPCError::new("Missing expression", parameters.dump()))?;
+        p.add_expression(expression.to_owned());
+        Ok(json::JsonValue::String(format!(
+            "Added expression {}",
+            expression
+        )))
+    }
+
+    fn set_layout(
+        p: &mut Context,
+        parameters: &json::JsonValue,
+    ) -> Result<json::JsonValue, IPCError> {
+        let layout = parameters["layout"]
+            .as_str()
+            .ok_or_else(|| IPCError::new("Missing layout", parameters.dump()))?;
+        // Check the layout here so that errors are reported to the client.
+        layout::parse(layout.to_owned())
+            .map_err(|e| IPCError::new("Invalid layout", e.to_string()))?;
+        p.try_change_layout(layout.to_owned());
+        Ok(json::JsonValue::String(format!(
+            "Changed layout to {}",
+            layout
+        )))
+    }
+
+    fn control_execution(
+        p: &mut Context,
+        parameters: &json::JsonValue,
+    ) -> Result<json::JsonValue, IPCError> {
+        let action = parameters["action"]
+            .as_str()
+            .ok_or_else(|| IPCError::new("Missing action", parameters.dump()))?;
+        let command = match action {
+            "interrupt" => {
+                p.gdb
+                    .mi
+                    .interrupt_execution()
+                    .map_err(|e| IPCError::new("Could not interrupt execution", e.to_string()))?;
+                return Ok(json::JsonValue::String("Interrupted execution".to_owned()));
+            }
+            "run" => MiCommand::exec_run(),
+            "continue" => MiCommand::exec_continue(),
+            "next" => MiCommand::exec_next(),
+            "step" => MiCommand::exec_step(),
+            "finish" => MiCommand::exec_finish(),
+            _ => return Err(IPCError::new("Unknown action", action)),
+        };
+        let result = p
+            .gdb
+            .mi
+            .execute(command)
+            .map_err(|e| execute_error("Could not control execution", e))?;
+        check_result("Could not control execution", &result)?;
+        Ok(json::JsonValue::String(format!("Executed {}", action)))
+    }
+}
+
+fn file_and_line(parameters: &json::JsonValue) -> Result<(&str, u32), IPCError> {
+    let parameters_obj = if let json::JsonValue::Object(parameters_obj) = parameters {
+        parameters_obj
+    } else {
+        return Err(IPCError::new(
+            "Parameters is not an object",
+            parameters.dump(),
+        ));
+    };
+    let file = parameters_obj
+        .get("file")
+        .and_then(|o| o.as_str())
+        .ok_or_else(|| IPCError::new("Missing file name", parameters.dump()))?;
+    let line = parameters_obj
+        .get("line")
+        .and_then(|o| o.as_u32())
+        .ok_or_else(|| IPCError::new("Missing integer line number", parameters.dump()))?;
+    Ok((file, line))
+}
+
+// Breakpoints are specified either by number or by file and line.
+fn breakpoints_at(
+    p: &Context,
+    parameters: &json::JsonValue,
+) -> Result<Vec<BreakPointNumber>, IPCError> {
+    let number = &parameters["number"];
+    if !number.is_null() {
+        let number = number
+            .as_str()
+            .map(|n| n.to_owned())
+            .or_else(|| number.as_usize().map(|n| n.to_string()))
+            .and_then(|n| n.parse::<BreakPointNumber>().ok())
+            .ok_or_else(|| IPCError::new("Invalid breakpoint number", parameters.dump()))?;
+        return Ok(if p.gdb.breakpoints.contains_key(&number) {
+            vec![number]
+        } else {
+            Vec::new()
+        });
+    }
+    let (file, line) = file_and_line(parameters)?;
+    let line = LineNumber::new(line as usize);
+    Ok(p.gdb
+        .breakpoints
+        .values()
+        .filter_map(|bp| match &bp.src_pos {
+            Some(src_pos) if src_pos.file == Path::new(file) && src_pos.line == line => {
+                Some(bp.number)
+            }
+            _ => None,
+        })
+        .collect())
+}
+
+fn display_numbers(numbers: &[BreakPointNumber]) -> String {
+    numbers
+        .iter()
+        .map(|n| n.to_string())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn execute_error(reason: &'static str, e: ExecuteError) -> IPCError {
+    IPCError::new(
+        reason,
+        match e {
+            ExecuteError::Busy => "GDB is busy",
+            ExecuteError::Quit => "GDB quit",
+            ExecuteError::Timeout => "GDB timed out",
+        },
+    )
+}
+
+// Modify breakpoints, interrupting and resuming a running program unless the request opts out via
+// `"interrupt": false`. The returned note tells the client whether the program was interrupted (and
+// resumed).
+fn modify_breakpoints<R>(
+    p: &mut Context,
+    parameters: &json::JsonValue,
+    operation: impl FnOnce(&mut GDB) -> Result<R, BreakpointOperationError>,
+) -> Result<(R, String), BreakpointOperationError> {
+    if parameters["interrupt"] == false {
+        return operation(&mut p.gdb).map(|r| (r, String::new()));
+    }
+    let (result, resumption) = p.gdb.while_stopped(p.command_timeout, operation)?;
+    let note = resumption
+        .describe()
+        .map(|note| format!(" ({})", note))
+        .unwrap_or_default();
+    Ok((result, note))
+}
+
+fn breakpoint_error(reason: &'static str, e: BreakpointOperationError) -> IPCError {
+    match e {
+        BreakpointOperationError::Busy => IPCError::new(reason, "GDB is busy"),
+        BreakpointOperationError::ExecutionError(msg) => IPCError::new(reason, msg),
+    }
+}
+
+fn check_result(reason: &'static str, result: &ResultRecord) -> Result<(), IPCError> {
+    if result.class == ResultClass::Error {
+        Err(IPCError::new(
+            reason,
+            result.results["msg"]
+                .as_str()
+                .map(|s| s.to_owned())
+                .unwrap_or_else(|| result.results.dump()),
+        ))
+    } else {
+        Ok(())
+    }
+}
+
+const EVENTS: &[&str] = &[
+    "stopped",
+    "running",
+    "breakpoint_created",
+    "breakpoint_modified",
+    "breakpoint_deleted",
+    "inferior_exited",
+];
+const SUBSCRIBER_WRITE_TIMEOUT: Duration = Duration::from_millis(100);
+
+struct Subscriber {
+    connection: UnixStream,
+    // None means all events
+    events: Option<Vec<String>>,
+}
+
+// Clients that receive events (see the `subscribe` function).
+#[derive(Default)]
+pub struct Subscribers {
+    subscribers: Vec<Subscriber>,
+}
+
+impl Subscribers {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn add(&mut self, connection: UnixStream, events: Option<Vec<String>>) {
+        self.subscribers.push(Subscriber { connection, events });
+    }
+
+    fn send(&mut self, event: &'static str, mut message: json::JsonValue) {
+        message["type"] = "event".into();
+        message["event"] = event.into();
+        let message = message.dump();
+        // Clients that closed the connection (or do not read their events) are dropped.
+        self.subscribers.retain_mut(|s| {
+            if s.events
+                .as_ref()
+                .is_some_and(|e| !e.iter().any(|e| e == event))
+            {
+                return true;
+            }
+            write_ipc_message(&mut s.connection, message.as_bytes()).is_ok()
+        });
+    }
+
+    // gdb does not notify us about breakpoints that ugdb creates or deletes itself (e.g., the
+    // watchpoints of the expression table).
+    pub fn breakpoint_created(&mut self, bp: &BreakPoint) {
+        if self.subscribers.is_empty() {
+            return;
+        }
+        self.send(
+            "breakpoint_created",
+            object! {
+                "breakpoints" => vec![breakpoint_to_json(bp)],
+            },
+        );
+    }
+
+    pub fn breakpoint_deleted(&mut self, number: BreakPointNumber) {
+        if self.subscribers.is_empty() {
+            return;
+        }
+        self.send(
+            "breakpoint_deleted",
+            object! {
+                "number" => number.to_string(),
+            },
+        );
+    }
+
+    // Translate a notification from gdb to events for the subscribers.
+    pub fn notify(&mut self, class: &AsyncClass, results: &Object) {
+        if self.subscribers.is_empty() {
+            return;
+        }
+        match class {
+            AsyncClass::Stopped => match StopEvent::decode(results) {
+                Ok(event) => self.send(
+                    "stopped",
+                    object! {
+                        "reason" => results.get("reason").and_then(|r| r.as_str()),
+                        "description" => event.reason.map(|r| r.to_string()),
+                        "thread_id" => event.thread_id,
+                        "frame" => event.frame.as_ref().map(frame_to_json),
+                    },
+                ),
+                Err(e) => log::warn!("Malformed stop event: {}", e),
+            },
+            AsyncClass::Running => self.send(
+                "running",
+                object! {
+                    "thread_id" => results.get("thread-id").and_then(|r| r.as_str()),
+                },
+            ),
+            AsyncClass::BreakPoint(BreakPointEvent::Deleted) => {
+                match BreakPointInfo::decode_deleted(results) {
+                    Ok(number) => self.send(
+                        "breakpoint_deleted",
+                        object! {
+                            "number" => number.to_string(),
+                        },
+                    ),
+                    Err(e) => log::warn!("Malformed breakpoint event: {}", e),
+                }
+            }
+            AsyncClass::BreakPoint(event) => match BreakPointInfo::decode_all(results) {
+                Ok(breakpoints) => self.send(
+                    if *event == BreakPointEvent::Created {
+                        "breakpoint_created"
+                    } else {
+                        "breakpoint_modified"
+                    },
+                    object! {
+                        "breakpoints" => breakpoints
+                            .into_iter()
+                            .map(|bp| breakpoint_to_json(&BreakPoint::from(bp)))
+                            .collect::<Vec<_>>(),
+                    },
+                ),
+                Err(e) => log::warn!("Malformed breakpoint event: {}", e),
+            },
+            AsyncClass::Thread(ThreadEvent::GroupExited) => match ThreadGroupEvent::decode(results)
+            {
+                Ok(group) => self.send(
+                    "inferior_exited",
+                    object! {
+                        "inferior" => group.id,
+                        "exit_code" => group.exit_code,
+                    },
+                ),
+                Err(e) => log::warn!("Malformed thread group event: {}", e),
+            },
+            _ => {}
+        }
+    }
+}
+
+fn frame_to_json(frame: &Frame) -> json::JsonValue {
+    object! {
+        "level" => frame.level,
+        "function" => frame.function.clone(),
+        "address" => frame.address.map(|a| a.to_string()),
+        "file" => frame.fullname.as_ref().map(|f| f.to_string_lossy().into_owned()),
+        "line" => frame.line,
+    }
+}
+
+fn breakpoint_to_json(bp: &BreakPoint) -> json::JsonValue {
+    object! {
+        "number" => bp.number.to_string(),
+        "enabled" => bp.enabled,
+        "address" => bp.address.map(|a| a.to_string()),
+        "file" => bp.src_pos.as_ref().map(|p| p.file.to_string_lossy().into_owned()),
+        "line" => bp.src_pos.as_ref().map(|p| p.line.raw_value()),
+        "location" => bp.original_location.clone(),
+        "watchpoint" => bp.watchpoint.as_ref().map(|(mode, expression)| object! {
+            "mode" => mode.name(),
+            "expression" => expression.clone(),
+        }),
+    }
 }
 
 const FALLBACK_RUNTIME_DIR: &str = "/tmp/";
@@ -200,12 +732,136 @@ const RUNTIME_SUBDIR: &str = "ugdb";
 const SOCKET_IDENTIFIER_LENGTH: usize = 64;
 const IPC_MSG_IDENTIFIER: &[u8] = b"ugdb-ipc";
 const HEADER_LENGTH: usize = 12;
+const METADATA_EXTENSION: &str = "json";
 
 #[allow(clippy::upper_case_acronyms)]
 pub struct IPC {
     socket_path: PathBuf,
 }
 
+// Metadata that is written next to the socket of each instance so that clients can choose one.
+#[derive(Debug, Clone, PartialEq)]
+pub struct InstanceInfo {
+    pub socket_path: PathBuf,
+    pub pid: u32,
+    pub working_directory: PathBuf,
+    pub target: Option<PathBuf>,
+    // Seconds since the unix epoch
+    pub start_time: u64,
+}
+
+impl InstanceInfo {
+    fn to_json(&self) -> json::JsonValue {
+        object! {
+            "pid" => self.pid,
+            "working_directory" => self.working_directory.to_string_lossy().into_owned(),
+            "target" => self.target.as_ref().map(|t| t.to_string_lossy().into_owned()),
+            "start_time" => self.start_time,
+        }
+    }
+
+    fn from_json(socket_path: PathBuf, info: &json::JsonValue) -> Option<Self> {
+        Some(InstanceInfo {
+            socket_path,
+            pid: info["pid"].as_u32()?,
+            working_directory: info["working_directory"].as_str()?.into(),
+            target: info["target"].as_str().map(PathBuf::from),
+            start_time: info["start_time"].as_u64()?,
+        })
+    }
+
+    fn metadata_path(socket_path: &Path) -> PathBuf {
+        socket_path.with_extension(METADATA_EXTENSION)
+    }
+}
+
+pub(crate) fn ugdb_dir() -> PathBuf {
+    match std::env::var_os("XDG_RUNTIME_DIR") {
+        Some(runtime_dir) => Path::new(&runtime_dir).join(RUNTIME_SUBDIR),
+        // The fallback is shared by all users, so everybody gets their own directory.
+        None => Path::new(FALLBACK_RUNTIME_DIR).join(format!(
+            "{}-{}",
+            RUNTIME_SUBDIR,
+            nix::unistd::getuid()
+        )),
+    }
+}
+
+// Anybody who can connect to a socket controls the debugged program, so the directory containing
+// them must only be accessible by the current user.
+pub(crate) fn create_private_dir(dir: &Path) -> std::io::Result<()> {
+    use std::os::unix::fs::{DirBuilderExt, MetadataExt, PermissionsExt};
+    match fs::DirBuilder::new().mode(0o700).create(dir) {
+        Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {}
+        other => other?,
+    }
+    let metadata = fs::symlink_metadata(dir)?;
+    if !metadata.is_dir() || metadata.uid() != nix::unistd::getuid().as_raw() {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::PermissionDenied,
+            format!(
+                "{} is not a directory owned by the current user",
+                dir.display()
+            ),
+        ));
+    }
+    if metadata.mode() & 0o077 != 0 {
+        fs::set_permissions(dir, fs::Permissions::from_mode(0o700))?;
+    }
+    Ok(())
+}
+
+pub(crate) fn is_same_user(connection: &UnixStream) -> bool {
+    use nix::sys::socket::{getsockopt, sockopt::PeerCredentials};
+    use std::os::unix::io::AsRawFd;
+    getsockopt(connection.as_raw_fd(), PeerCredentials)
+        .is_ok_and(|c| c.uid() == nix::unistd::getuid().as_raw())
+}
+
+fn is_alive(pid: u32) -> bool {
+    use nix::sys::signal;
+    use nix::unistd::Pid;
+    // Signal 0 only checks whether the process exists.
+    !matches!(
+        signal::kill(Pid::from_raw(pid as i32), None),
+        Err(nix::Error::Sys(nix::errno::Errno::ESRCH))
+    )
+}
+
+// List the running instances and remove the sockets of instances that are gone.
+pub fn list_instances() -> Vec<InstanceInfo> {
+    let entries = match fs::read_dir(ugdb_dir()) {
+        Ok(entries) => entries,
+        Err(_) => return Vec::new(),
+    };
+    let mut instances = Vec::new();
+    for path in entries.flatten().map(|e| e.path()) {
+        if path.extension().is_some() {
+            continue;
+        }
+        let metadata_path = InstanceInfo::metadata_path(&path);
+        let info = fs::read_to_string(&metadata_path)
+            .ok()
+            .and_then(|s| json::parse(&s).ok())
+            .and_then(|info| InstanceInfo::from_json(path.clone(), &info));
+        let is_stale = match &info {
+            Some(info) => !is_alive(info.pid),
+            // Instances of older versions do not write metadata, so we can only check whether
+            // anybody is listening.
+            None => UnixStream::connect(&path)
+                .is_err_and(|e| e.kind() == std::io::ErrorKind::ConnectionRefused),
+        };
+        if is_stale {
+            let _ = fs::remove_file(&path);
+            let _ = fs::remove_file(&metadata_path);
+        } else if let Some(info) = info {
+            instances.push(info);
+        }
+    }
+    instances.sort_by_key(|i| i.start_time);
+    instances
+}
+
 fn write_ipc_header<W: Write>(w: &mut W, msg_len: u32) -> std::io::Result<()> {
     let msg_len = msg_len.to_le();
     let msg_len_buf = [
@@ -219,13 +875,13 @@ fn write_ipc_header<W: Write>(w: &mut W, msg_len: u32) -> std::io::Result<()> {
     Ok(())
 }
 
-fn write_ipc_response<W: Write>(w: &mut W, msg: &[u8]) -> std::io::Result<()> {
+pub(crate) fn write_ipc_message<W: Write>(w: &mut W, msg: &[u8]) -> std::io::Result<()> {
     write_ipc_header(w, msg.len() as u32)?;
     w.write_all(msg)?;
     Ok(())
 }
 
-fn try_read_ipc_header<R: Read>(r: &mut R) -> Result<u32, ()> {
+pub(crate) fn try_read_ipc_header<R: Read>(r: &mut R) -> Result<u32, ()> {
     // The header has to look like this with four bytes (****) for the message length in little endian: "ugdb-ipc****"
     let mut buf = vec![0u8; HEADER_LENGTH];
     r.read_exact(&mut buf).map_err(|_| {})?;
@@ -249,10 +905,15 @@ fn try_read_ipc_request(connection: &mut UnixStream) -> Result<IPCRequest, ()> {
     Ok(IPCRequest {
         raw_request: msg_buf,
         response_channel: connection.try_clone().expect("clone handle"),
+        token: None,
     })
 }
 
-fn start_connection(mut connection: UnixStream, request_sink: std::sync::mpsc::Sender<Event>) {
+fn start_connection(
+    mut connection: UnixStream,
+    request_sink: std::sync::mpsc::Sender<Event>,
+    token: Option<Arc<String>>,
+) {
     let _ = thread::Builder::new()
         .name("IPC Connection".to_owned())
         .spawn(move || {
@@ -261,18 +922,24 @@ fn start_connection(mut connection: UnixStream, request_sink: std::sync::mpsc::S
             // Try to respond to requests as long as they are well formed. If the other side is
             // (for example) specifying an incorrect message length, we just terminate the
             // connection.
-            while let Ok(request) = try_read_ipc_request(&mut connection) {
+            while let Ok(mut request) = try_read_ipc_request(&mut connection) {
+                request.token = token.clone();
                 request_sink.send(Event::Ipc(request)).unwrap();
             }
         });
 }
 
 impl IPC {
-    pub fn setup(request_sink: std::sync::mpsc::Sender<Event>) -> std::io::Result<Self> {
-        let runtime_dir =
-            std::env::var_os("XDG_RUNTIME_DIR").unwrap_or_else(|| FALLBACK_RUNTIME_DIR.into());
-        let ugdb_dir = Path::join(runtime_dir.as_ref(), RUNTIME_SUBDIR);
-        let _ = fs::create_dir(&ugdb_dir); //Ignore error if dir exists, we check if we can access it soon.
+    pub fn setup(
+        request_sink: std::sync::mpsc::Sender<Event>,
+        working_directory: PathBuf,
+        target: Option<PathBuf>,
+        token: Option<String>,
+    ) -> std::io::Result<Self> {
+        use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+        let ugdb_dir = ugdb_dir();
+        create_private_dir(&ugdb_dir)?;
+        list_instances();
 
         use rand::Rng;
         let socket_name = rand::thread_rng()
@@ -282,22 +949,293 @@ impl IPC {
         let socket_path = ugdb_dir.join(socket_name);
 
         let listener = UnixListener::bind(&socket_path)?;
+        fs::set_permissions(&socket_path, fs::Permissions::from_mode(0o600))?;
+        let info = InstanceInfo {
+            socket_path: socket_path.clone(),
+            pid: std::process::id(),
+            working_directory,
+            target,
+            start_time: std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or(0),
+        };
+        fs::OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .mode(0o600)
+            .open(InstanceInfo::metadata_path(&socket_path))?
+            .write_all(info.to_json().dump().as_bytes())?;
 
+        let token = token.map(Arc::new);
         let _ = thread::Builder::new()
             .name("IPC Connection Listener".to_owned())
             .spawn(move || {
                 for connection in listener.incoming().flatten() {
-                    start_connection(connection, request_sink.clone());
+                    // The permissions of the socket should already prevent this, but they are not
+                    // respected on all systems.
+                    if !is_same_user(&connection) {
+                        log::warn!("Rejected ipc connection from another user");
+                        continue;
+                    }
+                    start_connection(connection, request_sink.clone(), token.clone());
                 }
             });
 
         Ok(IPC { socket_path })
     }
+
+    pub fn socket_path(&self) -> &Path {
+        &self.socket_path
+    }
 }
 
 impl Drop for IPC {
     fn drop(&mut self) {
         // We at least try to remove the socket. If it fails we cannot really do about it here.
         let _ = fs::remove_file(&self.socket_path);
+        let _ = fs::remove_file(InstanceInfo::metadata_path(&self.socket_path));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn results(json: &str) -> Object {
+        match json::parse(json).unwrap() {
+            json::JsonValue::Object(o) => o,
+            other => panic!("not an object: {}", other),
+        }
+    }
+
+    fn read_event(r: &mut UnixStream) -> json::JsonValue {
+        let len = try_read_ipc_header(r).unwrap();
+        let mut buf = vec![0u8; len as usize];
+        r.read_exact(&mut buf).unwrap();
+        json::parse(std::str::from_utf8(&buf).unwrap()).unwrap()
+    }
+
+    fn request(p: &mut Context, function: &str, parameters: json::JsonValue) -> json::JsonValue {
+        let (connection, _client) = UnixStream::pair().unwrap();
+        let request = object! {
+            "function" => function,
+            "parameters" => parameters,
+        };
+        match IPCRequest::handle(p, request.dump().into_bytes(), &connection, None) {
+            Ok(reply) => reply,
+            Err(e) => e.into_json(),
+        }
+    }
+
+    const TRANSCRIPT: &str = r#"> 1-break-insert /tmp/foo.c:12
+< 1^done,bkpt={number="1",type="breakpoint",disp="keep",enabled="y",addr="0x0000000000401126",func="main",file="foo.c",fullname="/tmp/foo.c",line="12",original-location="/tmp/foo.c:12"}
+< (gdb)
+> 2-break-disable 1
+< 2^done
+< (gdb)
+> 3-interpreter-exec console "info line 12"
+< ~"Line 12 of \"/tmp/foo.c\" starts at address 0x401126 <main+4>.\n"
+< 3^done
+< (gdb)
+> 4-environment-pwd
+< 4^done,cwd="/tmp"
+< (gdb)
+> 5-break-delete 1
+< 5^done
+< (gdb)
+"#;
+
+    #[test]
+    fn test_functions() {
+        let (mut p, _events) = Context::replay(TRANSCRIPT);
+        let location = object! {
+            "file" => "/tmp/foo.c",
+            "line" => 12,
+        };
+
+        let reply = request(&mut p, "toggle_breakpoint", location.clone());
+        assert_eq!(reply["type"], "success");
+        let mut disable = location.clone();
+        disable["enabled"] = false.into();
+        let reply = request(&mut p, "set_breakpoint_enabled", disable);
+        assert_eq!(reply["result"], "Disabled breakpoint 1");
+
+        let breakpoints = request(&mut p, "list_breakpoints", json::JsonValue::Null);
+        assert_eq!(breakpoints["result"].len(), 1);
+        assert_eq!(breakpoints["result"][0]["enabled"], false);
+        assert_eq!(breakpoints["result"][0]["line"], 12);
+
+        let reply = request(
+            &mut p,
+            "execute_command",
+            object! { "command" => "info line 12" },
+        );
+        assert_eq!(
+            reply["result"]["output"],
+            "Line 12 of \"/tmp/foo.c\" starts at address 0x401126 <main+4>.\n"
+        );
+
+        let reply = request(&mut p, "delete_breakpoint", object! { "number" => 1 });
+        assert_eq!(reply["result"], "Deleted breakpoint 1");
+        let reply = request(&mut p, "delete_breakpoint", location);
+        assert_eq!(reply["reason"], "No such breakpoint");
+
+        let reply = request(&mut p, "control_execution", object! { "action" => "jump" });
+        assert_eq!(reply["reason"], "Unknown action");
+    }
+
+    const RUNNING_TRANSCRIPT: &str = r#"> 1-exec-continue
+< 1^running
+< *running,thread-id="all"
+< (gdb)
+!interrupt
+< *stopped,reason="signal-received",signal-name="SIGINT",signal-meaning="Interrupt",thread-id="1",stopped-threads="all"
+< (gdb)
+> 2-break-insert /tmp/foo.c:12
+< 2^done,bkpt={number="1",type="breakpoint",disp="keep",enabled="y",addr="0x0000000000401126",func="main",file="foo.c",fullname="/tmp/foo.c",line="12",original-location="/tmp/foo.c:12"}
+< (gdb)
+> 3-exec-continue
+< 3^running
+< *running,thread-id="all"
+< (gdb)
+"#;
+
+    #[test]
+    fn test_breakpoint_while_running() {
+        let (mut p, events) = Context::replay(RUNNING_TRANSCRIPT);
+        let reply = request(
+            &mut p,
+            "control_execution",
+            object! { "action" => "continue" },
+        );
+        assert_eq!(reply["type"], "success");
+        assert!(p.gdb.mi.is_running());
+
+        let location = object! {
+            "file" => "/tmp/foo.c",
+            "line" => 12,
+            "interrupt" => false,
+        };
+        let reply = request(&mut p, "set_breakpoint", location.clone());
+        assert_eq!(reply["reason"], "Could not insert breakpoint");
+        assert_eq!(reply["details"], "GDB is busy");
+
+        let mut location = location;
+        location.remove("interrupt");
+        let reply = request(&mut p, "set_breakpoint", location);
+        assert_eq!(
+            reply["result"],
+            "Inserted breakpoint at /tmp/foo.c:12 (interrupted and resumed the program)"
+        );
+        assert!(p.gdb.mi.is_running());
+        assert_eq!(p.gdb.breakpoints.len(), 1);
+        // The stop caused by the interrupt is not reported.
+        assert!(!events.try_iter().any(|e| matches!(
+            e,
+            Event::OutOfBandRecord(crate::gdbmi::output::OutOfBandRecord::AsyncRecord {
+                class: AsyncClass::Stopped,
+                ..
+            })
+        )));
+    }
+
+    #[test]
+    fn test_breakpoint_not_resumed() {
+        let transcript = RUNNING_TRANSCRIPT.replace(
+            "< 3^running\n< *running,thread-id=\"all\"\n",
+            "< 3^error,msg=\"Cannot resume\"\n",
+        );
+        let (mut p, _events) = Context::replay(&transcript);
+        request(
+            &mut p,
+            "control_execution",
+            object! { "action" => "continue" },
+        );
+        let reply = request(
+            &mut p,
+            "set_breakpoint",
+            object! { "file" => "/tmp/foo.c", "line" => 12 },
+        );
+        assert_eq!(
+            reply["result"],
+            "Inserted breakpoint at /tmp/foo.c:12 (interrupted the program, but failed to resume it: Cannot resume)"
+        );
+        assert!(!p.gdb.mi.is_running());
+    }
+
+    #[test]
+    fn test_token() {
+        let (mut p, _events) = Context::replay("");
+        let (connection, _client) = UnixStream::pair().unwrap();
+        let mut request = object! {
+            "function" => "list_breakpoints",
+        };
+        let mut handle = |request: &json::JsonValue| match IPCRequest::handle(
+            &mut p,
+            request.dump().into_bytes(),
+            &connection,
+            Some("secret"),
+        ) {
+            Ok(reply) => reply,
+            Err(e) => e.into_json(),
+        };
+        assert_eq!(handle(&request)["reason"], "Invalid token");
+        request["token"] = "guess".into();
+        assert_eq!(handle(&request)["reason"], "Invalid token");
+        request["token"] = "secret".into();
+        assert_eq!(handle(&request)["type"], "success");
+    }
+
+    #[test]
+    fn test_create_private_dir() {
+        use std::os::unix::fs::PermissionsExt;
+        let dir = std::env::temp_dir().join(format!("ugdb-test-{}", std::process::id()));
+        let mode = |dir: &Path| fs::metadata(dir).unwrap().permissions().mode() & 0o777;
+
+        create_private_dir(&dir).unwrap();
+        assert_eq!(mode(&dir), 0o700);
+        fs::set_permissions(&dir, fs::Permissions::from_mode(0o755)).unwrap();
+        create_private_dir(&dir).unwrap();
+        assert_eq!(mode(&dir), 0o700);
+        fs::remove_dir(&dir).unwrap();
+
+        fs::write(&dir, "").unwrap();
+        assert!(create_private_dir(&dir).is_err());
+        fs::remove_file(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_subscribers() {
+        let (connection, mut client) = UnixStream::pair().unwrap();
+        let mut subscribers = Subscribers::new();
+        subscribers.add(connection, Some(vec!["stopped".to_owned()]));
+
+        // Filtered out
+        subscribers.notify(&AsyncClass::Running, &results(r#"{"thread-id":"all"}"#));
+        subscribers.notify(
+            &AsyncClass::Stopped,
+            &results(
+                r#"{"reason":"end-stepping-range","thread-id":"1","frame":{"addr":"0x1131","func":"main","fullname":"/tmp/test.c","line":"3"}}"#,
+            ),
+        );
+        let event = read_event(&mut client);
+        assert_eq!(event["type"], "event");
+        assert_eq!(event["event"], "stopped");
+        assert_eq!(event["reason"], "end-stepping-range");
+        assert_eq!(event["thread_id"], 1);
+        assert_eq!(event["frame"]["function"], "main");
+        assert_eq!(event["frame"]["address"], "0x1131");
+        assert_eq!(event["frame"]["file"], "/tmp/test.c");
+        assert_eq!(event["frame"]["line"], 3);
+
+        // Closed connections are dropped
+        drop(client);
+        subscribers.notify(
+            &AsyncClass::Stopped,
+            &results(r#"{"reason":"signal-received"}"#),
+        );
+        assert!(subscribers.subscribers.is_empty());
     }
 }
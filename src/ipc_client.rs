@@ -0,0 +1,209 @@
+// A command line client for the ipc interface of running ugdb instances (`ugdb ipc ...`).
+use crate::ipc::{self, InstanceInfo};
+use std::ffi::OsString;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use structopt::StructOpt;
+use unix_socket::UnixStream;
+
+#[derive(StructOpt)]
+#[structopt(
+    name = "ugdb ipc",
+    about = "Control running ugdb instances (see IPC.md)."
+)]
+struct IpcOptions {
+    #[structopt(
+        long = "socket",
+        help = "Socket of the instance to talk to. [default: the instance for the current directory]",
+        parse(from_os_str)
+    )]
+    socket: Option<PathBuf>,
+    #[structopt(
+        long = "token",
+        help = "Token for instances started with --ipc-token.",
+        raw(env = r#""UGDB_IPC_TOKEN""#, hide_env_values = "true")
+    )]
+    token: Option<String>,
+    #[structopt(subcommand)]
+    command: IpcCommand,
+}
+
+#[derive(StructOpt)]
+enum IpcCommand {
+    #[structopt(name = "list", about = "List running ugdb instances.")]
+    List,
+    #[structopt(
+        name = "call",
+        about = "Call a function and print the response. Events are printed until ugdb exits after `subscribe`."
+    )]
+    Call {
+        #[structopt(help = "Name of the function, e.g., show_file.")]
+        function: String,
+        #[structopt(help = "Parameters as a json object. [default: {}]")]
+        parameters: Option<String>,
+    },
+}
+
+// Run the client with the arguments following `ugdb ipc`.
+pub fn run(args: impl Iterator<Item = OsString>) -> i32 {
+    let options = IpcOptions::from_iter(std::iter::once("ugdb ipc".into()).chain(args));
+    match options.command {
+        IpcCommand::List => {
+            let now = now();
+            for instance in ipc::list_instances() {
+                println!(
+                    "{}\t{}\t{}\t{}\t{}",
+                    instance.socket_path.display(),
+                    instance.pid,
+                    instance.working_directory.display(),
+                    instance
+                        .target
+                        .as_ref()
+                        .map(|t| t.display().to_string())
+                        .unwrap_or_else(|| "-".to_owned()),
+                    format_age(now.saturating_sub(instance.start_time)),
+                );
+            }
+            0
+        }
+        IpcCommand::Call {
+            function,
+            parameters,
+        } => {
+            let parameters = match parameters.as_deref().map(json::parse) {
+                None => json::JsonValue::new_object(),
+                Some(Ok(parameters)) => parameters,
+                Some(Err(e)) => {
+                    eprintln!("Malformed parameters: {}", e);
+                    return 2;
+                }
+            };
+            let socket = match options.socket {
+                Some(socket) => socket,
+                None => {
+                    let current_dir = std::env::current_dir().unwrap_or_default();
+                    match choose_instance(&ipc::list_instances(), &current_dir) {
+                        Some(instance) => instance.socket_path.clone(),
+                        None => {
+                            eprintln!(
+                                "No ugdb instance for {} (see `ugdb ipc list` and --socket).",
+                                current_dir.display(),
+                            );
+                            return 1;
+                        }
+                    }
+                }
+            };
+            match call(&socket, &function, parameters, options.token.as_deref()) {
+                Ok(true) => 0,
+                Ok(false) => 1,
+                Err(e) => {
+                    eprintln!("Failed to talk to {}: {}", socket.display(), e);
+                    1
+                }
+            }
+        }
+    }
+}
+
+// Send the request and print all messages that we receive. Returns whether the call succeeded.
+fn call(
+    socket: &Path,
+    function: &str,
+    parameters: json::JsonValue,
+    token: Option<&str>,
+) -> std::io::Result<bool> {
+    let mut connection = UnixStream::connect(socket)?;
+    let mut request = json::object! {
+        "function" => function,
+        "parameters" => parameters,
+    };
+    if let Some(token) = token {
+        request["token"] = token.into();
+    }
+    ipc::write_ipc_message(&mut connection, request.dump().as_bytes())?;
+
+    let response = read_message(&mut connection)?;
+    println!("{}", response.pretty(4));
+    let success = response["type"] == "success";
+    if success && function == "subscribe" {
+        while let Ok(event) = read_message(&mut connection) {
+            println!("{}", event.dump());
+        }
+    }
+    Ok(success)
+}
+
+fn read_message(connection: &mut UnixStream) -> std::io::Result<json::JsonValue> {
+    let invalid = |msg: String| std::io::Error::new(std::io::ErrorKind::InvalidData, msg);
+    let len = ipc::try_read_ipc_header(connection)
+        .map_err(|_| invalid("Malformed or missing message header".to_owned()))?;
+    let mut buf = vec![0u8; len as usize];
+    connection.read_exact(&mut buf)?;
+    let msg = String::from_utf8(buf).map_err(|e| invalid(e.to_string()))?;
+    json::parse(&msg).map_err(|e| invalid(e.to_string()))
+}
+
+// Prefer the instance whose working directory is closest to the given directory. If there is only
+// one instance, it is used regardless of its directory.
+fn choose_instance<'a>(instances: &'a [InstanceInfo], dir: &Path) -> Option<&'a InstanceInfo> {
+    instances
+        .iter()
+        .filter(|i| dir.starts_with(&i.working_directory))
+        .max_by_key(|i| (i.working_directory.components().count(), i.start_time))
+        .or(if instances.len() == 1 {
+            instances.first()
+        } else {
+            None
+        })
+}
+
+fn now() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+fn format_age(seconds: u64) -> String {
+    match seconds {
+        s if s < 60 => format!("{}s ago", s),
+        s if s < 60 * 60 => format!("{}m ago", s / 60),
+        s if s < 24 * 60 * 60 => format!("{}h ago", s / (60 * 60)),
+        s => format!("{}d ago", s / (24 * 60 * 60)),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn instance(dir: &str, start_time: u64) -> InstanceInfo {
+        InstanceInfo {
+            socket_path: PathBuf::from(format!("/tmp/ugdb/{}", start_time)),
+            pid: 1,
+            working_directory: dir.into(),
+            target: None,
+            start_time,
+        }
+    }
+
+    #[test]
+    fn test_choose_instance() {
+        let instances = vec![
+            instance("/home/user", 1),
+            instance("/home/user/project", 2),
+            instance("/home/user/project", 3),
+            instance("/srv", 4),
+        ];
+        let chosen = |dir: &str| choose_instance(&instances, Path::new(dir)).map(|i| i.start_time);
+        assert_eq!(chosen("/home/user/project/src"), Some(3));
+        assert_eq!(chosen("/home/user/other"), Some(1));
+        assert_eq!(chosen("/home/username"), None);
+        assert_eq!(chosen("/"), None);
+        assert_eq!(
+            choose_instance(&instances[3..], Path::new("/")).map(|i| i.start_time),
+            Some(4)
+        );
+    }
+}
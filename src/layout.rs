@@ -89,7 +89,7 @@ impl<'a> Input<'a> {
 }
 
 fn try_parse_weight(i: &mut Input) -> f64 {
-    if !i.current().map(|v| v.is_digit(10)).unwrap_or(false) {
+    if !i.current().map(|v| v.is_ascii_digit()).unwrap_or(false) {
         return 1.0;
     }
     let mut w = 0;
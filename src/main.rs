@@ -1,8 +1,12 @@
+mod async_execution;
 mod completion;
+mod dap;
 mod gdb;
 mod gdb_expression_parsing;
 mod gdbmi;
+mod gdbserver;
 mod ipc;
+mod ipc_client;
 mod layout;
 mod tui;
 
@@ -12,19 +16,21 @@ use std::time::Duration;
 
 use std::sync::mpsc::Sender;
 
-use gdb::GDB;
-use gdbmi::output::OutOfBandRecord;
-use gdbmi::{GDBBuilder, OutOfBandRecordSink};
+use async_execution::{PendingCommands, Timeouts};
+use gdb::{response::GDBResponseError, GDB};
+use gdbmi::commands::{MiCommand, TargetType};
+use gdbmi::output::{str_field, OutOfBandRecord, ResultClass, ResultRecord};
+use gdbmi::{ExecuteError, GDBBuilder, OutOfBandRecordSink, Token};
 use log::{debug, warn};
 use nix::sys::signal::Signal;
 use nix::sys::termios;
 use std::path::PathBuf;
 use structopt::StructOpt;
 use tui::{Tui, TuiContainerType};
-use unsegen::base::{Color, StyleModifier, Terminal};
+use unsegen::base::{Color, RowIndex, StyleModifier, Terminal};
 use unsegen::container::ContainerManager;
 use unsegen::input::{Input, Key, NavigateBehavior, ToEvent};
-use unsegen::widget::{Blink, RenderingHints};
+use unsegen::widget::{Blink, RenderingHints, Widget};
 
 const EVENT_BUFFER_DURATION_MS: u64 = 10;
 const FOCUS_ESCAPE_MAX_DURATION_MS: u64 = 200;
@@ -32,7 +38,9 @@ const CURSOR_BLINK_PERIOD_MS: u64 = 500;
 const CURSOR_BLINK_TIMES: u8 = 20;
 
 #[derive(StructOpt)]
-#[structopt()]
+#[structopt(raw(
+    after_help = r#""Use `ugdb ipc --help` to control running instances from the command line.""#
+))]
 struct Options {
     #[structopt(
         long = "gdb",
@@ -67,6 +75,30 @@ struct Options {
         parse(from_os_str)
     )]
     rr_path: PathBuf,
+    #[structopt(
+        long = "remote",
+        help = "Connect to a remote target (e.g., a gdbserver listening on host:port) after startup.",
+        conflicts_with = "extended_remote"
+    )]
+    remote: Option<String>,
+    #[structopt(
+        long = "extended-remote",
+        help = "Connect to a remote target in extended mode (e.g., a gdbserver started with --multi) after startup."
+    )]
+    extended_remote: Option<String>,
+    #[structopt(
+        long = "gdbserver",
+        help = "Run the program in a local gdbserver whose io is connected to the terminal pane.",
+        raw(conflicts_with_all = r#"&["rr", "remote", "extended_remote"]"#)
+    )]
+    gdbserver: bool,
+    #[structopt(
+        long = "gdbserver-path",
+        help = "Path to alternative gdbserver binary.",
+        default_value = "gdbserver",
+        parse(from_os_str)
+    )]
+    gdbserver_path: PathBuf,
     #[structopt(
         long = "cd",
         help = "Run GDB using directory as its working directory, instead of the current directory.",
@@ -121,6 +153,56 @@ struct Options {
         help = "Define initial entries for the expression table."
     )]
     initial_expression_table_entries: Vec<String>,
+    #[structopt(
+        long = "expression-set",
+        help = "Load a set of expressions (see `!expr load`) into the expression table."
+    )]
+    expression_sets: Vec<String>,
+    #[structopt(
+        long = "var-objects",
+        help = "Back expression table entries by gdb variable objects (supports pretty printers)."
+    )]
+    var_objects: bool,
+    #[structopt(
+        long = "command-timeout",
+        help = "Time (in milliseconds) after which ugdb stops waiting for the result of a background gdb command.",
+        default_value = "5000"
+    )]
+    command_timeout_ms: u64,
+    #[structopt(
+        long = "record-mi",
+        help = "Record all gdb/mi traffic to the given file (e.g., to attach it to a bug report).",
+        parse(from_os_str)
+    )]
+    record_mi: Option<PathBuf>,
+    #[structopt(
+        long = "replay-mi",
+        help = "Do not start gdb, but replay a transcript recorded via --record-mi.",
+        parse(from_os_str)
+    )]
+    replay_mi: Option<PathBuf>,
+    #[structopt(
+        long = "ipc-token",
+        help = "Only accept ipc requests that contain the given token.",
+        raw(env = r#""UGDB_IPC_TOKEN""#, hide_env_values = "true")
+    )]
+    ipc_token: Option<String>,
+    #[structopt(
+        long = "dap",
+        help = "Serve the Debug Adapter Protocol (e.g., for editors) on the given unix domain socket path or tcp address (host:port, requires --dap-token). Stdio is not supported, because it is used by the tui."
+    )]
+    dap: Option<String>,
+    #[structopt(
+        long = "dap-token",
+        help = "Only accept debug adapter clients that pass the given token as \"token\" in their launch or attach request. Required for tcp addresses.",
+        raw(env = r#""UGDB_DAP_TOKEN""#, hide_env_values = "true")
+    )]
+    dap_token: Option<String>,
+    #[structopt(
+        long = "dap-allow-remote",
+        help = "Allow serving the Debug Adapter Protocol on tcp addresses that are reachable from other hosts."
+    )]
+    dap_allow_remote: bool,
     #[structopt(
         long = "layout",
         help = "Define the initial tui layout via a format string.",
@@ -172,8 +254,22 @@ impl Options {
         if let Some(src_dir) = self.source_dir {
             gdb_builder = gdb_builder.source_dir(src_dir);
         }
+        if let Some(transcript_file) = self.record_mi {
+            gdb_builder = gdb_builder.record_mi(transcript_file);
+        }
+        if let Some(target) = self.remote {
+            gdb_builder = gdb_builder.remote_target(TargetType::Remote, target);
+        }
+        if let Some(target) = self.extended_remote {
+            gdb_builder = gdb_builder.remote_target(TargetType::ExtendedRemote, target);
+        }
         if self.rr {
             gdb_builder = gdb_builder.rr_args(self.rr_path, self.program);
+        } else if self.gdbserver {
+            // The program is run by gdbserver, gdb only needs it for the symbols.
+            if let Some(program) = self.program.first() {
+                gdb_builder = gdb_builder.program(PathBuf::from(program));
+            }
         } else {
             let (program, args) = self
                 .program
@@ -190,25 +286,31 @@ impl Options {
     }
 }
 
+// Send errors are ignored: The receiver only goes away when ugdb shuts down (or a test ends), in
+// which case nobody is interested in the output of gdb anymore.
 struct MpscOobRecordSink(Sender<Event>);
 
 impl OutOfBandRecordSink for MpscOobRecordSink {
     fn send(&self, data: OutOfBandRecord) {
-        self.0.send(Event::OutOfBandRecord(data)).unwrap();
+        let _ = self.0.send(Event::OutOfBandRecord(data));
+    }
+    fn send_async_result(&self, record: ResultRecord) {
+        let _ = self.0.send(Event::AsyncResult(record));
     }
 }
 
 impl Drop for MpscOobRecordSink {
     fn drop(&mut self) {
-        self.0.send(Event::GdbShutdown).unwrap();
+        let _ = self.0.send(Event::GdbShutdown);
     }
 }
 
-struct MpscSlaveInputSink(Sender<Event>);
+// Forwards the output of the terminal of the inferior with the given id.
+struct MpscSlaveInputSink(Sender<Event>, String);
 
 impl unsegen_terminal::SlaveInputSink for MpscSlaveInputSink {
     fn receive_bytes_from_pty(&mut self, data: Box<[u8]>) {
-        self.0.send(Event::Pty(data)).unwrap();
+        self.0.send(Event::Pty(self.1.clone(), data)).unwrap();
     }
 }
 
@@ -230,6 +332,14 @@ impl MessageSink {
 pub struct Context {
     pub gdb: GDB,
     event_sink: Sender<Event>,
+    pending_commands: PendingCommands,
+    timeouts: Timeouts,
+    command_timeout: Duration,
+    // Used to restart gdb after a crash. Not available when replaying a transcript.
+    gdb_builder: Option<GDBBuilder>,
+    gdbserver: Option<gdbserver::GdbServer>,
+    ipc_subscribers: ipc::Subscribers,
+    dap_clients: dap::Clients,
 }
 
 impl Context {
@@ -248,6 +358,229 @@ impl Context {
     fn show_file(&mut self, file: String, line: unsegen::base::LineNumber) {
         self.event_sink.send(Event::ShowFile(file, line)).unwrap();
     }
+
+    fn add_expression(&mut self, expression: String) {
+        self.event_sink
+            .send(Event::AddExpression(expression))
+            .unwrap();
+    }
+
+    fn load_expressions(&mut self, name: String) {
+        self.event_sink.send(Event::LoadExpressions(name)).unwrap();
+    }
+
+    fn save_expressions(&mut self, name: String) {
+        self.event_sink.send(Event::SaveExpressions(name)).unwrap();
+    }
+
+    // Create a terminal for the inferior with the given id (see Event::Pty).
+    fn create_terminal(&self, inferior: &str) -> std::io::Result<unsegen_terminal::Terminal> {
+        unsegen_terminal::Terminal::new(MpscSlaveInputSink(
+            self.event_sink.clone(),
+            inferior.to_owned(),
+        ))
+    }
+
+    fn quit(&mut self) {
+        self.event_sink.send(Event::Quit).unwrap();
+    }
+
+    fn connect_to_remote_target(&mut self) {
+        let (target_type, target) = match self
+            .gdb_builder
+            .as_ref()
+            .and_then(|builder| builder.get_remote_target())
+        {
+            Some((target_type, target)) => (target_type, target.to_owned()),
+            None => return,
+        };
+        self.log(format!("Connecting to {}...", target));
+        // Connecting may take a while (gdb retries for some time), so we do not time out here.
+        self.execute_async_with_timeout(
+            MiCommand::target_select(target_type, &target),
+            None,
+            move |_, p, result| match result {
+                Ok(ResultRecord {
+                    class: ResultClass::Connected,
+                    ..
+                }) => p.log(format!("Connected to {}.", target)),
+                Ok(record) => p.log(format!(
+                    "Failed to connect to {}: {}",
+                    target,
+                    record.results["msg"].as_str().unwrap_or("unknown error")
+                )),
+                Err(e) => p.log(format!("Failed to connect to {}: {:?}", target, e)),
+            },
+        );
+    }
+
+    // Any command (including those of gdb's startup scripts) may change the working directory of
+    // gdb. It is therefore queried after commands and at stops to be able to restore it if gdb has
+    // to be restarted.
+    pub fn update_working_dir(&mut self) {
+        self.execute_async(MiCommand::environment_pwd(), |_, p, result| {
+            let dir = result
+                .map_err(GDBResponseError::from)
+                .and_then(|r| Ok(PathBuf::from(str_field(&r.results, "cwd")?)));
+            match dir {
+                Ok(dir) => p.gdb.working_dir = Some(dir),
+                // The directory is queried again at the next stop.
+                Err(GDBResponseError::Execution(ExecuteError::Busy)) => {}
+                Err(e) => warn!("Failed to query working directory: {:?}", e),
+            }
+        });
+    }
+
+    // Spawn a new gdb (with the original options) to replace one that has crashed and restore
+    // the previous session as far as possible.
+    fn restart_gdb(&mut self) {
+        let mut builder = match &self.gdb_builder {
+            Some(builder) => builder.clone().append_record(),
+            None => {
+                self.log("Cannot restart GDB.");
+                self.quit();
+                return;
+            }
+        };
+        // The gdbserver most likely exited together with the session that was connected to it.
+        if let Some(server) = &mut self.gdbserver {
+            match server.respawn().map(|()| server.address().to_owned()) {
+                Ok(address) => {
+                    builder = builder.remote_target(TargetType::Remote, address);
+                    self.gdb_builder = Some(builder.clone());
+                }
+                Err(e) => self.log(format!("Failed to restart gdbserver: {}", e)),
+            }
+        }
+        let mi = match builder.try_spawn(MpscOobRecordSink(self.event_sink.clone())) {
+            Ok(mi) => mi,
+            Err(e) => {
+                let reason = format!("Failed to restart GDB: {}", e);
+                self.defer(move |tui, _| tui.console.ask_to_restart_gdb(&reason));
+                return;
+            }
+        };
+        // The old gdb will never answer, so we fail all commands that are still pending.
+        for callback in self.pending_commands.take_all() {
+            self.defer(move |tui, p| callback(tui, p, Err(ExecuteError::Quit)));
+        }
+        let (failures, renumbered) = self.gdb.restore_session(mi);
+        self.defer(move |tui, _| {
+            tui.reset_terminals();
+            tui.expression_table.renumber_watchpoints(&renumbered);
+        });
+        for failure in failures {
+            self.log(failure);
+        }
+        self.log("Restarted GDB. The debugged program has to be started again.");
+        self.connect_to_remote_target();
+        self.defer(|tui, p| tui.expression_table.update_results(p));
+    }
+
+    // Execute the command without blocking and call `callback` from the event loop once the result
+    // is available, the command failed or it timed out (using the default command timeout).
+    pub fn execute_async<F>(&mut self, command: MiCommand, callback: F) -> Token
+    where
+        F: for<'t> FnOnce(&mut tui::Tui<'t>, &mut Context, Result<ResultRecord, ExecuteError>)
+            + 'static,
+    {
+        self.execute_async_with_timeout(command, Some(self.command_timeout), callback)
+    }
+
+    pub fn execute_async_with_timeout<F>(
+        &mut self,
+        command: MiCommand,
+        timeout: Option<Duration>,
+        callback: F,
+    ) -> Token
+    where
+        F: for<'t> FnOnce(&mut tui::Tui<'t>, &mut Context, Result<ResultRecord, ExecuteError>)
+            + 'static,
+    {
+        let result = self.gdb.mi.execute_async(command);
+        self.register_async(result, timeout, callback)
+    }
+
+    // Like execute_async, but the console output of the command is passed to the callback (as
+    // field "console" of the result) instead of being shown in the console.
+    pub fn execute_async_capturing_console<F>(&mut self, command: MiCommand, callback: F) -> Token
+    where
+        F: for<'t> FnOnce(&mut tui::Tui<'t>, &mut Context, Result<ResultRecord, ExecuteError>)
+            + 'static,
+    {
+        let result = self.gdb.mi.execute_async_capturing_console(command);
+        self.register_async(result, Some(self.command_timeout), callback)
+    }
+
+    fn register_async<F>(
+        &mut self,
+        result: Result<Token, ExecuteError>,
+        timeout: Option<Duration>,
+        callback: F,
+    ) -> Token
+    where
+        F: for<'t> FnOnce(&mut tui::Tui<'t>, &mut Context, Result<ResultRecord, ExecuteError>)
+            + 'static,
+    {
+        let token = match result {
+            Ok(token) => token,
+            Err(e) => {
+                // Report the error via the event loop as well, so that callers only have to
+                // handle errors in one place.
+                let token = self.gdb.mi.get_usable_token();
+                self.defer(move |tui, p| async_execution::dispatch(tui, p, token, Err(e)));
+                token
+            }
+        };
+        self.pending_commands.insert(token, Box::new(callback));
+        if let Some(timeout) = timeout {
+            self.timeouts.start(token, timeout);
+        }
+        token
+    }
+
+    // The callback of the command will not be called. Any result gdb returns will be dropped.
+    pub fn cancel_async(&mut self, token: Token) {
+        let _ = self.pending_commands.remove(token);
+    }
+
+    pub fn is_pending(&self, token: Token) -> bool {
+        self.pending_commands.is_pending(token)
+    }
+
+    // Run the callback from the event loop after the current event has been handled.
+    pub fn defer<F>(&mut self, callback: F)
+    where
+        F: for<'t> FnOnce(&mut tui::Tui<'t>, &mut Context) + 'static,
+    {
+        self.pending_commands.defer(Box::new(callback));
+    }
+
+    // Create a context that talks to a fake gdb replaying the given transcript. Events are sent
+    // to the returned receiver.
+    #[cfg(test)]
+    pub fn replay(transcript: &str) -> (Self, std::sync::mpsc::Receiver<Event>) {
+        let transcript = gdbmi::transcript::Transcript::parse(transcript.as_bytes()).unwrap();
+        let (event_sink, events) = std::sync::mpsc::channel();
+        let mi = gdbmi::GDB::replay(
+            transcript,
+            MpscOobRecordSink(event_sink.clone()),
+            "gdb".into(),
+        )
+        .unwrap();
+        let context = Context {
+            gdb: GDB::new(mi),
+            timeouts: Timeouts::new(event_sink.clone()),
+            event_sink,
+            pending_commands: PendingCommands::new(),
+            command_timeout: Duration::from_secs(1),
+            gdb_builder: None,
+            gdbserver: None,
+            ipc_subscribers: ipc::Subscribers::new(),
+            dap_clients: dap::Clients::new(None),
+        };
+        (context, events)
+    }
 }
 
 // A timer that can be used to receive an event at any time,
@@ -324,16 +657,23 @@ impl InputMode {
 pub enum Event {
     Signal(nix::sys::signal::Signal),
     Input(Input),
-    Pty(Box<[u8]>),
+    Pty(String, Box<[u8]>),
     CursorTimer,
     RenderTimer,
     FocusEscTimer,
     OutOfBandRecord(OutOfBandRecord),
+    AsyncResult(ResultRecord),
+    AsyncTimeout(Token),
     Log(String),
     ChangeLayout(String),
     ShowFile(String, unsegen::base::LineNumber),
+    AddExpression(String),
+    LoadExpressions(String),
+    SaveExpressions(String),
     GdbShutdown,
+    Quit,
     Ipc(IPCRequest),
+    Dap(dap::Message),
 }
 
 fn run() -> i32 {
@@ -368,7 +708,12 @@ fn run() -> i32 {
     let options = Options::from_args();
     let log_dir = options.log_dir.to_owned();
     let initial_expression_table_entries = options.initial_expression_table_entries.clone();
+    let var_objects = options.var_objects;
+    let expression_sets = options.expression_sets.clone();
     let layout = options.layout.clone();
+    let rr = options.rr;
+    let command_timeout = Duration::from_millis(options.command_timeout_ms);
+    let dap_token = options.dap_token.clone();
 
     std::panic::set_hook(Box::new(move |info| {
         // Switch back to main screen
@@ -398,25 +743,111 @@ fn run() -> i32 {
     }
 
     // Create terminal and setup slave input piping
-    let tui_terminal = unsegen_terminal::Terminal::new(MpscSlaveInputSink(event_sink.clone()))
-        .expect("Create PTY");
+    let tui_terminal = unsegen_terminal::Terminal::new(MpscSlaveInputSink(
+        event_sink.clone(),
+        tui::INITIAL_INFERIOR.to_owned(),
+    ))
+    .expect("Create PTY");
 
     // Setup ipc
-    let _ipc = ipc::IPC::setup(event_sink.clone()).expect("Setup ipc");
+    let working_directory = {
+        let current_dir = std::env::current_dir().unwrap_or_default();
+        match &options.cd {
+            Some(cd) => current_dir.join(cd),
+            None => current_dir,
+        }
+    };
+    let target = options
+        .program
+        .first()
+        .map(PathBuf::from)
+        .or_else(|| options.symbol_file.clone());
+    let ipc = match ipc::IPC::setup(
+        event_sink.clone(),
+        working_directory,
+        target,
+        options.ipc_token.clone(),
+    ) {
+        Ok(ipc) => ipc,
+        Err(e) => {
+            eprintln!("Failed to set up the ipc socket: {}", e);
+            return 0xf8;
+        }
+    };
+    let dap = match &options.dap {
+        Some(address) => {
+            match dap::Server::setup(
+                address,
+                options.dap_token.is_some(),
+                options.dap_allow_remote,
+                event_sink.clone(),
+            ) {
+                Ok(server) => Some(server),
+                Err(e) => {
+                    eprintln!(
+                        "Failed to serve the debug adapter protocol on {}: {}",
+                        address, e
+                    );
+                    return 0xf9;
+                }
+            }
+        }
+        None => None,
+    };
 
     // Start gdb and setup output event piping
     let gdb_path = options.gdb_path.to_string_lossy().to_string();
-    let mut gdb_builder = options.create_gdb_builder();
-    gdb_builder = gdb_builder.tty(tui_terminal.slave_name().into());
-    let gdb = GDB::new(
-        match gdb_builder.try_spawn(MpscOobRecordSink(event_sink.clone())) {
-            Ok(gdb) => gdb,
+    let gdbserver = if options.gdbserver {
+        if options.program.is_empty() {
+            eprintln!("--gdbserver requires a program to debug.");
+            return 0xfa;
+        }
+        match gdbserver::GdbServer::spawn(
+            &options.gdbserver_path,
+            std::path::Path::new(tui_terminal.slave_name()),
+            &options.program,
+        ) {
+            Ok(server) => Some(server),
             Err(e) => {
-                eprintln!("Failed to spawn gdb process (\"{}\"): {}", gdb_path, e);
-                return 0xfc;
+                eprintln!(
+                    "Failed to spawn gdbserver (\"{}\"): {}",
+                    options.gdbserver_path.to_string_lossy(),
+                    e
+                );
+                return 0xfa;
             }
-        },
-    );
+        }
+    } else {
+        None
+    };
+    let (mi, gdb_builder) = if let Some(transcript_file) = options.replay_mi.clone() {
+        let mi = gdbmi::transcript::Transcript::load(&transcript_file).and_then(|transcript| {
+            gdbmi::GDB::replay(
+                transcript,
+                MpscOobRecordSink(event_sink.clone()),
+                options.gdb_path.clone(),
+            )
+        });
+        (mi, None)
+    } else {
+        let mut gdb_builder = options.create_gdb_builder();
+        gdb_builder = gdb_builder.tty(tui_terminal.slave_name().into());
+        if let Some(server) = &gdbserver {
+            gdb_builder =
+                gdb_builder.remote_target(TargetType::Remote, server.address().to_owned());
+        }
+        let mi = gdb_builder
+            .clone()
+            .try_spawn(MpscOobRecordSink(event_sink.clone()));
+        (mi, Some(gdb_builder))
+    };
+    let gdb = GDB::new(match mi {
+        Ok(gdb) => gdb,
+        Err(e) => {
+            eprintln!("Failed to spawn gdb process (\"{}\"): {}", gdb_path, e);
+            return 0xfc;
+        }
+    });
 
     let stdout = std::io::stdout();
 
@@ -433,7 +864,24 @@ fn run() -> i32 {
     let mut context = Context {
         gdb,
         event_sink: event_sink.clone(),
+        pending_commands: PendingCommands::new(),
+        timeouts: Timeouts::new(event_sink.clone()),
+        command_timeout,
+        gdb_builder,
+        gdbserver,
+        ipc_subscribers: ipc::Subscribers::new(),
+        dap_clients: dap::Clients::new(dap_token),
     };
+    if let Some(dap) = &dap {
+        let address = dap
+            .local_addr()
+            .map(|address| address.to_string())
+            .or_else(|| dap.socket_path().map(|path| path.display().to_string()))
+            .unwrap_or_default();
+        context.log(format!("Serving the debug adapter protocol on {}", address));
+    }
+    context.connect_to_remote_target();
+    context.update_working_dir();
 
     {
         let mut terminal = match Terminal::new(stdout.lock()) {
@@ -444,9 +892,15 @@ fn run() -> i32 {
             }
         };
         let mut tui = Tui::new(tui_terminal, &theme_set.themes["base16-ocean.dark"]);
+        tui.status_bar.set_rr(rr);
+        tui.status_bar.set_ipc_socket(ipc.socket_path().to_owned());
+        tui.expression_table.use_var_objects(var_objects);
         for entry in initial_expression_table_entries {
             tui.expression_table.add_entry(entry);
         }
+        for name in expression_sets {
+            tui.expression_table.load_set(&name, &mut context);
+        }
 
         // Start stdin thread _after_ building terminal (and setting the actual terminal to raw
         // mode to avoid race condition where the first 'set of input' is buffered
@@ -549,12 +1003,35 @@ fn run() -> i32 {
                     Event::OutOfBandRecord(record) => {
                         tui.add_out_of_band_record(record, &mut context);
                     }
+                    Event::AsyncResult(record) => {
+                        let token = record.token.expect("async results always have a token");
+                        async_execution::dispatch(&mut tui, &mut context, token, Ok(record));
+                    }
+                    Event::AsyncTimeout(token) => {
+                        async_execution::dispatch(
+                            &mut tui,
+                            &mut context,
+                            token,
+                            Err(ExecuteError::Timeout),
+                        );
+                    }
                     Event::Log(msg) => {
                         tui.console.write_to_gdb_log(msg);
                     }
                     Event::ShowFile(file, line) => {
                         tui.src_view.show_file(file, line, &mut context);
                     }
+                    Event::AddExpression(expression) => {
+                        tui.expression_table.add_entry(expression);
+                        tui.expression_table.update_results(&mut context);
+                    }
+                    Event::LoadExpressions(name) => {
+                        tui.expression_table.load_set(&name, &mut context);
+                        tui.expression_table.update_results(&mut context);
+                    }
+                    Event::SaveExpressions(name) => {
+                        tui.expression_table.save_set(&name, &mut context);
+                    }
                     Event::ChangeLayout(layout) => {
                         match layout::parse(layout) {
                             Ok(layout) => {
@@ -565,14 +1042,26 @@ fn run() -> i32 {
                             }
                         };
                     }
-                    Event::GdbShutdown => {
+                    Event::GdbShutdown => match wait_for_exit(&mut context.gdb.mi) {
+                        Some(status) if !status.success() && context.gdb_builder.is_some() => {
+                            tui.console.ask_to_restart_gdb(&format!(
+                                "GDB terminated unexpectedly ({})",
+                                status
+                            ));
+                        }
+                        _ => break 'runloop,
+                    },
+                    Event::Quit => {
                         break 'runloop;
                     }
                     Event::Ipc(request) => {
                         request.respond(&mut context);
                     }
-                    Event::Pty(pty_output) => {
-                        tui.add_pty_input(&pty_output);
+                    Event::Dap(message) => {
+                        dap::handle(&mut context, message);
+                    }
+                    Event::Pty(inferior, pty_output) => {
+                        tui.add_pty_input(&inferior, &pty_output);
                     }
                     Event::Signal(signal_event) => {
                         let sig = signal_event;
@@ -583,7 +1072,12 @@ fn run() -> i32 {
                                     warn!("Unable to handle SIGTSTP: {}", e);
                                 }
                             }
-                            Signal::SIGTERM => context.gdb.kill(),
+                            Signal::SIGTERM => {
+                                if has_exited(&mut context.gdb.mi) {
+                                    break 'runloop;
+                                }
+                                context.gdb.kill()
+                            }
                             _ => {}
                         }
                         debug!("received signal {:?}", sig);
@@ -597,17 +1091,32 @@ fn run() -> i32 {
                             .try_start(Duration::from_millis(FOCUS_ESCAPE_MAX_DURATION_MS));
                     }
                 }
+                async_execution::run_deferred(&mut tui, &mut context);
                 tui.update_after_event(&mut context);
+                context
+                    .dap_clients
+                    .sync_breakpoints(&context.gdb.breakpoints, None);
                 render_delay_timer.try_start(Duration::from_millis(EVENT_BUFFER_DURATION_MS));
             }
             if esc_timer_needs_reset {
                 focus_esc_timer.reset();
             }
+            let hints = RenderingHints::default().blink(cursor_status);
+            let window = terminal.create_root_window();
+            // The last line is reserved for the status bar.
+            let status_line = RowIndex::new(window.get_height().raw_value() - 1);
+            let window = match window.split(status_line) {
+                Ok((window, status_window)) => {
+                    (&tui.status_bar).draw(status_window, hints);
+                    window
+                }
+                Err(window) => window,
+            };
             app.draw(
-                terminal.create_root_window(),
+                window,
                 &mut tui,
                 input_mode.associated_border_style(),
-                RenderingHints::default().blink(cursor_status),
+                hints,
             );
             terminal.present();
         }
@@ -616,7 +1125,7 @@ fn run() -> i32 {
     let mut join_retry_counter = 0;
     let join_retry_duration = Duration::from_millis(100);
     let child_exit_status = loop {
-        if let Some(ret) = context.gdb.mi.process.try_wait().expect("gdb exited") {
+        if let Some(ret) = context.gdb.mi.try_wait().expect("gdb exited") {
             break ret;
         }
         std::thread::sleep(join_retry_duration);
@@ -633,7 +1142,28 @@ fn run() -> i32 {
     }
 }
 
+fn has_exited(gdb: &mut gdbmi::GDB) -> bool {
+    matches!(gdb.try_wait(), Ok(Some(_)) | Err(_))
+}
+
+// Gdb closes its output right before exiting, so we may have to wait a little for its exit status.
+fn wait_for_exit(gdb: &mut gdbmi::GDB) -> Option<std::process::ExitStatus> {
+    for _ in 0..10 {
+        match gdb.try_wait() {
+            Ok(Some(status)) => return Some(status),
+            Ok(None) => std::thread::sleep(Duration::from_millis(100)),
+            Err(_) => return None,
+        }
+    }
+    None
+}
+
 fn main() {
-    let exit_code = run();
+    // Handle `ugdb ipc ...` here, because the positional arguments of ugdb are taken by the program.
+    let exit_code = if std::env::args_os().nth(1).is_some_and(|arg| arg == "ipc") {
+        ipc_client::run(std::env::args_os().skip(2))
+    } else {
+        run()
+    };
     std::process::exit(exit_code);
 }
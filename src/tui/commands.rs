@@ -7,12 +7,14 @@ use crate::Context;
 
 use log::error;
 
+type CommandFn = Box<dyn FnMut(&mut Context) -> Result<(), ExecuteError>>;
+
 pub struct Command {
-    cmd: Box<dyn FnMut(&mut Context) -> Result<(), ExecuteError>>,
+    cmd: CommandFn,
 }
 
 impl Command {
-    fn new(cmd: Box<dyn FnMut(&mut Context) -> Result<(), ExecuteError>>) -> Command {
+    fn new(cmd: CommandFn) -> Command {
         Command { cmd }
     }
     fn from_mi_with_msg(cmd: MiCommand, success_msg: &'static str) -> Command {
@@ -33,7 +35,8 @@ impl Command {
 
 pub enum CommandState {
     Idle,
-    WaitingForConfirmation(Command),
+    // Second command (if any) is executed if the user declines.
+    WaitingForConfirmation(Command, Option<Command>),
 }
 
 impl CommandState {
@@ -42,20 +45,45 @@ impl CommandState {
         std::mem::swap(&mut tmp_state, self);
         *self = match tmp_state {
             CommandState::Idle => Self::dispatch_command(line, p),
-            CommandState::WaitingForConfirmation(cmd) => Self::execute_if_confirmed(line, cmd, p),
+            CommandState::WaitingForConfirmation(cmd, otherwise) => {
+                Self::execute_if_confirmed(line, cmd, otherwise, p)
+            }
         }
     }
 
-    fn execute_if_confirmed(line: &str, cmd: Command, p: &mut Context) -> Self {
+    pub fn ask_to_restart_gdb() -> Self {
+        CommandState::WaitingForConfirmation(
+            Command::new(Box::new(|p: &mut Context| {
+                p.restart_gdb();
+                Ok(())
+            })),
+            Some(Command::new(Box::new(|p: &mut Context| {
+                p.quit();
+                Ok(())
+            }))),
+        )
+    }
+
+    fn execute_if_confirmed(
+        line: &str,
+        cmd: Command,
+        otherwise: Option<Command>,
+        p: &mut Context,
+    ) -> Self {
         match line {
             "y" | "Y" | "yes" => {
                 Self::try_execute(cmd, p);
                 CommandState::Idle
             }
-            "n" | "N" | "no" => CommandState::Idle,
+            "n" | "N" | "no" => {
+                if let Some(otherwise) = otherwise {
+                    Self::try_execute(otherwise, p);
+                }
+                CommandState::Idle
+            }
             _ => {
                 p.log("Please type 'y' or 'n'.");
-                CommandState::WaitingForConfirmation(cmd)
+                CommandState::WaitingForConfirmation(cmd, otherwise)
             }
         }
     }
@@ -64,6 +92,7 @@ impl CommandState {
         match e {
             ExecuteError::Quit => p.log("quit"),
             ExecuteError::Busy => p.log("GDB is running!"),
+            ExecuteError::Timeout => p.log("GDB did not respond in time."),
         }
     }
 
@@ -85,7 +114,7 @@ impl CommandState {
                     "A debugging session is active. {} (y or n)",
                     confirmation_question
                 ));
-                CommandState::WaitingForConfirmation(cmd)
+                CommandState::WaitingForConfirmation(cmd, None)
             }
             Ok(false) => {
                 Self::try_execute(cmd, p);
@@ -106,7 +135,9 @@ impl CommandState {
         let args_str = &line[args_begin..];
         match cmd {
             "!stop" => {
-                p.gdb.mi.interrupt_execution().expect("interrupted gdb");
+                if let Err(e) = p.gdb.mi.interrupt_execution() {
+                    p.log(format!("Failed to interrupt GDB: {}", e));
+                }
                 // This does not always seem to unblock gdb, but only hang it
                 //gdb.execute(&MiCommand::exec_interrupt()).expect("Interrupt");
 
@@ -122,6 +153,17 @@ impl CommandState {
 
                 CommandState::Idle
             }
+            "!expr" => {
+                let (subcommand, name) = args_str.split_once(' ').unwrap_or((args_str, ""));
+                match (subcommand, name.trim()) {
+                    (_, "") => p.log("Usage: !expr load <name> or !expr save <name>"),
+                    ("load", name) => p.load_expressions(name.to_owned()),
+                    ("save", name) => p.save_expressions(name.to_owned()),
+                    (other, _) => p.log(format!("Unknown subcommand: {}", other)),
+                }
+
+                CommandState::Idle
+            }
             "!reload" => match p.gdb.get_target() {
                 Ok(Some(target)) => Self::ask_if_session_active(
                     Command::from_mi_with_msg(
@@ -163,7 +205,15 @@ impl CommandState {
                         // We therefore (only) write the error message to debug log to avoid duplicates.
                         error!("{}", results["msg"].as_str().unwrap_or(&results.pretty(2)));
                     }
-                    Ok(_) => {}
+                    Ok(_) => {
+                        p.update_working_dir();
+                        // Switching to an inferior without threads is not announced by gdb.
+                        if cmd == "inferior" {
+                            if let Ok(number) = args_str.trim().parse::<u32>() {
+                                p.gdb.inferiors.select(&format!("i{}", number));
+                            }
+                        }
+                    }
                     Err(e) => Self::print_execute_error(e, p),
                 }
                 CommandState::Idle
@@ -11,7 +11,7 @@ use unsegen::{
     },
 };
 
-use crate::completion::{CmdlineCompleter, Completer, CompletionState};
+use crate::completion::{complete_cmdline, CompletionState};
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
 enum GDBState {
@@ -51,6 +51,14 @@ impl Console {
         write!(self.gdb_log, "{}", msg.as_ref()).expect("Write Message");
     }
 
+    pub fn ask_to_restart_gdb(&mut self, reason: &str) {
+        self.write_to_gdb_log(format!(
+            "{}. Restart GDB and restore breakpoints? Otherwise ugdb will quit. (y or n)\n",
+            reason
+        ));
+        self.command_state = CommandState::ask_to_restart_gdb();
+    }
+
     fn handle_newline(&mut self, p: &mut Context) {
         let line = if self.prompt_line.active_line().is_empty() {
             self.prompt_line.previous_line(1).unwrap_or("").to_owned()
@@ -60,6 +68,36 @@ impl Console {
         self.write_to_gdb_log(format!("{}{}\n", STOPPED_PROMPT, line));
         self.command_state.handle_input_line(&line, p);
     }
+    fn set_completion(&mut self) {
+        let completion = self.completion_state.as_ref().unwrap();
+        let (begin, option, after) = completion.current_line_parts();
+        self.prompt_line
+            .set(format!("{}{}{}", begin, option, after));
+        self.prompt_line
+            .set_cursor_pos(begin.len() + option.len())
+            .unwrap();
+    }
+
+    fn start_completion(&mut self, p: &mut Context) {
+        complete_cmdline(
+            p,
+            self.prompt_line.active_line(),
+            self.prompt_line.cursor_pos(),
+            |tui, _, completion| tui.console.apply_completion(completion),
+        );
+    }
+
+    fn apply_completion(&mut self, completion: CompletionState) {
+        // Discard the completion if the line has been edited in the meantime.
+        if completion.matches(
+            self.prompt_line.active_line(),
+            self.prompt_line.cursor_pos(),
+        ) {
+            self.completion_state = Some(completion);
+            self.set_completion();
+        }
+    }
+
     pub fn update_after_event(&mut self, p: &mut Context) {
         if p.gdb.mi.is_running() {
             if self.last_gdb_state != GDBState::Running {
@@ -75,37 +113,22 @@ impl Console {
 
 impl Container<Context> for Console {
     fn input(&mut self, input: Input, p: &mut Context) -> Option<Input> {
-        let set_completion = |completion_state: &Option<CompletionState>,
-                              prompt_line: &mut PromptLine| {
-            let completion = completion_state.as_ref().unwrap();
-            let (begin, option, after) = completion.current_line_parts();
-            prompt_line.set(&format!("{}{}{}", begin, option, after));
-            prompt_line
-                .set_cursor_pos(begin.len() + option.len())
-                .unwrap();
-        };
         let after_completion = input
             .chain((&[Key::Ctrl('p'), Key::Char('\t')][..], || {
                 if let Some(s) = &mut self.completion_state {
                     s.select_next_option();
+                    self.set_completion();
                 } else {
-                    self.completion_state = Some(CmdlineCompleter(p).complete(
-                        self.prompt_line.active_line(),
-                        self.prompt_line.cursor_pos(),
-                    ));
+                    self.start_completion(p);
                 }
-                set_completion(&self.completion_state, &mut self.prompt_line);
             }))
             .chain((Key::Ctrl('n'), || {
                 if let Some(s) = &mut self.completion_state {
                     s.select_prev_option();
+                    self.set_completion();
                 } else {
-                    self.completion_state = Some(CmdlineCompleter(p).complete(
-                        self.prompt_line.active_line(),
-                        self.prompt_line.cursor_pos(),
-                    ));
+                    self.start_completion(p);
                 }
-                set_completion(&self.completion_state, &mut self.prompt_line);
             }))
             .finish();
         if let Some(input) = after_completion {
@@ -127,7 +150,9 @@ impl Container<Context> for Console {
                 )
                 .chain(ScrollBehavior::new(&mut self.prompt_line).to_end_on(Key::Ctrl('r')))
                 .chain((Key::Ctrl('c'), || {
-                    p.gdb.mi.interrupt_execution().expect("interrupted gdb")
+                    if let Err(e) = p.gdb.mi.interrupt_execution() {
+                        p.log(format!("Failed to interrupt GDB: {}", e));
+                    }
                 }))
                 .chain(
                     ScrollBehavior::new(&mut self.gdb_log)
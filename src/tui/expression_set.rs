@@ -0,0 +1,125 @@
+// Expression sets store the rows of the expression table in a file: One expression per line.
+// Expressions after a `[function <name>]` header form a group that is only shown while the program
+// is stopped in that function. Empty lines and lines starting with `#` are ignored.
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const CONFIG_SUBDIR: &str = "ugdb/expressions";
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct FunctionGroup {
+    pub function: String,
+    pub expressions: Vec<String>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ExpressionSet {
+    pub expressions: Vec<String>,
+    pub groups: Vec<FunctionGroup>,
+}
+
+impl ExpressionSet {
+    pub fn parse(content: &str) -> Result<Self, String> {
+        let mut set = ExpressionSet::default();
+        for (number, line) in content.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            if let Some(header) = line.strip_prefix('[') {
+                let function = header
+                    .strip_suffix(']')
+                    .and_then(|h| h.trim().strip_prefix("function "))
+                    .map(|f| f.trim())
+                    .filter(|f| !f.is_empty())
+                    .ok_or_else(|| format!("line {}: Expected `[function <name>]`", number + 1))?;
+                set.groups.push(FunctionGroup {
+                    function: function.to_owned(),
+                    expressions: Vec::new(),
+                });
+                continue;
+            }
+            match set.groups.last_mut() {
+                Some(group) => group.expressions.push(line.to_owned()),
+                None => set.expressions.push(line.to_owned()),
+            }
+        }
+        Ok(set)
+    }
+}
+
+impl fmt::Display for ExpressionSet {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for expression in &self.expressions {
+            writeln!(f, "{}", expression)?;
+        }
+        for group in &self.groups {
+            writeln!(f, "\n[function {}]", group.function)?;
+            for expression in &group.expressions {
+                writeln!(f, "{}", expression)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+// Names containing a slash are paths, all others refer to files in the config directory.
+pub fn path(name: &str) -> PathBuf {
+    if name.contains('/') {
+        return PathBuf::from(name);
+    }
+    let config_dir = match std::env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) => PathBuf::from(dir),
+        None => Path::new(&std::env::var_os("HOME").unwrap_or_default()).join(".config"),
+    };
+    config_dir.join(CONFIG_SUBDIR).join(name)
+}
+
+pub fn load(name: &str) -> Result<ExpressionSet, String> {
+    let path = path(name);
+    let content = fs::read_to_string(&path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    ExpressionSet::parse(&content).map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+// Returns the path of the file.
+pub fn save(name: &str, set: &ExpressionSet) -> Result<PathBuf, String> {
+    let path = path(name);
+    if let Some(dir) = path.parent().filter(|d| !d.as_os_str().is_empty()) {
+        fs::create_dir_all(dir).map_err(|e| format!("{}: {}", dir.display(), e))?;
+    }
+    fs::write(&path, set.to_string()).map_err(|e| format!("{}: {}", path.display(), e))?;
+    Ok(path)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let set = ExpressionSet::parse(
+            "# state\nthis->state\n\n[function  parse ]\ntoken\n  pos \n[function main]\n",
+        )
+        .unwrap();
+        assert_eq!(
+            set,
+            ExpressionSet {
+                expressions: vec!["this->state".to_owned()],
+                groups: vec![
+                    FunctionGroup {
+                        function: "parse".to_owned(),
+                        expressions: vec!["token".to_owned(), "pos".to_owned()],
+                    },
+                    FunctionGroup {
+                        function: "main".to_owned(),
+                        expressions: vec![],
+                    },
+                ],
+            }
+        );
+        assert_eq!(ExpressionSet::parse(&set.to_string()).unwrap(), set);
+        assert!(ExpressionSet::parse("x\n[func main]").is_err());
+        assert!(ExpressionSet::parse("[function ]").is_err());
+    }
+}
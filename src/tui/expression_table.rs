@@ -1,154 +1,1553 @@
-use crate::gdb_expression_parsing::Format;
-use crate::gdbmi::{commands::MiCommand, output::ResultClass, ExecuteError};
+use crate::gdb::BreakpointOperationError;
+use crate::gdb_expression_parsing::{
+    format_leaf, format_string, is_number, parse_gdb_value, Format, Node, ANON_KEY,
+};
+use crate::gdbmi::{
+    commands::{BreakPointNumber, MiCommand, WatchMode},
+    output::{
+        opt_str_field, str_field, Frame, ResultClass, ResultRecord, StopReason, VarObjectChange,
+        VarObjectInfo, VarObjectScope,
+    },
+    ExecuteError, Token,
+};
 use crate::Context;
+use log::info;
+use std::collections::{HashMap, HashSet};
+use std::fmt::{self, Write};
 use unsegen::{
-    base::{Color, GraphemeCluster, StyleModifier},
+    base::{Color, Cursor, GraphemeCluster, StyleModifier, Window},
     container::Container,
-    input::{EditBehavior, Input, Key, NavigateBehavior, ScrollBehavior},
+    input::{EditBehavior, Input, Key, Navigatable, NavigateBehavior, ScrollBehavior},
     widget::{
         builtin::{Column, LineEdit, Table, TableRow},
-        SeparatingStyle, Widget,
+        text_width, Demand, Demand2D, HLayout, RenderingHints, SeparatingStyle, VLayout, Widget,
     },
 };
-use unsegen_jsonviewer::JsonViewer;
 
-use crate::completion::{Completer, CompletionState, IdentifierCompleter};
+use super::expression_set::{self, ExpressionSet, FunctionGroup};
+use super::value_history::{HistoryPanel, HistoryView, Stop, ValueHistory};
+use super::value_tree::{Children, TreeNode, ValueTree};
+use crate::completion::{complete_identifier, CompletionState};
+
+pub type RowId = usize;
+
+// Elements of arrays and children of var objects are shown in pages of this size (which is mostly
+// relevant for large arrays and pretty printers of large containers).
+const PAGE_SIZE: usize = 100;
+// Chars of arrays that are shown as strings
+const MAX_STRING_LENGTH: usize = 10000;
+
+// Instead of parsing the printed value, rows can be backed by a gdb var object, which supports
+// pretty printers and only lists children when they are expanded.
+struct VarObject {
+    // Name of the root var object (once it has been created)
+    name: Option<String>,
+    // The expression that the var object was (or is being) created for
+    expression: String,
+    creating: bool,
+    tree: ValueTree,
+    // The parent and the index of the next page of children (by id of the "..." node)
+    more: HashMap<String, (String, usize)>,
+}
+
+impl VarObject {
+    fn new() -> Self {
+        VarObject {
+            name: None,
+            expression: String::new(),
+            creating: false,
+            tree: ValueTree::message(" "),
+            more: HashMap::new(),
+        }
+    }
+
+    fn delete(&mut self, p: &mut Context) {
+        if let Some(name) = self.name.take() {
+            delete_var_object(p, name);
+        }
+    }
+}
+
+fn delete_var_object(p: &mut Context, name: String) {
+    p.execute_async(MiCommand::var_delete(name, false), |_, _, result| {
+        if let Err(e) = check_result(result) {
+            info!("Could not delete var object: {}", e);
+        }
+    });
+}
+
+fn check_result(result: Result<ResultRecord, ExecuteError>) -> Result<ResultRecord, String> {
+    let res = result.map_err(|e| format!("{:?}", e))?;
+    match res.class {
+        ResultClass::Done => Ok(res),
+        ResultClass::Error => Err(res.results["msg"].as_str().unwrap_or("").to_owned()),
+        o => Err(format!("Unexpected result class: {:?}", o)),
+    }
+}
+
+// The number of the value in gdb's value history from the output of `print` (`$N = value`)
+fn history_number(printed: &str) -> Option<usize> {
+    printed.strip_prefix('$')?.split(" = ").next()?.parse().ok()
+}
+
+fn var_object_node(var: VarObjectInfo, key: String) -> TreeNode {
+    let has_children = var.has_children();
+    TreeNode::new(var.name, key, var.value.unwrap_or_default(), has_children)
+}
+
+// List the page of children that starts at the given index.
+fn list_children(p: &mut Context, id: RowId, name: String, from: usize) {
+    let range = from as u64..(from + PAGE_SIZE) as u64;
+    p.execute_async(
+        MiCommand::var_list_children(name.clone(), true, Some(range)),
+        move |tui, p, result| {
+            tui.expression_table
+                .handle_var_children(id, &name, from, result, p);
+        },
+    );
+}
+
+// The id of the node that stands for the children after those that are shown
+fn more_id(parent: &str) -> String {
+    format!("{}[...]", parent)
+}
+
+fn more_node(parent: &str, note: String) -> TreeNode {
+    let mut node = TreeNode::new(more_id(parent), String::new(), "...".to_owned(), false);
+    node.note = Some(note);
+    node
+}
+
+// Elements of arrays in parsed values beyond the first page
+#[derive(Default)]
+struct ArrayPages {
+    // The number of elements that are shown (by id of the array) if it is not PAGE_SIZE
+    shown: HashMap<String, usize>,
+    // Values of slices (`a[200]@100`) with elements that gdb did not print
+    fetched: HashMap<String, Vec<String>>,
+    // Lengths of the arrays that slices are fetched for
+    lengths: HashMap<String, usize>,
+}
+
+// Elements that gdb did not print, starting at index start of the array with the given id
+#[derive(Clone, Debug, PartialEq)]
+struct Fetch {
+    array: String,
+    access: String,
+    start: usize,
+}
+
+// What happens when the "..." node at the end of an array is expanded
+#[derive(Clone, Debug, PartialEq)]
+enum More {
+    // Show the next page of the array with the given id.
+    Page(String),
+    Fetch(Fetch),
+}
+
+fn is_identifier(s: &str) -> bool {
+    let mut chars = s.chars();
+    chars.next().is_some_and(|c| c.is_alphabetic() || c == '_')
+        && chars.all(|c| c.is_alphanumeric() || c == '_')
+}
+
+// Builds the tree for a parsed value in which the leaves are shown in the format chosen for their
+// subtree.
+struct ParsedTreeBuilder<'a> {
+    formats: &'a HashMap<String, Format>,
+    sizes: &'a HashMap<String, Option<usize>>,
+    // Expressions whose size is needed, but not known
+    missing_sizes: Vec<String>,
+    // Expressions that evaluate to the nodes (by id), if they can be accessed at all
+    accesses: HashMap<String, String>,
+    pages: &'a ArrayPages,
+    // The actions of the "..." nodes of arrays (by id)
+    more: HashMap<String, More>,
+}
+
+impl ParsedTreeBuilder<'_> {
+    // The expression that accesses exactly the node with the given id, if there is one.
+    fn exact_access(&self, id: &str) -> Option<String> {
+        self.accesses.get(id).cloned()
+    }
+
+    fn size(&mut self, access: &Option<String>) -> Option<usize> {
+        let access = access.as_ref()?;
+        match self.sizes.get(access) {
+            Some(size) => *size,
+            None => {
+                if !self.missing_sizes.contains(access) {
+                    self.missing_sizes.push(access.clone());
+                }
+                None
+            }
+        }
+    }
+
+    // The access expression (if any) has the same type as the node and is used to determine the
+    // size of leaves. Elements of arrays are represented by the first one, so expressions that
+    // access exactly a node are derived from the one recorded for its parent.
+    fn build(
+        &mut self,
+        node: &Node,
+        id: String,
+        key: String,
+        access: Option<String>,
+        inherited: Format,
+    ) -> TreeNode {
+        let explicit = self.formats.get(&id).copied();
+        let format = explicit.unwrap_or(inherited);
+        let mut tree_node = match node {
+            Node::Leaf(leaf) => {
+                let value = if format != Format::Natural && is_number(leaf) {
+                    format_leaf(leaf, format, self.size(&access))
+                } else {
+                    None
+                };
+                TreeNode::new(id, key, value.unwrap_or_else(|| leaf.to_string()), false)
+            }
+            Node::Char(code, _) => {
+                let value = if format != Format::Natural {
+                    format_leaf(code, format, self.size(&access))
+                } else {
+                    None
+                };
+                TreeNode::new(id, key, value.unwrap_or_else(|| node.to_string()), false)
+            }
+            Node::String(_) | Node::Unavailable(_) | Node::Function { .. } => {
+                TreeNode::new(id, key, node.to_string(), false)
+            }
+            // References are shown like the value they refer to.
+            Node::Reference(_, address, value) => {
+                let mut tree_node = self.build(value, id, key, access, inherited);
+                add_note(&mut tree_node, format!("@{}", address));
+                return tree_node;
+            }
+            Node::Tuple(description, items) => {
+                // Elements of tuple variants cannot be accessed.
+                let tuple_access = access.filter(|_| description.is_none());
+                let exact = self.exact_access(&id).filter(|_| description.is_none());
+                let children = items
+                    .iter()
+                    .enumerate()
+                    .map(|(i, item)| {
+                        let child_id = format!("{}.{}", id, i);
+                        if let Some(a) = &exact {
+                            self.accesses
+                                .insert(child_id.clone(), format!("{}.{}", a, i));
+                        }
+                        let child_access = tuple_access.as_ref().map(|a| format!("{}.{}", a, i));
+                        self.build(item, child_id, i.to_string(), child_access, format)
+                    })
+                    .collect();
+                let mut tree_node = composite_node(id, key, *description, None, children);
+                if description.is_none() {
+                    tree_node.value = node.summary();
+                }
+                tree_node
+            }
+            Node::Array(description, items) => {
+                // Elements of pretty printed containers cannot be accessed via an index in general.
+                let array_access = access.filter(|_| description.is_none());
+                let element_access = array_access.as_ref().map(|a| format!("{}[0]", a));
+                let exact = self.exact_access(&id).filter(|_| description.is_none());
+                let pages = self.pages;
+                // Elements that were fetched separately follow the printed ones.
+                let slices = pages
+                    .fetched
+                    .get(&id)
+                    .into_iter()
+                    .flatten()
+                    .filter_map(|slice| match parse_gdb_value(slice) {
+                        Ok(Node::Array(None, items)) => Some(items),
+                        _ => None,
+                    })
+                    .collect::<Vec<_>>();
+                // Runs of repeated elements are a single child: (index, count, element)
+                let mut elements = Vec::new();
+                let mut len = 0;
+                let mut truncated = false;
+                for item in items.iter().chain(slices.iter().flatten()) {
+                    match item {
+                        Node::Repeated(element, count) => {
+                            elements.push((len, *count, &**element));
+                            len += count;
+                        }
+                        Node::Truncated => truncated = true,
+                        item => {
+                            elements.push((len, 1, item));
+                            len += 1;
+                        }
+                    }
+                }
+                let unprinted = truncated && pages.lengths.get(&id).is_none_or(|&l| len < l);
+
+                let mut value = None;
+                let mut element_format = format;
+                if format == Format::String {
+                    let leaves = elements
+                        .iter()
+                        .map(|&(_, count, item)| match item {
+                            Node::Leaf(leaf) => Some(std::iter::repeat_n(*leaf, count)),
+                            _ => None,
+                        })
+                        .collect::<Option<Vec<_>>>();
+                    if let Some(leaves) = leaves {
+                        // Runs of repeated chars may be arbitrarily long.
+                        let leaves = leaves.into_iter().flatten().take(MAX_STRING_LENGTH);
+                        let cut = unprinted || len > MAX_STRING_LENGTH;
+                        value = format_string(leaves, self.size(&element_access)).map(|s| {
+                            if cut {
+                                s + "..."
+                            } else {
+                                s
+                            }
+                        });
+                        element_format = Format::Char;
+                    }
+                }
+
+                let shown = pages.shown.get(&id).copied().unwrap_or(PAGE_SIZE);
+                let mut children = Vec::new();
+                for &(i, count, item) in elements.iter().take_while(|&&(i, _, _)| i < shown) {
+                    let child_id = format!("{}[{}]", id, i);
+                    let child_key = if count == 1 {
+                        format!("[{}]", i)
+                    } else {
+                        format!("[{}..{}]", i, i + count)
+                    };
+                    if let Some(a) = &exact {
+                        self.accesses
+                            .insert(child_id.clone(), format!("{}[{}]", a, i));
+                    }
+                    let access = element_access.clone();
+                    let mut child = self.build(item, child_id, child_key, access, element_format);
+                    if count > 1 {
+                        add_note(&mut child, format!("repeated {} times", count));
+                    }
+                    children.push(child);
+                }
+                let more = match elements.iter().find(|&&(i, _, _)| i >= shown) {
+                    Some(&(first, _, _)) => Some((
+                        format!("{} more elements", len - first),
+                        Some(More::Page(id.clone())),
+                    )),
+                    None if unprinted => Some((
+                        "more elements not printed by gdb".to_owned(),
+                        exact.map(|access| {
+                            More::Fetch(Fetch {
+                                array: id.clone(),
+                                access,
+                                start: len,
+                            })
+                        }),
+                    )),
+                    None => None,
+                };
+                if let Some((note, action)) = more {
+                    if let Some(action) = action {
+                        self.more.insert(more_id(&id), action);
+                    }
+                    children.push(more_node(&id, note));
+                }
+                composite_node(id, key, *description, value, children)
+            }
+            // Only elements of arrays are repeated or truncated.
+            Node::Repeated(element, _) => self.build(element, id, key, access, inherited),
+            Node::Truncated => TreeNode::new(id, key, "...".to_owned(), false),
+            Node::Map(description, items) => {
+                let exact = self.exact_access(&id).filter(|_| description.is_none());
+                let children = items
+                    .iter()
+                    .map(|(k, item)| {
+                        let child_id = format!("{}.{}", id, k);
+                        if *k == ANON_KEY {
+                            if let Some(a) = &exact {
+                                self.accesses.insert(child_id.clone(), a.clone());
+                            }
+                            return self.build_anonymous(item, child_id, access.clone(), format);
+                        }
+                        if let (Some(a), true) = (&exact, is_identifier(k)) {
+                            self.accesses
+                                .insert(child_id.clone(), format!("{}.{}", a, k));
+                        }
+                        let child_access = access
+                            .as_ref()
+                            .filter(|_| description.is_none() && is_identifier(k))
+                            .map(|a| format!("{}.{}", a, k));
+                        self.build(item, child_id, k.to_string(), child_access, format)
+                    })
+                    .collect();
+                composite_node(id, key, *description, None, children)
+            }
+        };
+        tree_node.note = explicit.map(|f| f.name().to_owned());
+        tree_node
+    }
+
+    // Members of unnamed structs and unions are accessed via the parent. Several of them are
+    // grouped in an array.
+    fn build_anonymous(
+        &mut self,
+        node: &Node,
+        id: String,
+        parent_access: Option<String>,
+        inherited: Format,
+    ) -> TreeNode {
+        match node {
+            Node::Array(None, items) => {
+                let format = self.formats.get(&id).copied().unwrap_or(inherited);
+                let children = items
+                    .iter()
+                    .enumerate()
+                    .map(|(i, item)| {
+                        let child_id = format!("{}[{}]", id, i);
+                        if let Some(a) = self.exact_access(&id) {
+                            self.accesses.insert(child_id.clone(), a);
+                        }
+                        self.build_anonymous(item, child_id, parent_access.clone(), format)
+                    })
+                    .collect();
+                let mut tree_node = composite_node(id, ANON_KEY.to_owned(), None, None, children);
+                tree_node.note = self.formats.get(&tree_node.id).map(|f| f.name().to_owned());
+                tree_node
+            }
+            node => self.build(node, id, ANON_KEY.to_owned(), parent_access, inherited),
+        }
+    }
+}
+
+// Notes of the node are separated by commas, the format comes last.
+fn add_note(node: &mut TreeNode, note: String) {
+    node.note = Some(match node.note.take() {
+        Some(other) => format!("{}, {}", note, other),
+        None => note,
+    });
+}
+
+fn composite_node(
+    id: String,
+    key: String,
+    description: Option<&str>,
+    value: Option<String>,
+    children: Vec<TreeNode>,
+) -> TreeNode {
+    let description = description.map(|d| d.trim_end_matches('=').trim_end().to_owned());
+    let value = value
+        .or(description)
+        .unwrap_or_else(|| if children.is_empty() { "{}" } else { "{...}" }.to_owned());
+    let mut node = TreeNode::new(id, key, value, !children.is_empty());
+    if !children.is_empty() {
+        node.children = Children::Loaded(children);
+        node.expanded = true;
+    }
+    node
+}
+
+struct Watchpoint {
+    number: BreakPointNumber,
+    mode: WatchMode,
+    // The expression at the time the watchpoint was set
+    expression: String,
+    // Set if gdb deleted the watchpoint because its frame was left
+    out_of_scope: bool,
+    // What happened at the last stop, if the watchpoint caused it
+    trigger: Option<String>,
+}
+
+fn breakpoint_error(e: BreakpointOperationError) -> String {
+    match e {
+        BreakpointOperationError::Busy => "Gdb is busy".to_owned(),
+        BreakpointOperationError::ExecutionError(msg) => msg,
+    }
+}
+
+// Rows of function groups are removed while the program is stopped, so it is only interrupted if
+// the user removes a row while it is running.
+fn delete_watchpoint(number: BreakPointNumber, p: &mut Context) {
+    if p.gdb.mi.is_running() {
+        match p.gdb.while_stopped(p.command_timeout, |gdb| {
+            gdb.delete_breakpoints(std::iter::once(number))
+        }) {
+            Ok(_) => {
+                p.log(format!("Removed watchpoint {}", number));
+                p.ipc_subscribers.breakpoint_deleted(number);
+            }
+            Err(e) => p.log(format!(
+                "Failed to remove watchpoint: {}",
+                breakpoint_error(e)
+            )),
+        }
+        return;
+    }
+    p.execute_async(
+        MiCommand::delete_breakpoints(std::iter::once(number)),
+        move |_, p, result| match check_result(result) {
+            Ok(_) => {
+                p.gdb.breakpoints.remove_breakpoint(number);
+                p.log(format!("Removed watchpoint {}", number));
+                p.ipc_subscribers.breakpoint_deleted(number);
+            }
+            Err(e) => p.log(format!("Failed to remove watchpoint: {}", e)),
+        },
+    );
+}
+
+fn watchpoint_note(watchpoint: &Watchpoint, expression: &str) -> String {
+    let mut text = format!(
+        "{} watchpoint {}",
+        watchpoint.mode.name(),
+        watchpoint.number
+    );
+    if watchpoint.expression != expression {
+        let _ = write!(text, " on {}", watchpoint.expression);
+    }
+    if watchpoint.out_of_scope {
+        text.push_str(": out of scope");
+    } else if let Some(trigger) = &watchpoint.trigger {
+        let _ = write!(text, ": {}", trigger);
+    }
+    text
+}
+
+// A thread and frame that a row is evaluated in instead of the selected ones
+#[derive(Clone, Debug, PartialEq)]
+struct Pin {
+    thread: u64,
+    frame: PinnedFrame,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum PinnedFrame {
+    Level(u64),
+    // The innermost frame of the function
+    Function(String),
+}
+
+impl fmt::Display for Pin {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.frame {
+            PinnedFrame::Level(level) => write!(f, "in thread {}, frame {}", self.thread, level),
+            PinnedFrame::Function(function) => {
+                write!(f, "in thread {}, function {}", self.thread, function)
+            }
+        }
+    }
+}
+
+// A single line of text, e.g., a note below the expression that describes its watchpoint
+struct TextLine(String, StyleModifier);
+
+impl TextLine {
+    fn note(text: String) -> Self {
+        TextLine(
+            text,
+            StyleModifier::new().fg_color(Color::Yellow).italic(true),
+        )
+    }
+}
+
+impl Widget for TextLine {
+    fn space_demand(&self) -> Demand2D {
+        Demand2D {
+            width: Demand::exact(text_width(&self.0)),
+            height: Demand::exact(1),
+        }
+    }
+    fn draw(&self, mut window: Window, _hints: RenderingHints) {
+        let mut cursor = Cursor::new(&mut window).style_modifier(self.1);
+        cursor.write(&self.0);
+    }
+}
 
 pub struct ExpressionRow {
+    id: RowId,
     expression: LineEdit,
     completion_state: Option<CompletionState>,
-    result: JsonViewer,
-    format: Option<crate::gdb_expression_parsing::Format>,
+    result: ValueTree,
+    // The printed value that is shown (parsed) in result
+    value: Option<String>,
+    // The number of the shown value in gdb's value history (`$N`)
+    value_number: Option<usize>,
+    // Formats of subtrees (by node id). Subtrees without a format use that of their parent.
+    formats: HashMap<String, Format>,
+    // Sizes of the types of (parts of) the expression, which are needed for formatting
+    sizes: HashMap<String, Option<usize>>,
+    pending_sizes: HashSet<String>,
+    // Parsed values at previous stops
+    history: ValueHistory,
+    pending_evaluation: Option<Token>,
+    var_object: Option<VarObject>,
+    watchpoint: Option<Watchpoint>,
+    // The next key selects the mode of the watchpoint (see choose_watch_mode).
+    choosing_watch_mode: bool,
+    pin: Option<Pin>,
+    // The frame level that the pin resolved to at the last evaluation
+    pinned_level: Option<u64>,
+    // The function of the group that added the row. Edits of the row are written back to the group
+    // (see store_group_rows).
+    group: Option<String>,
+    // Expressions that evaluate to the nodes of the parsed value (by id)
+    accesses: HashMap<String, String>,
+    // Only a summary of the value is shown.
+    collapsed: bool,
+    pages: ArrayPages,
+    more: HashMap<String, More>,
+    // Only members whose key or value contains the filter are shown (see ValueTree::set_filter).
+    filter: LineEdit,
+    editing_filter: bool,
 }
 
+// The order in which formats are cycled through.
+const FORMATS: &[Format] = &[
+    Format::Hex,
+    Format::Decimal,
+    Format::Octal,
+    Format::Binary,
+    Format::Char,
+    Format::Float,
+    Format::HexFloat,
+    Format::String,
+    Format::RawBytes,
+    Format::Natural,
+];
+
 fn next_format(f: Option<Format>) -> Option<Format> {
     match f {
-        None => Some(Format::Hex),
-        Some(Format::Hex) => Some(Format::Decimal),
-        Some(Format::Decimal) => Some(Format::Octal),
-        Some(Format::Octal) => Some(Format::Binary),
-        Some(Format::Binary) => None,
+        None => Some(FORMATS[0]),
+        Some(f) => FORMATS.iter().skip_while(|&&o| o != f).nth(1).copied(),
     }
 }
 
 impl ExpressionRow {
-    fn new() -> Self {
+    fn new(id: RowId, use_var_object: bool) -> Self {
         ExpressionRow {
+            id,
             expression: LineEdit::new(),
             completion_state: None,
-            result: JsonViewer::new(" "),
-            format: None,
+            result: ValueTree::message(" "),
+            value: None,
+            value_number: None,
+            formats: HashMap::new(),
+            sizes: HashMap::new(),
+            pending_sizes: HashSet::new(),
+            history: ValueHistory::default(),
+            pending_evaluation: None,
+            var_object: if use_var_object {
+                Some(VarObject::new())
+            } else {
+                None
+            },
+            watchpoint: None,
+            choosing_watch_mode: false,
+            pin: None,
+            pinned_level: None,
+            group: None,
+            accesses: HashMap::new(),
+            collapsed: false,
+            pages: ArrayPages::default(),
+            more: HashMap::new(),
+            filter: LineEdit::new(),
+            editing_filter: false,
         }
     }
 
     fn is_empty(&self) -> bool {
         self.expression.get().is_empty()
     }
+    // The tree that shows the value
+    fn tree_mut(&mut self) -> &mut ValueTree {
+        match &mut self.var_object {
+            Some(var) => &mut var.tree,
+            None => &mut self.result,
+        }
+    }
+    // Edit the filter until Enter is pressed (or Ctrl-c clears it). Returns the input that is not
+    // meant for the filter.
+    fn edit_filter(&mut self, input: Input) -> Option<Input> {
+        let res = input
+            .chain((Key::Char('\n'), || self.editing_filter = false))
+            .chain((Key::Ctrl('c'), || {
+                self.filter.set("");
+                self.editing_filter = false;
+            }))
+            .chain(
+                EditBehavior::new(&mut self.filter)
+                    .left_on(Key::Left)
+                    .right_on(Key::Right)
+                    .delete_forwards_on(Key::Delete)
+                    .delete_backwards_on(Key::Backspace)
+                    .go_to_beginning_of_line_on(Key::Home)
+                    .go_to_end_of_line_on(Key::End),
+            )
+            .finish();
+        let filter = self.filter.get().to_owned();
+        self.tree_mut().set_filter(&filter);
+        res
+    }
+    fn clear_filter(&mut self) {
+        self.filter.set("");
+        self.editing_filter = false;
+        self.tree_mut().set_filter("");
+    }
+    // Release everything that gdb keeps for the row, which has been removed from the table.
+    fn discard(mut self, p: &mut Context) {
+        if let Some(token) = self.pending_evaluation.take() {
+            p.cancel_async(token);
+        }
+        if let Some(var) = &mut self.var_object {
+            var.delete(p);
+        }
+        if let Some(watchpoint) = self.watchpoint.filter(|w| !w.out_of_scope) {
+            delete_watchpoint(watchpoint.number, p);
+        }
+    }
     fn update_result(&mut self, p: &mut Context) {
-        let expr = self.expression.get().to_owned();
-        if expr.is_empty() {
-            self.result.update(" ");
-        } else {
-            match p.gdb.mi.execute(MiCommand::data_evaluate_expression(expr)) {
-                Ok(res) => match res.class {
-                    ResultClass::Error => {
-                        self.result.update(&res.results["msg"]);
+        // Results of previous evaluations are outdated now.
+        if let Some(token) = self.pending_evaluation.take() {
+            p.cancel_async(token);
+        }
+        if self.var_object.is_some() {
+            self.update_var_object(p);
+            return;
+        }
+        if self.is_empty() {
+            self.value = None;
+            self.result.show_message(" ");
+            return;
+        }
+        match &self.pin {
+            None => self.evaluate(None, p),
+            Some(Pin {
+                frame: PinnedFrame::Level(level),
+                ..
+            }) => self.evaluate(Some(*level), p),
+            // The level of the function's frame has to be looked up first.
+            Some(Pin {
+                thread,
+                frame: PinnedFrame::Function(_),
+            }) => {
+                let id = self.id;
+                self.pending_evaluation = Some(p.execute_async(
+                    MiCommand::stack_list_frames().in_thread(*thread),
+                    move |tui, p, result| {
+                        tui.expression_table.handle_frames(id, result, p);
+                    },
+                ));
+            }
+        }
+    }
+    // Evaluate the expression in the given level of the pinned thread (if any). The value is
+    // printed into gdb's value history, so that elements that gdb did not print can be fetched
+    // from it later without evaluating the expression again.
+    fn evaluate(&mut self, level: Option<u64>, p: &mut Context) {
+        self.pinned_level = level;
+        let id = self.id;
+        let command = self.in_scope(MiCommand::cli_exec(&format!(
+            "print {}",
+            self.expression.get()
+        )));
+        self.pending_evaluation = Some(p.execute_async_capturing_console(
+            command,
+            move |tui, p, result| {
+                tui.expression_table.handle_printed_value(id, result, p);
+            },
+        ));
+    }
+    // The value is shown as -data-evaluate-expression prints it, because `print` adds the types of
+    // pointers etc.
+    fn show_printed_value(
+        &mut self,
+        result: Result<ResultRecord, ExecuteError>,
+        stop: &Stop,
+        p: &mut Context,
+    ) {
+        let res = match result {
+            Ok(res) if res.class == ResultClass::Done => res,
+            other => return self.show_result(other, None, stop, p),
+        };
+        let printed = res.results["console"].as_str().unwrap_or("");
+        let number = match history_number(printed) {
+            Some(number) => number,
+            None => {
+                return self.show_message(format!("*Malformed result*: {}", printed.trim_end()))
+            }
+        };
+        let id = self.id;
+        self.pending_evaluation = Some(p.execute_async(
+            MiCommand::data_evaluate_expression(format!("${}", number)),
+            move |tui, p, result| {
+                tui.expression_table
+                    .handle_evaluation_result(id, Some(number), result, p);
+            },
+        ));
+    }
+    fn in_scope(&self, command: MiCommand) -> MiCommand {
+        match (&self.pin, self.pinned_level) {
+            (Some(pin), Some(level)) => command.in_frame(pin.thread, level),
+            _ => command,
+        }
+    }
+    fn evaluate_in_function(
+        &mut self,
+        result: Result<ResultRecord, ExecuteError>,
+        p: &mut Context,
+    ) {
+        let (thread, function) = match &self.pin {
+            Some(Pin {
+                thread,
+                frame: PinnedFrame::Function(function),
+            }) => (*thread, function),
+            _ => return,
+        };
+        if let Err(ExecuteError::Busy) = result {
+            return;
+        }
+        let frames = check_result(result)
+            .and_then(|res| Frame::decode_list(&res.results).map_err(|e| e.to_string()));
+        match frames {
+            Ok(frames) => match frames
+                .iter()
+                .find(|f| f.function.as_ref() == Some(function))
+            {
+                Some(frame) => self.evaluate(Some(frame.level.unwrap_or(0)), p),
+                None => self.show_message(format!(
+                    "*Not on the stack of thread {}*: {}",
+                    thread, function
+                )),
+            },
+            Err(e) => self.show_message(format!("*Failed to list frames*: {}", e)),
+        }
+    }
+    // Cycle between pinning the row to the given frame (by level and by function) and not
+    // pinning it.
+    fn cycle_pin(&mut self, thread: Option<u64>, frame: Option<&Frame>, p: &mut Context) {
+        if self.var_object.is_some() {
+            p.log("Rows backed by variable objects cannot be pinned");
+            return;
+        }
+        let function = frame.and_then(|f| f.function.clone());
+        self.pin = match (&self.pin, thread, function) {
+            (None, Some(thread), _) => Some(Pin {
+                thread,
+                frame: PinnedFrame::Level(frame.and_then(|f| f.level).unwrap_or(0)),
+            }),
+            (
+                Some(Pin {
+                    thread,
+                    frame: PinnedFrame::Level(_),
+                }),
+                _,
+                Some(function),
+            ) => Some(Pin {
+                thread: *thread,
+                frame: PinnedFrame::Function(function),
+            }),
+            (None, None, _) => {
+                p.log("Cannot pin the row: No thread is selected");
+                return;
+            }
+            (Some(_), _, _) => None,
+        };
+        self.pinned_level = None;
+        // Values of a different scope are not comparable.
+        self.history.clear();
+        self.update_result(p);
+    }
+    fn show_message(&mut self, message: impl Into<String>) {
+        self.value = None;
+        self.value_number = None;
+        self.result.show_message(message);
+    }
+    fn show_result(
+        &mut self,
+        result: Result<ResultRecord, ExecuteError>,
+        number: Option<usize>,
+        stop: &Stop,
+        p: &mut Context,
+    ) {
+        match result {
+            Ok(res) => match res.class {
+                ResultClass::Error => {
+                    self.show_message(res.results["msg"].as_str().unwrap_or(""));
+                }
+                ResultClass::Done => match str_field(&res.results, "value") {
+                    Ok(v) => {
+                        self.history.record(stop, v);
+                        self.value = Some(v.to_owned());
+                        self.value_number = number;
+                        // Unprinted elements of the previous value are outdated.
+                        self.pages.fetched.clear();
+                        self.pages.lengths.clear();
+                        self.rebuild_result(p, true);
                     }
-                    ResultClass::Done => {
-                        let to_parse = res.results["value"].as_str().expect("value present");
-                        match crate::gdb_expression_parsing::parse_gdb_value(to_parse) {
-                            Ok(n) => {
-                                let v = crate::gdb_expression_parsing::Value {
-                                    node: &n,
-                                    format: self.format,
-                                };
-                                self.result.update(v);
-                            }
-                            Err(_) => {
-                                self.result
-                                    .update(format!("*Error parsing*: {}", to_parse).as_str());
-                            }
+                    Err(e) => self.show_message(format!("*Malformed result*: {}", e)),
+                },
+                other => panic!("unexpected result class: {:?}", other),
+            },
+            Err(ExecuteError::Busy) => {}
+            Err(ExecuteError::Timeout) => {
+                self.show_message("*Timed out*");
+            }
+            // The value is evaluated again once gdb has been restarted.
+            Err(ExecuteError::Quit) => {
+                self.show_message("*GDB quit*");
+            }
+        }
+    }
+    // Show the printed value in the current formats. The sizes of types that are needed for that
+    // are requested from gdb and the tree is rebuilt once all of them have arrived.
+    fn rebuild_result(&mut self, p: &mut Context, mark_changes: bool) {
+        let value = match &self.value {
+            Some(value) => value,
+            None => return,
+        };
+        let node = match parse_gdb_value(value) {
+            Ok(node) => node,
+            Err(_) => {
+                self.result
+                    .show_message(format!("*Error parsing*: {}", value));
+                return;
+            }
+        };
+        let mut builder = ParsedTreeBuilder {
+            formats: &self.formats,
+            sizes: &self.sizes,
+            missing_sizes: Vec::new(),
+            accesses: HashMap::new(),
+            pages: &self.pages,
+            more: HashMap::new(),
+        };
+        // Nodes are identified by their path starting at the expression (e.g., `s.a[1]`).
+        let expr = self.expression.get();
+        let access = format!("({})", expr);
+        builder.accesses.insert(expr.to_owned(), access.clone());
+        let root = builder.build(
+            &node,
+            expr.to_owned(),
+            String::new(),
+            Some(access),
+            Format::Natural,
+        );
+        let missing_sizes = builder.missing_sizes;
+        self.accesses = builder.accesses;
+        self.more = builder.more;
+        self.result.update(root, mark_changes);
+        for expr in missing_sizes {
+            if !self.pending_sizes.insert(expr.clone()) {
+                continue;
+            }
+            let id = self.id;
+            p.execute_async(
+                self.in_scope(MiCommand::data_evaluate_expression(format!(
+                    "sizeof({})",
+                    expr
+                ))),
+                move |tui, p, result| {
+                    tui.expression_table.handle_size(id, expr, result, p);
+                },
+            );
+        }
+    }
+
+    fn show_size(
+        &mut self,
+        expr: String,
+        result: Result<ResultRecord, ExecuteError>,
+        p: &mut Context,
+    ) {
+        // The expression may have been changed in the meantime.
+        if !self.pending_sizes.remove(&expr) {
+            return;
+        }
+        let size = check_result(result)
+            .ok()
+            .and_then(|res| str_field(&res.results, "value").ok()?.parse().ok());
+        self.sizes.insert(expr, size);
+        if self.pending_sizes.is_empty() {
+            self.rebuild_result(p, false);
+        }
+    }
+
+    // Show more elements (or children) if the selected node is the "..." at the end of an array.
+    fn expand_more(&mut self, p: &mut Context) -> bool {
+        if let Some(var) = &mut self.var_object {
+            let selected = match var.tree.selected() {
+                Some(node) => node.id.clone(),
+                None => return false,
+            };
+            let (parent, from) = match var.more.remove(&selected) {
+                Some(more) => more,
+                None => return false,
+            };
+            if let Some(node) = var.tree.find_mut(&selected) {
+                node.value = "(loading...)".to_owned();
+            }
+            list_children(p, self.id, parent, from);
+            return true;
+        }
+        let selected = match self.result.selected() {
+            Some(node) if self.value.is_some() => node.id.clone(),
+            _ => return false,
+        };
+        match self.more.remove(&selected) {
+            Some(More::Page(array)) => {
+                *self.pages.shown.entry(array).or_insert(PAGE_SIZE) += PAGE_SIZE;
+                self.rebuild_result(p, false);
+            }
+            Some(More::Fetch(fetch)) => {
+                if let Some(node) = self.result.find_mut(&selected) {
+                    node.value = "(loading...)".to_owned();
+                }
+                if self.pages.lengths.contains_key(&fetch.array) {
+                    self.fetch_elements(fetch, p);
+                } else if let Some(access) = self.history_access(&fetch.access, p) {
+                    let (id, value) = (self.id, self.value.clone());
+                    p.execute_async(
+                        MiCommand::data_evaluate_expression(format!(
+                            "sizeof({0})/sizeof({0}[0])",
+                            access
+                        )),
+                        move |tui, p, result| {
+                            tui.expression_table
+                                .handle_length(id, value, fetch, result, p);
+                        },
+                    );
+                }
+            }
+            None => return false,
+        }
+        true
+    }
+
+    fn show_length(
+        &mut self,
+        fetch: Fetch,
+        result: Result<ResultRecord, ExecuteError>,
+        p: &mut Context,
+    ) {
+        let length = check_result(result).and_then(|res| {
+            let value = str_field(&res.results, "value").map_err(|e| e.to_string())?;
+            value.parse::<usize>().map_err(|e| e.to_string())
+        });
+        match length {
+            Ok(length) => {
+                self.pages.lengths.insert(fetch.array.clone(), length);
+                self.fetch_elements(fetch, p);
+            }
+            Err(e) => {
+                p.log(format!("Cannot determine the length of the array: {}", e));
+                self.rebuild_result(p, false);
+            }
+        }
+    }
+
+    // The access to a node of the shown value via gdb's value history, so that the elements of
+    // the value are fetched instead of those of a new evaluation of the expression.
+    fn history_access(&mut self, access: &str, p: &mut Context) -> Option<String> {
+        let root = format!("({})", self.expression.get());
+        match (self.value_number, access.strip_prefix(&root)) {
+            (Some(number), Some(path)) => Some(format!("${}{}", number, path)),
+            _ => {
+                p.log("Cannot fetch more elements: The value is not in gdb's value history");
+                self.rebuild_result(p, false);
+                None
+            }
+        }
+    }
+
+    // Fetch the next page of elements that gdb did not print with a slice expression.
+    fn fetch_elements(&mut self, fetch: Fetch, p: &mut Context) {
+        let length = self.pages.lengths.get(&fetch.array).copied().unwrap_or(0);
+        if fetch.start >= length {
+            self.rebuild_result(p, false);
+            return;
+        }
+        let count = PAGE_SIZE.min(length - fetch.start);
+        let access = match self.history_access(&fetch.access, p) {
+            Some(access) => access,
+            None => return,
+        };
+        let command =
+            MiCommand::data_evaluate_expression(format!("{}[{}]@{}", access, fetch.start, count));
+        let (id, value) = (self.id, self.value.clone());
+        p.execute_async(command, move |tui, p, result| {
+            tui.expression_table
+                .handle_slice(id, value, fetch, result, p);
+        });
+    }
+
+    fn show_slice(
+        &mut self,
+        fetch: Fetch,
+        result: Result<ResultRecord, ExecuteError>,
+        p: &mut Context,
+    ) {
+        let slice = check_result(result).and_then(|res| {
+            str_field(&res.results, "value")
+                .map(|v| v.to_owned())
+                .map_err(|e| e.to_string())
+        });
+        match slice {
+            Ok(slice) if matches!(parse_gdb_value(&slice), Ok(Node::Array(None, _))) => {
+                self.pages
+                    .fetched
+                    .entry(fetch.array.clone())
+                    .or_default()
+                    .push(slice);
+                let shown = self.pages.shown.entry(fetch.array).or_insert(PAGE_SIZE);
+                *shown = (*shown).max(fetch.start + PAGE_SIZE);
+            }
+            Ok(slice) => p.log(format!("Unexpected value of array elements: {}", slice)),
+            Err(e) => p.log(format!("Failed to fetch more elements: {}", e)),
+        }
+        self.rebuild_result(p, false);
+    }
+
+    // The expression that evaluates to the node of the parsed value. The parentheses around the
+    // expression are dropped if they are not needed.
+    fn access_of(&self, id: &str) -> Option<String> {
+        let access = self.accesses.get(id)?;
+        let expr = self.expression.get();
+        match access.strip_prefix(&format!("({})", expr)) {
+            Some(rest) if is_identifier(expr) => Some(format!("{}{}", expr, rest)),
+            _ => Some(access.clone()),
+        }
+    }
+
+    // The value in a single line (for collapsed rows)
+    fn summary(&self) -> String {
+        match (&self.var_object, &self.value) {
+            (Some(var), _) => var.tree.root().value.clone(),
+            (None, Some(value)) => match parse_gdb_value(value) {
+                Ok(node) => node.to_string(),
+                Err(_) => value.replace('\n', " "),
+            },
+            (None, None) => self.result.root().value.clone(),
+        }
+    }
+
+    // Cycle through the formats of the subtree with the given id.
+    fn cycle_format(&mut self, id: String, p: &mut Context) {
+        let mut format = next_format(self.formats.get(&id).copied());
+        // The root cannot inherit a format, so natural is its default.
+        if id == self.expression.get() && format == Some(Format::Natural) {
+            format = None;
+        }
+        match format {
+            Some(format) => self.formats.insert(id, format),
+            None => self.formats.remove(&id),
+        };
+        self.rebuild_result(p, false);
+    }
+
+    // After Ctrl-w, the next key selects a write (w), read (r) or access (a) watchpoint for the
+    // expression or deletes it (d). Other keys cancel.
+    fn choose_watch_mode(&mut self, input: Input, p: &mut Context) -> Option<Input> {
+        if !self.choosing_watch_mode {
+            return Some(input);
+        }
+        self.choosing_watch_mode = false;
+        let mode = if input.matches(Key::Char('w')) {
+            Some(WatchMode::Write)
+        } else if input.matches(Key::Char('r')) {
+            Some(WatchMode::Read)
+        } else if input.matches(Key::Char('a')) {
+            Some(WatchMode::Access)
+        } else if input.matches(Key::Char('d')) {
+            None
+        } else {
+            return None;
+        };
+        self.set_watchpoint(mode, p);
+        None
+    }
+
+    // Replace the watchpoint of the row with one of the given mode (or just delete it). A running
+    // program is interrupted only once for that.
+    fn set_watchpoint(&mut self, mode: Option<WatchMode>, p: &mut Context) {
+        let active = self.watchpoint.as_ref().filter(|w| !w.out_of_scope);
+        if active.map(|w| w.mode) == mode {
+            // Only the note remains of a watchpoint that is out of scope.
+            if mode.is_none() {
+                self.watchpoint = None;
+            }
+            return;
+        }
+        let old = active.map(|w| w.number);
+        let expression = self.expression.get().to_owned();
+        if expression.is_empty() && mode.is_some() {
+            return;
+        }
+        let result = p.gdb.while_stopped(p.command_timeout, |gdb| {
+            if let Some(number) = old {
+                gdb.delete_breakpoints(std::iter::once(number))?;
+            }
+            Ok(mode.map(|mode| (mode, gdb.insert_watchpoint(&expression, mode))))
+        });
+        match result {
+            Ok((inserted, resumption)) => {
+                self.watchpoint = None;
+                if let Some(number) = old {
+                    p.log(format!("Removed watchpoint {}", number));
+                    p.ipc_subscribers.breakpoint_deleted(number);
+                }
+                match inserted {
+                    Some((mode, Ok(number))) => {
+                        p.log(format!(
+                            "Inserted {} watchpoint {} for expression \"{}\"",
+                            mode.name(),
+                            number,
+                            expression
+                        ));
+                        if let Some(bp) = p.gdb.breakpoints.get(&number) {
+                            p.ipc_subscribers.breakpoint_created(bp);
                         }
+                        self.watchpoint = Some(Watchpoint {
+                            number,
+                            mode,
+                            expression,
+                            out_of_scope: false,
+                            trigger: None,
+                        });
+                    }
+                    Some((_, Err(e))) => {
+                        p.log(format!("Failed to set watchpoint: {}", breakpoint_error(e)));
+                    }
+                    None => {}
+                }
+                if let Some(note) = resumption.describe() {
+                    p.log(format!("To change the watchpoint, ugdb {}.", note));
+                }
+            }
+            Err(e) => p.log(format!(
+                "Failed to remove watchpoint: {}",
+                breakpoint_error(e)
+            )),
+        }
+    }
+
+    // Show the values from the stop record if the watchpoint of this row triggered. Watchpoints
+    // that went out of scope are dropped at the stop after.
+    fn update_watchpoint(&mut self, reason: Option<&StopReason>) {
+        let watchpoint = match &mut self.watchpoint {
+            Some(w) if w.out_of_scope => {
+                self.watchpoint = None;
+                return;
+            }
+            Some(w) => w,
+            None => return,
+        };
+        watchpoint.trigger = None;
+        match reason {
+            Some(StopReason::WatchpointTrigger {
+                number: Some(number),
+                old_value,
+                new_value,
+                ..
+            }) if *number == watchpoint.number => {
+                watchpoint.trigger = match (old_value, new_value) {
+                    (Some(old), Some(new)) => Some(format!("{} -> {}", old, new)),
+                    (None, Some(new)) => Some(format!("value = {}", new)),
+                    (Some(old), None) => Some(format!("old value = {}", old)),
+                    (None, None) => Some("triggered".to_owned()),
+                };
+            }
+            Some(StopReason::WatchpointScope {
+                number: Some(number),
+            }) if *number == watchpoint.number => {
+                watchpoint.out_of_scope = true;
+            }
+            _ => {}
+        }
+    }
+
+    fn toggle_var_object(&mut self, p: &mut Context) {
+        // Var objects are evaluated in the selected frame.
+        self.pin = None;
+        self.pinned_level = None;
+        match self.var_object.take() {
+            Some(mut var) => var.delete(p),
+            None => self.var_object = Some(VarObject::new()),
+        }
+        self.clear_filter();
+    }
+
+    // Create the var object if the expression has changed or update it otherwise.
+    fn update_var_object(&mut self, p: &mut Context) {
+        let expr = self.expression.get().to_owned();
+        let id = self.id;
+        let var = self.var_object.as_mut().unwrap();
+        if var.expression == expr {
+            if let Some(name) = &var.name {
+                p.execute_async(
+                    MiCommand::var_update(name.clone()),
+                    move |tui, p, result| {
+                        tui.expression_table.handle_var_update(id, result, p);
+                    },
+                );
+                return;
+            }
+            if var.creating {
+                return;
+            }
+        }
+        var.delete(p);
+        var.expression = expr.clone();
+        if expr.is_empty() {
+            var.tree.show_message(" ");
+            return;
+        }
+        var.creating = true;
+        // Pretty printers are only used for var objects once they have been enabled.
+        p.execute_async(MiCommand::enable_pretty_printing(), |_, _, _| {});
+        p.execute_async(
+            MiCommand::var_create_floating(&expr),
+            move |tui, p, result| {
+                tui.expression_table.handle_var_created(id, expr, result, p);
+            },
+        );
+    }
+
+    fn show_var_object(
+        &mut self,
+        expr: String,
+        created: Result<VarObjectInfo, String>,
+        p: &mut Context,
+    ) {
+        let var = self.var_object.as_mut().unwrap();
+        if var.expression != expr || var.name.is_some() {
+            // The expression has been changed while the var object was being created.
+            if let Ok(created) = created {
+                delete_var_object(p, created.name);
+            }
+            return;
+        }
+        var.creating = false;
+        match created {
+            Ok(created) => {
+                var.name = Some(created.name.clone());
+                // The first level is shown right away, just like for parsed values.
+                let mut root = var_object_node(created, String::new());
+                root.expanded = true;
+                let to_load = ValueTree::start_loading(&mut root);
+                var.tree.replace(root);
+                if let Some(name) = to_load {
+                    list_children(p, self.id, name, 0);
+                }
+            }
+            Err(e) => var.tree.show_message(e),
+        }
+    }
+
+    fn apply_var_changes(&mut self, result: Result<ResultRecord, ExecuteError>, p: &mut Context) {
+        let id = self.id;
+        let var = self.var_object.as_mut().unwrap();
+        let res = match result {
+            Ok(res) => res,
+            // The program is running or gdb is slow. The var object is updated at the next stop.
+            Err(ExecuteError::Busy) | Err(ExecuteError::Timeout) => return,
+            // The var objects were lost together with gdb, which is restarted (if at all) with
+            // none of them.
+            Err(ExecuteError::Quit) => {
+                var.name = None;
+                return self.update_var_object(p);
+            }
+        };
+        let changes = match check_result(Ok(res)) {
+            Ok(res) => VarObjectChange::decode_list(&res.results),
+            Err(e) => {
+                // gdb does not know the var object (anymore) or cannot update it, so we start over.
+                info!("Could not update var object: {}", e);
+                var.delete(p);
+                return self.update_var_object(p);
+            }
+        };
+        let changes = match changes {
+            Ok(changes) => changes,
+            Err(e) => {
+                info!("Malformed var object changes: {}", e);
+                return;
+            }
+        };
+        var.tree.clear_changed();
+        let mut to_load = Vec::new();
+        for change in changes {
+            if change.scope == VarObjectScope::Invalid {
+                var.delete(p);
+                return self.update_var_object(p);
+            }
+            let node = match var.tree.find_mut(&change.name) {
+                Some(node) => node,
+                None => continue,
+            };
+            node.changed = true;
+            if change.scope == VarObjectScope::OutOfScope {
+                node.value = "<out of scope>".to_owned();
+                continue;
+            }
+            if let Some(value) = change.value {
+                node.value = value;
+            }
+            if change.new_type.is_some()
+                || change.new_num_children.is_some()
+                || !change.new_children.is_empty()
+            {
+                let has_children = change.new_num_children.is_none_or(|n| n > 0) || change.has_more;
+                node.children = if has_children {
+                    Children::Unloaded
+                } else {
+                    Children::None
+                };
+                if node.expanded {
+                    to_load.extend(ValueTree::start_loading(node));
+                }
+            }
+        }
+        for name in to_load {
+            list_children(p, id, name, 0);
+        }
+    }
+
+    // Show the page of children that starts at the given index.
+    fn show_var_children(
+        &mut self,
+        name: &str,
+        from: usize,
+        result: Result<ResultRecord, ExecuteError>,
+        p: &mut Context,
+    ) {
+        let id = self.id;
+        let var = self.var_object.as_mut().unwrap();
+        let children = check_result(result).and_then(|res| {
+            let has_more = opt_str_field(&res.results, "has_more") == Ok(Some("1"));
+            VarObjectInfo::decode_children(&res.results)
+                .map(|children| (children, has_more))
+                .map_err(|e| e.to_string())
+        });
+        let mut to_load = Vec::new();
+        let children = match children {
+            Ok((children, has_more)) => {
+                let mut nodes = Vec::new();
+                for child in children {
+                    // Pseudo children (public, private, ...) do not have a type and are expanded
+                    // right away.
+                    let is_pseudo_child = child.typ.is_none();
+                    let key = child.exp.clone().unwrap_or_else(|| child.name.clone());
+                    let mut node = var_object_node(child, key);
+                    if is_pseudo_child {
+                        node.expanded = true;
+                        to_load.extend(ValueTree::start_loading(&mut node));
                     }
-                    other => panic!("unexpected result class: {:?}", other),
-                },
-                Err(ExecuteError::Busy) => {}
-                Err(ExecuteError::Quit) => {
-                    panic!("GDB quit!");
+                    nodes.push(node);
                 }
+                // Not all var objects report whether there are more children.
+                if has_more || nodes.len() == PAGE_SIZE {
+                    let next = from + nodes.len();
+                    var.more.insert(more_id(name), (name.to_owned(), next));
+                    nodes.push(more_node(name, "more children".to_owned()));
+                }
+                nodes
             }
+            Err(e) => vec![TreeNode::new(String::new(), String::new(), e, false)],
+        };
+        if from == 0 {
+            var.tree.set_children(name, Children::Loaded(children));
+        } else if let Some(Children::Loaded(loaded)) =
+            var.tree.find_mut(name).map(|node| &mut node.children)
+        {
+            // The "..." node is replaced by the next page.
+            let more = more_id(name);
+            loaded.retain(|c| c.id != more);
+            loaded.extend(children);
+        }
+        for name in to_load {
+            list_children(p, id, name, 0);
         }
     }
+
+    fn set_completion(&mut self) {
+        let completion = self.completion_state.as_ref().unwrap();
+        let (begin, option, after) = completion.current_line_parts();
+        self.expression.set(format!("{}{}{}", begin, option, after));
+        self.expression
+            .set_cursor_pos(begin.len() + option.len())
+            .unwrap();
+    }
+    fn start_completion(&mut self, p: &mut Context) {
+        let id = self.id;
+        complete_identifier(
+            p,
+            self.expression.get(),
+            self.expression.cursor_pos(),
+            move |tui, _, completion| {
+                tui.expression_table.apply_completion(id, completion);
+            },
+        );
+    }
 }
 impl TableRow for ExpressionRow {
     type BehaviorContext = Context;
     const COLUMNS: &'static [Column<ExpressionRow>] = &[
         Column {
-            access: |r| Box::new(r.expression.as_widget()),
+            access: |r| {
+                let notes =
+                    r.pin
+                        .as_ref()
+                        .map(|pin| pin.to_string())
+                        .into_iter()
+                        .chain(
+                            r.watchpoint
+                                .as_ref()
+                                .map(|w| watchpoint_note(w, r.expression.get())),
+                        )
+                        .chain(r.choosing_watch_mode.then(|| {
+                            "watchpoint: (w)rite, (r)ead, (a)ccess or (d)elete?".to_owned()
+                        }))
+                        .collect::<Vec<_>>();
+                if notes.is_empty() {
+                    return Box::new(r.expression.as_widget());
+                }
+                let mut layout = VLayout::new().widget(r.expression.as_widget());
+                for note in notes {
+                    layout = layout.widget(TextLine::note(note));
+                }
+                Box::new(layout)
+            },
             behavior: |r, input, p| {
-                let mut format_changed = false;
+                let mut var_object_toggled = false;
                 let prev_content = r.expression.get().to_owned();
-                let set_completion =
-                    |completion_state: &Option<CompletionState>, expression: &mut LineEdit| {
-                        let completion = completion_state.as_ref().unwrap();
-                        let (begin, option, after) = completion.current_line_parts();
-                        expression.set(&format!("{}{}{}", begin, option, after));
-                        expression
-                            .set_cursor_pos(begin.len() + option.len())
-                            .unwrap();
-                    };
                 let res = input
+                    .chain(|input| r.choose_watch_mode(input, p))
                     .chain((&[Key::Ctrl('n'), Key::Char('\t')][..], || {
                         if let Some(s) = &mut r.completion_state {
                             s.select_next_option();
+                            r.set_completion();
                         } else {
-                            r.completion_state = Some(
-                                IdentifierCompleter(p)
-                                    .complete(r.expression.get(), r.expression.cursor_pos()),
-                            );
+                            r.start_completion(p);
                         }
-                        set_completion(&r.completion_state, &mut r.expression);
                     }))
                     .chain((Key::Ctrl('p'), || {
                         if let Some(s) = &mut r.completion_state {
                             s.select_prev_option();
+                            r.set_completion();
                         } else {
-                            r.completion_state = Some(
-                                IdentifierCompleter(p)
-                                    .complete(r.expression.get(), r.expression.cursor_pos()),
-                            );
+                            r.start_completion(p);
                         }
-                        set_completion(&r.completion_state, &mut r.expression);
                     }))
                     .chain((Key::Ctrl('f'), || {
-                        r.format = next_format(r.format);
-                        format_changed = true;
+                        if r.var_object.is_none() {
+                            r.cycle_format(r.expression.get().to_owned(), p);
+                        }
+                    }))
+                    .chain((Key::Ctrl('v'), || {
+                        r.toggle_var_object(p);
+                        var_object_toggled = true;
                     }))
                     .if_not_consumed(|| r.completion_state = None)
                     .chain((Key::Ctrl('w'), || {
-                        match p.gdb.mi.execute(MiCommand::insert_watchpoing(
-                            r.expression.get(),
-                            crate::gdbmi::commands::WatchMode::Access,
-                        )) {
-                            Ok(o) => match o.class {
-                                ResultClass::Done => {
-                                    p.log(format!(
-                                        "Inserted watchpoint for expression \"{}\"",
-                                        r.expression.get()
-                                    ));
-                                }
-                                ResultClass::Error => {
-                                    p.log(format!(
-                                        "Failed to set watchpoint: {}",
-                                        o.results["msg"].as_str().unwrap(),
-                                    ));
-                                }
-                                other => panic!("unexpected result class: {:?}", other),
-                            },
-                            Err(e) => {
-                                p.log(format!("Failed to set watchpoint: {:?}", e));
-                            }
-                        }
+                        r.choosing_watch_mode =
+                            !r.expression.get().is_empty() || r.watchpoint.is_some();
                     }))
                     .chain(
                         EditBehavior::new(&mut r.expression)
@@ -164,18 +1563,76 @@ impl TableRow for ExpressionRow {
                     )
                     .finish();
 
-                if r.expression.get() != prev_content || format_changed {
+                if r.expression.get() != prev_content {
+                    // Formats and sizes refer to parts of the previous value.
+                    r.formats.clear();
+                    r.sizes.clear();
+                    r.pending_sizes.clear();
+                    r.pages = ArrayPages::default();
+                    r.clear_filter();
+                    r.history.clear();
+                    r.update_result(p);
+                } else if var_object_toggled {
                     r.update_result(p);
                 }
                 res
             },
         },
         Column {
-            access: |r| Box::new(r.result.as_widget()),
-            behavior: |r, input, _| {
+            access: |r| {
+                let tree = match &r.var_object {
+                    _ if r.collapsed => {
+                        return Box::new(TextLine(
+                            format!("▸ {}", r.summary()),
+                            StyleModifier::new(),
+                        ))
+                    }
+                    Some(var) => &var.tree,
+                    None => &r.result,
+                };
+                if r.editing_filter {
+                    let filter = HLayout::new()
+                        .widget(TextLine::note("/".to_owned()))
+                        .widget(r.filter.as_widget());
+                    Box::new(VLayout::new().widget(filter).widget(tree))
+                } else if !tree.filter().is_empty() {
+                    let filter = TextLine::note(format!("/{}", tree.filter()));
+                    Box::new(VLayout::new().widget(filter).widget(tree))
+                } else {
+                    Box::new(tree)
+                }
+            },
+            behavior: |r, input, p| {
+                if r.collapsed {
+                    return input
+                        .chain((Key::Char(' '), || r.collapsed = false))
+                        .finish();
+                }
+                let id = r.id;
+                let input = input.chain(|evt: Input| {
+                    if evt.matches(Key::Char(' ')) && r.expand_more(p) {
+                        return None;
+                    }
+                    if evt.matches(Key::Char('/')) {
+                        r.editing_filter = true;
+                        return None;
+                    }
+                    if r.var_object.is_none() && r.value.is_some() && evt.matches(Key::Char('f')) {
+                        if let Some(node) = r.result.selected() {
+                            let node_id = node.id.clone();
+                            r.cycle_format(node_id, p);
+                            return None;
+                        }
+                    }
+                    Some(evt)
+                });
+                let tree = match &mut r.var_object {
+                    Some(var) => &mut var.tree,
+                    None => &mut r.result,
+                };
                 input
                     .chain(
-                        ScrollBehavior::new(&mut r.result)
+                        ScrollBehavior::new(tree)
                             .forwards_on(Key::PageDown)
                             .backwards_on(Key::PageUp)
                             .forwards_on(Key::Down)
@@ -185,10 +1642,13 @@ impl TableRow for ExpressionRow {
                     )
                     .chain(|evt: Input| {
                         if evt.matches(Key::Char(' ')) {
-                            if r.result.toggle_active_element().is_ok() {
-                                None
-                            } else {
-                                Some(evt)
+                            match tree.toggle_selected() {
+                                Ok(Some(name)) => {
+                                    list_children(p, id, name, 0);
+                                    None
+                                }
+                                Ok(None) => None,
+                                Err(()) => Some(evt),
                             }
                         } else {
                             Some(evt)
@@ -202,30 +1662,173 @@ impl TableRow for ExpressionRow {
 
 pub struct ExpressionTable {
     table: Table<ExpressionRow>,
+    next_row_id: RowId,
+    // Whether new rows are backed by var objects
+    use_var_objects: bool,
+    // The last time the program stopped
+    stop: Stop,
+    // The row whose history is shown
+    history_view: Option<(RowId, HistoryView)>,
+    // The selected thread and frame, which rows can be pinned to
+    thread: Option<u64>,
+    frame: Option<Frame>,
+    // Rows that are shown while the selected frame belongs to a function
+    groups: Vec<FunctionGroup>,
+    // The function of the group whose rows are shown
+    active_group: Option<String>,
 }
 
 impl ExpressionTable {
     pub fn new() -> Self {
-        let mut table = Table::new();
-        table.rows_mut().push(ExpressionRow::new()); //Invariant: always at least one line
-        ExpressionTable { table }
+        let mut table = ExpressionTable {
+            table: Table::new(),
+            next_row_id: 0,
+            use_var_objects: false,
+            stop: Stop::default(),
+            history_view: None,
+            thread: None,
+            frame: None,
+            groups: Vec::new(),
+            active_group: None,
+        };
+        let row = table.new_row();
+        table.table.rows_mut().push(row); //Invariant: always at least one line
+        table
+    }
+    // Back new (and empty) rows by var objects.
+    pub fn use_var_objects(&mut self, enabled: bool) {
+        self.use_var_objects = enabled;
+        for row in self.table.rows_mut().iter_mut().filter(|r| r.is_empty()) {
+            row.var_object = if enabled {
+                Some(VarObject::new())
+            } else {
+                None
+            };
+        }
+    }
+    fn new_row(&mut self) -> ExpressionRow {
+        let row = ExpressionRow::new(self.next_row_id, self.use_var_objects);
+        self.next_row_id += 1;
+        row
     }
     pub fn add_entry(&mut self, entry: String) {
+        self.add_group_entry(entry, None);
+    }
+    fn add_group_entry(&mut self, entry: String, group: Option<String>) {
+        let mut new_row = self.new_row();
         {
             let mut rows = self.table.rows_mut();
             match rows.last_mut() {
                 Some(row) if row.is_empty() => {
                     row.expression.set(entry);
+                    row.group = group;
                 }
                 _ => {
-                    let mut row = ExpressionRow::new();
-                    row.expression.set(entry);
-                    rows.push(row);
+                    new_row.expression.set(entry);
+                    new_row.group = group;
+                    rows.push(new_row);
                 }
             }
         }
         self.shrink_to_fit();
     }
+
+    pub fn load_set(&mut self, name: &str, p: &mut Context) {
+        let set = match expression_set::load(name) {
+            Ok(set) => set,
+            Err(e) => {
+                p.log(format!("Failed to load expression set: {}", e));
+                return;
+            }
+        };
+        p.log(format!(
+            "Loaded {} expressions and {} function groups",
+            set.expressions.len(),
+            set.groups.len()
+        ));
+        for expression in set.expressions {
+            self.add_entry(expression);
+        }
+        for group in set.groups {
+            // Rows of the previous version of the group are replaced.
+            self.remove_group_rows(|function| function == group.function, p);
+            if self.active_group.as_ref() == Some(&group.function) {
+                self.active_group = None;
+            }
+            self.groups.retain(|g| g.function != group.function);
+            self.groups.push(group);
+        }
+    }
+
+    // Rows of active groups are stored in their group only.
+    pub fn save_set(&mut self, name: &str, p: &mut Context) {
+        self.store_group_rows();
+        let set = ExpressionSet {
+            expressions: self
+                .table
+                .rows()
+                .iter()
+                .filter(|r| !r.is_empty() && r.group.is_none())
+                .map(|r| r.expression.get().to_owned())
+                .collect(),
+            groups: self.groups.clone(),
+        };
+        match expression_set::save(name, &set) {
+            Ok(path) => p.log(format!("Saved expression set to {}", path.display())),
+            Err(e) => p.log(format!("Failed to save expression set: {}", e)),
+        }
+    }
+
+    fn remove_group_rows(&mut self, remove: impl Fn(&str) -> bool, p: &mut Context) {
+        let rows = std::mem::take(&mut *self.table.rows_mut());
+        let (removed, kept): (Vec<_>, Vec<_>) = rows
+            .into_iter()
+            .partition(|r| r.group.as_deref().is_some_and(&remove));
+        *self.table.rows_mut() = kept;
+        for row in removed {
+            row.discard(p);
+        }
+    }
+
+    // Write the (edited or removed) rows of the active group back to the group.
+    fn store_group_rows(&mut self) {
+        let function = match &self.active_group {
+            Some(function) => function,
+            None => return,
+        };
+        let expressions = self
+            .table
+            .rows()
+            .iter()
+            .filter(|r| !r.is_empty() && r.group.as_ref() == Some(function))
+            .map(|r| r.expression.get().to_owned())
+            .collect();
+        if let Some(group) = self.groups.iter_mut().find(|g| &g.function == function) {
+            group.expressions = expressions;
+        }
+    }
+
+    // Show the group of the function of the selected frame and hide all others.
+    fn activate_groups(&mut self, p: &mut Context) {
+        let function = self.frame.as_ref().and_then(|f| f.function.clone());
+        if self.active_group.is_some() && self.active_group == function {
+            return;
+        }
+        self.store_group_rows();
+        self.remove_group_rows(|g| Some(g) != function.as_deref(), p);
+        self.active_group = None;
+        let group = self
+            .groups
+            .iter()
+            .find(|g| Some(&g.function) == function.as_ref())
+            .cloned();
+        if let Some(group) = group {
+            for expression in group.expressions {
+                self.add_group_entry(expression, Some(group.function.clone()));
+            }
+            self.active_group = Some(group.function);
+        }
+    }
     fn shrink_to_fit(&mut self) {
         let begin_of_empty_range = {
             let iter = self.table.rows().iter().enumerate().rev();
@@ -236,26 +1839,414 @@ impl ExpressionTable {
                 0
             }
         };
+        let new_row = self.new_row();
         let mut rows = self.table.rows_mut();
         rows.drain(begin_of_empty_range..);
-        rows.push(ExpressionRow::new());
+        rows.push(new_row);
+    }
+
+    fn with_row<R>(&mut self, id: RowId, f: impl FnOnce(&mut ExpressionRow) -> R) -> Option<R> {
+        self.table.rows_mut().iter_mut().find(|r| r.id == id).map(f)
+    }
+
+    // Values are recorded in the history of the rows when the next results arrive.
+    pub fn record_stop(&mut self, frame: Option<&Frame>, reason: Option<&StopReason>) {
+        self.stop = self.stop.next(frame);
+        for row in self.table.rows_mut().iter_mut() {
+            row.update_watchpoint(reason);
+        }
+    }
+
+    // gdb was restarted and restored the watchpoints under new numbers. The others are gone.
+    pub fn renumber_watchpoints(
+        &mut self,
+        renumbered: &HashMap<BreakPointNumber, BreakPointNumber>,
+    ) {
+        for row in self.table.rows_mut().iter_mut() {
+            row.watchpoint = row
+                .watchpoint
+                .take()
+                .filter(|watchpoint| !watchpoint.out_of_scope)
+                .and_then(|mut watchpoint| {
+                    watchpoint.number = *renumbered.get(&watchpoint.number)?;
+                    Some(watchpoint)
+                });
+        }
+    }
+
+    // The watchpoint has been deleted (e.g., from the console).
+    pub fn forget_watchpoint(&mut self, number: BreakPointNumber) {
+        for row in self.table.rows_mut().iter_mut() {
+            if row.watchpoint.as_ref().map(|w| w.number) == Some(number) {
+                row.watchpoint = None;
+            }
+        }
+    }
+
+    pub fn set_frame(&mut self, frame: Frame, thread_id: Option<u64>) {
+        self.frame = Some(frame);
+        if thread_id.is_some() {
+            self.thread = thread_id;
+        }
     }
 
     pub fn update_results(&mut self, p: &mut Context) {
+        self.activate_groups(p);
         for row in self.table.rows_mut().iter_mut() {
             row.update_result(p);
         }
     }
+
+    fn handle_printed_value(
+        &mut self,
+        id: RowId,
+        result: Result<ResultRecord, ExecuteError>,
+        p: &mut Context,
+    ) {
+        let stop = self.stop.clone();
+        self.with_row(id, |row| {
+            row.pending_evaluation = None;
+            row.show_printed_value(result, &stop, p);
+        });
+    }
+
+    fn handle_evaluation_result(
+        &mut self,
+        id: RowId,
+        number: Option<usize>,
+        result: Result<ResultRecord, ExecuteError>,
+        p: &mut Context,
+    ) {
+        let stop = self.stop.clone();
+        self.with_row(id, |row| {
+            row.pending_evaluation = None;
+            row.show_result(result, number, &stop, p);
+        });
+    }
+
+    fn handle_frames(
+        &mut self,
+        id: RowId,
+        result: Result<ResultRecord, ExecuteError>,
+        p: &mut Context,
+    ) {
+        self.with_row(id, |row| {
+            row.pending_evaluation = None;
+            row.evaluate_in_function(result, p);
+        });
+    }
+
+    fn cycle_pin(&mut self, p: &mut Context) {
+        let (thread, frame) = (self.thread, self.frame.as_ref());
+        if let Some(row) = self.table.current_row_mut() {
+            row.cycle_pin(thread, frame, p);
+        }
+    }
+
+    fn current_index(&self) -> Option<usize> {
+        let id = self.table.current_row()?.id;
+        self.table.rows().iter().position(|r| r.id == id)
+    }
+
+    fn delete_current_row(&mut self, p: &mut Context) {
+        let index = match self.current_index() {
+            Some(index) => index,
+            None => return,
+        };
+        let row = self.table.rows_mut().remove(index);
+        row.discard(p);
+        self.shrink_to_fit();
+    }
+
+    // Swap the current row with the one below (or above), but keep the empty row at the end.
+    fn move_current_row(&mut self, down: bool) {
+        let index = match self.current_index() {
+            Some(index) => index,
+            None => return,
+        };
+        let other = if down {
+            index + 1
+        } else {
+            match index.checked_sub(1) {
+                Some(other) => other,
+                None => return,
+            }
+        };
+        let last = self.table.rows().len() - 1;
+        if index == last || other == last {
+            return;
+        }
+        self.table.rows_mut().swap(index, other);
+        let _ = if down {
+            self.table.move_down()
+        } else {
+            self.table.move_up()
+        };
+    }
+
+    // Insert a row below the one with the given id (in the same scope) and select it.
+    fn insert_row_after(&mut self, id: RowId, expression: String, p: &mut Context) {
+        let index = match self.table.rows().iter().position(|r| r.id == id) {
+            Some(index) => index,
+            None => return,
+        };
+        let mut row = self.new_row();
+        let original = &self.table.rows()[index];
+        row.expression.set(expression);
+        row.pin = original.pin.clone();
+        if original.var_object.is_some() {
+            row.var_object = Some(VarObject::new());
+        }
+        row.update_result(p);
+        self.table.rows_mut().insert(index + 1, row);
+        if self.table.current_row().map(|r| r.id) == Some(id) {
+            let _ = self.table.move_down();
+        }
+    }
+
+    fn duplicate_current_row(&mut self, p: &mut Context) {
+        let (id, expression, formats, collapsed) = match self.table.current_row() {
+            Some(row) if !row.is_empty() => (
+                row.id,
+                row.expression.get().to_owned(),
+                row.formats.clone(),
+                row.collapsed,
+            ),
+            _ => return,
+        };
+        self.insert_row_after(id, expression, p);
+        if let Some(row) = self.table.current_row_mut() {
+            row.formats = formats;
+            row.collapsed = collapsed;
+        }
+    }
+
+    // While the filter of the current row is edited, it gets all input that it can handle.
+    fn filter_input(&mut self, input: Input) -> Option<Input> {
+        match self.table.current_row_mut() {
+            Some(row) if row.editing_filter => row.edit_filter(input),
+            _ => Some(input),
+        }
+    }
+
+    fn toggle_collapsed(&mut self) {
+        if let Some(row) = self.table.current_row_mut() {
+            row.collapsed ^= true;
+        }
+    }
+
+    // Add a row for the node that is selected in the value of the current row, optionally
+    // dereferencing it.
+    fn add_selected_node(&mut self, dereference: bool, p: &mut Context) {
+        let row = match self.table.current_row() {
+            Some(row) if !row.collapsed => row,
+            _ => return,
+        };
+        let id = row.id;
+        let prefix = if dereference { "*" } else { "" };
+        match &row.var_object {
+            // Var objects know how to access their children.
+            Some(var) => {
+                let name = match var.tree.selected() {
+                    Some(node) if var.name.is_some() => node.id.clone(),
+                    _ => return,
+                };
+                p.execute_async(
+                    MiCommand::var_info_path_expression(name),
+                    move |tui, p, result| match check_result(result).and_then(|res| {
+                        str_field(&res.results, "path_expr")
+                            .map(|path| path.to_owned())
+                            .map_err(|e| e.to_string())
+                    }) {
+                        Ok(path) => tui.expression_table.insert_row_after(
+                            id,
+                            format!("{}{}", prefix, path),
+                            p,
+                        ),
+                        Err(e) => p.log(format!("Cannot access the member: {}", e)),
+                    },
+                );
+            }
+            None => {
+                let access = row
+                    .result
+                    .selected()
+                    .filter(|_| row.value.is_some())
+                    .and_then(|node| row.access_of(&node.id));
+                match access {
+                    Some(access) => self.insert_row_after(id, format!("{}{}", prefix, access), p),
+                    None => p.log("The selected member cannot be accessed by an expression"),
+                }
+            }
+        }
+    }
+
+    fn handle_size(
+        &mut self,
+        id: RowId,
+        expr: String,
+        result: Result<ResultRecord, ExecuteError>,
+        p: &mut Context,
+    ) {
+        self.with_row(id, |row| row.show_size(expr, result, p));
+    }
+
+    fn handle_length(
+        &mut self,
+        id: RowId,
+        value: Option<String>,
+        fetch: Fetch,
+        result: Result<ResultRecord, ExecuteError>,
+        p: &mut Context,
+    ) {
+        // The value may have changed in the meantime.
+        self.with_row(id, |row| {
+            if row.value == value {
+                row.show_length(fetch, result, p);
+            }
+        });
+    }
+
+    fn handle_slice(
+        &mut self,
+        id: RowId,
+        value: Option<String>,
+        fetch: Fetch,
+        result: Result<ResultRecord, ExecuteError>,
+        p: &mut Context,
+    ) {
+        self.with_row(id, |row| {
+            if row.value == value {
+                row.show_slice(fetch, result, p);
+            }
+        });
+    }
+
+    fn handle_var_created(
+        &mut self,
+        id: RowId,
+        expr: String,
+        result: Result<ResultRecord, ExecuteError>,
+        p: &mut Context,
+    ) {
+        let created = check_result(result)
+            .and_then(|res| VarObjectInfo::decode(&res.results).map_err(|e| e.to_string()));
+        let mut rows = self.table.rows_mut();
+        match rows
+            .iter_mut()
+            .find(|r| r.id == id && r.var_object.is_some())
+        {
+            Some(row) => row.show_var_object(expr, created, p),
+            None => {
+                // The row has been removed or switched back in the meantime.
+                if let Ok(created) = created {
+                    delete_var_object(p, created.name);
+                }
+            }
+        }
+    }
+
+    fn handle_var_update(
+        &mut self,
+        id: RowId,
+        result: Result<ResultRecord, ExecuteError>,
+        p: &mut Context,
+    ) {
+        self.with_row(id, |row| {
+            if row.var_object.is_some() {
+                row.apply_var_changes(result, p);
+            }
+        });
+    }
+
+    fn handle_var_children(
+        &mut self,
+        id: RowId,
+        name: &str,
+        from: usize,
+        result: Result<ResultRecord, ExecuteError>,
+        p: &mut Context,
+    ) {
+        self.with_row(id, |row| {
+            if row.var_object.is_some() {
+                row.show_var_children(name, from, result, p);
+            }
+        });
+    }
+
+    fn toggle_history(&mut self) {
+        self.history_view = match self.history_view {
+            Some(_) => None,
+            None => self
+                .table
+                .current_row()
+                .map(|r| (r.id, HistoryView::default())),
+        };
+    }
+
+    // While the history is shown, the navigation keys select its entries instead of table cells.
+    fn history_input(&mut self, input: Input) -> Option<Input> {
+        let (id, view) = match &mut self.history_view {
+            Some((id, view)) => (*id, view),
+            None => return Some(input),
+        };
+        let history = match self.table.rows().iter().find(|r| r.id == id) {
+            Some(row) => &row.history,
+            None => return Some(input),
+        };
+        // Keys are consumed even at the end of the history so that they do not reach the table.
+        let _ = if input.matches(Key::Up) {
+            view.select_older(history)
+        } else if input.matches(Key::Down) {
+            view.select_newer(history)
+        } else if input.matches(Key::Home) {
+            view.select_oldest(history)
+        } else if input.matches(Key::End) {
+            view.select_latest(history)
+        } else if input.matches(Key::Char(' ')) {
+            view.toggle_base(history)
+        } else {
+            return Some(input);
+        };
+        None
+    }
+
+    fn apply_completion(&mut self, id: RowId, completion: CompletionState) {
+        self.with_row(id, |row| {
+            // Discard the completion if the expression has been edited in the meantime.
+            if completion.matches(row.expression.get(), row.expression.cursor_pos()) {
+                row.completion_state = Some(completion);
+                row.set_completion();
+            }
+        });
+    }
 }
 
 impl Container<Context> for ExpressionTable {
     fn input(&mut self, input: Input, p: &mut Context) -> Option<Input> {
+        if self.history_view.is_some() {
+            return input
+                .chain((Key::Ctrl('o'), || self.toggle_history()))
+                .chain(|input| self.history_input(input))
+                .finish();
+        }
         let res = input
+            .chain(|input| self.filter_input(input))
+            .chain((Key::Ctrl('o'), || self.toggle_history()))
+            .chain((Key::Ctrl('t'), || self.cycle_pin(p)))
+            .chain((Key::Ctrl('x'), || self.delete_current_row(p)))
+            .chain((Key::Ctrl('u'), || self.move_current_row(false)))
+            .chain((Key::Ctrl('d'), || self.move_current_row(true)))
+            .chain((Key::Ctrl('y'), || self.duplicate_current_row(p)))
+            .chain((Key::Ctrl('g'), || self.toggle_collapsed()))
             .chain(
                 NavigateBehavior::new(&mut self.table) //TODO: Fix this properly in lineedit
                     .down_on(Key::Char('\n')),
             )
             .chain(self.table.current_cell_behavior(p))
+            // Only the value column passes these on (the expression column inserts them).
+            .chain((Key::Char('*'), || self.add_selected_node(true, p)))
+            .chain((Key::Char('a'), || self.add_selected_node(false, p)))
             .chain(
                 NavigateBehavior::new(&mut self.table)
                     .up_on(Key::Up)
@@ -269,16 +2260,677 @@ impl Container<Context> for ExpressionTable {
     }
 
     fn as_widget<'a>(&'a self) -> Box<dyn Widget + 'a> {
-        Box::new(
-            self.table
-                .as_widget()
-                .row_separation(SeparatingStyle::AlternatingStyle(
-                    StyleModifier::new().bg_color(Color::Black),
-                ))
-                .col_separation(SeparatingStyle::Draw(
-                    GraphemeCluster::try_from('│').unwrap(),
-                ))
-                .focused(StyleModifier::new().bold(true)),
-        )
+        let table = self
+            .table
+            .as_widget()
+            .row_separation(SeparatingStyle::AlternatingStyle(
+                StyleModifier::new().bg_color(Color::Black),
+            ))
+            .col_separation(SeparatingStyle::Draw(
+                GraphemeCluster::try_from('│').unwrap(),
+            ))
+            .focused(StyleModifier::new().bold(true));
+        let history = self.history_view.as_ref().and_then(|(id, view)| {
+            let row = self.table.rows().iter().find(|r| r.id == *id)?;
+            Some(HistoryPanel {
+                expression: row.expression.get(),
+                history: if row.var_object.is_none() {
+                    Some(&row.history)
+                } else {
+                    None
+                },
+                view,
+            })
+        });
+        match history {
+            Some(history) => Box::new(
+                VLayout::new()
+                    .separator(GraphemeCluster::try_from('─').unwrap())
+                    .widget(table)
+                    .widget(history),
+            ),
+            None => Box::new(table),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::Event;
+    use unsegen::input::{Scrollable, ToEvent};
+
+    fn done(json: &str) -> Result<ResultRecord, ExecuteError> {
+        match json::parse(json).unwrap() {
+            json::JsonValue::Object(results) => Ok(ResultRecord {
+                token: None,
+                class: ResultClass::Done,
+                results,
+            }),
+            other => panic!("not an object: {}", other),
+        }
+    }
+
+    fn lines(tree: &ValueTree) -> Vec<String> {
+        fn collect(node: &TreeNode, out: &mut Vec<String>) {
+            out.push(format!(
+                "{}={}{}",
+                node.key,
+                node.value,
+                if node.changed { "*" } else { "" }
+            ));
+            if let Children::Loaded(children) = &node.children {
+                for child in children.iter().filter(|_| node.expanded) {
+                    collect(child, out);
+                }
+            }
+        }
+        let mut out = Vec::new();
+        collect(tree.root(), &mut out);
+        out
+    }
+
+    #[test]
+    fn test_var_object() {
+        let (mut p, _events) = Context::replay("");
+        let mut row = ExpressionRow::new(0, true);
+        row.expression.set("s");
+        row.update_var_object(&mut p);
+        assert!(row.var_object.as_ref().unwrap().creating);
+
+        let created = VarObjectInfo::decode(
+            &json::parse(r#"{"name":"var1","numchild":"2","value":"{...}","type":"struct s"}"#)
+                .unwrap(),
+        );
+        row.show_var_object("s".to_owned(), created.map_err(|e| e.to_string()), &mut p);
+        let var = row.var_object.as_mut().unwrap();
+        assert_eq!(var.name.as_deref(), Some("var1"));
+        assert!(matches!(var.tree.root().children, Children::Loading));
+
+        row.show_var_children(
+            "var1",
+            0,
+            done(r#"{"numchild":"2","children":[{"name":"var1.public","exp":"public","numchild":"1"},{"name":"var1.b","exp":"b","numchild":"0","value":"2","type":"int"}],"has_more":"0"}"#),
+            &mut p,
+        );
+        // Pseudo children are expanded right away.
+        row.show_var_children(
+            "var1.public",
+            0,
+            done(r#"{"numchild":"1","children":[{"name":"var1.public.a","exp":"a","numchild":"0","value":"1","type":"int"}],"has_more":"0"}"#),
+            &mut p,
+        );
+        let var = row.var_object.as_ref().unwrap();
+        assert_eq!(lines(&var.tree), ["={...}", "public=", "a=1", "b=2"]);
+
+        row.apply_var_changes(
+            done(r#"{"changelist":[{"name":"var1.public.a","value":"3","in_scope":"true","type_changed":"false","has_more":"0"}]}"#),
+            &mut p,
+        );
+        let var = row.var_object.as_ref().unwrap();
+        assert_eq!(lines(&var.tree), ["={...}", "public=", "a=3*", "b=2"]);
+
+        // The var object is kept if it cannot be updated while the program is running.
+        row.apply_var_changes(Err(ExecuteError::Busy), &mut p);
+        row.apply_var_changes(Err(ExecuteError::Timeout), &mut p);
+        let var = row.var_object.as_ref().unwrap();
+        assert_eq!(var.name.as_deref(), Some("var1"));
+        assert_eq!(lines(&var.tree), ["={...}", "public=", "a=3*", "b=2"]);
+    }
+
+    #[test]
+    fn test_formats() {
+        let (mut p, _events) = Context::replay("");
+        let mut row = ExpressionRow::new(0, false);
+        row.expression.set("s");
+        let stop = Stop::default();
+        row.show_result(
+            done(r#"{"value":"{a = -1, c = {104, 105}}"}"#),
+            None,
+            &stop,
+            &mut p,
+        );
+        assert_eq!(
+            lines(&row.result),
+            ["={...}", "a=-1", "c={...}", "[0]=104", "[1]=105"]
+        );
+        assert!(row.pending_sizes.is_empty());
+
+        row.cycle_format("s".to_owned(), &mut p);
+        assert_eq!(lines(&row.result)[1], "a=-0x1");
+        let mut pending = row.pending_sizes.iter().cloned().collect::<Vec<_>>();
+        pending.sort();
+        assert_eq!(pending, ["(s).a", "(s).c[0]"]);
+        row.show_size("(s).a".to_owned(), done(r#"{"value":"4"}"#), &mut p);
+        // The tree is rebuilt once all sizes are known.
+        assert_eq!(lines(&row.result)[1], "a=-0x1");
+        row.show_size("(s).c[0]".to_owned(), done(r#"{"value":"1"}"#), &mut p);
+        assert_eq!(
+            lines(&row.result),
+            ["={...}", "a=0xffffffff", "c={...}", "[0]=0x68", "[1]=0x69"]
+        );
+
+        // Subtrees can have their own format.
+        row.formats.insert("s.c".to_owned(), Format::String);
+        row.rebuild_result(&mut p, false);
+        assert_eq!(
+            lines(&row.result),
+            [
+                "={...}",
+                "a=0xffffffff",
+                "c=\"hi\"",
+                "[0]=104 'h'",
+                "[1]=105 'i'"
+            ]
+        );
+        let note = row.result.find_mut("s.c").and_then(|n| n.note.clone());
+        assert_eq!(note.as_deref(), Some("string"));
+
+        row.show_result(
+            done(r#"{"value":"{a = -2, c = {104, 105}}"}"#),
+            None,
+            &stop,
+            &mut p,
+        );
+        assert_eq!(lines(&row.result)[1], "a=0xfffffffe*");
+        assert_eq!(lines(&row.result)[2], "c=\"hi\"");
+
+        // Long runs of repeated chars are cut off.
+        row.show_result(
+            done(r#"{"value":"{a = -2, c = {104, 120 <repeats 4000000000 times>}}"}"#),
+            None,
+            &stop,
+            &mut p,
+        );
+        let c = &lines(&row.result)[2];
+        assert!(c.starts_with("c=\"hxxx"));
+        assert!(c.ends_with("x\"...*"));
+        assert!(c.len() < MAX_STRING_LENGTH + 10);
+    }
+
+    #[test]
+    fn test_array_pages() {
+        let (mut p, _events) = Context::replay("");
+        let mut row = ExpressionRow::new(0, false);
+        row.expression.set("a");
+        let value = format!(
+            "{{{}, 7 <repeats 20 times>, 8...}}",
+            (0..150)
+                .map(|i| i.to_string())
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+        let value = format!(r#"{{"value":"{}"}}"#, value);
+        row.show_result(done(&value), Some(4), &Stop::default(), &mut p);
+        let shown = lines(&row.result);
+        assert_eq!(shown.len(), 1 + PAGE_SIZE + 1);
+        assert_eq!(shown[PAGE_SIZE + 1], "=...");
+        assert_eq!(row.more.get("a[...]"), Some(&More::Page("a".to_owned())));
+
+        while row.result.scroll_forwards().is_ok() {}
+        assert!(row.expand_more(&mut p));
+        let shown = lines(&row.result);
+        assert_eq!(shown[151], "[150..170]=7");
+        assert_eq!(shown[152], "[170]=8");
+        assert_eq!(shown[153], "=...");
+        let note = row.result.find_mut("a[150]").and_then(|n| n.note.clone());
+        assert_eq!(note.as_deref(), Some("repeated 20 times"));
+        assert_eq!(row.access_of("a[170]").as_deref(), Some("a[170]"));
+        let fetch = Fetch {
+            array: "a".to_owned(),
+            access: "(a)".to_owned(),
+            start: 171,
+        };
+        assert_eq!(row.more.get("a[...]"), Some(&More::Fetch(fetch.clone())));
+        // The elements are fetched from the value in gdb's value history.
+        assert_eq!(
+            row.history_access(&fetch.access, &mut p).as_deref(),
+            Some("$4")
+        );
+
+        // gdb did not print the remaining elements, so they are fetched with a slice.
+        while row.result.scroll_forwards().is_ok() {}
+        assert!(row.expand_more(&mut p));
+        row.show_length(fetch.clone(), done(r#"{"value":"173"}"#), &mut p);
+        row.show_slice(fetch, done(r#"{"value":"{9, 10}"}"#), &mut p);
+        let shown = lines(&row.result);
+        assert_eq!(&shown[153..], ["[171]=9", "[172]=10"]);
+        assert!(row.more.is_empty());
+
+        // Fetched elements belong to the value they were fetched for.
+        row.show_result(done(&value), None, &Stop::default(), &mut p);
+        assert_eq!(lines(&row.result).len(), 1 + 152 + 1);
+    }
+
+    #[test]
+    fn test_value_history() {
+        let (mut p, events) = Context::replay(
+            r#"> 1-interpreter-exec console "print a"
+< ~"$4 = {1, 2}\n"
+< 1^done
+< (gdb)
+> 2-data-evaluate-expression "$4"
+< 2^done,value="{1, 2}"
+< (gdb)
+"#,
+        );
+        let next_result = || match events
+            .recv_timeout(std::time::Duration::from_secs(5))
+            .unwrap()
+        {
+            Event::AsyncResult(record) => Ok(record),
+            event => panic!("Unexpected event {:?}", event),
+        };
+        let mut row = ExpressionRow::new(0, false);
+        row.expression.set("a");
+        row.evaluate(None, &mut p);
+
+        // The output of print is not shown in the console.
+        let printed = next_result();
+        assert_eq!(
+            printed.as_ref().unwrap().results["console"].as_str(),
+            Some("$4 = {1, 2}\n")
+        );
+        row.show_printed_value(printed, &Stop::default(), &mut p);
+        let value = next_result();
+        row.show_result(value, Some(4), &Stop::default(), &mut p);
+        assert_eq!(lines(&row.result), ["={...}", "[0]=1", "[1]=2"]);
+        assert_eq!(row.value_number, Some(4));
+        assert_eq!(
+            row.history_access("(a)[1]", &mut p).as_deref(),
+            Some("$4[1]")
+        );
+    }
+
+    #[test]
+    fn test_var_object_pages() {
+        let (mut p, _events) = Context::replay("");
+        let mut row = ExpressionRow::new(0, true);
+        row.expression.set("v");
+        row.update_var_object(&mut p);
+        let created = VarObjectInfo::decode(
+            &json::parse(r#"{"name":"var1","numchild":"0","value":"{...}","type":"vector","dynamic":"1","has_more":"1"}"#)
+                .unwrap(),
+        );
+        row.show_var_object("v".to_owned(), created.map_err(|e| e.to_string()), &mut p);
+        let child = |i: usize| {
+            format!(
+                r#"{{"name":"var1.[{0}]","exp":"[{0}]","numchild":"0","value":"{0}","type":"int"}}"#,
+                i
+            )
+        };
+        let page = |children: Vec<String>, has_more| {
+            done(&format!(
+                r#"{{"numchild":"{}","children":[{}],"has_more":"{}"}}"#,
+                children.len(),
+                children.join(","),
+                has_more
+            ))
+        };
+        row.show_var_children("var1", 0, page(vec![child(0), child(1)], 1), &mut p);
+        let var = row.var_object.as_mut().unwrap();
+        assert_eq!(lines(&var.tree), ["={...}", "[0]=0", "[1]=1", "=..."]);
+
+        while var.tree.scroll_forwards().is_ok() {}
+        assert!(row.expand_more(&mut p));
+        let var = row.var_object.as_ref().unwrap();
+        assert_eq!(lines(&var.tree)[3], "=(loading...)");
+        row.show_var_children("var1", 2, page(vec![child(2)], 0), &mut p);
+        let var = row.var_object.as_ref().unwrap();
+        assert_eq!(lines(&var.tree), ["={...}", "[0]=0", "[1]=1", "[2]=2"]);
+    }
+
+    #[test]
+    fn test_typed_values() {
+        let (mut p, _events) = Context::replay("");
+        let mut row = ExpressionRow::new(0, false);
+        row.expression.set("s");
+        row.show_result(
+            done(r#"{"value":"{c = 97 'a', r = @0x10: {x = 1}, t = (2, <optimized out>)}"}"#),
+            None,
+            &Stop::default(),
+            &mut p,
+        );
+        assert_eq!(
+            lines(&row.result),
+            [
+                "={...}",
+                "c=97 'a'",
+                "r={...}",
+                "x=1",
+                "t=(...)",
+                "0=2",
+                "1=<optimized out>"
+            ]
+        );
+        let note = row.result.find_mut("s.r").and_then(|n| n.note.clone());
+        assert_eq!(note.as_deref(), Some("@0x10"));
+        assert_eq!(row.access_of("s.r.x").as_deref(), Some("s.r.x"));
+        assert_eq!(row.access_of("s.t.0").as_deref(), Some("s.t.0"));
+
+        row.formats.insert("s.c".to_owned(), Format::Hex);
+        row.sizes.insert("(s).c".to_owned(), Some(1));
+        row.rebuild_result(&mut p, false);
+        assert_eq!(lines(&row.result)[1], "c=0x61");
+    }
+
+    #[test]
+    fn test_gdb_quit() {
+        // gdb may crash while the value is evaluated (and be restarted afterwards).
+        let (mut p, _events) = Context::replay("");
+        let mut row = ExpressionRow::new(0, false);
+        row.expression.set("s");
+        row.show_result(Err(ExecuteError::Quit), None, &Stop::default(), &mut p);
+        assert_eq!(lines(&row.result), ["=*GDB quit*"]);
+    }
+
+    #[test]
+    fn test_filter() {
+        let (mut p, _events) = Context::replay("");
+        let key = |c| Input {
+            event: Key::Char(c).to_event(),
+            raw: Vec::new(),
+        };
+        let mut table = ExpressionTable::new();
+        table.add_entry("foo".to_owned());
+        let row = table.table.current_row_mut().unwrap();
+        row.show_result(
+            done(r#"{"value":"{bar = {{baz = 1, qux = 2}, {baz = 3, qux = 4}}, baz = 5}"}"#),
+            None,
+            &Stop::default(),
+            &mut p,
+        );
+        let _ = table.table.move_right();
+        for c in "/qux\n".chars() {
+            assert!(table.input(key(c), &mut p).is_none());
+        }
+        let row = table.table.current_row_mut().unwrap();
+        assert!(!row.editing_filter);
+        // The lines that are shown (by selecting them one after another)
+        let mut shown = Vec::new();
+        let _ = row.result.scroll_to_beginning();
+        loop {
+            shown.push(row.result.selected().unwrap().id.clone());
+            if row.result.scroll_forwards().is_err() {
+                break;
+            }
+        }
+        assert_eq!(
+            shown,
+            [
+                "foo",
+                "foo.bar",
+                "foo.bar[0]",
+                "foo.bar[0].qux",
+                "foo.bar[1]",
+                "foo.bar[1].qux"
+            ]
+        );
+
+        // Copy the access path of a match as a new row.
+        assert!(table.input(key('a'), &mut p).is_none());
+        let current = table.table.current_row().unwrap();
+        assert_eq!(current.expression.get(), "foo.bar[1].qux");
+
+        // Changing the expression clears the filter.
+        let _ = table.table.move_up();
+        let row = table.table.current_row_mut().unwrap();
+        assert_eq!(row.result.filter(), "qux");
+        row.expression.set("");
+        row.clear_filter();
+        assert_eq!(row.result.filter(), "");
+    }
+
+    #[test]
+    fn test_watchpoint() {
+        let mut table = ExpressionTable::new();
+        table.add_entry("x".to_owned());
+        let number = |n: usize| BreakPointNumber {
+            major: n,
+            minor: None,
+        };
+        table.table.rows_mut()[0].watchpoint = Some(Watchpoint {
+            number: number(2),
+            mode: WatchMode::Write,
+            expression: "x".to_owned(),
+            out_of_scope: false,
+            trigger: None,
+        });
+        let text = |table: &ExpressionTable| {
+            let row = &table.table.rows()[0];
+            row.watchpoint
+                .as_ref()
+                .map(|watchpoint| watchpoint_note(watchpoint, row.expression.get()))
+        };
+        let trigger = |n| StopReason::WatchpointTrigger {
+            mode: WatchMode::Write,
+            number: Some(number(n)),
+            expression: Some("x".to_owned()),
+            old_value: Some("1".to_owned()),
+            new_value: Some("2".to_owned()),
+        };
+
+        table.record_stop(None, Some(&trigger(2)));
+        assert_eq!(text(&table).unwrap(), "write watchpoint 2: 1 -> 2");
+        // Other watchpoints and stops clear the values.
+        table.record_stop(None, Some(&trigger(3)));
+        assert_eq!(text(&table).unwrap(), "write watchpoint 2");
+
+        table.record_stop(
+            None,
+            Some(&StopReason::WatchpointScope {
+                number: Some(number(2)),
+            }),
+        );
+        assert_eq!(text(&table).unwrap(), "write watchpoint 2: out of scope");
+        table.record_stop(None, None);
+        assert_eq!(text(&table), None);
+    }
+
+    #[test]
+    fn test_choose_watch_mode() {
+        let (mut p, _events) = Context::replay(
+            r#"> 1-break-watch -r -- x
+< 1^done,hw-rwpt={number="2",exp="x"}
+< (gdb)
+> 2-break-delete 2
+< 2^done
+< (gdb)
+> 3-break-watch -a -- x
+< 3^done,hw-awpt={number="3",exp="x"}
+< (gdb)
+"#,
+        );
+        let key = |c| Input {
+            event: Key::Char(c).to_event(),
+            raw: Vec::new(),
+        };
+        let mut row = ExpressionRow::new(0, false);
+        row.expression.set("x");
+        // Keys only select the mode after Ctrl-w.
+        assert!(row.choose_watch_mode(key('r'), &mut p).is_some());
+
+        row.choosing_watch_mode = true;
+        assert!(row.choose_watch_mode(key('r'), &mut p).is_none());
+        assert!(!row.choosing_watch_mode);
+        let watchpoint = row.watchpoint.as_ref().unwrap();
+        assert_eq!(
+            (watchpoint.number.major, watchpoint.mode),
+            (2, WatchMode::Read)
+        );
+        // The watchpoint is known like any other breakpoint (e.g., for ipc and dap clients).
+        let bp = &p.gdb.breakpoints[&watchpoint.number];
+        assert_eq!(bp.watchpoint, Some((WatchMode::Read, "x".to_owned())));
+
+        // Other keys cancel, selecting the same mode keeps the watchpoint.
+        row.choosing_watch_mode = true;
+        assert!(row.choose_watch_mode(key('q'), &mut p).is_none());
+        row.choosing_watch_mode = true;
+        row.choose_watch_mode(key('r'), &mut p);
+        assert_eq!(row.watchpoint.as_ref().unwrap().number.major, 2);
+
+        row.choosing_watch_mode = true;
+        row.choose_watch_mode(key('a'), &mut p);
+        let watchpoint = row.watchpoint.as_ref().unwrap();
+        assert_eq!(
+            (watchpoint.number.major, watchpoint.mode),
+            (3, WatchMode::Access)
+        );
+        assert_eq!(p.gdb.breakpoints.len(), 1);
+    }
+
+    #[test]
+    fn test_pin() {
+        let (mut p, _events) = Context::replay("");
+        let mut row = ExpressionRow::new(0, false);
+        row.expression.set("this->state");
+        let frame = Frame {
+            level: Some(1),
+            function: Some("run".to_owned()),
+            ..Frame::default()
+        };
+        row.cycle_pin(None, Some(&frame), &mut p);
+        assert_eq!(row.pin, None);
+
+        row.cycle_pin(Some(2), Some(&frame), &mut p);
+        assert_eq!(
+            row.pin.as_ref().unwrap().to_string(),
+            "in thread 2, frame 1"
+        );
+        assert_eq!(row.pinned_level, Some(1));
+        row.cycle_pin(Some(2), Some(&frame), &mut p);
+        assert_eq!(
+            row.pin.as_ref().unwrap().to_string(),
+            "in thread 2, function run"
+        );
+
+        // The innermost frame of the function is used.
+        row.evaluate_in_function(
+            done(r#"{"stack":[{"level":"0","func":"step"},{"level":"1","func":"run"},{"level":"2","func":"run"}]}"#),
+            &mut p,
+        );
+        assert_eq!(row.pinned_level, Some(1));
+        row.evaluate_in_function(done(r#"{"stack":[{"level":"0","func":"main"}]}"#), &mut p);
+        assert_eq!(lines(&row.result), ["=*Not on the stack of thread 2*: run"]);
+
+        row.cycle_pin(Some(2), Some(&frame), &mut p);
+        assert_eq!(row.pin, None);
+    }
+
+    #[test]
+    fn test_function_groups() {
+        let (mut p, _events) = Context::replay("");
+        let mut table = ExpressionTable::new();
+        table.add_entry("global".to_owned());
+        table.groups.push(FunctionGroup {
+            function: "parse".to_owned(),
+            expressions: vec!["token".to_owned(), "pos".to_owned()],
+        });
+        let expressions = |table: &ExpressionTable| {
+            table
+                .table
+                .rows()
+                .iter()
+                .map(|r| r.expression.get().to_owned())
+                .collect::<Vec<_>>()
+        };
+        let stop_in = |table: &mut ExpressionTable, p: &mut Context, function: &str| {
+            let frame = Frame {
+                function: Some(function.to_owned()),
+                ..Frame::default()
+            };
+            table.set_frame(frame, Some(1));
+            table.update_results(p);
+        };
+
+        stop_in(&mut table, &mut p, "parse");
+        assert_eq!(expressions(&table), ["global", "token", "pos", ""]);
+        // Rows are not added twice.
+        stop_in(&mut table, &mut p, "parse");
+        assert_eq!(expressions(&table), ["global", "token", "pos", ""]);
+        table.table.rows_mut()[2].expression.set("pos + 1");
+
+        let evaluation = table.table.rows()[1].pending_evaluation.unwrap();
+        assert!(p.is_pending(evaluation));
+        stop_in(&mut table, &mut p, "main");
+        assert_eq!(expressions(&table), ["global", ""]);
+        // The evaluation of the removed row is cancelled.
+        assert!(!p.is_pending(evaluation));
+        // Edits of the rows are kept in the group.
+        assert_eq!(table.groups[0].expressions, ["token", "pos + 1"]);
+        stop_in(&mut table, &mut p, "parse");
+        assert_eq!(expressions(&table), ["global", "token", "pos + 1", ""]);
+
+        // Removed rows are removed from the group as well.
+        table.table.rows_mut().remove(1);
+        table.store_group_rows();
+        assert_eq!(table.groups[0].expressions, ["pos + 1"]);
+    }
+
+    #[test]
+    fn test_row_management() {
+        let (mut p, _events) = Context::replay("");
+        let mut table = ExpressionTable::new();
+        for expression in ["s", "b", "c"].iter() {
+            table.add_entry(expression.to_string());
+        }
+        let expressions = |table: &ExpressionTable| {
+            table
+                .table
+                .rows()
+                .iter()
+                .map(|r| r.expression.get().to_owned())
+                .collect::<Vec<_>>()
+        };
+        let current = |table: &ExpressionTable| {
+            table
+                .table
+                .current_row()
+                .unwrap()
+                .expression
+                .get()
+                .to_owned()
+        };
+
+        table.move_current_row(true);
+        assert_eq!(expressions(&table), ["b", "s", "c", ""]);
+        assert_eq!(current(&table), "s");
+        table.move_current_row(true);
+        // The empty row stays at the end.
+        table.move_current_row(true);
+        assert_eq!(expressions(&table), ["b", "c", "s", ""]);
+        table.move_current_row(false);
+        table.move_current_row(false);
+        table.move_current_row(false);
+        assert_eq!(expressions(&table), ["s", "b", "c", ""]);
+
+        table.duplicate_current_row(&mut p);
+        assert_eq!(expressions(&table), ["s", "s", "b", "c", ""]);
+        table.delete_current_row(&mut p);
+        assert_eq!(expressions(&table), ["s", "b", "c", ""]);
+        assert_eq!(current(&table), "b");
+        let _ = table.table.move_up();
+
+        let row = table.table.current_row_mut().unwrap();
+        row.show_result(
+            done(r#"{"value":"{a = 1, p = 0x601040 <buf>, c = {104, 105}}"}"#),
+            None,
+            &Stop::default(),
+            &mut p,
+        );
+        assert_eq!(row.summary(), "{a = 1, p = 0x601040 <buf>, c = {104, 105}}");
+        let _ = row.result.scroll_forwards();
+        let _ = row.result.scroll_forwards();
+        table.add_selected_node(true, &mut p);
+        assert_eq!(current(&table), "*s.p");
+        let _ = table.table.move_up();
+        // The selection is kept: Skip `c` and its first element.
+        let row = table.table.current_row_mut().unwrap();
+        for _ in 0..3 {
+            let _ = row.result.scroll_forwards();
+        }
+        table.add_selected_node(false, &mut p);
+        assert_eq!(current(&table), "s.c[1]");
+        assert_eq!(expressions(&table), ["s", "s.c[1]", "*s.p", "b", "c", ""]);
     }
 }
@@ -1,8 +1,12 @@
 pub mod commands;
 pub mod console;
+pub mod expression_set;
 pub mod expression_table;
 pub mod srcview;
+pub mod statusbar;
 #[allow(clippy::module_inception)]
 pub mod tui;
+pub mod value_history;
+pub mod value_tree;
 
 pub use self::tui::*;
@@ -1,8 +1,13 @@
-use crate::gdb::{response::*, Address, BreakPoint, BreakpointOperationError, SrcPosition};
+use crate::gdb::{
+    response::*, Address, BreakPoint, BreakpointOperationError, Resumption, SrcPosition,
+};
 use crate::gdbmi::{
     commands::{BreakPointLocation, BreakPointNumber, DisassembleMode, MiCommand},
-    output::{JsonValue, Object, ResultClass},
-    ExecuteError,
+    output::{
+        field, AsmInstruction, DecodeError, Frame, Object, ResultClass, ResultRecord,
+        SrcAndAsmLine, StopReason,
+    },
+    ExecuteError, Token,
 };
 use crate::Context;
 use log::warn;
@@ -47,6 +52,28 @@ impl Display for PagerShowError {
     }
 }
 
+// Tell the user why a breakpoint could not be inserted or removed or that the program had to be
+// interrupted for it.
+fn report_breakpoint_operation<R>(
+    action: &str,
+    result: Result<(R, Resumption), BreakpointOperationError>,
+    p: &mut Context,
+) {
+    match result {
+        Ok((_, resumption)) => {
+            if let Some(note) = resumption.describe() {
+                p.log(format!("To {} the breakpoint, ugdb {}.", action, note));
+            }
+        }
+        Err(BreakpointOperationError::Busy) => {
+            p.log(format!("Cannot {} breakpoint: Gdb is busy.", action));
+        }
+        Err(BreakpointOperationError::ExecutionError(msg)) => {
+            p.log(format!("Cannot {} breakpoint: {}", action, msg));
+        }
+    }
+}
+
 #[derive(Clone)]
 struct AssemblyDebugLocation {
     func_name: String,
@@ -54,12 +81,10 @@ struct AssemblyDebugLocation {
 }
 
 impl AssemblyDebugLocation {
-    fn try_from_value(val: &JsonValue) -> Option<Self> {
-        let func_name = val["func-name"].as_str()?;
-        let offset = val["offset"].as_str()?.parse::<usize>().ok()?;
+    fn try_from_instruction(insn: &AsmInstruction) -> Option<Self> {
         Some(AssemblyDebugLocation {
-            func_name: func_name.to_owned(),
-            offset,
+            func_name: insn.func_name.clone()?,
+            offset: insn.offset?,
         })
     }
 }
@@ -116,15 +141,8 @@ impl AssemblyDecorator {
                 })
             })
             .collect();
-        let stop_position = if let Some(p) = stop_position {
-            if address_range.start <= p && p < address_range.end {
-                Some(p)
-            } else {
-                None
-            }
-        } else {
-            None
-        };
+        let stop_position =
+            stop_position.filter(|p| address_range.start <= *p && *p < address_range.end);
         AssemblyDecorator {
             stop_position,
             breakpoint_addresses: addresses,
@@ -299,83 +317,35 @@ impl<'a> AssemblyView<'a> {
         );
     }
 
-    fn get_instructions(disass_results: &Object) -> Result<Vec<AssemblyLine>, GDBResponseError> {
-        if let JsonValue::Array(line_objs) = &disass_results["asm_insns"] {
-            let mut lines = Vec::<AssemblyLine>::new();
-            for line_obj in line_objs {
-                let line = LineNumber::new(
-                    get_str(line_obj, "line")?
-                        .parse::<usize>()
-                        .map_err(|_| GDBResponseError::Other("Malformed line".into()))?,
-                );
-
-                let file = get_str(line_obj, "fullname")?;
-                let src_pos = Some(SrcPosition::new(PathBuf::from(file), line));
-                for tuple in line_obj["line_asm_insn"].members() {
-                    let instruction = get_str(tuple, "inst")?;
-                    let address = get_addr(tuple, "address")?;
-                    lines.push(AssemblyLine::new(
-                        instruction.to_owned(),
-                        address,
-                        src_pos.clone(),
-                        AssemblyDebugLocation::try_from_value(tuple),
-                    ));
-                }
+    fn get_instructions(disass_results: &Object) -> Result<Vec<AssemblyLine>, DecodeError> {
+        let mut lines = Vec::<AssemblyLine>::new();
+        for line in SrcAndAsmLine::decode_list(disass_results)? {
+            let line_number = LineNumber::new(line.line);
+            let src_pos = line
+                .fullname
+                .map(|file| SrcPosition::new(file, line_number));
+            for insn in line.instructions {
+                let debug_location = AssemblyDebugLocation::try_from_instruction(&insn);
+                lines.push(AssemblyLine::new(
+                    insn.inst,
+                    insn.address,
+                    src_pos.clone(),
+                    debug_location,
+                ));
             }
-            lines.sort_by_key(|l| l.address);
-            Ok(lines)
-        } else {
-            Err(GDBResponseError::MissingField(
-                "asm_insns",
-                JsonValue::Object(disass_results.clone()),
-            ))
         }
+        lines.sort_by_key(|l| l.address);
+        Ok(lines)
     }
 
-    fn show_file<P: AsRef<Path>, L: Into<LineNumber>>(
-        &mut self,
-        file: P,
-        line: L,
-        p: &mut Context,
-    ) -> Result<(), DisassembleError> {
-        let line_u: usize = line.into().into();
-        let disass_results = p
-            .gdb
-            .mi
-            .execute(MiCommand::data_disassemble_file(
-                file.as_ref(),
-                line_u,
-                None,
-                DisassembleMode::MixedSourceAndDisassembly,
-            ))?
-            .results;
-
-        let lines = Self::get_instructions(&disass_results)?;
-        self.show_lines(lines, p);
-        Ok(())
-    }
-
-    fn show_address(
-        &mut self,
-        address_start: Address,
-        address_end: Address,
-        p: &mut Context,
-    ) -> Result<(), DisassembleError> {
-        let line_objs = disassemble_address(address_start, address_end, p)?;
-
-        let mut lines = Vec::<AssemblyLine>::new();
-        for line_tuple in line_objs {
-            let instruction = get_str(&line_tuple, "inst")?;
-            let address = get_addr(&line_tuple, "address")?;
-            lines.push(AssemblyLine::new(
-                instruction.to_owned(),
-                address,
-                None,
-                AssemblyDebugLocation::try_from_value(&line_tuple),
-            ));
-        }
-        self.show_lines(lines, p);
-        Ok(())
+    fn instructions_from_address_range(insns: Vec<AsmInstruction>) -> Vec<AssemblyLine> {
+        insns
+            .into_iter()
+            .map(|insn| {
+                let debug_location = AssemblyDebugLocation::try_from_instruction(&insn);
+                AssemblyLine::new(insn.inst, insn.address, None, debug_location)
+            })
+            .collect()
     }
 
     fn toggle_breakpoint(&self, p: &mut Context) {
@@ -396,29 +366,17 @@ impl<'a> AssemblyView<'a> {
                     }
                 })
                 .collect();
+            let timeout = p.command_timeout;
             if active_bps.is_empty() {
-                match p
-                    .gdb
-                    .insert_breakpoint(BreakPointLocation::Address(line.address.0))
-                {
-                    Ok(()) => {}
-                    Err(BreakpointOperationError::Busy) => {
-                        p.log("Cannot insert breakpoint: Gdb is busy.");
-                    }
-                    Err(BreakpointOperationError::ExecutionError(msg)) => {
-                        p.log(format!("Cannot insert breakpoint: {}", msg));
-                    }
-                }
+                let result = p.gdb.while_stopped(timeout, |gdb| {
+                    gdb.insert_breakpoint(BreakPointLocation::Address(line.address.0))
+                });
+                report_breakpoint_operation("insert", result, p);
             } else {
-                match p.gdb.delete_breakpoints(active_bps.into_iter()) {
-                    Ok(()) => {}
-                    Err(BreakpointOperationError::Busy) => {
-                        p.log("Cannot remove breakpoint: Gdb is busy.");
-                    }
-                    Err(BreakpointOperationError::ExecutionError(msg)) => {
-                        p.log(format!("Cannot remove breakpoint: {}", msg));
-                    }
-                }
+                let result = p.gdb.while_stopped(timeout, |gdb| {
+                    gdb.delete_breakpoints(active_bps.into_iter())
+                });
+                report_breakpoint_operation("remove", result, p);
             }
         }
     }
@@ -710,15 +668,17 @@ impl<'a> SourceView<'a> {
                     }
                 })
                 .collect();
+            let timeout = p.command_timeout;
             if active_bps.is_empty() {
-                if p.gdb
-                    .insert_breakpoint(BreakPointLocation::Line(path, line.into()))
-                    .is_err()
-                {
-                    p.log("Cannot insert breakpoint: Gdb is busy.");
-                }
-            } else if p.gdb.delete_breakpoints(active_bps.into_iter()).is_err() {
-                p.log("Cannot remove breakpoint: Gdb is busy.");
+                let result = p.gdb.while_stopped(timeout, |gdb| {
+                    gdb.insert_breakpoint(BreakPointLocation::Line(path, line.into()))
+                });
+                report_breakpoint_operation("insert", result, p);
+            } else {
+                let result = p.gdb.while_stopped(timeout, |gdb| {
+                    gdb.delete_breakpoints(active_bps.into_iter())
+                });
+                report_breakpoint_operation("remove", result, p);
             }
         }
     }
@@ -759,17 +719,68 @@ enum AsmContentState {
     Unavailable,
     NotYetLoadedFile(PathBuf, LineIndex),
     NotYetLoadedAddr(Address, Address),
+    // The range of the function around the address has to be found first.
+    NotYetLoadedFunction(Address),
+    Loading(Token),
+}
+
+// Without (usable) debug information, the function around an address is found in several steps:
+// The first instruction tells where the function begins, then blocks are disassembled until the
+// function changes. If that fails, a block after the address is disassembled instead.
+enum FunctionSearch {
+    Begin(Address),
+    End {
+        at: Address,
+        begin: Address,
+        func_name: String,
+        current: Address,
+    },
+    Block(Address),
+}
+
+const FUNCTION_SEARCH_BLOCK_SIZE: usize = 128;
+
+// Explains why the program stopped (or exited) the last time.
+struct StopBanner(StopReason);
+
+impl Widget for &StopBanner {
+    fn space_demand(&self) -> Demand2D {
+        Demand2D {
+            width: Demand::at_least(Width::new(1).unwrap()),
+            height: Demand::exact(Height::new(1).unwrap()),
+        }
+    }
+    fn draw(&self, mut window: Window, _hints: RenderingHints) {
+        use std::fmt::Write;
+        let color = match &self.0 {
+            StopReason::SignalReceived { .. }
+            | StopReason::ExitedSignalled { .. }
+            | StopReason::Exited { .. } => Color::Red,
+            StopReason::WatchpointTrigger { .. } | StopReason::WatchpointScope { .. } => {
+                Color::Yellow
+            }
+            StopReason::ExitedNormally => Color::Green,
+            _ => Color::Cyan,
+        };
+        let mut cursor = Cursor::new(&mut window)
+            .style_modifier(StyleModifier::new().bold(true).fg_color(color));
+        let _ = write!(cursor, "{}", self.0);
+    }
 }
 
 #[derive(Default)]
 struct StackInfo {
+    // Only shown if there are multiple inferiors.
+    inferior: Option<String>,
     stack_level: Option<u64>,
     stack_depth: Option<u64>,
     file_path: Option<PathBuf>,
     function: Option<String>,
+    // Commands that are still loading the stack level and depth
+    loading: Vec<Token>,
 }
 
-impl<'a> Widget for &'a StackInfo {
+impl Widget for &StackInfo {
     fn space_demand(&self) -> Demand2D {
         Demand2D {
             // TODO: reenable this once configurable layouts are a thing.
@@ -795,6 +806,9 @@ impl<'a> Widget for &'a StackInfo {
         use std::fmt::Write;
         let width = window.get_width();
         let mut cursor = Cursor::new(&mut window).style_modifier(StyleModifier::new().bold(true));
+        if let Some(inferior) = &self.inferior {
+            let _ = write!(cursor, "Inferior {} ", inferior);
+        }
         let _ = write!(cursor, "[");
         if let Some(l) = self.stack_level {
             let _ = write!(cursor, "{}", l);
@@ -851,49 +865,51 @@ impl From<ExecuteError> for DisassembleError {
         GDBResponseError::Execution(e).into()
     }
 }
+impl From<DecodeError> for DisassembleError {
+    fn from(e: DecodeError) -> Self {
+        GDBResponseError::Decode(e).into()
+    }
+}
 
-fn disassemble_address(
-    address_start: Address,
-    address_end: Address,
-    p: &mut Context,
-) -> Result<Vec<JsonValue>, DisassembleError> {
-    let mut disass_results = match p.gdb.mi.execute(MiCommand::data_disassemble_address(
-        address_start.0,
-        address_end.0,
-        DisassembleMode::DisassemblyOnly,
-    )) {
+fn check_disassembly_result(
+    result: Result<ResultRecord, ExecuteError>,
+) -> Result<Object, DisassembleError> {
+    match result {
         Ok(o) => {
             if o.class == ResultClass::Error {
                 return Err(DisassembleError::Other(
                     o.results["msg"].as_str().unwrap_or("unknown").to_owned(),
                 ));
             }
-            o.results
-        }
-        Err(e) => {
-            return Err(GDBResponseError::Execution(e).into());
+            Ok(o.results)
         }
-    };
-    if let JsonValue::Array(line_objs) = disass_results["asm_insns"].take() {
-        let mut line_objs = line_objs
-            .into_iter()
-            .map(|l| {
-                let addr = get_addr(&l, "address")?;
-                Ok((addr, l))
-            })
-            .collect::<Result<Vec<(Address, JsonValue)>, DisassembleError>>()?;
-        //I'm not sure if GDB does this already, but we better not rely on it...
-        line_objs.sort_by_key(|(a, _)| *a);
-
-        Ok(line_objs.into_iter().map(|(_, o)| o).collect::<Vec<_>>())
-    } else {
-        Err(
-            GDBResponseError::MissingField("asm_insns", JsonValue::Object(disass_results.clone()))
-                .into(),
-        )
+        Err(e) => Err(GDBResponseError::Execution(e).into()),
     }
 }
 
+fn sorted_address_disassembly(
+    disass_results: Object,
+) -> Result<Vec<AsmInstruction>, DisassembleError> {
+    let mut insns = AsmInstruction::decode_list(&disass_results)?;
+    //I'm not sure if GDB does this already, but we better not rely on it...
+    insns.sort_by_key(|i| i.address);
+    Ok(insns)
+}
+
+fn penultimate(lines: &[AsmInstruction]) -> Result<&AsmInstruction, DisassembleError> {
+    lines
+        .len()
+        .checked_sub(2)
+        .map(|index| &lines[index])
+        .ok_or_else(|| DisassembleError::Other("Not enough lines".to_owned()))
+}
+
+// Where to move the assembly view once loading is complete.
+enum AsmGoto {
+    LastStopPosition,
+    SrcLine(PathBuf, LineNumber),
+}
+
 pub struct CodeWindow<'a> {
     src_view: SourceView<'a>,
     asm_view: AssemblyView<'a>,
@@ -902,6 +918,7 @@ pub struct CodeWindow<'a> {
     asm_state: AsmContentState,
     last_bp_update: std::time::Instant,
     stack_info: StackInfo,
+    stop_banner: Option<StopBanner>,
 }
 
 impl<'a> CodeWindow<'a> {
@@ -914,6 +931,7 @@ impl<'a> CodeWindow<'a> {
             asm_state: AsmContentState::Unavailable,
             last_bp_update: std::time::Instant::now(),
             stack_info: Default::default(),
+            stop_banner: None,
         }
     }
 
@@ -929,6 +947,9 @@ impl<'a> CodeWindow<'a> {
             }
             (DisplayMode::SideBySide, SrcContentState::Available, _) => DisplayMode::Source,
             (DisplayMode::SideBySide, _, AsmContentState::Available) => DisplayMode::Assembly,
+            (_, _, AsmContentState::Loading(_)) => {
+                DisplayMode::Message("Disassembling...".to_owned())
+            }
             (_, _, _) => DisplayMode::Message("Neither source nor assembly available!".to_owned()),
         }
     }
@@ -953,27 +974,81 @@ impl<'a> CodeWindow<'a> {
         }
     }
 
-    fn try_load_asm_content(&mut self, p: &mut Context) -> Result<(), DisassembleError> {
-        match self.asm_state.clone() {
-            AsmContentState::NotYetLoadedFile(path, line) => {
-                let ret = self.asm_view.show_file(path, line, p);
-                if ret.is_ok() {
-                    self.asm_state = AsmContentState::Available;
-                } else {
-                    self.asm_state = AsmContentState::Unavailable;
+    // Start disassembling in the background. The content will be shown once gdb has finished.
+    fn try_load_asm_content(&mut self, p: &mut Context, goto: AsmGoto) {
+        let token = match self.asm_state.clone() {
+            AsmContentState::NotYetLoadedFile(path, line) => p.execute_async(
+                MiCommand::data_disassemble_file(
+                    &path,
+                    line.raw_value(),
+                    None,
+                    DisassembleMode::MixedSourceAndDisassembly,
+                ),
+                move |tui, p, result| {
+                    let lines = check_disassembly_result(result)
+                        .and_then(|res| Ok(AssemblyView::get_instructions(&res)?));
+                    tui.src_view.finish_loading_asm_content(lines, goto, p);
+                },
+            ),
+            AsmContentState::NotYetLoadedFunction(at) => {
+                self.search_function(FunctionSearch::Begin(at), goto, p);
+                return;
+            }
+            AsmContentState::NotYetLoadedAddr(begin, end) => p.execute_async(
+                MiCommand::data_disassemble_address(
+                    begin.0,
+                    end.0,
+                    DisassembleMode::DisassemblyOnly,
+                ),
+                move |tui, p, result| {
+                    let lines = check_disassembly_result(result)
+                        .and_then(sorted_address_disassembly)
+                        .map(AssemblyView::instructions_from_address_range);
+                    tui.src_view.finish_loading_asm_content(lines, goto, p);
+                },
+            ),
+            _ => return,
+        };
+        self.asm_state = AsmContentState::Loading(token);
+    }
+
+    fn finish_loading_asm_content(
+        &mut self,
+        lines: Result<Vec<AssemblyLine>, DisassembleError>,
+        goto: AsmGoto,
+        p: &mut Context,
+    ) {
+        match lines {
+            Ok(lines) => {
+                self.asm_view.show_lines(lines, p);
+                self.asm_state = AsmContentState::Available;
+                let res = match goto {
+                    AsmGoto::LastStopPosition => self.asm_view.go_to_last_stop_position(),
+                    AsmGoto::SrcLine(path, line) => {
+                        self.asm_view.go_to_first_applicable_line(&path, line)
+                    }
+                };
+                if let Err(e) = res {
+                    log::debug!("Failed to go to position after disassembling: {:?}", e);
                 }
-                ret
             }
-            AsmContentState::NotYetLoadedAddr(begin, end) => {
-                let ret = self.asm_view.show_address(begin, end, p);
-                if ret.is_ok() {
-                    self.asm_state = AsmContentState::Available;
+            Err(e) => {
+                if let DisassembleError::GDB(GDBResponseError::Execution(ExecuteError::Busy)) = e {
+                    p.log("Cannot disassemble: Gdb is busy.");
                 } else {
-                    self.asm_state = AsmContentState::Unavailable;
+                    warn!("Failed to load assembly: {:?}", e);
                 }
-                ret
+                self.asm_state = AsmContentState::Unavailable;
+                // Fall back to source view, if possible.
+                self.try_load_active_content(p);
             }
-            _ => Ok(()),
+        }
+    }
+
+    fn cancel_loading_asm_content(&mut self, p: &mut Context) {
+        if let AsmContentState::Loading(token) = self.asm_state {
+            p.cancel_async(token);
+            self.asm_state = AsmContentState::Unavailable;
         }
     }
 
@@ -983,13 +1058,8 @@ impl<'a> CodeWindow<'a> {
                 warn!("Failed to load file: {}", e);
             }
         };
-        let try_load_asm = |s: &mut Self, p: &mut Context| match s.try_load_asm_content(p) {
-            Err(DisassembleError::GDB(GDBResponseError::Execution(ExecuteError::Busy))) => {
-                p.log("Cannot disassemble: Gdb is busy.");
-            }
-            Err(e) => warn!("Failed to load assembly: {:?}", e),
-            Ok(_) => {}
-        };
+        let try_load_asm =
+            |s: &mut Self, p: &mut Context| s.try_load_asm_content(p, AsmGoto::LastStopPosition);
         match self.preferred_mode {
             DisplayMode::SideBySide => {
                 try_load_src(self, p);
@@ -1011,133 +1081,184 @@ impl<'a> CodeWindow<'a> {
         }
     }
 
-    fn find_function_range(at: Address, p: &mut Context) -> Result<(Address, Address), ()> {
-        let first_lines = disassemble_address(at, at + 16, p).map_err(|_| ())?;
-        let current = first_lines.first().ok_or(())?;
-        let asm_debug_location = AssemblyDebugLocation::try_from_value(current).ok_or(())?;
-        let begin = at - asm_debug_location.offset;
-
-        let block_size = 128;
-        let mut current = at;
-        let func_change_block = loop {
-            let current_block_lines =
-                disassemble_address(current, current + block_size, p).map_err(|_| ())?;
-            {
-                let penultimate_index = current_block_lines.len().checked_sub(2).ok_or(())?;
-                let penultimate = current_block_lines
-                    .get(penultimate_index)
-                    .expect("We know penulatimate_index is valid");
-                if let Some(penultimate_func_name) = penultimate["func-name"].as_str() {
-                    if penultimate_func_name == asm_debug_location.func_name {
-                        current = get_addr(penultimate, "address").map_err(|_| ())?;
-                        continue;
-                    }
-                }
+    fn search_function(&mut self, step: FunctionSearch, goto: AsmGoto, p: &mut Context) {
+        let (begin, end) = match &step {
+            FunctionSearch::Begin(at) => (*at, *at + 16),
+            FunctionSearch::End { current, .. } => {
+                (*current, *current + FUNCTION_SEARCH_BLOCK_SIZE)
             }
-            //func-name is None or different => we found our block
-            break current_block_lines;
+            FunctionSearch::Block(at) => (*at, *at + FUNCTION_SEARCH_BLOCK_SIZE),
+        };
+        let token = p.execute_async(
+            MiCommand::data_disassemble_address(begin.0, end.0, DisassembleMode::DisassemblyOnly),
+            move |tui, p, result| {
+                let lines = check_disassembly_result(result).and_then(sorted_address_disassembly);
+                tui.src_view.continue_function_search(step, lines, goto, p);
+            },
+        );
+        self.asm_state = AsmContentState::Loading(token);
+    }
+
+    fn continue_function_search(
+        &mut self,
+        step: FunctionSearch,
+        lines: Result<Vec<AsmInstruction>, DisassembleError>,
+        goto: AsmGoto,
+        p: &mut Context,
+    ) {
+        let fallback = match &step {
+            FunctionSearch::Begin(at) | FunctionSearch::End { at, .. } => Some(*at),
+            FunctionSearch::Block(_) => None,
         };
-        for line in func_change_block {
-            if line["func-name"] != asm_debug_location.func_name {
-                let end = get_addr(&line, "address").map_err(|_| ())?;
-                return Ok((begin, end));
+        // Either the range of the function or the next step of the search
+        let next = lines.and_then(|lines| match step {
+            FunctionSearch::Begin(at) => {
+                let location = lines
+                    .first()
+                    .and_then(AssemblyDebugLocation::try_from_instruction)
+                    .ok_or_else(|| DisassembleError::Other("Unknown function".to_owned()))?;
+                Ok(Err(FunctionSearch::End {
+                    at,
+                    begin: at - location.offset,
+                    func_name: location.func_name,
+                    current: at,
+                }))
+            }
+            FunctionSearch::End {
+                at,
+                begin,
+                func_name,
+                ..
+            } => {
+                let penultimate = penultimate(&lines)?;
+                if penultimate.func_name.as_ref() == Some(&func_name) {
+                    return Ok(Err(FunctionSearch::End {
+                        at,
+                        begin,
+                        func_name,
+                        current: penultimate.address,
+                    }));
+                }
+                //func-name is None or different => we found our block
+                let end = lines
+                    .iter()
+                    .find(|line| line.func_name.as_ref() != Some(&func_name))
+                    .expect("At least the penultimate line belongs to another function");
+                Ok(Ok((begin, end.address)))
             }
+            FunctionSearch::Block(at) => Ok(Ok((at, penultimate(&lines)?.address))),
+        });
+        match (next, fallback) {
+            (Ok(Ok((begin, end))), _) => {
+                self.asm_state = AsmContentState::NotYetLoadedAddr(begin, end);
+                self.try_load_asm_content(p, goto);
+            }
+            (Ok(Err(step)), _) => self.search_function(step, goto, p),
+            // Trying another range only helps if gdb could not make sense of this one.
+            (Err(e @ DisassembleError::GDB(GDBResponseError::Execution(_))), _)
+            | (Err(e), None) => self.finish_loading_asm_content(Err(e), goto, p),
+            (Err(_), Some(at)) => self.search_function(FunctionSearch::Block(at), goto, p),
         }
-        unreachable!("func_change_block has to contain changing line");
     }
-    fn find_valid_address_range(
-        at: Address,
-        approx_byte_size: usize,
-        p: &mut Context,
-    ) -> Result<(Address, Address), DisassembleError> {
-        let block_lines = disassemble_address(at, at + approx_byte_size, p)?;
 
-        let penultimate_index = block_lines
-            .len()
-            .checked_sub(2)
-            .ok_or_else(|| DisassembleError::Other("Not enough lines".to_owned()))?;
-        let penultimate = block_lines
-            .get(penultimate_index)
-            .ok_or_else(|| DisassembleError::Other("Not enough lines".to_owned()))?;
-        let end_address = get_addr(penultimate, "address")?;
-        Ok((at, end_address))
+    pub fn show_file(&mut self, file: String, line: LineNumber, p: &mut Context) {
+        let frame = Frame {
+            fullname: Some(PathBuf::from(file)),
+            line: Some(line.raw_value()),
+            ..Default::default()
+        };
+        self.show_frame(&frame, p);
     }
 
-    pub fn show_file(&mut self, file: String, line: LineNumber, p: &mut Context) {
-        let mut object = Object::new();
-        object.insert("fullname", JsonValue::String(file));
-        object.insert("line", JsonValue::String(line.to_string()));
-        self.show_frame(&object, p);
+    pub fn set_stop_reason(&mut self, reason: Option<StopReason>) {
+        self.stop_banner = reason.map(StopBanner);
+    }
+
+    pub fn set_inferior(&mut self, inferior: Option<String>) {
+        self.stack_info.inferior = inferior;
+    }
+
+    // The stack level and depth are only shown, so they do not have to be loaded right away.
+    fn load_stack_info(&mut self, p: &mut Context) {
+        for token in self.stack_info.loading.drain(..) {
+            p.cancel_async(token);
+        }
+        self.stack_info.stack_level = None;
+        self.stack_info.stack_depth = None;
+        let level = p.execute_async(MiCommand::stack_info_frame(None), |tui, _, result| {
+            tui.src_view.show_stack_level(result)
+        });
+        let depth = p.execute_async(MiCommand::stack_info_depth(), |tui, _, result| {
+            tui.src_view.show_stack_depth(result)
+        });
+        self.stack_info.loading = vec![level, depth];
+    }
+
+    fn show_stack_level(&mut self, result: Result<ResultRecord, ExecuteError>) {
+        self.stack_info.stack_level = stack_level(result).ok();
+    }
+
+    fn show_stack_depth(&mut self, result: Result<ResultRecord, ExecuteError>) {
+        self.stack_info.stack_depth = stack_depth(result).ok();
     }
 
-    pub fn show_frame(&mut self, frame: &Object, p: &mut Context) {
+    pub fn show_frame(&mut self, frame: &Frame, p: &mut Context) {
         // Always try to switch away from (relatively unhelpful) message to srcview:
         if let DisplayMode::Message(_) = self.preferred_mode {
             self.preferred_mode = DisplayMode::Source;
         }
 
+        self.cancel_loading_asm_content(p);
         self.src_state = SrcContentState::Unavailable;
         self.asm_state = AsmContentState::Unavailable;
 
-        self.stack_info.stack_level = p.gdb.get_stack_level().ok();
-        self.stack_info.stack_depth = p.gdb.get_stack_depth().ok();
-        self.stack_info.file_path = frame["fullname"].as_str().map(PathBuf::from);
-        self.stack_info.function = frame["func"].as_str().map(|s| s.to_owned());
-
-        if let Some(path) = frame["fullname"].as_str() {
-            let path = PathBuf::from(path);
+        self.load_stack_info(p);
+        self.stack_info.file_path = frame.fullname.clone();
+        self.stack_info.function = frame.function.clone();
 
+        if let Some(path) = &frame.fullname {
             self.src_state = match self.src_view.current_file() {
                 Some(f) if f == path => SrcContentState::Available,
                 _ => SrcContentState::NotYetLoaded(path.clone()),
             };
 
-            match get_u64_obj(frame, "line") {
-                Ok(line) => {
-                    let line = LineNumber::new(line as usize);
+            match frame.line {
+                Some(line) => {
+                    let line = LineNumber::new(line);
 
                     self.src_view.set_last_stop_position(path.clone(), line);
 
                     self.asm_state = if self
                         .asm_view
-                        .go_to_first_applicable_line(&path, line)
+                        .go_to_first_applicable_line(path, line)
                         .is_ok()
                     {
                         AsmContentState::Available
                     } else {
-                        AsmContentState::NotYetLoadedFile(path, line.into())
+                        AsmContentState::NotYetLoadedFile(path.clone(), line.into())
                     };
-                    match get_addr_obj(frame, "addr") {
-                        Ok(address) => self.asm_view.set_last_stop_position(address),
-                        Err(e) => warn!("Failed get address from frame: {:?}", e),
+                    match frame.address {
+                        Some(address) => self.asm_view.set_last_stop_position(address),
+                        None => warn!("Failed get address from frame: {:?}", frame),
                     }
                 }
-                Err(e) => warn!("Failed get line from frame: {:?}", e),
+                None => warn!("Failed get line from frame: {:?}", frame),
             }
         };
 
         // If we were not able to load asm via file information, try loading from the address.
         // This may be the case for jit compiled code or PLT entries or something like that.
         if self.asm_state == AsmContentState::Unavailable {
-            match get_addr_obj(frame, "addr") {
-                Ok(address) => {
-                    if self.asm_view.go_to_address(address).is_ok() {
-                        self.asm_state = AsmContentState::Available;
+            match frame.address {
+                Some(address) => {
+                    self.asm_state = if self.asm_view.go_to_address(address).is_ok() {
+                        AsmContentState::Available
                     } else {
-                        match Self::find_function_range(address, p)
-                            .or_else(|_| Self::find_valid_address_range(address, 128, p))
-                        {
-                            Ok((begin, end)) => {
-                                self.asm_state = AsmContentState::NotYetLoadedAddr(begin, end)
-                            }
-                            Err(e) => {
-                                warn!("Failed to disassemble from address {}: {:?}", address, e)
-                            }
-                        };
-                    }
+                        AsmContentState::NotYetLoadedFunction(address)
+                    };
                     self.asm_view.set_last_stop_position(address);
                 }
-                Err(e) => warn!("Failed get address from frame: {:?}", e),
+                None => warn!("Failed get address from frame: {:?}", frame),
             }
         }
 
@@ -1171,19 +1292,18 @@ impl<'a> CodeWindow<'a> {
             self.preferred_mode = prev_mode;
         } else if sync_asm_to_src {
             if let Some(path) = self.src_view.current_file() {
+                let path = path.to_owned();
+                let line = self.src_view.current_line_number();
                 if self
                     .asm_view
-                    .go_to_first_applicable_line(path, self.src_view.current_line_number())
+                    .go_to_first_applicable_line(&path, line)
                     .is_err()
-                    && self
-                        .asm_view
-                        .show_file(path, self.src_view.current_line_number(), p)
-                        .is_ok()
+                    && self.asm_state == AsmContentState::Available
                 {
-                    // The current line may not have associated assembly!
-                    let _ = self
-                        .asm_view
-                        .go_to_first_applicable_line(path, self.src_view.current_line_number());
+                    // The current line may not have associated assembly, but we try to disassemble
+                    // the file anyway.
+                    self.asm_state = AsmContentState::NotYetLoadedFile(path.clone(), line.into());
+                    self.try_load_asm_content(p, AsmGoto::SrcLine(path, line));
                 }
             }
         }
@@ -1205,14 +1325,8 @@ impl<'a> CodeWindow<'a> {
             match p.gdb.mi.execute(MiCommand::stack_info_frame(None)) {
                 Ok(o) => {
                     if o.class == ResultClass::Done {
-                        if let JsonValue::Object(ref frame) = o.results["frame"] {
-                            self.show_frame(frame, p);
-                        } else {
-                            return Err(GDBResponseError::MissingField(
-                                "frame",
-                                JsonValue::Object(o.results.clone()),
-                            ));
-                        }
+                        let frame = Frame::decode(field(&o.results, "frame")?)?;
+                        self.show_frame(&frame, p);
                     } else {
                         return Err(GDBResponseError::Other(format!(
                             "Unexpected result class: {:?}",
@@ -1273,6 +1387,9 @@ impl<'a> Container<Context> for CodeWindow<'a> {
         let mode = self.available_display_mode();
 
         let mut r = VLayout::new();
+        if let Some(banner) = &self.stop_banner {
+            r = r.widget(banner);
+        }
         if let DisplayMode::Assembly | DisplayMode::Source | DisplayMode::SideBySide = mode {
             r = r.widget(&self.stack_info)
         }
@@ -1293,3 +1410,124 @@ impl<'a> Container<Context> for CodeWindow<'a> {
         Box::new(r)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::Event;
+    use std::sync::mpsc::Receiver;
+
+    // The result of the next command that the (replayed) gdb answered
+    fn next_result(events: &Receiver<Event>) -> Result<ResultRecord, ExecuteError> {
+        match events
+            .recv_timeout(std::time::Duration::from_secs(5))
+            .unwrap()
+        {
+            Event::AsyncResult(record) => Ok(record),
+            event => panic!("Unexpected event {:?}", event),
+        }
+    }
+
+    const TRANSCRIPT: &str = r#"> 1-stack-info-frame
+< 1^done,frame={level="1",addr="0x0000555555555149",func="foo",file="main.c",fullname="{path}",line="7",arch="i386:x86-64"}
+< (gdb)
+> 2-stack-info-depth
+< 2^done,depth="3"
+< (gdb)
+"#;
+
+    #[test]
+    fn test_show_frame() {
+        // Any existing file will do as source.
+        let path = PathBuf::from(concat!(env!("CARGO_MANIFEST_DIR"), "/src/main.rs"));
+        let transcript = TRANSCRIPT.replace("{path}", path.to_str().unwrap());
+        let (mut context, events) = Context::replay(&transcript);
+        let theme_set = unsegen_pager::ThemeSet::load_defaults();
+        let mut code_window = CodeWindow::new(&theme_set.themes["base16-ocean.dark"], "welcome");
+        let frame = Frame {
+            level: Some(1),
+            address: Some(Address(0x555555555149)),
+            function: Some("foo".to_owned()),
+            file: Some("main.rs".to_owned()),
+            fullname: Some(path.clone()),
+            line: Some(7),
+        };
+        code_window.show_frame(&frame, &mut context);
+
+        assert_eq!(code_window.stack_info.stack_level, None);
+        assert_eq!(code_window.stack_info.function.as_deref(), Some("foo"));
+        assert!(code_window.src_state == SrcContentState::Available);
+        assert_eq!(code_window.src_view.current_file(), Some(path.as_path()));
+        assert!(
+            code_window.asm_state == AsmContentState::NotYetLoadedFile(path, LineIndex::new(6))
+        );
+        assert!(code_window.preferred_mode == DisplayMode::Source);
+
+        code_window.show_stack_level(next_result(&events));
+        code_window.show_stack_depth(next_result(&events));
+        assert_eq!(code_window.stack_info.stack_level, Some(1));
+        assert_eq!(code_window.stack_info.stack_depth, Some(3));
+    }
+
+    const FUNCTION_TRANSCRIPT: &str = r#"> 1-stack-info-frame
+< 1^done,frame={level="0",addr="0x0000000000001008",func="bar"}
+< (gdb)
+> 2-stack-info-depth
+< 2^done,depth="1"
+< (gdb)
+> 3-data-disassemble -s 4104 -e 4120 -- 0
+< 3^done,asm_insns=[{address="0x0000000000001008",func-name="bar",offset="8",inst="nop"},{address="0x0000000000001009",func-name="bar",offset="9",inst="nop"}]
+< (gdb)
+> 4-data-disassemble -s 4104 -e 4232 -- 0
+< 4^done,asm_insns=[{address="0x0000000000001008",func-name="bar",offset="8",inst="nop"},{address="0x0000000000001009",func-name="bar",offset="9",inst="ret"},{address="0x0000000000001010",func-name="baz",offset="0",inst="nop"},{address="0x0000000000001011",func-name="baz",offset="1",inst="nop"}]
+< (gdb)
+> 5-data-disassemble -s 4096 -e 4112 -- 0
+< 5^done,asm_insns=[{address="0x0000000000001000",func-name="bar",offset="0",inst="push"},{address="0x0000000000001008",func-name="bar",offset="8",inst="nop"},{address="0x0000000000001009",func-name="bar",offset="9",inst="ret"}]
+< (gdb)
+"#;
+
+    #[test]
+    fn test_function_search() {
+        let (mut context, events) = Context::replay(FUNCTION_TRANSCRIPT);
+        let theme_set = unsegen_pager::ThemeSet::load_defaults();
+        let mut code_window = CodeWindow::new(&theme_set.themes["base16-ocean.dark"], "welcome");
+        let frame = Frame {
+            address: Some(Address(0x1008)),
+            function: Some("bar".to_owned()),
+            ..Default::default()
+        };
+        // Without a source file, the function around the address is disassembled in the background.
+        code_window.show_frame(&frame, &mut context);
+        assert!(matches!(code_window.asm_state, AsmContentState::Loading(_)));
+        code_window.show_stack_level(next_result(&events));
+        code_window.show_stack_depth(next_result(&events));
+
+        let disassembly = |events| {
+            check_disassembly_result(next_result(events)).and_then(sorted_address_disassembly)
+        };
+        code_window.continue_function_search(
+            FunctionSearch::Begin(Address(0x1008)),
+            disassembly(&events),
+            AsmGoto::LastStopPosition,
+            &mut context,
+        );
+        code_window.continue_function_search(
+            FunctionSearch::End {
+                at: Address(0x1008),
+                begin: Address(0x1000),
+                func_name: "bar".to_owned(),
+                current: Address(0x1008),
+            },
+            disassembly(&events),
+            AsmGoto::LastStopPosition,
+            &mut context,
+        );
+        assert!(matches!(code_window.asm_state, AsmContentState::Loading(_)));
+        let lines = check_disassembly_result(next_result(&events))
+            .and_then(sorted_address_disassembly)
+            .map(AssemblyView::instructions_from_address_range);
+        code_window.finish_loading_asm_content(lines, AsmGoto::LastStopPosition, &mut context);
+        assert!(code_window.asm_state == AsmContentState::Available);
+        assert!(code_window.available_display_mode() == DisplayMode::Assembly);
+    }
+}
@@ -0,0 +1,109 @@
+// A single line at the bottom of the screen that summarizes the state of gdb and the debugged
+// program. In contrast to the console prompt it is visible regardless of the layout.
+use crate::gdbmi::output::{Frame, StopReason};
+use crate::Context;
+use std::fmt::Write;
+use std::path::PathBuf;
+use unsegen::base::{Cursor, Height, StyleModifier, Width, Window};
+use unsegen::widget::{Demand, Demand2D, RenderingHints, Widget};
+
+#[derive(Default)]
+pub struct StatusBar {
+    running: bool,
+    pid: Option<u32>,
+    thread_id: Option<u64>,
+    frame: Option<Frame>,
+    stop_reason: Option<StopReason>,
+    recording: bool,
+    rr: bool,
+    ipc_socket: Option<PathBuf>,
+}
+
+impl StatusBar {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn set_rr(&mut self, rr: bool) {
+        self.rr = rr;
+    }
+
+    pub fn set_ipc_socket(&mut self, path: PathBuf) {
+        self.ipc_socket = Some(path);
+    }
+
+    pub fn set_recording(&mut self, recording: bool) {
+        self.recording = recording;
+    }
+
+    pub fn set_stop(&mut self, reason: Option<StopReason>) {
+        self.stop_reason = reason;
+    }
+
+    pub fn set_frame(&mut self, frame: Frame, thread_id: Option<u64>) {
+        self.frame = Some(frame);
+        if thread_id.is_some() {
+            self.thread_id = thread_id;
+        }
+    }
+
+    pub fn set_running(&mut self) {
+        self.stop_reason = None;
+        self.frame = None;
+    }
+
+    pub fn update_after_event(&mut self, p: &mut Context) {
+        self.running = p.gdb.mi.is_running();
+        self.pid = p.gdb.inferiors.current().and_then(|i| i.pid);
+    }
+}
+
+impl Widget for &StatusBar {
+    fn space_demand(&self) -> Demand2D {
+        Demand2D {
+            width: Demand::at_least(Width::new(1).unwrap()),
+            height: Demand::exact(Height::new(1).unwrap()),
+        }
+    }
+    fn draw(&self, mut window: Window, _hints: RenderingHints) {
+        window.modify_default_style(StyleModifier::new().invert(true));
+        window.clear();
+        let mut cursor = Cursor::new(&mut window);
+        {
+            let mut cursor = cursor.save().style_modifier();
+            cursor.apply_style_modifier(StyleModifier::new().bold(true));
+            let _ = write!(
+                cursor,
+                " {} ",
+                if self.running { "RUNNING" } else { "STOPPED" }
+            );
+        }
+        if let Some(pid) = self.pid {
+            let _ = write!(cursor, "| pid {} ", pid);
+        }
+        if let Some(thread_id) = self.thread_id {
+            let _ = write!(cursor, "| thread {} ", thread_id);
+        }
+        if let Some(frame) = &self.frame {
+            // Frames of stop events do not specify a level, but are always the innermost one.
+            let _ = write!(
+                cursor,
+                "| #{} {} ",
+                frame.level.unwrap_or(0),
+                frame.function.as_deref().unwrap_or("??")
+            );
+        }
+        if let Some(reason) = &self.stop_reason {
+            let _ = write!(cursor, "| {} ", reason);
+        }
+        if self.rr {
+            let _ = write!(cursor, "| rr ");
+        }
+        if self.recording {
+            let _ = write!(cursor, "| recording ");
+        }
+        if let Some(socket) = &self.ipc_socket {
+            let _ = write!(cursor, "| ipc: {} ", socket.to_string_lossy());
+        }
+    }
+}
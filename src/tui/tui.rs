@@ -1,24 +1,39 @@
 use crate::Context;
 use unsegen_pager::Theme;
 
+use crate::gdbmi::commands::MiCommand;
 use crate::gdbmi::output::{
-    AsyncClass, AsyncKind, JsonValue, Object, OutOfBandRecord, ThreadEvent,
+    AsyncClass, AsyncKind, BreakPointEvent, BreakPointInfo, Frame, JsonValue, Object,
+    OutOfBandRecord, ResultClass, StopEvent, ThreadEvent, ThreadGroupEvent, ThreadSelectedEvent,
 };
+use crate::gdbmi::ExecuteError;
+use std::collections::HashMap;
 
 use super::console::Console;
 use super::expression_table::ExpressionTable;
 use super::srcview::CodeWindow;
-use log::{debug, info};
+use super::statusbar::StatusBar;
+use log::{debug, info, warn};
 use unsegen::container::{Container, ContainerProvider};
 use unsegen_terminal::Terminal;
 
 pub struct Tui<'a> {
     pub console: Console,
     pub expression_table: ExpressionTable,
-    process_pty: Terminal,
+    // Every inferior has its own terminal, but only the one of the active inferior is shown.
+    terminals: HashMap<String, Terminal>,
+    active_inferior: String,
+    // Inferiors whose terminal could not be passed to gdb, because the program was running
+    unset_ttys: Vec<String>,
+    // Used to restore the source position when switching between inferiors.
+    last_frames: HashMap<String, Frame>,
     pub src_view: CodeWindow<'a>,
+    pub status_bar: StatusBar,
 }
 
+// The inferior gdb creates on startup. It uses the terminal passed via --tty.
+pub const INITIAL_INFERIOR: &str = "i1";
+
 const WELCOME_MSG: &str = concat!(
     r#"       Welcome to        
  _   _  __ _  __| | |__  
@@ -35,11 +50,135 @@ revision         "#,
 
 impl<'a> Tui<'a> {
     pub fn new(terminal: Terminal, highlighting_theme: &'a Theme) -> Self {
+        let mut terminals = HashMap::new();
+        terminals.insert(INITIAL_INFERIOR.to_owned(), terminal);
         Tui {
             console: Console::new(),
             expression_table: ExpressionTable::new(),
-            process_pty: terminal,
+            terminals,
+            active_inferior: INITIAL_INFERIOR.to_owned(),
+            unset_ttys: Vec::new(),
+            last_frames: HashMap::new(),
             src_view: CodeWindow::new(highlighting_theme, WELCOME_MSG),
+            status_bar: StatusBar::new(),
+        }
+    }
+
+    // The terminal of the initial inferior is never removed, so it can always be shown instead.
+    fn active_terminal(&self) -> &Terminal {
+        self.terminals
+            .get(&self.active_inferior)
+            .unwrap_or(&self.terminals[INITIAL_INFERIOR])
+    }
+
+    fn active_terminal_mut(&mut self) -> &mut Terminal {
+        let inferior = if self.terminals.contains_key(&self.active_inferior) {
+            self.active_inferior.as_str()
+        } else {
+            INITIAL_INFERIOR
+        };
+        self.terminals.get_mut(inferior).unwrap()
+    }
+
+    fn add_terminal(&mut self, inferior: String, p: &mut Context) {
+        if self.terminals.contains_key(&inferior) {
+            return;
+        }
+        let terminal = match p.create_terminal(&inferior) {
+            Ok(t) => t,
+            Err(e) => {
+                warn!("Failed to create terminal for inferior {}: {}", inferior, e);
+                return;
+            }
+        };
+        self.terminals.insert(inferior.clone(), terminal);
+        self.set_tty(inferior, p);
+    }
+
+    // Inferiors usually appear while the program is running (e.g., after a fork), when gdb does
+    // not accept commands. The terminal is then passed to gdb at the next stop instead, which is
+    // still before the inferior can be (re)started.
+    fn set_tty(&mut self, inferior: String, p: &mut Context) {
+        let tty = match self.terminals.get(&inferior) {
+            Some(terminal) => terminal.slave_name().to_owned(),
+            None => return,
+        };
+        p.execute_async(
+            MiCommand::inferior_tty_set(&inferior, &tty),
+            move |tui, p, result| match result {
+                Ok(r) if r.class == ResultClass::Done => {}
+                Ok(r) => p.log(format!(
+                    "Failed to set terminal of inferior: {}",
+                    r.results["msg"].as_str().unwrap_or("unknown error")
+                )),
+                Err(ExecuteError::Busy) => tui.unset_ttys.push(inferior),
+                Err(e) => p.log(format!("Failed to set terminal of inferior: {:?}", e)),
+            },
+        );
+    }
+
+    fn set_pending_ttys(&mut self, p: &mut Context) {
+        for inferior in std::mem::take(&mut self.unset_ttys) {
+            self.set_tty(inferior, p);
+        }
+    }
+
+    // A restarted gdb only has the initial inferior and announces it (and any further ones)
+    // again, so that the terminals of the others are set up anew.
+    pub fn reset_terminals(&mut self) {
+        self.terminals
+            .retain(|inferior, _| inferior == INITIAL_INFERIOR);
+        self.active_inferior = INITIAL_INFERIOR.to_owned();
+        self.unset_ttys.clear();
+        self.last_frames.clear();
+    }
+
+    fn remove_terminal(&mut self, inferior: &str) {
+        // The active terminal is kept until another inferior is activated. The initial one is
+        // always kept, because gdb uses it for new inferiors that are not announced (e.g., after
+        // a restart) and because it is shown if there is no other terminal.
+        if inferior != self.active_inferior && inferior != INITIAL_INFERIOR {
+            self.terminals.remove(inferior);
+        }
+        self.unset_ttys.retain(|i| i != inferior);
+        self.last_frames.remove(inferior);
+    }
+
+    fn show_stop_frame(&mut self, frame: Frame, thread_id: Option<u64>, p: &mut Context) {
+        if let Some(thread_id) = thread_id {
+            p.gdb.inferiors.select_thread(thread_id);
+        }
+        if let Some(inferior) = p.gdb.inferiors.current() {
+            self.last_frames.insert(inferior.id.clone(), frame.clone());
+        }
+        self.status_bar.set_frame(frame.clone(), thread_id);
+        self.expression_table.set_frame(frame.clone(), thread_id);
+        // Do not show the last frame of the inferior again, we are already showing a newer one.
+        self.update_active_inferior(p, false);
+        self.src_view.show_frame(&frame, p);
+    }
+
+    fn update_active_inferior(&mut self, p: &mut Context, show_last_frame: bool) {
+        let current = match p.gdb.inferiors.current() {
+            Some(inferior) => inferior,
+            None => return,
+        };
+        self.src_view.set_inferior(if p.gdb.inferiors.len() > 1 {
+            Some(current.number().to_owned())
+        } else {
+            None
+        });
+        if current.id == self.active_inferior || !self.terminals.contains_key(&current.id) {
+            return;
+        }
+        let previous = std::mem::replace(&mut self.active_inferior, current.id.clone());
+        if previous != INITIAL_INFERIOR && !p.gdb.inferiors.iter().any(|i| i.id == previous) {
+            self.terminals.remove(&previous);
+        }
+        if show_last_frame {
+            if let Some(frame) = self.last_frames.get(&self.active_inferior).cloned() {
+                self.src_view.show_frame(&frame, p);
+            }
         }
     }
 
@@ -50,22 +189,86 @@ impl<'a> Tui<'a> {
         results: &Object,
         p: &mut Context,
     ) {
+        p.ipc_subscribers.notify(&class, results);
+        p.dap_clients.notify(&class, results);
         match (kind, class) {
-            (AsyncKind::Exec, AsyncClass::Stopped)
-            | (AsyncKind::Notify, AsyncClass::Thread(ThreadEvent::Selected)) => {
+            (AsyncKind::Exec, AsyncClass::Stopped) => {
                 debug!("stopped: {}", JsonValue::Object(results.clone()).pretty(2));
-                if let JsonValue::Object(ref frame) = results["frame"] {
-                    self.src_view.show_frame(frame, p);
+                match StopEvent::decode(results) {
+                    Ok(event) => {
+                        if let Some(reason) = &event.reason {
+                            self.console.write_to_gdb_log(format!("{}\n", reason));
+                        }
+                        self.expression_table
+                            .record_stop(event.frame.as_ref(), event.reason.as_ref());
+                        self.status_bar.set_stop(event.reason.clone());
+                        self.src_view.set_stop_reason(event.reason);
+                        if let Some(frame) = event.frame {
+                            self.show_stop_frame(frame, event.thread_id, p);
+                        }
+                    }
+                    Err(e) => warn!("Malformed stop event: {}", e),
+                }
+                self.set_pending_ttys(p);
+                p.update_working_dir();
+                self.expression_table.update_results(p);
+            }
+            (AsyncKind::Exec, AsyncClass::Running) => {
+                self.src_view.set_stop_reason(None);
+                self.status_bar.set_running();
+            }
+            (AsyncKind::Notify, AsyncClass::RecordStarted) => {
+                self.status_bar.set_recording(true);
+            }
+            (AsyncKind::Notify, AsyncClass::RecordStopped) => {
+                self.status_bar.set_recording(false);
+            }
+            (AsyncKind::Notify, AsyncClass::Thread(ThreadEvent::Selected)) => {
+                debug!(
+                    "thread selected: {}",
+                    JsonValue::Object(results.clone()).pretty(2)
+                );
+                match ThreadSelectedEvent::decode(results) {
+                    Ok(event) => {
+                        if let Some(frame) = event.frame {
+                            self.show_stop_frame(frame, Some(event.id), p);
+                        } else {
+                            p.gdb.inferiors.select_thread(event.id);
+                        }
+                    }
+                    Err(e) => warn!("Malformed thread-selected event: {}", e),
                 }
                 self.expression_table.update_results(p);
             }
+            (AsyncKind::Notify, AsyncClass::Thread(event)) => {
+                debug!(
+                    "thread {:?}: {}",
+                    event,
+                    JsonValue::Object(results.clone()).pretty(2)
+                );
+                if let Err(e) = p.gdb.inferiors.handle_event(event, results) {
+                    warn!("Malformed thread event: {}", e);
+                }
+                match (event, ThreadGroupEvent::decode(results)) {
+                    (ThreadEvent::GroupAdded, Ok(group)) => self.add_terminal(group.id, p),
+                    (ThreadEvent::GroupRemoved, Ok(group)) => self.remove_terminal(&group.id),
+                    _ => {}
+                }
+            }
             (AsyncKind::Notify, AsyncClass::BreakPoint(event)) => {
                 debug!(
                     "bkpoint {:?}: {}",
                     event,
                     JsonValue::Object(results.clone()).pretty(2)
                 );
-                p.gdb.handle_breakpoint_event(event, results);
+                if let Err(e) = p.gdb.handle_breakpoint_event(event, results) {
+                    warn!("Malformed breakpoint event: {}", e);
+                }
+                if let (BreakPointEvent::Deleted, Ok(number)) =
+                    (event, BreakPointInfo::decode_deleted(results))
+                {
+                    self.expression_table.forget_watchpoint(number);
+                }
             }
             (kind, class) => {
                 info!(
@@ -80,7 +283,8 @@ impl<'a> Tui<'a> {
 
     pub fn add_out_of_band_record(&mut self, record: OutOfBandRecord, p: &mut Context) {
         match record {
-            OutOfBandRecord::StreamRecord { kind: _, data } => {
+            OutOfBandRecord::StreamRecord { kind, data } => {
+                p.dap_clients.output(kind, &data);
                 self.console.write_to_gdb_log(data);
             }
             OutOfBandRecord::AsyncRecord {
@@ -94,11 +298,15 @@ impl<'a> Tui<'a> {
         }
     }
 
-    pub fn add_pty_input(&mut self, input: &[u8]) {
-        self.process_pty.add_byte_input(input);
+    pub fn add_pty_input(&mut self, inferior: &str, input: &[u8]) {
+        if let Some(terminal) = self.terminals.get_mut(inferior) {
+            terminal.add_byte_input(input);
+        }
     }
 
     pub fn update_after_event(&mut self, p: &mut Context) {
+        self.update_active_inferior(p, true);
+        self.status_bar.update_after_event(p);
         self.src_view.update_after_event(p);
         self.console.update_after_event(p);
     }
@@ -120,7 +328,7 @@ impl<'t> ContainerProvider for Tui<'t> {
             TuiContainerType::SrcView => &self.src_view,
             TuiContainerType::Console => &self.console,
             TuiContainerType::ExpressionTable => &self.expression_table,
-            TuiContainerType::Terminal => &self.process_pty,
+            TuiContainerType::Terminal => self.active_terminal(),
         }
     }
     fn get_mut<'a, 'b: 'a>(
@@ -131,7 +339,7 @@ impl<'t> ContainerProvider for Tui<'t> {
             TuiContainerType::SrcView => &mut self.src_view,
             TuiContainerType::Console => &mut self.console,
             TuiContainerType::ExpressionTable => &mut self.expression_table,
-            TuiContainerType::Terminal => &mut self.process_pty,
+            TuiContainerType::Terminal => self.active_terminal_mut(),
         }
     }
     const DEFAULT_CONTAINER: TuiContainerType = TuiContainerType::Console;
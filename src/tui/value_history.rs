@@ -0,0 +1,365 @@
+// The values that an expression had when the program stopped and a panel that shows how the value
+// evolved and what changed between two of them.
+use crate::gdb_expression_parsing::{diff, parse_gdb_value, Difference};
+use crate::gdbmi::output::Frame;
+use std::collections::VecDeque;
+use std::fmt::Write;
+use unsegen::base::{
+    BoolModifyMode, Color, Cursor, CursorTarget, ExtentEstimationWindow, StyleModifier, Width,
+    Window,
+};
+use unsegen::input::OperationResult;
+use unsegen::widget::{Demand, Demand2D, RenderingHints, Widget};
+
+// Older values are dropped.
+const MAX_ENTRIES: usize = 1000;
+// The number of values and differences that are shown at once
+const VISIBLE_ENTRIES: usize = 8;
+const VISIBLE_DIFFERENCES: usize = 16;
+
+// Stops are numbered (starting at 1) so that they can be referred to in the history.
+#[derive(Clone, Default)]
+pub struct Stop {
+    pub number: usize,
+    pub location: String,
+}
+
+impl Stop {
+    pub fn next(&self, frame: Option<&Frame>) -> Stop {
+        let location = match frame {
+            Some(frame) => {
+                let mut location = frame
+                    .function
+                    .clone()
+                    .or_else(|| frame.address.map(|a| a.to_string()))
+                    .unwrap_or_else(|| "??".to_owned());
+                if let (Some(file), Some(line)) = (&frame.file, frame.line) {
+                    let _ = write!(location, " at {}:{}", file, line);
+                }
+                location
+            }
+            None => "-".to_owned(),
+        };
+        Stop {
+            number: self.number + 1,
+            location,
+        }
+    }
+}
+
+pub struct HistoryEntry {
+    pub stop: Stop,
+    pub value: String,
+}
+
+impl HistoryEntry {
+    fn single_line_value(&self) -> String {
+        match parse_gdb_value(&self.value) {
+            Ok(node) => node.to_string(),
+            Err(_) => self.value.replace('\n', " "),
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct ValueHistory {
+    entries: VecDeque<HistoryEntry>,
+}
+
+impl ValueHistory {
+    // Evaluating the expression again at the same stop (e.g., after selecting another thread)
+    // replaces the value.
+    pub fn record(&mut self, stop: &Stop, value: &str) {
+        if let Some(last) = self.entries.back_mut() {
+            if last.stop.number == stop.number {
+                last.value = value.to_owned();
+                return;
+            }
+        }
+        if self.entries.len() == MAX_ENTRIES {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(HistoryEntry {
+            stop: stop.clone(),
+            value: value.to_owned(),
+        });
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    fn index_of(&self, stop: usize) -> Option<usize> {
+        self.entries
+            .binary_search_by_key(&stop, |e| e.stop.number)
+            .ok()
+    }
+
+    // Compare the values of two entries member by member.
+    fn diff(&self, expression: &str, old: usize, new: usize) -> Vec<Difference> {
+        let (old, new) = (&self.entries[old].value, &self.entries[new].value);
+        match (parse_gdb_value(old), parse_gdb_value(new)) {
+            (Ok(old), Ok(new)) => diff(expression, &old, &new),
+            _ if old != new => vec![Difference::Changed {
+                path: expression.to_owned(),
+                old: old.clone(),
+                new: new.clone(),
+            }],
+            _ => Vec::new(),
+        }
+    }
+}
+
+// Which entries of a history are shown and compared. Entries are referred to by their stop number
+// so that they stay selected while new ones are recorded.
+#[derive(Default)]
+pub struct HistoryView {
+    // The latest entry, if not set
+    selected: Option<usize>,
+    // The entry before the selected one, if not set
+    base: Option<usize>,
+}
+
+impl HistoryView {
+    fn selected_index(&self, history: &ValueHistory) -> Option<usize> {
+        self.selected
+            .and_then(|stop| history.index_of(stop))
+            .or_else(|| history.entries.len().checked_sub(1))
+    }
+
+    fn base_index(&self, history: &ValueHistory) -> Option<usize> {
+        match self.base {
+            Some(stop) => history.index_of(stop),
+            None => self.selected_index(history)?.checked_sub(1),
+        }
+    }
+
+    fn select(&mut self, history: &ValueHistory, index: Option<usize>) -> OperationResult {
+        let index = index.filter(|&i| i < history.entries.len()).ok_or(())?;
+        self.selected = Some(history.entries[index].stop.number);
+        Ok(())
+    }
+
+    pub fn select_older(&mut self, history: &ValueHistory) -> OperationResult {
+        let index = self.selected_index(history).and_then(|i| i.checked_sub(1));
+        self.select(history, index)
+    }
+
+    pub fn select_newer(&mut self, history: &ValueHistory) -> OperationResult {
+        let index = self.selected_index(history).map(|i| i + 1);
+        self.select(history, index)
+    }
+
+    pub fn select_oldest(&mut self, history: &ValueHistory) -> OperationResult {
+        self.select(history, Some(0))
+    }
+
+    pub fn select_latest(&mut self, _history: &ValueHistory) -> OperationResult {
+        self.selected = None;
+        Ok(())
+    }
+
+    // Compare the other entries with the selected one instead of their predecessor (or stop doing
+    // so if it is the base already).
+    pub fn toggle_base(&mut self, history: &ValueHistory) -> OperationResult {
+        let selected = history.entries[self.selected_index(history).ok_or(())?]
+            .stop
+            .number;
+        self.base = if self.base == Some(selected) {
+            None
+        } else {
+            Some(selected)
+        };
+        Ok(())
+    }
+}
+
+pub struct HistoryPanel<'a> {
+    pub expression: &'a str,
+    // None if the row does not record values
+    pub history: Option<&'a ValueHistory>,
+    pub view: &'a HistoryView,
+}
+
+impl HistoryPanel<'_> {
+    fn draw_lines<T: CursorTarget>(&self, cursor: &mut Cursor<T>, hints: RenderingHints) {
+        {
+            let mut cursor = cursor.save().style_modifier();
+            cursor.apply_style_modifier(StyleModifier::new().bold(true));
+            let _ = write!(cursor, "History of {}", self.expression);
+        }
+        cursor.write(" (Space: compare with the selected value, Ctrl-o: close)");
+        let history = match self.history {
+            Some(history) => history,
+            None => {
+                cursor.wrap_line();
+                cursor.write("Not available for rows backed by variable objects");
+                return;
+            }
+        };
+        let selected = match self.view.selected_index(history) {
+            Some(selected) => selected,
+            None => {
+                cursor.wrap_line();
+                cursor.write("No values yet (values are recorded when the program stops)");
+                return;
+            }
+        };
+        let base = self.view.base_index(history);
+
+        let num_entries = history.entries.len();
+        let first = selected
+            .saturating_sub(VISIBLE_ENTRIES / 2)
+            .min(num_entries.saturating_sub(VISIBLE_ENTRIES));
+        for (i, entry) in history
+            .entries
+            .iter()
+            .enumerate()
+            .skip(first)
+            .take(VISIBLE_ENTRIES)
+        {
+            cursor.wrap_line();
+            let mut cursor = cursor.save().style_modifier();
+            if i == selected {
+                cursor.apply_style_modifier(if hints.active {
+                    StyleModifier::new()
+                        .invert(BoolModifyMode::Toggle)
+                        .bold(true)
+                } else {
+                    StyleModifier::new().bold(true)
+                });
+            }
+            let marker = if Some(i) == base && self.view.base.is_some() {
+                "*"
+            } else {
+                " "
+            };
+            let _ = write!(
+                cursor,
+                "{} #{} {}: {}",
+                marker,
+                entry.stop.number,
+                entry.stop.location,
+                entry.single_line_value()
+            );
+        }
+
+        cursor.wrap_line();
+        let base = match base {
+            Some(base) => base,
+            None => {
+                cursor.write("No earlier value to compare with");
+                return;
+            }
+        };
+        let _ = write!(
+            cursor,
+            "Changes from #{} to #{}:",
+            history.entries[base].stop.number, history.entries[selected].stop.number
+        );
+        let differences = history.diff(self.expression, base, selected);
+        if differences.is_empty() {
+            cursor.write(" none");
+        }
+        for difference in differences.iter().take(VISIBLE_DIFFERENCES) {
+            cursor.wrap_line();
+            let mut cursor = cursor.save().style_modifier();
+            let _ = match difference {
+                Difference::Changed { path, old, new } => {
+                    cursor.apply_style_modifier(StyleModifier::new().fg_color(Color::Yellow));
+                    write!(cursor, "~ {}: {} -> {}", path, old, new)
+                }
+                Difference::Added { path, value } => {
+                    cursor.apply_style_modifier(StyleModifier::new().fg_color(Color::Green));
+                    write!(cursor, "+ {} = {}", path, value)
+                }
+                Difference::Removed { path, value } => {
+                    cursor.apply_style_modifier(StyleModifier::new().fg_color(Color::Red));
+                    write!(cursor, "- {} = {}", path, value)
+                }
+            };
+        }
+        if differences.len() > VISIBLE_DIFFERENCES {
+            cursor.wrap_line();
+            let _ = write!(
+                cursor,
+                "... and {} more",
+                differences.len() - VISIBLE_DIFFERENCES
+            );
+        }
+    }
+}
+
+impl Widget for HistoryPanel<'_> {
+    fn space_demand(&self) -> Demand2D {
+        let mut window = ExtentEstimationWindow::unbounded();
+        self.draw_lines(&mut Cursor::new(&mut window), RenderingHints::default());
+        Demand2D {
+            width: Demand::at_least(Width::new(1).unwrap()),
+            height: Demand::exact(window.extent_y()),
+        }
+    }
+    fn draw(&self, mut window: Window, hints: RenderingHints) {
+        self.draw_lines(&mut Cursor::new(&mut window), hints);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use unsegen::base::Height;
+
+    fn stop(number: usize) -> Stop {
+        Stop {
+            number,
+            location: format!("line {}", number),
+        }
+    }
+
+    #[test]
+    fn test_history() {
+        let mut history = ValueHistory::default();
+        let mut view = HistoryView::default();
+        assert!(view.toggle_base(&history).is_err());
+        history.record(&stop(1), "{a = 1, b = 1}");
+        history.record(&stop(2), "{a = 2, b = 1}");
+        history.record(&stop(2), "{a = 3, b = 1}");
+        history.record(&stop(4), "{a = 3, b = 2}");
+        assert_eq!(history.entries.len(), 3);
+
+        let changed = |path: &str, old: &str, new: &str| Difference::Changed {
+            path: path.to_owned(),
+            old: old.to_owned(),
+            new: new.to_owned(),
+        };
+        let differences = |view: &HistoryView, history: &ValueHistory| {
+            let base = view.base_index(history).unwrap();
+            history.diff("s", base, view.selected_index(history).unwrap())
+        };
+        // By default, the latest value is compared with the one before.
+        assert_eq!(differences(&view, &history), [changed("s.b", "1", "2")]);
+        let panel = HistoryPanel {
+            expression: "s",
+            history: Some(&history),
+            view: &view,
+        };
+        // Title, values, summary and the changed member
+        assert_eq!(panel.space_demand().height.min, Height::new(6).unwrap());
+
+        view.select_oldest(&history).unwrap();
+        assert!(view.base_index(&history).is_none());
+        view.toggle_base(&history).unwrap();
+        view.select_latest(&history).unwrap();
+        assert_eq!(
+            differences(&view, &history),
+            [changed("s.a", "1", "3"), changed("s.b", "1", "2")]
+        );
+        // The selection sticks to its stop when new values are recorded.
+        view.select_older(&history).unwrap();
+        history.record(&stop(5), "{a = 4, b = 2}");
+        assert_eq!(differences(&view, &history), [changed("s.a", "1", "3")]);
+        assert!(view.select_newer(&history).is_ok());
+        assert!(view.select_newer(&history).is_ok());
+        assert!(view.select_newer(&history).is_err());
+    }
+}
@@ -0,0 +1,486 @@
+// A viewer for values that are organized in a tree whose children can be loaded on demand (like
+// gdb var objects).
+use std::collections::HashMap;
+use std::fmt::Write;
+use unsegen::base::{
+    BoolModifyMode, Color, Cursor, CursorTarget, ExtentEstimationWindow, StyleModifier, Window,
+};
+use unsegen::input::{OperationResult, Scrollable};
+use unsegen::widget::{Demand, Demand2D, RenderingHints, Widget};
+
+pub enum Children {
+    None,
+    Unloaded,
+    Loading,
+    Loaded(Vec<TreeNode>),
+}
+
+pub struct TreeNode {
+    // Used to refer to the node when its children are loaded (e.g., the name of a var object)
+    pub id: String,
+    pub key: String,
+    pub value: String,
+    pub children: Children,
+    pub expanded: bool,
+    pub changed: bool,
+    // Shown after the value, e.g., the format chosen for the subtree
+    pub note: Option<String>,
+    // State of the filter of the tree
+    matched: bool,
+    hidden: bool,
+}
+
+impl TreeNode {
+    pub fn new(id: String, key: String, value: String, has_children: bool) -> Self {
+        TreeNode {
+            id,
+            key,
+            value,
+            children: if has_children {
+                Children::Unloaded
+            } else {
+                Children::None
+            },
+            expanded: false,
+            changed: false,
+            note: None,
+            matched: false,
+            hidden: false,
+        }
+    }
+
+    fn visible_children(&self) -> impl Iterator<Item = &TreeNode> {
+        let children: &[TreeNode] = match &self.children {
+            Children::Loaded(children) if self.expanded => children,
+            _ => &[],
+        };
+        children.iter().filter(|c| !c.hidden)
+    }
+
+    fn num_visible_lines(&self) -> usize {
+        1 + self
+            .visible_children()
+            .map(|c| c.num_visible_lines())
+            .sum::<usize>()
+    }
+
+    // Visit all visible nodes (depth first) with their depth.
+    fn visit_visible<'a>(&'a self, depth: usize, f: &mut impl FnMut(&'a TreeNode, usize)) {
+        f(self, depth);
+        for child in self.visible_children() {
+            child.visit_visible(depth + 1, f);
+        }
+    }
+
+    fn nth_visible_mut(&mut self, n: usize) -> Option<&mut TreeNode> {
+        if n == 0 {
+            return Some(self);
+        }
+        let mut n = n - 1;
+        if !self.expanded {
+            return None;
+        }
+        if let Children::Loaded(children) = &mut self.children {
+            for child in children.iter_mut().filter(|c| !c.hidden) {
+                let lines = child.num_visible_lines();
+                if n < lines {
+                    return child.nth_visible_mut(n);
+                }
+                n -= lines;
+            }
+        }
+        None
+    }
+
+    pub fn find_mut(&mut self, id: &str) -> Option<&mut TreeNode> {
+        if self.id == id {
+            return Some(self);
+        }
+        match &mut self.children {
+            Children::Loaded(children) => children.iter_mut().find_map(|c| c.find_mut(id)),
+            _ => None,
+        }
+    }
+
+    // Keep the expansion state of the corresponding nodes (i.e., those with the same id) of the old
+    // tree and mark values that differ from them as changed (or keep their change markers).
+    fn take_state_from(&mut self, old: &TreeNode, mark_changes: bool) {
+        if !matches!(old.children, Children::None) {
+            self.expanded = old.expanded;
+        }
+        self.changed = if mark_changes {
+            self.value != old.value
+        } else {
+            old.changed
+        };
+        if let (Children::Loaded(children), Children::Loaded(old_children)) =
+            (&mut self.children, &old.children)
+        {
+            let old_children = old_children
+                .iter()
+                .map(|c| (c.id.as_str(), c))
+                .collect::<HashMap<_, _>>();
+            for child in children {
+                if let Some(old) = old_children.get(child.id.as_str()) {
+                    child.take_state_from(old, mark_changes);
+                }
+            }
+        }
+    }
+
+    // Show the node if it or one of its ancestors or descendants matches the (lowercase) filter.
+    // Ancestors of matching nodes are expanded. Returns whether the subtree contains a match.
+    fn apply_filter(&mut self, filter: &str, ancestor_matched: bool) -> bool {
+        self.matched = !filter.is_empty()
+            && (self.key.to_lowercase().contains(filter)
+                || self.value.to_lowercase().contains(filter));
+        let mut descendant_matched = false;
+        if let Children::Loaded(children) = &mut self.children {
+            for child in children {
+                descendant_matched |= child.apply_filter(filter, ancestor_matched || self.matched);
+            }
+        }
+        if descendant_matched {
+            self.expanded = true;
+        }
+        self.hidden =
+            !(filter.is_empty() || ancestor_matched || self.matched || descendant_matched);
+        self.matched || descendant_matched
+    }
+
+    fn clear_changed(&mut self) {
+        self.changed = false;
+        if let Children::Loaded(children) = &mut self.children {
+            for child in children {
+                child.clear_changed();
+            }
+        }
+    }
+}
+
+pub struct ValueTree {
+    root: TreeNode,
+    selected: usize,
+    filter: String,
+}
+
+fn message_node(text: impl Into<String>) -> TreeNode {
+    TreeNode::new(String::new(), String::new(), text.into(), false)
+}
+
+impl ValueTree {
+    pub fn new(root: TreeNode) -> Self {
+        ValueTree {
+            root,
+            selected: 0,
+            filter: String::new(),
+        }
+    }
+
+    // A tree that only shows the given text (e.g., an error message).
+    pub fn message(text: impl Into<String>) -> Self {
+        Self::new(message_node(text))
+    }
+
+    // Replace the tree without taking over any state of the old one, except for the filter.
+    pub fn replace(&mut self, root: TreeNode) {
+        self.root = root;
+        self.selected = 0;
+        self.apply_filter();
+    }
+
+    pub fn show_message(&mut self, text: impl Into<String>) {
+        self.replace(message_node(text));
+    }
+
+    pub fn filter(&self) -> &str {
+        &self.filter
+    }
+
+    // Only show nodes whose key or value contains the filter (ignoring case), see
+    // TreeNode::apply_filter. The empty filter shows all nodes.
+    pub fn set_filter(&mut self, filter: &str) {
+        if filter != self.filter {
+            self.filter = filter.to_owned();
+            self.apply_filter();
+        }
+    }
+
+    fn apply_filter(&mut self) {
+        self.root.apply_filter(&self.filter.to_lowercase(), false);
+        self.root.hidden = false;
+        self.fix_selection();
+    }
+
+    pub fn root(&self) -> &TreeNode {
+        &self.root
+    }
+
+    pub fn find_mut(&mut self, id: &str) -> Option<&mut TreeNode> {
+        self.root.find_mut(id)
+    }
+
+    pub fn selected(&self) -> Option<&TreeNode> {
+        let mut line = 0;
+        let mut selected = None;
+        self.root.visit_visible(0, &mut |node, _| {
+            if line == self.selected {
+                selected = Some(node);
+            }
+            line += 1;
+        });
+        selected
+    }
+
+    // Replace the tree with a new version of it (see TreeNode::take_state_from).
+    pub fn update(&mut self, mut root: TreeNode, mark_changes: bool) {
+        if root.id == self.root.id {
+            root.take_state_from(&self.root, mark_changes);
+        }
+        self.root = root;
+        self.apply_filter();
+    }
+
+    // Reset the change markers, e.g., before applying the changes of the next update.
+    pub fn clear_changed(&mut self) {
+        self.root.clear_changed();
+    }
+
+    // Expand or collapse the selected node. If its children have to be loaded first, the node is
+    // marked as loading and its id is returned.
+    pub fn toggle_selected(&mut self) -> Result<Option<String>, ()> {
+        let node = self.root.nth_visible_mut(self.selected).ok_or(())?;
+        if let Children::None = node.children {
+            return Err(());
+        }
+        node.expanded ^= true;
+        Ok(if node.expanded {
+            Self::start_loading(node)
+        } else {
+            None
+        })
+    }
+
+    // Mark the node as loading and return its id if its children have not been loaded yet.
+    pub fn start_loading(node: &mut TreeNode) -> Option<String> {
+        if let Children::Unloaded = node.children {
+            node.children = Children::Loading;
+            Some(node.id.clone())
+        } else {
+            None
+        }
+    }
+
+    pub fn set_children(&mut self, id: &str, children: Children) {
+        if let Some(node) = self.find_mut(id) {
+            node.children = children;
+        }
+        self.apply_filter();
+    }
+
+    fn fix_selection(&mut self) {
+        self.selected = self.selected.min(self.root.num_visible_lines() - 1);
+    }
+
+    fn draw_lines<T: CursorTarget>(&self, cursor: &mut Cursor<T>, hints: RenderingHints) {
+        let focused_style = if hints.active {
+            StyleModifier::new()
+                .invert(BoolModifyMode::Toggle)
+                .bold(true)
+        } else {
+            StyleModifier::new().bold(true)
+        };
+        let mut line = 0;
+        self.root.visit_visible(0, &mut |node, depth| {
+            if line > 0 {
+                cursor.wrap_line();
+            }
+            let mut cursor = cursor.save().style_modifier();
+            if line == self.selected {
+                cursor.apply_style_modifier(focused_style);
+            }
+            let marker = match node.children {
+                Children::None => " ",
+                _ if node.expanded => "▾",
+                _ => "▸",
+            };
+            let _ = write!(cursor, "{:indent$}{} ", "", marker, indent = 2 * depth);
+            if !node.key.is_empty() {
+                {
+                    let mut cursor = cursor.save().style_modifier();
+                    if node.matched {
+                        cursor.apply_style_modifier(StyleModifier::new().underline(true));
+                    }
+                    cursor.write(&node.key);
+                }
+                cursor.write(" = ");
+            }
+            {
+                let mut cursor = cursor.save().style_modifier();
+                if node.changed {
+                    cursor.apply_style_modifier(StyleModifier::new().bg_color(Color::Red));
+                }
+                cursor.write(&node.value);
+            }
+            if node.expanded {
+                if let Children::Loading = node.children {
+                    cursor.write(" (loading...)");
+                }
+            }
+            if let Some(note) = &node.note {
+                let mut cursor = cursor.save().style_modifier();
+                cursor.apply_style_modifier(StyleModifier::new().italic(true));
+                let _ = write!(cursor, " [{}]", note);
+            }
+            line += 1;
+        });
+    }
+}
+
+impl Widget for &ValueTree {
+    fn space_demand(&self) -> Demand2D {
+        let mut window = ExtentEstimationWindow::unbounded();
+        self.draw_lines(&mut Cursor::new(&mut window), RenderingHints::default());
+        Demand2D {
+            width: Demand::at_least(window.extent_x()),
+            height: Demand::exact(window.extent_y()),
+        }
+    }
+    fn draw(&self, mut window: Window, hints: RenderingHints) {
+        self.draw_lines(&mut Cursor::new(&mut window), hints);
+    }
+}
+
+impl Scrollable for ValueTree {
+    fn scroll_backwards(&mut self) -> OperationResult {
+        if self.selected > 0 {
+            self.selected -= 1;
+            Ok(())
+        } else {
+            Err(())
+        }
+    }
+    fn scroll_forwards(&mut self) -> OperationResult {
+        if self.selected + 1 < self.root.num_visible_lines() {
+            self.selected += 1;
+            Ok(())
+        } else {
+            Err(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn leaf(id: &str, value: &str) -> TreeNode {
+        TreeNode::new(id.to_owned(), id.to_owned(), value.to_owned(), false)
+    }
+
+    #[test]
+    fn test_lazy_children() {
+        let mut tree = ValueTree::new(TreeNode::new(
+            "var1".to_owned(),
+            String::new(),
+            "{...}".to_owned(),
+            true,
+        ));
+        assert_eq!(tree.toggle_selected(), Ok(Some("var1".to_owned())));
+        // Nothing to select until the children are loaded.
+        assert!(tree.scroll_forwards().is_err());
+
+        let inner = TreeNode::new(
+            "var1.b".to_owned(),
+            "b".to_owned(),
+            "{...}".to_owned(),
+            true,
+        );
+        tree.set_children("var1", Children::Loaded(vec![leaf("var1.a", "1"), inner]));
+        assert_eq!(tree.root().num_visible_lines(), 3);
+        tree.scroll_to_end().unwrap();
+        assert_eq!(tree.toggle_selected(), Ok(Some("var1.b".to_owned())));
+        tree.set_children("var1.b", Children::Loaded(vec![leaf("var1.b.c", "2")]));
+        assert_eq!(tree.root().num_visible_lines(), 4);
+        // Collapsing and expanding again does not load the children twice.
+        assert_eq!(tree.toggle_selected(), Ok(None));
+        assert_eq!(tree.toggle_selected(), Ok(None));
+        tree.scroll_to_end().unwrap();
+        assert_eq!(tree.toggle_selected(), Err(()));
+        assert_eq!(
+            tree.find_mut("var1.b.c").map(|n| n.value.clone()),
+            Some("2".to_owned())
+        );
+
+        tree.scroll_to_beginning().unwrap();
+        assert_eq!(tree.toggle_selected(), Ok(None));
+        assert_eq!(tree.root().num_visible_lines(), 1);
+    }
+
+    #[test]
+    fn test_update() {
+        let value = |x: &str| {
+            let mut inner = TreeNode::new("s".to_owned(), "s".to_owned(), "{...}".to_owned(), true);
+            inner.children = Children::Loaded(vec![leaf("s.x", x)]);
+            let mut root = TreeNode::new(String::new(), String::new(), "{...}".to_owned(), true);
+            root.children = Children::Loaded(vec![leaf("a", "1"), inner]);
+            root.expanded = true;
+            root
+        };
+        let mut tree = ValueTree::new(value("1"));
+        tree.scroll_to_end().unwrap();
+        assert_eq!(tree.selected().map(|n| n.id.as_str()), Some("s"));
+        assert_eq!(tree.toggle_selected(), Ok(None));
+
+        tree.update(value("2"), true);
+        assert_eq!(tree.root().num_visible_lines(), 4);
+        let changed = |tree: &mut ValueTree, id| tree.find_mut(id).unwrap().changed;
+        assert!(changed(&mut tree, "s.x"));
+        assert!(!changed(&mut tree, "a"));
+        // E.g., if only the format has changed
+        tree.update(value("2"), false);
+        assert!(changed(&mut tree, "s.x"));
+        tree.update(value("2"), true);
+        assert!(!changed(&mut tree, "s.x"));
+    }
+
+    #[test]
+    fn test_filter() {
+        let value = |x: &str| {
+            let mut inner = TreeNode::new("s".to_owned(), "s".to_owned(), "{...}".to_owned(), true);
+            inner.children = Children::Loaded(vec![leaf("s.x", x), leaf("s.y", "2")]);
+            let mut root = TreeNode::new(String::new(), String::new(), "{...}".to_owned(), true);
+            root.children = Children::Loaded(vec![leaf("a", "1"), inner]);
+            root.expanded = true;
+            root
+        };
+        let visible = |tree: &ValueTree| {
+            let mut ids = Vec::new();
+            tree.root
+                .visit_visible(0, &mut |node, _| ids.push(node.id.clone()));
+            ids
+        };
+        let mut tree = ValueTree::new(value("1"));
+        tree.set_filter("S.X");
+        // Ancestors of matches are expanded.
+        assert_eq!(visible(&tree), ["", "s", "s.x"]);
+        tree.scroll_to_end().unwrap();
+        assert_eq!(tree.selected().map(|n| n.id.as_str()), Some("s.x"));
+
+        // Values match as well and the filter is kept for new versions of the tree.
+        tree.update(value("12"), true);
+        tree.set_filter("2");
+        assert_eq!(visible(&tree), ["", "s", "s.x", "s.y"]);
+        // Descendants of matches are shown.
+        tree.set_filter("s");
+        assert_eq!(visible(&tree), ["", "s", "s.x", "s.y"]);
+        tree.set_filter("nothing");
+        assert_eq!(visible(&tree), [""]);
+        assert!(tree.selected().is_some());
+        tree.show_message("error");
+        assert_eq!(tree.filter(), "nothing");
+        tree.update(value("1"), false);
+        tree.set_filter("");
+        assert_eq!(visible(&tree), ["", "a", "s"]);
+    }
+}